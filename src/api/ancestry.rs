@@ -0,0 +1,262 @@
+//! Walking ownerReferences from pods up to the workloads that made them, and back
+use std::collections::{BTreeMap, HashMap};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+
+use crate::api::{
+    DynamicObject, GroupVersionKind, GroupVersionResource, KubeObject, ListParams, Object, ObjectList,
+    OwnerReference, RawApi, Reflector, Resource,
+};
+use crate::client::APIClient;
+use crate::{ErrorKind, Result};
+use failure::ResultExt;
+
+type Pod = Object<PodSpec, PodStatus>;
+
+/// Kinds that own pods, by group, kind, plural and the names kubectl accepts for them
+const WORKLOADS: &[(&str, &str, &str, &[&str])] = &[
+    ("", "Pod", "pods", &["po", "pod"]),
+    ("apps", "ReplicaSet", "replicasets", &["rs", "replicaset"]),
+    ("apps", "Deployment", "deployments", &["deploy", "deployment"]),
+    ("apps", "StatefulSet", "statefulsets", &["sts", "statefulset"]),
+    ("apps", "DaemonSet", "daemonsets", &["ds", "daemonset"]),
+    ("batch", "Job", "jobs", &["job"]),
+    ("batch", "CronJob", "cronjobs", &["cj", "cronjob"]),
+    ("", "ReplicationController", "replicationcontrollers", &["rc", "replicationcontroller"]),
+];
+
+/// The versions workloads are read in when named by kind alone
+fn preferred_version(kind: &str) -> &'static str {
+    if kind == "CronJob" { "v1beta1" } else { "v1" }
+}
+
+/// The workload named by `kind`, which may be plural or a short name, like `deploy`
+fn workload(kind: &str) -> Option<(&'static str, &'static str)> {
+    let kind = kind.to_lowercase();
+    WORKLOADS.iter()
+        .find(|(_, k, plural, names)| k.to_lowercase() == kind || *plural == kind || names.contains(&kind.as_str()))
+        .map(|(g, k, _, _)| (*g, *k))
+}
+
+type Lookup = Box<dyn Fn(&str, &str) -> Result<Option<DynamicObject>> + Send + Sync>;
+
+/// Resolves the owners of objects, from reflector caches when given, else from the apiserver
+///
+/// Only controller references are followed, so a pod resolves to the one chain that
+/// manages it: `Pod → ReplicaSet → Deployment`, or `Pod → Job → CronJob`.
+/// Owners of kinds other than the built-in workloads end the chain, unless a
+/// reflector caching them is given.
+///
+/// ```no_run
+/// use kube::{api::Ancestry, client::APIClient, config};
+///
+/// let config = config::load_kube_config().expect("failed to load kubeconfig");
+/// let ancestry = Ancestry::new(APIClient::new(config));
+/// for pod in ancestry.resolve_pods("blog", "deploy/web").unwrap() {
+///     println!("{}", pod.metadata.name);
+/// }
+/// ```
+pub struct Ancestry {
+    client: APIClient,
+    cached: BTreeMap<(String, String), Lookup>,
+    pods: Option<Reflector<Pod>>,
+}
+
+impl Ancestry {
+    pub fn new(client: APIClient) -> Self {
+        Ancestry { client, cached: BTreeMap::new(), pods: None }
+    }
+
+    /// Read owners of the reflector's kind from its cache
+    pub fn cached<K>(mut self, reflector: Reflector<K>) -> Self
+    where
+        K: Resource + KubeObject + Clone + DeserializeOwned + Serialize + Send + Sync + 'static,
+    {
+        let types = GroupVersionKind::of::<K>();
+        let key = (types.group.clone(), types.kind.clone());
+        self.cached.insert(key, Box::new(move |ns, name| {
+            let found = match reflector.get_within(name, ns)? {
+                Some(o) => o,
+                None => return Ok(None),
+            };
+            let mut value = serde_json::to_value(&found).context(ErrorKind::SerdeParse)?;
+            types.apply_to(&mut value);
+            Ok(Some(serde_json::from_value(value).context(ErrorKind::SerdeParse)?))
+        }));
+        self
+    }
+
+    /// List pods from the reflector's cache instead of the apiserver
+    pub fn cached_pods(mut self, reflector: Reflector<Pod>) -> Self {
+        self.pods = Some(reflector);
+        self
+    }
+
+    /// Read an object from the cache or the apiserver, `None` if gone or of an unknown kind
+    fn read(&self, namespace: &str, gvk: &GroupVersionKind, name: &str) -> Result<Option<DynamicObject>> {
+        if let Some(lookup) = self.cached.get(&(gvk.group.clone(), gvk.kind.clone())) {
+            return lookup(namespace, name);
+        }
+        let plural = match WORKLOADS.iter().find(|(g, k, _, _)| *g == gvk.group && *k == gvk.kind) {
+            Some((_, _, plural, _)) => plural,
+            None => {
+                debug!("Not reading {} {} of unknown kind", gvk, name);
+                return Ok(None);
+            },
+        };
+        let api = GroupVersionResource::new(&gvk.group, &gvk.version, plural).raw_api().within(namespace);
+        match self.client.request::<DynamicObject>(api.get(name)?) {
+            Ok(o) => Ok(Some(o)),
+            Err(e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The owner `oref` points to, `None` if it is gone or was replaced by one with another uid
+    pub fn owner(&self, namespace: &str, oref: &OwnerReference) -> Result<Option<DynamicObject>> {
+        let gvk = GroupVersionKind::from_api_version(&oref.apiVersion, &oref.kind)?;
+        let owner = self.read(namespace, &gvk, &oref.name)?;
+        Ok(owner.filter(|o| o.metadata.uid.as_ref() == Some(&oref.uid)))
+    }
+
+    /// The controllers of `obj`, its own first and the topmost last
+    pub fn ancestors<K: KubeObject>(&self, obj: &K) -> Result<Vec<DynamicObject>> {
+        let namespace = obj.meta().namespace.clone().unwrap_or_default();
+        let mut chain: Vec<DynamicObject> = vec![];
+        let mut next = obj.meta().controller_reference().cloned();
+        while let Some(oref) = next.take() {
+            if chain.iter().any(|o| o.metadata.uid.as_ref() == Some(&oref.uid)) {
+                warn!("Ownership cycle through {} {}", oref.kind, oref.name);
+                break;
+            }
+            if let Some(owner) = self.owner(&namespace, &oref)? {
+                next = owner.metadata.controller_reference().cloned();
+                chain.push(owner);
+            }
+        }
+        Ok(chain)
+    }
+
+    /// The pods of `namespace` that `kind` `name` controls, directly or through others
+    ///
+    /// `kind` may be any of the names kubectl takes, like `deploy` or `cronjobs`.
+    /// Owners are read once for all pods sharing them, and matched by uid, so pods
+    /// left behind by an earlier object of the same name are not included.
+    pub fn pods_of(&self, namespace: &str, kind: &str, name: &str) -> Result<Vec<Pod>> {
+        let (group, target) = workload(kind).ok_or_else(|| ErrorKind::RequestValidation(format!(
+            "{} does not control pods, only {}", kind,
+            WORKLOADS.iter().map(|(_, k, _, _)| *k).collect::<Vec<_>>().join(", "))))?;
+        let pods = match &self.pods {
+            Some(reflector) => reflector.read()?.into_iter()
+                .filter(|p| p.metadata.namespace.as_deref() == Some(namespace))
+                .collect(),
+            None => {
+                let req = RawApi::v1Pod().within(namespace).list(&ListParams::default())?;
+                self.client.request::<ObjectList<Pod>>(req)?.items
+            },
+        };
+        if target == "Pod" {
+            return Ok(pods.into_iter().filter(|p| p.metadata.name == name).collect());
+        }
+        let gvk = GroupVersionKind::new(group, preferred_version(target), target);
+        let uid = match self.read(namespace, &gvk, name)?.and_then(|o| o.metadata.uid) {
+            Some(uid) => uid,
+            None => return Err(ErrorKind::RequestValidation(format!("{} {} not found in {}", target, name, namespace)).into()),
+        };
+        // whether an owner is, or is controlled by, the target
+        let mut seen: HashMap<String, bool> = HashMap::new();
+        let mut selected = vec![];
+        for pod in pods {
+            let mut path = vec![];
+            let mut next = pod.metadata.controller_reference().cloned();
+            let matched = loop {
+                let oref = match next.take() {
+                    Some(oref) => oref,
+                    None => break false,
+                };
+                if let Some(known) = seen.get(&oref.uid) {
+                    break *known;
+                }
+                if oref.uid == uid {
+                    break true;
+                }
+                if path.contains(&oref.uid) {
+                    break false;
+                }
+                path.push(oref.uid.clone());
+                next = self.owner(namespace, &oref)?.and_then(|o| o.metadata.controller_reference().cloned());
+            };
+            for uid in path {
+                seen.insert(uid, matched);
+            }
+            if matched {
+                selected.push(pod);
+            }
+        }
+        Ok(selected)
+    }
+
+    /// The pods a target like `deploy/web`, `cronjob/backup` or `web-0` (a pod) stands for
+    pub fn resolve_pods(&self, namespace: &str, target: &str) -> Result<Vec<Pod>> {
+        match target.find('/') {
+            Some(i) => self.pods_of(namespace, &target[..i], &target[i + 1..]),
+            None => self.pods_of(namespace, "pod", target),
+        }
+    }
+}
+
+#[test]
+fn pods_are_resolved_through_their_owners() {
+    use crate::client::fake::Fake;
+    let owned = |kind: &str, name: &str, uid: &str, owner: Option<(&str, &str, &str)>| {
+        let refs: Vec<_> = owner.into_iter().map(|(kind, name, uid)| serde_json::json!({
+            "apiVersion": if kind == "CronJob" { "batch/v1beta1" } else if kind == "Job" { "batch/v1" } else { "apps/v1" },
+            "kind": kind, "name": name, "uid": uid, "controller": true,
+        })).collect();
+        serde_json::json!({
+            "apiVersion": if kind == "Job" { "batch/v1" } else if kind == "Pod" { "v1" } else { "apps/v1" },
+            "kind": kind,
+            "metadata": { "name": name, "namespace": "blog", "uid": uid, "ownerReferences": refs },
+            "spec": { "containers": [] },
+        })
+    };
+    let fake = Fake::new()
+        .on("GET", "/api/v1/namespaces/blog/pods", 200, serde_json::json!({ "metadata": {}, "items": [
+            owned("Pod", "web-1", "p1", Some(("ReplicaSet", "web-abc", "rs1"))),
+            owned("Pod", "web-2", "p2", Some(("ReplicaSet", "web-abc", "rs1"))),
+            owned("Pod", "backup-1", "p3", Some(("Job", "backup-123", "j1"))),
+            owned("Pod", "stray", "p4", None),
+        ]}).to_string())
+        .on("GET", "/apis/apps/v1/namespaces/blog/replicasets/web-abc", 200,
+            owned("ReplicaSet", "web-abc", "rs1", Some(("Deployment", "web", "d1"))).to_string())
+        .on("GET", "/apis/apps/v1/namespaces/blog/deployments/web", 200, owned("Deployment", "web", "d1", None).to_string())
+        .on("GET", "/apis/batch/v1/namespaces/blog/jobs/backup-123", 200,
+            owned("Job", "backup-123", "j1", Some(("CronJob", "backup", "replaced"))).to_string())
+        .on("GET", "/apis/batch/v1beta1/namespaces/blog/cronjobs/backup", 200, owned("CronJob", "backup", "cj1", None).to_string());
+    let client = fake.client();
+    let ancestry = Ancestry::new(client);
+    let names = |pods: Vec<Pod>| pods.into_iter().map(|p| p.metadata.name).collect::<Vec<_>>();
+
+    assert_eq!(names(ancestry.resolve_pods("blog", "deploy/web").unwrap()), vec!["web-1", "web-2"]);
+    let owner_reads = fake.requests().iter().filter(|r| !r.path().ends_with("/pods")).count();
+    assert_eq!(owner_reads, 4, "the deployment, then each owner once");
+    assert_eq!(names(ancestry.resolve_pods("blog", "rs/web-abc").unwrap()), vec!["web-1", "web-2"]);
+    assert_eq!(names(ancestry.resolve_pods("blog", "jobs/backup-123").unwrap()), vec!["backup-1"]);
+    assert!(ancestry.resolve_pods("blog", "cronjob/backup").unwrap().is_empty(), "the job's owner was replaced");
+    assert!(ancestry.resolve_pods("blog", "deploy/gone").is_err());
+    assert_eq!(names(ancestry.resolve_pods("blog", "stray").unwrap()), vec!["stray"]);
+    assert!(ancestry.resolve_pods("blog", "svc/web").is_err());
+
+    let pod: Pod = serde_json::from_value(serde_json::json!({
+        "metadata": { "name": "web-1", "namespace": "blog", "ownerReferences": [
+            { "apiVersion": "apps/v1", "kind": "ReplicaSet", "name": "web-abc", "uid": "rs1", "controller": true },
+        ]},
+        "spec": { "containers": [] },
+    })).unwrap();
+    let chain = ancestry.ancestors(&pod).unwrap();
+    let kinds: Vec<_> = chain.iter().map(|o| o.types.kind.clone().unwrap()).collect();
+    assert_eq!(kinds, vec!["ReplicaSet", "Deployment"]);
+}
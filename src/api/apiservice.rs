@@ -0,0 +1,92 @@
+//! Registration of aggregated API servers
+//!
+//! An extension apiserver registers itself by creating an APIService that points
+//! the main apiserver at its Service, and is usable once that APIService is `Available`.
+use std::time::Duration;
+use serde_json::json;
+
+use crate::api::{Api, Object, ObjectMeta, TypeMeta, PostParams, PatchParams, Conditions};
+use crate::api::wait::poll_until;
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+use k8s_openapi::ByteString;
+use k8s_openapi::kube_aggregator::pkg::apis::apiregistration::v1::{
+    APIServiceSpec, APIServiceStatus, ServiceReference,
+};
+
+type APIService = Object<APIServiceSpec, APIServiceStatus>;
+
+impl APIService {
+    /// An APIService for `group/version`, served by a Service
+    ///
+    /// The name follows the required `<version>.<group>` convention.
+    /// Priorities decide the order in discovery; see the APIService docs for sensible values.
+    pub fn for_service(group: &str, version: &str, namespace: &str, service: &str, ca_bundle: &[u8]) -> Self {
+        Object {
+            types: TypeMeta {
+                apiVersion: Some("apiregistration.k8s.io/v1".into()),
+                kind: Some("APIService".into()),
+            },
+            metadata: ObjectMeta { name: format!("{}.{}", version, group), ..Default::default() },
+            spec: APIServiceSpec {
+                group: Some(group.into()),
+                version: Some(version.into()),
+                service: ServiceReference {
+                    namespace: Some(namespace.into()),
+                    name: Some(service.into()),
+                    port: Some(443),
+                },
+                ca_bundle: Some(ByteString(ca_bundle.to_vec())),
+                group_priority_minimum: 1000,
+                version_priority: 15,
+                insecure_skip_tls_verify: None,
+            },
+            status: None,
+        }
+    }
+
+    /// Whether the aggregator can reach the backing apiserver
+    pub fn is_available(&self) -> bool {
+        self.status.as_ref()
+            .and_then(|s| s.conditions.as_ref())
+            .map(|c| c.is_condition_true("Available"))
+            .unwrap_or(false)
+    }
+}
+
+impl Api<APIService> {
+    /// Create or update an APIService to match `apiservice`
+    pub fn register(&self, apiservice: APIService) -> Result<APIService> {
+        let name = apiservice.metadata.name.clone();
+        let spec = apiservice.spec.clone();
+        self.upsert(&name, &PostParams::default(), || apiservice.clone(), |o| o.spec = spec.clone())
+    }
+
+    /// Set the CA bundle used to verify the backing apiserver, e.g. after a certificate rotation
+    pub fn inject_ca_bundle(&self, name: &str, ca_bundle: &[u8]) -> Result<APIService> {
+        let patch = json!({
+            "spec": { "caBundle": base64::encode(ca_bundle) }
+        });
+        let data = serde_json::to_vec(&patch).context(ErrorKind::SerdeParse)?;
+        self.patch(name, &PatchParams::default(), data)
+    }
+
+    /// Block until the APIService is `Available`, or fail after `timeout`
+    pub fn wait_for_available(&self, name: &str, timeout: Duration) -> Result<APIService> {
+        poll_until(&format!("apiservice {} to be available", name), timeout, || {
+            let svc = self.get(name)?;
+            Ok(if svc.is_available() { Some(svc) } else { None })
+        })
+    }
+}
+
+#[test]
+fn apiservice_for_service() {
+    let svc = APIService::for_service("metrics.clux.dev", "v1beta1", "monitoring", "metrics", b"CA");
+    let json = serde_json::to_value(&svc).unwrap();
+    assert_eq!(json["metadata"]["name"], "v1beta1.metrics.clux.dev");
+    assert_eq!(json["spec"]["caBundle"], base64::encode("CA"));
+    assert_eq!(json["spec"]["service"]["namespace"], "monitoring");
+    assert!(!svc.is_available());
+}
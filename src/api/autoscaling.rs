@@ -0,0 +1,186 @@
+//! HorizontalPodAutoscalers with autoscaling/v2 metrics, and PodDisruptionBudgets
+use std::collections::BTreeMap;
+
+use crate::api::{Object, ObjectMeta, TypeMeta, Conditions};
+use crate::{Result, Error, ErrorKind};
+
+use k8s_openapi::api::autoscaling::v2beta2::{
+    HorizontalPodAutoscalerSpec, HorizontalPodAutoscalerStatus,
+    CrossVersionObjectReference, MetricSpec, MetricTarget, MetricIdentifier,
+    ResourceMetricSource, PodsMetricSource,
+};
+use k8s_openapi::api::policy::v1beta1::{PodDisruptionBudgetSpec, PodDisruptionBudgetStatus};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+
+type HorizontalPodAutoscaler = Object<HorizontalPodAutoscalerSpec, HorizontalPodAutoscalerStatus>;
+type PodDisruptionBudget = Object<PodDisruptionBudgetSpec, PodDisruptionBudgetStatus>;
+
+/// Scale on the average utilization of a container resource, in percent of requests
+pub fn resource_utilization_metric(resource: &str, percent: i32) -> MetricSpec {
+    MetricSpec {
+        type_: "Resource".into(),
+        resource: Some(ResourceMetricSource {
+            name: resource.into(),
+            target: MetricTarget {
+                type_: "Utilization".into(),
+                average_utilization: Some(percent),
+                ..Default::default()
+            },
+        }),
+        ..Default::default()
+    }
+}
+
+/// Scale on the average cpu utilization, in percent of requests
+pub fn cpu_utilization_metric(percent: i32) -> MetricSpec {
+    resource_utilization_metric("cpu", percent)
+}
+
+/// Scale on a per-pod custom metric averaging to a target value
+pub fn pods_metric(name: &str, average_value: &str) -> MetricSpec {
+    MetricSpec {
+        type_: "Pods".into(),
+        pods: Some(PodsMetricSource {
+            metric: MetricIdentifier { name: name.into(), selector: None },
+            target: MetricTarget {
+                type_: "AverageValue".into(),
+                average_value: Some(Quantity(average_value.into())),
+                ..Default::default()
+            },
+        }),
+        ..Default::default()
+    }
+}
+
+impl HorizontalPodAutoscaler {
+    /// An autoscaler for a Deployment
+    pub fn for_deployment(name: &str, deployment: &str, min: i32, max: i32, metrics: Vec<MetricSpec>) -> Self {
+        Self::for_target(name, CrossVersionObjectReference {
+            api_version: Some("apps/v1".into()),
+            kind: "Deployment".into(),
+            name: deployment.into(),
+        }, min, max, metrics)
+    }
+
+    /// An autoscaler for any object implementing the scale subresource
+    pub fn for_target(name: &str, target: CrossVersionObjectReference, min: i32, max: i32, metrics: Vec<MetricSpec>) -> Self {
+        Object {
+            types: TypeMeta {
+                apiVersion: Some("autoscaling/v2beta2".into()),
+                kind: Some("HorizontalPodAutoscaler".into()),
+            },
+            metadata: ObjectMeta { name: name.into(), ..Default::default() },
+            spec: HorizontalPodAutoscalerSpec {
+                scale_target_ref: target,
+                min_replicas: Some(min),
+                max_replicas: max,
+                metrics: Some(metrics),
+            },
+            status: None,
+        }
+    }
+
+    /// Current and desired replica counts as seen by the autoscaler
+    pub fn replicas(&self) -> Option<(i32, i32)> {
+        self.status.as_ref().map(|s| (s.current_replicas, s.desired_replicas))
+    }
+
+    /// Whether the autoscaler can fetch metrics and update the scale target
+    pub fn is_able_to_scale(&self) -> bool {
+        self.status.as_ref().map(|s| s.conditions.is_condition_true("AbleToScale")).unwrap_or(false)
+    }
+
+    /// Whether the desired replica count is being clamped by `minReplicas` or `maxReplicas`
+    pub fn is_scaling_limited(&self) -> bool {
+        self.status.as_ref().map(|s| s.conditions.is_condition_true("ScalingLimited")).unwrap_or(false)
+    }
+}
+
+/// Which pods in a PodDisruptionBudget must stay up
+#[derive(Clone, Debug)]
+pub enum DisruptionBudget {
+    /// At least this many (or this percentage, e.g. `"50%"`) pods must be available
+    MinAvailable(IntOrString),
+    /// At most this many (or this percentage) pods may be unavailable
+    MaxUnavailable(IntOrString),
+}
+
+impl PodDisruptionBudget {
+    /// A budget for pods matching the given labels
+    pub fn for_selector(name: &str, labels: BTreeMap<String, String>, budget: DisruptionBudget) -> Self {
+        let (min_available, max_unavailable) = match budget {
+            DisruptionBudget::MinAvailable(n) => (Some(n), None),
+            DisruptionBudget::MaxUnavailable(n) => (None, Some(n)),
+        };
+        Object {
+            types: TypeMeta {
+                apiVersion: Some("policy/v1beta1".into()),
+                kind: Some("PodDisruptionBudget".into()),
+            },
+            metadata: ObjectMeta { name: name.into(), ..Default::default() },
+            spec: PodDisruptionBudgetSpec {
+                selector: Some(LabelSelector { match_labels: Some(labels), ..Default::default() }),
+                min_available,
+                max_unavailable,
+            },
+            status: None,
+        }
+    }
+
+    /// The budget in the spec
+    ///
+    /// Fails if both or neither of `minAvailable` and `maxUnavailable` are set.
+    pub fn budget(&self) -> Result<DisruptionBudget> {
+        match (&self.spec.min_available, &self.spec.max_unavailable) {
+            (Some(n), None) => Ok(DisruptionBudget::MinAvailable(n.clone())),
+            (None, Some(n)) => Ok(DisruptionBudget::MaxUnavailable(n.clone())),
+            _ => Err(Error::from(ErrorKind::RequestValidation(format!(
+                "pdb {} must set exactly one of minAvailable and maxUnavailable", self.metadata.name)))),
+        }
+    }
+
+    /// How many pods can currently be disrupted, if the controller has computed it
+    pub fn disruptions_allowed(&self) -> Option<i32> {
+        self.status.as_ref()
+            .filter(|s| s.observed_generation.unwrap_or(0) >= self.metadata.generation.unwrap_or(0.0) as i64)
+            .map(|s| s.disruptions_allowed)
+    }
+
+    /// Whether the budget currently allows evicting a pod
+    pub fn allows_disruption(&self) -> bool {
+        self.disruptions_allowed().map(|n| n > 0).unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn hpa_and_pdb_serialize() {
+        let hpa: HorizontalPodAutoscaler = Object::for_deployment("blog", "blog", 2, 10, vec![
+            cpu_utilization_metric(80),
+            pods_metric("requests_per_second", "100"),
+        ]);
+        let json = serde_json::to_value(&hpa).unwrap();
+        assert_eq!(json["spec"]["metrics"][0]["resource"]["target"]["averageUtilization"], 80);
+        assert_eq!(json["spec"]["metrics"][1]["pods"]["target"]["averageValue"], "100");
+        assert_eq!(json["spec"]["scaleTargetRef"]["kind"], "Deployment");
+
+        let mut labels = BTreeMap::new();
+        labels.insert("app".to_string(), "blog".to_string());
+        let mut pdb: PodDisruptionBudget = Object::for_selector("blog", labels,
+            DisruptionBudget::MinAvailable(IntOrString::String("50%".into())));
+        assert!(pdb.budget().is_ok());
+        assert!(!pdb.allows_disruption());
+        pdb.status = serde_json::from_value(json!({
+            "currentHealthy": 3, "desiredHealthy": 2, "disruptionsAllowed": 1, "expectedPods": 3,
+        })).unwrap();
+        assert_eq!(pdb.disruptions_allowed(), Some(1));
+        pdb.spec.max_unavailable = Some(IntOrString::Int(1));
+        assert!(pdb.budget().is_err());
+    }
+}
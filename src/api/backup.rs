@@ -0,0 +1,481 @@
+//! Exporting cluster state to a directory of manifests, and restoring it
+//!
+//! The layout follows Velero's, so objects can be found by resource and namespace:
+//!
+//! ```text
+//! <dir>/resources/<resource.group>/namespaces/<namespace>/<name>.yaml
+//! <dir>/resources/<resource.group>/cluster/<name>.yaml
+//! ```
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::api::{discover, sanitize_for_create, ApiResource, GroupVersionKind, ListParams, PatchParams, PostParams, RawApi};
+use crate::api::manifest::split_documents;
+use crate::api::wait::poll_until;
+use crate::client::APIClient;
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+/// Resources that are either recreated by the cluster itself or meaningless elsewhere
+const DEFAULT_EXCLUDES: &[&str] = &["events", "events.events.k8s.io", "nodes", "componentstatuses"];
+
+/// Whether `name` refers to `res`: its plural, `resource.group` or kind
+pub(crate) fn matches_resource(res: &ApiResource, name: &str) -> bool {
+    name == res.resource || name == res.qualified_name() || name.eq_ignore_ascii_case(&res.kind)
+}
+
+/// The path of an exported object below the export directory
+pub fn object_path(res: &ApiResource, obj: &Value) -> PathBuf {
+    let mut path = PathBuf::from("resources").join(res.qualified_name());
+    match obj["metadata"]["namespace"].as_str() {
+        Some(ns) if res.namespaced => path.push(Path::new("namespaces").join(ns)),
+        _ => path.push("cluster"),
+    }
+    path.join(format!("{}.yaml", obj["metadata"]["name"].as_str().unwrap_or("")))
+}
+
+/// Lists everything that is selected, sanitized for creating it again
+///
+/// By default all listable resources except events and nodes are exported, in all
+/// namespaces. Cluster scoped resources are only included without a namespace filter,
+/// unless asked for with `cluster_resources`.
+pub struct Exporter {
+    client: APIClient,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    namespaces: Vec<String>,
+    exclude_namespaces: Vec<String>,
+    cluster_resources: Option<bool>,
+    label_selector: Option<String>,
+    page_size: u32,
+}
+
+impl Exporter {
+    pub fn new(client: APIClient) -> Self {
+        Exporter {
+            client,
+            include: vec![],
+            exclude: DEFAULT_EXCLUDES.iter().map(|s| s.to_string()).collect(),
+            namespaces: vec![],
+            exclude_namespaces: vec![],
+            cluster_resources: None,
+            label_selector: None,
+            page_size: 500,
+        }
+    }
+
+    /// Only export these resources, named by plural, `resource.group`, or kind
+    pub fn include_resources(mut self, names: &[&str]) -> Self {
+        self.include = names.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Skip these resources in addition to events and nodes
+    pub fn exclude_resources(mut self, names: &[&str]) -> Self {
+        self.exclude.extend(names.iter().map(|s| s.to_string()));
+        self
+    }
+
+    /// Only export objects in these namespaces
+    pub fn namespaces(mut self, namespaces: &[&str]) -> Self {
+        self.namespaces = namespaces.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Skip objects in these namespaces
+    pub fn exclude_namespaces(mut self, namespaces: &[&str]) -> Self {
+        self.exclude_namespaces = namespaces.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    /// Whether to export cluster scoped resources like namespaces and CRDs
+    pub fn cluster_resources(mut self, include: bool) -> Self {
+        self.cluster_resources = Some(include);
+        self
+    }
+
+    /// Only export objects matching a label selector
+    pub fn labels(mut self, selector: &str) -> Self {
+        self.label_selector = Some(selector.to_string());
+        self
+    }
+
+    /// Objects fetched per list call
+    pub fn page_size(mut self, n: u32) -> Self {
+        self.page_size = n;
+        self
+    }
+
+    /// The discovered resources that will be exported
+    ///
+    /// Resources the legacy `extensions` group serves next to their current group, like
+    /// deployments, are only exported from the current one.
+    pub fn resources(&self) -> Result<Vec<ApiResource>> {
+        let cluster = self.cluster_resources.unwrap_or(self.namespaces.is_empty());
+        let resources: Vec<_> = discover(&self.client)?.into_iter()
+            .filter(|r| r.supports("list"))
+            .filter(|r| r.namespaced || cluster)
+            .filter(|r| self.include.is_empty() || self.include.iter().any(|n| matches_resource(r, n)))
+            .filter(|r| !self.exclude.iter().any(|n| matches_resource(r, n)))
+            .collect();
+        let moved = |r: &ApiResource| r.group == "extensions" && resources.iter()
+            .any(|o| o.group != r.group && o.resource == r.resource && o.kind == r.kind);
+        Ok(resources.iter().filter(|r| !moved(r)).cloned().collect())
+    }
+
+    fn selected(&self, obj: &Value) -> bool {
+        match obj["metadata"]["namespace"].as_str() {
+            Some(ns) => !self.exclude_namespaces.iter().any(|n| n == ns),
+            None => true,
+        }
+    }
+
+    /// Call `f` with each selected object and its resource
+    ///
+    /// With `namespaces`, namespaced resources are listed in each of them rather than
+    /// across the cluster.
+    pub fn export_with<F>(&self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&ApiResource, Value) -> Result<()>,
+    {
+        for res in self.resources()? {
+            if res.namespaced && !self.namespaces.is_empty() {
+                for ns in &self.namespaces {
+                    self.export_list(&res, res.raw_api().within(ns), &mut f)?;
+                }
+            } else {
+                self.export_list(&res, res.raw_api(), &mut f)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn export_list<F>(&self, res: &ApiResource, api: RawApi, f: &mut F) -> Result<()>
+    where
+        F: FnMut(&ApiResource, Value) -> Result<()>,
+    {
+        let mut lp = ListParams {
+            label_selector: self.label_selector.clone(),
+            limit: Some(self.page_size),
+            ..Default::default()
+        };
+        loop {
+            let mut list: Value = self.client.request(api.list(&lp)?)?;
+            let items = match list["items"].take() {
+                Value::Array(items) => items,
+                _ => vec![],
+            };
+            for mut obj in items.into_iter().filter(|o| self.selected(o)) {
+                // list items come without their type
+                res.gvk().apply_to(&mut obj);
+                sanitize_for_create(&mut obj);
+                f(res, obj)?;
+            }
+            match list["metadata"]["continue"].as_str() {
+                Some(token) if !token.is_empty() => lp.continue_token = Some(token.to_string()),
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// All selected objects
+    pub fn export(&self) -> Result<Vec<Value>> {
+        let mut objects = vec![];
+        self.export_with(|_, obj| {
+            objects.push(obj);
+            Ok(())
+        })?;
+        Ok(objects)
+    }
+
+    /// Write all selected objects below `dir` as yaml, returning how many were written
+    pub fn export_to_dir<P: AsRef<Path>>(&self, dir: P) -> Result<usize> {
+        let mut count = 0;
+        self.export_with(|res, obj| {
+            let path = dir.as_ref().join(object_path(res, &obj));
+            let io_err = || ErrorKind::Io(path.display().to_string());
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).with_context(|_| io_err())?;
+            }
+            let yaml = serde_yaml::to_string(&obj).context(ErrorKind::SerdeParse)?;
+            fs::write(&path, yaml).with_context(|_| io_err())?;
+            count += 1;
+            Ok(())
+        })?;
+        Ok(count)
+    }
+}
+
+/// Read all yaml and json manifests below `dir`, in path order
+///
+/// Symlinked directories are not followed, so links back up the tree cannot loop.
+pub fn read_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<Value>> {
+    fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        let entries = fs::read_dir(dir).with_context(|_| ErrorKind::Io(dir.display().to_string()))?;
+        for entry in entries {
+            let path = entry.with_context(|_| ErrorKind::Io(dir.display().to_string()))?.path();
+            let meta = fs::symlink_metadata(&path).with_context(|_| ErrorKind::Io(path.display().to_string()))?;
+            if meta.is_dir() {
+                walk(&path, files)?;
+            } else if meta.file_type().is_symlink() && path.is_dir() {
+                debug!("Not following symlinked directory {}", path.display());
+            } else if path.extension().is_some_and(|e| e == "yaml" || e == "yml" || e == "json") {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+    let mut files = vec![];
+    walk(dir.as_ref(), &mut files)?;
+    files.sort();
+    let mut objects = vec![];
+    for path in files {
+        let input = fs::read_to_string(&path).with_context(|_| ErrorKind::Io(path.display().to_string()))?;
+        objects.extend(split_documents(&input)?);
+    }
+    Ok(objects)
+}
+
+/// When an object of `kind` is restored, lower goes first
+///
+/// Namespaces and CRDs come before anything living in them, RBAC and configuration
+/// before the workloads using them, and webhooks and APIServices last, so they cannot
+/// reject or intercept the restore before their backends are running.
+pub fn restore_priority(kind: &str) -> u8 {
+    match kind {
+        "Namespace" => 0,
+        "CustomResourceDefinition" => 1,
+        "StorageClass" | "PriorityClass" | "PodSecurityPolicy" => 2,
+        "ServiceAccount" | "ClusterRole" | "Role" => 3,
+        "ClusterRoleBinding" | "RoleBinding" => 4,
+        "ConfigMap" | "Secret" | "PersistentVolume" | "PersistentVolumeClaim"
+        | "LimitRange" | "ResourceQuota" => 5,
+        "Service" => 6,
+        "ValidatingWebhookConfiguration" | "MutatingWebhookConfiguration" | "APIService" => 8,
+        _ => 7,
+    }
+}
+
+/// What to do with objects that already exist
+#[derive(Clone, Debug, PartialEq)]
+pub enum Existing {
+    /// Leave them as they are
+    Skip,
+    /// Merge patch them with the saved object
+    Update,
+}
+
+/// What happened to a restored object
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Created,
+    Updated,
+    /// It already existed
+    Skipped,
+    Failed(String),
+}
+
+/// The result of restoring one object
+#[derive(Clone, Debug)]
+pub struct RestoreResult {
+    pub api_version: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+/// Creates saved objects in dependency order
+///
+/// The restore carries on past objects that fail, and reports each in its result.
+/// Custom resources are created after their CRDs are `Established`.
+pub struct Importer {
+    client: APIClient,
+    existing: Existing,
+    dry_run: bool,
+    crd_timeout: Duration,
+}
+
+impl Importer {
+    pub fn new(client: APIClient) -> Self {
+        Importer { client, existing: Existing::Skip, dry_run: false, crd_timeout: Duration::from_secs(60) }
+    }
+
+    /// What to do with objects that already exist, skipping them by default
+    pub fn existing(mut self, existing: Existing) -> Self {
+        self.existing = existing;
+        self
+    }
+
+    /// Only validate the objects with the apiserver
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// How long to wait for restored CRDs to be established
+    pub fn crd_timeout(mut self, timeout: Duration) -> Self {
+        self.crd_timeout = timeout;
+        self
+    }
+
+    /// Restore everything in a directory written by `Exporter::export_to_dir`
+    pub fn restore_dir<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<RestoreResult>> {
+        self.restore(read_dir(dir)?)
+    }
+
+    /// Restore objects, returning a result for each in the order they were restored
+    ///
+    /// Only failing discovery aborts the restore.
+    pub fn restore(&self, mut objects: Vec<Value>) -> Result<Vec<RestoreResult>> {
+        objects.sort_by_key(|o| restore_priority(o["kind"].as_str().unwrap_or("")));
+        let mut resources = discover(&self.client)?;
+        let mut crds: Vec<(RawApi, String)> = vec![];
+        let mut results = vec![];
+        for obj in objects {
+            let kind = obj["kind"].as_str().unwrap_or("").to_string();
+            if restore_priority(&kind) > restore_priority("CustomResourceDefinition") && !crds.is_empty() {
+                for (api, name) in crds.drain(..) {
+                    if let Err(e) = self.wait_established(&api, &name) {
+                        warn!("Custom resources of {} may fail to restore: {}", name, e);
+                    }
+                }
+                resources = discover(&self.client)?;
+            }
+            let name = obj["metadata"]["name"].as_str().unwrap_or("").to_string();
+            let outcome = match self.restore_one(&resources, &obj) {
+                Ok((outcome, api)) => {
+                    if kind == "CustomResourceDefinition" && !self.dry_run && outcome != Outcome::Skipped {
+                        crds.push((api, name.clone()));
+                    }
+                    outcome
+                },
+                Err(e) => Outcome::Failed(e.to_string()),
+            };
+            results.push(RestoreResult {
+                api_version: obj["apiVersion"].as_str().unwrap_or("").into(),
+                kind,
+                namespace: obj["metadata"]["namespace"].as_str().map(String::from),
+                name,
+                outcome,
+            });
+        }
+        Ok(results)
+    }
+
+    fn restore_one(&self, resources: &[ApiResource], obj: &Value) -> Result<(Outcome, RawApi)> {
+        let gvk = GroupVersionKind::from_value(obj)?;
+        let res = resources.iter().find(|r| r.gvk() == gvk)
+            .ok_or_else(|| ErrorKind::RequestValidation(format!("{} is not served by the apiserver", gvk)))?;
+        let mut api = res.raw_api();
+        if res.namespaced {
+            api.namespace = Some(obj["metadata"]["namespace"].as_str().unwrap_or("default").into());
+        }
+        let data = serde_json::to_vec(obj).context(ErrorKind::SerdeParse)?;
+        let pp = PostParams { dry_run: self.dry_run, ..Default::default() };
+        let outcome = match self.client.request::<Value>(api.create(&pp, data.clone())?) {
+            Ok(_) => Outcome::Created,
+            Err(ref e) if e.status_code() == Some(409) => match self.existing {
+                Existing::Skip => Outcome::Skipped,
+                Existing::Update => {
+                    let name = obj["metadata"]["name"].as_str().unwrap_or("");
+                    let pp = PatchParams { dry_run: self.dry_run, ..Default::default() };
+                    self.client.request::<Value>(api.patch(name, &pp, data)?)?;
+                    Outcome::Updated
+                },
+            },
+            Err(e) => return Err(e),
+        };
+        Ok((outcome, api))
+    }
+
+    fn wait_established(&self, api: &RawApi, name: &str) -> Result<()> {
+        poll_until(&format!("CRD {} to be established", name), self.crd_timeout, || {
+            let crd: Value = self.client.request(api.get(name)?)?;
+            let established = crd["status"]["conditions"].as_array().is_some_and(|conds| {
+                conds.iter().any(|c| c["type"] == "Established" && c["status"] == "True")
+            });
+            Ok(if established { Some(()) } else { None })
+        })
+    }
+}
+
+#[test]
+fn restore_orders_and_reports() {
+    use serde_json::json;
+    use crate::client::fake::{status, Fake};
+    let fake = Fake::new()
+        .on("GET", "/api", 200, r#"{"versions":["v1"]}"#)
+        .on("GET", "/api/v1", 200, r#"{"resources":[
+            {"name":"configmaps","namespaced":true,"kind":"ConfigMap","verbs":["create"]},
+            {"name":"namespaces","namespaced":false,"kind":"Namespace","verbs":["create"]}]}"#)
+        .on("GET", "/apis", 200, r#"{"groups":[]}"#)
+        .on("POST", "/api/v1/namespaces/blog/configmaps", 409, status(409, "AlreadyExists"))
+        .on("POST", "*", 201, "{}");
+    let client = fake.client();
+    let results = Importer::new(client).restore(vec![
+        json!({ "apiVersion": "v1", "kind": "ConfigMap", "metadata": { "name": "cfg", "namespace": "blog" } }),
+        json!({ "apiVersion": "example.com/v1", "kind": "Foo", "metadata": { "name": "foo", "namespace": "blog" } }),
+        json!({ "apiVersion": "v1", "kind": "Namespace", "metadata": { "name": "blog" } }),
+    ]).unwrap();
+    let posted: Vec<_> = fake.lines().into_iter().filter(|r| r.starts_with("POST")).collect();
+    assert_eq!(posted, vec!["POST /api/v1/namespaces", "POST /api/v1/namespaces/blog/configmaps"]);
+    let outcomes: Vec<_> = results.iter().map(|r| (r.name.as_str(), &r.outcome)).collect();
+    assert_eq!(outcomes[0], ("blog", &Outcome::Created));
+    assert_eq!(outcomes[1], ("cfg", &Outcome::Skipped));
+    assert!(matches!(outcomes[2], ("foo", Outcome::Failed(_))));
+}
+
+#[test]
+fn export_writes_selected_objects() {
+    use crate::client::fake::Fake;
+    let fake = Fake::new()
+        .on("GET", "/api", 200, r#"{"versions":["v1"]}"#)
+        .on("GET", "/api/v1", 200, r#"{"resources":[
+            {"name":"configmaps","namespaced":true,"kind":"ConfigMap","verbs":["list"]},
+            {"name":"namespaces","namespaced":false,"kind":"Namespace","verbs":["list"]},
+            {"name":"events","namespaced":true,"kind":"Event","verbs":["list"]},
+            {"name":"pods/log","namespaced":true,"kind":"Pod","verbs":["get"]}]}"#)
+        .on("GET", "/apis", 200, r#"{"groups":[
+            {"name":"extensions","preferredVersion":{"groupVersion":"extensions/v1beta1"}},
+            {"name":"apps","preferredVersion":{"groupVersion":"apps/v1"}}]}"#)
+        .on("GET", "/apis/extensions/v1beta1", 200, r#"{"resources":[
+            {"name":"deployments","namespaced":true,"kind":"Deployment","verbs":["list"]}]}"#)
+        .on("GET", "/apis/apps/v1", 200, r#"{"resources":[
+            {"name":"deployments","namespaced":true,"kind":"Deployment","verbs":["list"]}]}"#)
+        .on("GET", "/api/v1/namespaces/blog/configmaps?continue=2", 200,
+            r#"{"metadata":{},"items":[{"metadata":{"name":"b","namespace":"blog"}}]}"#)
+        .on("GET", "/api/v1/namespaces/blog/configmaps", 200, r#"{"metadata":{"continue":"2"},"items":[
+            {"metadata":{"name":"a","namespace":"blog","uid":"1"},"data":{"k":"v"}}]}"#)
+        .on("GET", "/apis/apps/v1/namespaces/blog/deployments", 200, r#"{"metadata":{},"items":[
+            {"metadata":{"name":"web","namespace":"blog"}}]}"#);
+    let client = fake.client();
+    let dir = tempfile::tempdir().unwrap();
+    let written = Exporter::new(client).namespaces(&["blog"]).export_to_dir(dir.path()).unwrap();
+    assert_eq!(written, 3);
+    assert!(dir.path().join("resources/configmaps/namespaces/blog/b.yaml").exists());
+    assert!(dir.path().join("resources/deployments.apps/namespaces/blog/web.yaml").exists());
+    assert!(!fake.lines().iter().any(|r| r.contains("/extensions/v1beta1/")), "deployments are exported once");
+    let yaml = fs::read_to_string(dir.path().join("resources/configmaps/namespaces/blog/a.yaml")).unwrap();
+    let obj: Value = serde_yaml::from_str(&yaml).unwrap();
+    assert_eq!(obj["kind"], "ConfigMap");
+    assert_eq!(obj["apiVersion"], "v1");
+    assert!(obj["metadata"].get("uid").is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_directories_are_not_followed() {
+    let dir = tempfile::tempdir().unwrap();
+    let nested = dir.path().join("nested");
+    fs::create_dir(&nested).unwrap();
+    fs::write(nested.join("cm.yaml"), "kind: ConfigMap\napiVersion: v1\nmetadata:\n  name: a\n").unwrap();
+    std::os::unix::fs::symlink(dir.path(), nested.join("loop")).unwrap();
+    std::os::unix::fs::symlink(nested.join("cm.yaml"), dir.path().join("linked.yaml")).unwrap();
+    let objects = read_dir(dir.path()).unwrap();
+    // the linked file is read, the loop is not
+    assert_eq!(objects.len(), 2);
+}
@@ -0,0 +1,47 @@
+//! Pod to node bindings for custom schedulers
+use serde_json::json;
+
+use crate::api::{Api, Object, PostParams};
+use crate::client::Status;
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+
+/// The `v1.Binding` assigning a pod to a node
+fn binding(pod: &str, namespace: Option<&str>, node: &str) -> serde_json::Value {
+    json!({
+        "apiVersion": "v1",
+        "kind": "Binding",
+        "metadata": {
+            "name": pod,
+            "namespace": namespace,
+        },
+        "target": {
+            "apiVersion": "v1",
+            "kind": "Node",
+            "name": node,
+        }
+    })
+}
+
+impl Api<Object<PodSpec, PodStatus>> {
+    /// Schedule a pending pod onto a node, like the default scheduler does
+    ///
+    /// Pods with `spec.schedulerName` set to your scheduler are left pending
+    /// by the default scheduler, waiting for this call.
+    pub fn bind(&self, name: &str, node: &str, pp: &PostParams) -> Result<Status> {
+        let body = binding(name, self.api.namespace.as_deref(), node);
+        let data = serde_json::to_vec(&body).context(ErrorKind::SerdeParse)?;
+        let req = self.api.create_binding(name, pp, data)?;
+        self.client.request::<Status>(req)
+    }
+}
+
+#[test]
+fn binding_targets_node() {
+    let b = binding("blog", Some("ns"), "node-1");
+    assert_eq!(b["kind"], "Binding");
+    assert_eq!(b["metadata"]["namespace"], "ns");
+    assert_eq!(b["target"]["name"], "node-1");
+}
@@ -0,0 +1,174 @@
+//! What nodes can fit and what their pods request, as the scheduler counts it
+use k8s_openapi::api::core::v1::{Container, NodeSpec, NodeStatus, PodSpec, PodStatus};
+
+use crate::api::quota::parse;
+use crate::api::{Object, Quantity, QuantityFormat, Reflector, Resources, NODE_INDEX};
+use crate::Result;
+
+type Pod = Object<PodSpec, PodStatus>;
+type Node = Object<NodeSpec, NodeStatus>;
+
+/// Add `b` to `a` for each resource
+fn add(a: &mut Resources, b: &Resources) {
+    for (name, q) in b {
+        *a.entry(name.clone()).or_insert_with(|| Quantity::from_millis(0)) += *q;
+    }
+}
+
+/// Raise `a` to `b` for each resource
+fn max(a: &mut Resources, b: &Resources) {
+    for (name, q) in b {
+        let current = a.entry(name.clone()).or_insert(*q);
+        if *q > *current {
+            *current = *q;
+        }
+    }
+}
+
+/// The requests or limits of a pod: its containers together, or its largest init container
+fn effective(pod: &Pod, of: impl Fn(&Container) -> Resources) -> Resources {
+    let mut total = Resources::new();
+    for c in &pod.spec.containers {
+        add(&mut total, &of(c));
+    }
+    for c in pod.spec.init_containers.iter().flatten() {
+        max(&mut total, &of(c));
+    }
+    total
+}
+
+impl Pod {
+    /// The resources the scheduler reserves for the pod
+    ///
+    /// Init containers run one at a time before the others, so each only needs
+    /// to fit on its own.
+    pub fn resource_requests(&self) -> Resources {
+        effective(self, |c| parse(c.resources.as_ref().and_then(|r| r.requests.as_ref())))
+    }
+
+    /// The limits of the pod, counted like requests
+    ///
+    /// Containers without a limit on a resource add nothing, though they may use all of it.
+    pub fn resource_limits(&self) -> Resources {
+        effective(self, |c| parse(c.resources.as_ref().and_then(|r| r.limits.as_ref())))
+    }
+
+    /// Whether the pod is done, so no longer holds on to resources
+    fn is_terminated(&self) -> bool {
+        matches!(self.phase(), Some("Succeeded") | Some("Failed"))
+    }
+}
+
+impl Node {
+    /// What pods can use in total, the capacity less what is reserved for the system
+    pub fn allocatable(&self) -> Resources {
+        parse(self.status.as_ref().and_then(|s| s.allocatable.as_ref()))
+    }
+
+    /// The resources of the machine
+    pub fn capacity(&self) -> Resources {
+        parse(self.status.as_ref().and_then(|s| s.capacity.as_ref()))
+    }
+}
+
+/// What is requested of a node, against what it can fit
+#[derive(Clone, Debug)]
+pub struct NodeUsage {
+    pub node: String,
+    pub allocatable: Resources,
+    /// Summed requests of the pods on the node, with `pods` counting them
+    pub requested: Resources,
+    /// Summed limits of the pods on the node, beyond allocatable when overcommitted
+    pub limits: Resources,
+}
+
+impl NodeUsage {
+    /// Sum the pods on `node`, leaving out terminated ones
+    pub fn new<'a>(node: &Node, pods: impl IntoIterator<Item = &'a Pod>) -> Self {
+        let (mut requested, mut limits) = (Resources::new(), Resources::new());
+        let mut count = 0;
+        for pod in pods.into_iter().filter(|p| !p.is_terminated()) {
+            add(&mut requested, &pod.resource_requests());
+            add(&mut limits, &pod.resource_limits());
+            count += 1;
+        }
+        requested.insert("pods".into(), Quantity::from_units(count, QuantityFormat::DecimalSI));
+        NodeUsage { node: node.metadata.name.clone(), allocatable: node.allocatable(), requested, limits }
+    }
+
+    /// What is left to request, negative if pods bypassing the scheduler took more
+    pub fn free(&self) -> Resources {
+        self.allocatable.iter()
+            .map(|(name, a)| {
+                let requested = self.requested.get(name).copied().unwrap_or_else(|| Quantity::from_millis(0));
+                (name.clone(), *a - requested)
+            })
+            .collect()
+    }
+
+    /// The share of a resource that is requested, e.g. `0.75` of `cpu`
+    pub fn requested_fraction(&self, resource: &str) -> Option<f64> {
+        let allocatable = self.allocatable.get(resource).filter(|a| !a.is_zero())?;
+        let requested = self.requested.get(resource).map(Quantity::as_f64).unwrap_or(0.0);
+        Some(requested / allocatable.as_f64())
+    }
+
+    /// Whether a pod with these requests would fit, as far as resources go
+    ///
+    /// Resources the node does not have, like an extended resource, do not fit.
+    pub fn fits(&self, requests: &Resources) -> bool {
+        let free = self.free();
+        requests.iter().all(|(name, q)| q.is_zero() || free.get(name).is_some_and(|f| f >= q))
+    }
+}
+
+impl Reflector<Pod> {
+    /// The usage of each node by the cached pods, which must be indexed by `index_by_node`
+    pub fn node_usage(&self, nodes: &[Node]) -> Result<Vec<NodeUsage>> {
+        nodes.iter()
+            .map(|node| Ok(NodeUsage::new(node, &self.by_index(NODE_INDEX, &node.metadata.name)?)))
+            .collect()
+    }
+}
+
+#[test]
+fn node_usage_counts_requests_like_the_scheduler() {
+    use serde_json::json;
+    let pod = |name: &str, phase: &str, containers: serde_json::Value, init: serde_json::Value| -> Pod {
+        serde_json::from_value(json!({
+            "metadata": { "name": name, "namespace": "blog" },
+            "spec": { "nodeName": "node-1", "containers": containers, "initContainers": init },
+            "status": { "phase": phase },
+        })).unwrap()
+    };
+    let container = |cpu: &str, memory: &str| json!({
+        "name": "c", "resources": { "requests": { "cpu": cpu, "memory": memory }, "limits": { "memory": memory } },
+    });
+    let node: Node = serde_json::from_value(json!({
+        "metadata": { "name": "node-1" },
+        "spec": {},
+        "status": {
+            "capacity": { "cpu": "4", "memory": "16Gi", "pods": "110" },
+            "allocatable": { "cpu": "3800m", "memory": "15Gi", "pods": "110" },
+        },
+    })).unwrap();
+    let pods = vec![
+        pod("web", "Running", json!([container("500m", "1Gi"), container("250m", "512Mi")]), json!([container("1", "256Mi")])),
+        pod("db", "Running", json!([container("2", "8Gi")]), json!([])),
+        pod("migrate", "Succeeded", json!([container("2", "4Gi")]), json!([])),
+    ];
+    let q = |s: &str| s.parse::<Quantity>().unwrap();
+    let web = pods[0].resource_requests();
+    assert_eq!((web["cpu"], web["memory"]), (q("1"), q("1536Mi")), "the init container needs more cpu");
+
+    let usage = NodeUsage::new(&node, &pods);
+    assert_eq!(usage.requested["cpu"], q("3"));
+    assert_eq!(usage.requested["pods"], q("2"));
+    assert_eq!(usage.limits["memory"], q("9728Mi"));
+    assert_eq!(usage.free()["cpu"], q("800m"));
+    assert_eq!(usage.requested_fraction("pods"), Some(2.0 / 110.0));
+    let requests = |cpu: &str| vec![("cpu".to_string(), q(cpu))].into_iter().collect::<Resources>();
+    assert!(usage.fits(&requests("800m")));
+    assert!(!usage.fits(&requests("801m")));
+    assert!(!usage.fits(&vec![("nvidia.com/gpu".to_string(), q("1"))].into_iter().collect()));
+}
@@ -0,0 +1,137 @@
+//! Collapsing bursts of watch events for the same object
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::api::resource::{KubeObject, WatchEvent};
+
+type Key = (Option<String>, String);
+
+fn key<K: KubeObject>(obj: &K) -> Key {
+    (obj.meta().namespace.clone(), obj.meta().name.clone())
+}
+
+/// Holds watch events back for a window, merging events for the same object
+///
+/// The first event for an object starts its window; later events within it replace it
+/// with the latest state, so a handler sees one event per object per window:
+/// - `Added` then `Modified` is an `Added` of the latest object
+/// - `Modified` then `Deleted` is a `Deleted`
+/// - `Added` then `Deleted` is dropped, as the object never outlived the window
+/// - `Deleted` then `Added` (a recreation) is a `Modified` of the new object
+///
+/// `Error` events are not held back.
+pub struct Coalescer<K> where
+    K: Clone + KubeObject
+{
+    window: Duration,
+    order: VecDeque<(Instant, Key)>,
+    pending: HashMap<Key, WatchEvent<K>>,
+    errors: VecDeque<WatchEvent<K>>,
+}
+
+impl<K> Coalescer<K> where
+    K: Clone + KubeObject
+{
+    /// Merge events arriving within `window` of the first event for an object
+    pub fn new(window: Duration) -> Self {
+        Coalescer {
+            window,
+            order: VecDeque::new(),
+            pending: HashMap::new(),
+            errors: VecDeque::new(),
+        }
+    }
+
+    /// Add an event, merging it with a pending one for the same object
+    pub fn push(&mut self, event: WatchEvent<K>) {
+        let k = match &event {
+            WatchEvent::Added(o) | WatchEvent::Modified(o) | WatchEvent::Deleted(o) => key(o),
+            WatchEvent::Error(_) => {
+                self.errors.push_back(event);
+                return;
+            },
+        };
+        let merged = match (self.pending.remove(&k), event) {
+            (None, e) => {
+                self.order.push_back((Instant::now(), k.clone()));
+                Some(e)
+            },
+            (Some(WatchEvent::Added(_)), WatchEvent::Modified(o)) => Some(WatchEvent::Added(o)),
+            (Some(WatchEvent::Added(_)), WatchEvent::Deleted(_)) => {
+                self.order.retain(|(_, o)| o != &k);
+                None
+            },
+            (Some(WatchEvent::Deleted(_)), WatchEvent::Added(o)) => Some(WatchEvent::Modified(o)),
+            (Some(_), e) => Some(e),
+        };
+        if let Some(e) = merged {
+            self.pending.insert(k, e);
+        }
+    }
+
+    /// The next event whose window has passed
+    pub fn pop(&mut self) -> Option<WatchEvent<K>> {
+        if let Some(e) = self.errors.pop_front() {
+            return Some(e);
+        }
+        while let Some((since, _)) = self.order.front() {
+            if since.elapsed() < self.window {
+                return None;
+            }
+            let (_, k) = self.order.pop_front().unwrap();
+            if let Some(e) = self.pending.remove(&k) {
+                return Some(e);
+            }
+        }
+        None
+    }
+
+    /// Number of events held back
+    pub fn len(&self) -> usize {
+        self.pending.len() + self.errors.len()
+    }
+
+    /// Whether no events are held back
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all pending events
+    pub fn clear(&mut self) {
+        self.order.clear();
+        self.pending.clear();
+        self.errors.clear();
+    }
+}
+
+#[test]
+fn bursts_are_merged_per_object() {
+    use crate::api::{Object, ObjectMeta, Void};
+    let obj = |name: &str, rv: &str| Object::<Void, Void> {
+        types: Default::default(),
+        metadata: ObjectMeta { name: name.into(), resourceVersion: Some(rv.into()), ..Default::default() },
+        spec: Void {},
+        status: None,
+    };
+    let mut c = Coalescer::new(Duration::from_millis(0));
+    c.push(WatchEvent::Added(obj("a", "1")));
+    c.push(WatchEvent::Modified(obj("b", "2")));
+    c.push(WatchEvent::Modified(obj("a", "3")));
+    c.push(WatchEvent::Modified(obj("b", "4")));
+    c.push(WatchEvent::Added(obj("c", "5")));
+    c.push(WatchEvent::Deleted(obj("c", "6")));
+    assert_eq!(c.len(), 2);
+    match c.pop() {
+        Some(WatchEvent::Added(o)) => assert_eq!(o.metadata.resourceVersion.as_deref(), Some("3")),
+        e => panic!("unexpected {:?}", e),
+    }
+    match c.pop() {
+        Some(WatchEvent::Modified(o)) => assert_eq!(o.metadata.resourceVersion.as_deref(), Some("4")),
+        e => panic!("unexpected {:?}", e),
+    }
+    assert!(c.pop().is_none());
+
+    let mut c = Coalescer::new(Duration::from_secs(60));
+    c.push(WatchEvent::Added(obj("a", "1")));
+    assert!(c.pop().is_none());
+}
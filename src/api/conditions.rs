@@ -0,0 +1,215 @@
+//! Status condition handling following the Kubernetes API conventions
+//!
+//! `Condition` mirrors the standardised `metav1.Condition` (KEP-1623) for use in
+//! custom resource statuses. The read side works on any native condition type too.
+#![allow(non_snake_case)]
+
+use chrono::{DateTime, Utc, SubsecRound};
+
+/// A standard status condition
+///
+/// https://github.com/kubernetes/enhancements/tree/master/keps/sig-api-machinery/1623-standardize-conditions
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Condition {
+    /// Type of condition in CamelCase, e.g. `Ready`
+    #[serde(rename = "type")]
+    pub type_: String,
+    /// One of `True`, `False` or `Unknown`
+    pub status: String,
+    /// The `.metadata.generation` the condition was set based upon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub observedGeneration: Option<i64>,
+    /// Last time the condition transitioned from one status to another
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lastTransitionTime: Option<DateTime<Utc>>,
+    /// Programmatic identifier for the last transition in CamelCase
+    #[serde(default)]
+    pub reason: String,
+    /// Human readable details about the last transition
+    #[serde(default)]
+    pub message: String,
+}
+
+impl Condition {
+    /// A condition of a given type with a boolean status
+    pub fn new(type_: &str, status: bool, reason: &str, message: &str) -> Self {
+        Condition {
+            type_: type_.into(),
+            status: if status { "True" } else { "False" }.into(),
+            reason: reason.into(),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// A condition of a given type in the `Unknown` status
+    pub fn unknown(type_: &str, reason: &str, message: &str) -> Self {
+        Condition {
+            type_: type_.into(),
+            status: "Unknown".into(),
+            reason: reason.into(),
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Record the generation this condition was computed from
+    pub fn observed(mut self, generation: i64) -> Self {
+        self.observedGeneration = Some(generation);
+        self
+    }
+}
+
+/// Read access to the fields every condition type shares
+pub trait ConditionLike {
+    fn type_(&self) -> &str;
+    fn status(&self) -> &str;
+    fn reason(&self) -> Option<&str>;
+    fn message(&self) -> Option<&str>;
+}
+
+impl ConditionLike for Condition {
+    fn type_(&self) -> &str { &self.type_ }
+    fn status(&self) -> &str { &self.status }
+    fn reason(&self) -> Option<&str> { Some(&self.reason).filter(|r| !r.is_empty()).map(String::as_str) }
+    fn message(&self) -> Option<&str> { Some(&self.message).filter(|m| !m.is_empty()).map(String::as_str) }
+}
+
+/// Lookups on a list of conditions
+pub trait Conditions {
+    type Item: ConditionLike;
+
+    /// Find the condition of a given type
+    fn find_condition(&self, type_: &str) -> Option<&Self::Item>;
+
+    /// Whether the condition of a given type exists with status `True`
+    fn is_condition_true(&self, type_: &str) -> bool {
+        self.find_condition(type_).map(|c| c.status() == "True").unwrap_or(false)
+    }
+
+    /// Whether the condition of a given type exists with status `False`
+    fn is_condition_false(&self, type_: &str) -> bool {
+        self.find_condition(type_).map(|c| c.status() == "False").unwrap_or(false)
+    }
+}
+
+impl<C: ConditionLike> Conditions for [C] {
+    type Item = C;
+    fn find_condition(&self, type_: &str) -> Option<&C> {
+        self.iter().find(|c| c.type_() == type_)
+    }
+}
+
+impl<C: ConditionLike> Conditions for Vec<C> {
+    type Item = C;
+    fn find_condition(&self, type_: &str) -> Option<&C> {
+        self.as_slice().find_condition(type_)
+    }
+}
+
+/// Mutation of a list of standard conditions
+pub trait ConditionsMut {
+    /// Insert or update a condition by type
+    ///
+    /// `lastTransitionTime` is only bumped when the status actually changes,
+    /// unless the new condition carries an explicit time.
+    /// Returns whether anything changed, so callers can skip no-op status writes.
+    fn set_condition(&mut self, cond: Condition) -> bool;
+
+    /// Remove a condition by type, returning whether it was present
+    fn remove_condition(&mut self, type_: &str) -> bool;
+}
+
+impl ConditionsMut for Vec<Condition> {
+    fn set_condition(&mut self, mut cond: Condition) -> bool {
+        match self.iter_mut().find(|c| c.type_ == cond.type_) {
+            Some(existing) => {
+                if existing.status != cond.status {
+                    cond.lastTransitionTime = cond.lastTransitionTime.or_else(|| Some(Utc::now().trunc_subsecs(0)));
+                } else {
+                    cond.lastTransitionTime = cond.lastTransitionTime.or(existing.lastTransitionTime);
+                }
+                let changed = *existing != cond;
+                *existing = cond;
+                changed
+            },
+            None => {
+                cond.lastTransitionTime = cond.lastTransitionTime.or_else(|| Some(Utc::now().trunc_subsecs(0)));
+                self.push(cond);
+                true
+            }
+        }
+    }
+
+    fn remove_condition(&mut self, type_: &str) -> bool {
+        let before = self.len();
+        self.retain(|c| c.type_ != type_);
+        self.len() != before
+    }
+}
+
+#[cfg(feature = "openapi")]
+macro_rules! native_condition {
+    ($($t:ty),*) => {
+        $(
+            impl ConditionLike for $t {
+                fn type_(&self) -> &str { &self.type_ }
+                fn status(&self) -> &str { &self.status }
+                fn reason(&self) -> Option<&str> { self.reason.as_deref() }
+                fn message(&self) -> Option<&str> { self.message.as_deref() }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "openapi")]
+native_condition!(
+    k8s_openapi::api::apps::v1::DeploymentCondition,
+    k8s_openapi::api::apps::v1::StatefulSetCondition,
+    k8s_openapi::api::apps::v1::DaemonSetCondition,
+    k8s_openapi::api::apps::v1::ReplicaSetCondition,
+    k8s_openapi::api::batch::v1::JobCondition,
+    k8s_openapi::api::core::v1::PodCondition,
+    k8s_openapi::api::core::v1::NodeCondition,
+    k8s_openapi::api::core::v1::PersistentVolumeClaimCondition,
+    k8s_openapi::api::core::v1::ReplicationControllerCondition,
+    k8s_openapi::api::autoscaling::v2beta2::HorizontalPodAutoscalerCondition,
+    k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1beta1::CustomResourceDefinitionCondition,
+    k8s_openapi::kube_aggregator::pkg::apis::apiregistration::v1::APIServiceCondition
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transition_time_only_moves_on_status_change() {
+        let mut conds = vec![];
+        assert!(conds.set_condition(Condition::new("Ready", false, "Starting", "")));
+        let first = conds.find_condition("Ready").unwrap().lastTransitionTime;
+        assert!(first.is_some());
+
+        // same status, new message: changed, but transition time kept
+        assert!(conds.set_condition(Condition::new("Ready", false, "Starting", "pulling image")));
+        assert_eq!(conds.find_condition("Ready").unwrap().lastTransitionTime, first);
+        // identical condition: no-op
+        assert!(!conds.set_condition(Condition::new("Ready", false, "Starting", "pulling image")));
+
+        let t = Utc::now() + chrono::Duration::seconds(5);
+        let mut ready = Condition::new("Ready", true, "Running", "").observed(2);
+        ready.lastTransitionTime = Some(t);
+        assert!(conds.set_condition(ready));
+        assert!(conds.is_condition_true("Ready"));
+        assert_eq!(conds.find_condition("Ready").unwrap().lastTransitionTime, Some(t));
+        assert_eq!(conds.len(), 1);
+
+        // an explicit time is kept even when the status stays the same
+        let mut again = Condition::new("Ready", true, "Running", "").observed(2);
+        again.lastTransitionTime = Some(first.unwrap());
+        assert!(conds.set_condition(again));
+        assert_eq!(conds.find_condition("Ready").unwrap().lastTransitionTime, first);
+
+        assert!(conds.remove_condition("Ready"));
+        assert!(conds.find_condition("Ready").is_none());
+    }
+}
@@ -0,0 +1,232 @@
+//! Conversion webhook types for multi-version custom resources
+//!
+//! The apiserver POSTs a `ConversionReview` to a CRD's conversion webhook
+//! whenever it needs an object in a version other than the stored one.
+//! This module exposes the review types along with a `Converter` trait
+//! that can be handed to `ConversionReview::respond` from any http server.
+#![allow(non_snake_case)]
+
+use std::collections::BTreeMap;
+use serde_json::Value;
+
+use crate::api::metadata::Status;
+use crate::{Result, Error, ErrorKind};
+
+/// ConversionReview describes a conversion request/response
+///
+/// https://kubernetes.io/docs/tasks/access-kubernetes-api/custom-resources/custom-resource-definition-versioning/#webhook-request-and-response
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct ConversionReview {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apiVersion: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+
+    /// The request sent by the apiserver
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request: Option<ConversionRequest>,
+
+    /// The response returned by the webhook
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<ConversionResponse>,
+}
+
+/// ConversionRequest describes the conversion request parameters
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct ConversionRequest {
+    /// Identifier for the individual request/response, copied to the response
+    pub uid: String,
+    /// Version to convert given objects to, e.g. "clux.dev/v2"
+    pub desiredAPIVersion: String,
+    /// The objects to be converted
+    ///
+    /// These may be in mixed versions.
+    #[serde(default)]
+    pub objects: Vec<Value>,
+}
+
+/// ConversionResponse describes a conversion response
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct ConversionResponse {
+    /// Must match the uid of the request
+    pub uid: String,
+    /// The converted objects in the same order as the request
+    ///
+    /// Must be empty when the conversion failed.
+    #[serde(default)]
+    pub convertedObjects: Vec<Value>,
+    /// Success or failure of the conversion
+    pub result: Status,
+}
+
+impl ConversionResponse {
+    /// A successful response with the converted objects
+    pub fn success(uid: &str, objects: Vec<Value>) -> Self {
+        ConversionResponse {
+            uid: uid.into(),
+            convertedObjects: objects,
+            result: Status {
+                status: Some("Success".into()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// A failed response with a message explaining why
+    pub fn failure(uid: &str, message: &str) -> Self {
+        ConversionResponse {
+            uid: uid.into(),
+            convertedObjects: vec![],
+            result: Status {
+                status: Some("Failure".into()),
+                message: Some(message.into()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Whether the conversion was successful
+    pub fn is_success(&self) -> bool {
+        self.result.status.as_deref() == Some("Success")
+    }
+}
+
+/// Conversion logic for a custom resource
+///
+/// Implement this for your own types, or use a `ConversionDispatcher`.
+pub trait Converter {
+    /// Convert a single object to the `desired` apiVersion
+    fn convert(&self, object: Value, desired: &str) -> Result<Value>;
+}
+
+type ConversionFn = Box<dyn Fn(Value) -> Result<Value> + Send + Sync>;
+
+/// A `Converter` that dispatches on (from, to) apiVersion pairs
+///
+/// Objects already in the desired version are passed through untouched,
+/// and the `apiVersion` of converted objects is always set to the desired one.
+///
+/// ```
+/// use kube::api::ConversionDispatcher;
+/// let dispatcher = ConversionDispatcher::default()
+///     .route("clux.dev/v1", "clux.dev/v2", |mut o| {
+///         o["spec"]["replicas"] = o["spec"]["count"].take();
+///         Ok(o)
+///     });
+/// ```
+#[derive(Default)]
+pub struct ConversionDispatcher {
+    routes: BTreeMap<(String, String), ConversionFn>,
+}
+
+impl ConversionDispatcher {
+    /// Register a conversion function from one apiVersion to another
+    pub fn route<F>(mut self, from: &str, to: &str, f: F) -> Self
+    where
+        F: Fn(Value) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.routes.insert((from.into(), to.into()), Box::new(f));
+        self
+    }
+}
+
+impl Converter for ConversionDispatcher {
+    fn convert(&self, object: Value, desired: &str) -> Result<Value> {
+        let from = object["apiVersion"].as_str().map(String::from).ok_or_else(|| {
+            Error::from(ErrorKind::Conversion("object is missing apiVersion".into()))
+        })?;
+        if from == desired {
+            return Ok(object);
+        }
+        let f = self.routes.get(&(from.clone(), desired.to_string())).ok_or_else(|| {
+            Error::from(ErrorKind::Conversion(format!("no conversion from {} to {}", from, desired)))
+        })?;
+        let mut converted = f(object)?;
+        converted["apiVersion"] = Value::String(desired.into());
+        Ok(converted)
+    }
+}
+
+impl ConversionReview {
+    /// Construct a review as sent by the apiserver
+    ///
+    /// Mostly useful for testing a `Converter` without a cluster.
+    pub fn request(uid: &str, desired: &str, objects: Vec<Value>) -> Self {
+        ConversionReview {
+            apiVersion: Some("apiextensions.k8s.io/v1beta1".into()),
+            kind: Some("ConversionReview".into()),
+            request: Some(ConversionRequest {
+                uid: uid.into(),
+                desiredAPIVersion: desired.into(),
+                objects,
+            }),
+            response: None,
+        }
+    }
+
+    /// Run a `Converter` over every object in the request and produce the response review
+    ///
+    /// A single failed conversion fails the whole review, as the apiserver requires.
+    pub fn respond(self, converter: &dyn Converter) -> Self {
+        let response = match self.request {
+            Some(req) => {
+                let desired = req.desiredAPIVersion;
+                let converted = req.objects.into_iter()
+                    .map(|o| converter.convert(o, &desired))
+                    .collect::<Result<Vec<_>>>();
+                match converted {
+                    Ok(objects) => ConversionResponse::success(&req.uid, objects),
+                    Err(e) => {
+                        warn!("Conversion to {} failed: {}", desired, e);
+                        ConversionResponse::failure(&req.uid, &e.to_string())
+                    }
+                }
+            },
+            None => ConversionResponse::failure("", "ConversionReview is missing a request"),
+        };
+        ConversionReview {
+            apiVersion: self.apiVersion,
+            kind: self.kind,
+            request: None,
+            response: Some(response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn dispatcher() -> ConversionDispatcher {
+        ConversionDispatcher::default()
+            .route("clux.dev/v1", "clux.dev/v2", |mut o| {
+                o["spec"]["replicas"] = o["spec"]["count"].take();
+                Ok(o)
+            })
+    }
+
+    #[test]
+    fn converts_mixed_versions() {
+        let review = ConversionReview::request("abc", "clux.dev/v2", vec![
+            json!({"apiVersion": "clux.dev/v1", "kind": "Foo", "spec": {"count": 2}}),
+            json!({"apiVersion": "clux.dev/v2", "kind": "Foo", "spec": {"replicas": 3}}),
+        ]);
+        let res = review.respond(&dispatcher()).response.unwrap();
+        assert!(res.is_success());
+        assert_eq!(res.uid, "abc");
+        assert_eq!(res.convertedObjects[0]["apiVersion"], "clux.dev/v2");
+        assert_eq!(res.convertedObjects[0]["spec"]["replicas"], 2);
+        assert_eq!(res.convertedObjects[1]["spec"]["replicas"], 3);
+    }
+
+    #[test]
+    fn unknown_route_fails_review() {
+        let review = ConversionReview::request("abc", "clux.dev/v1", vec![
+            json!({"apiVersion": "clux.dev/v2", "kind": "Foo", "spec": {}}),
+        ]);
+        let res = review.respond(&dispatcher()).response.unwrap();
+        assert!(!res.is_success());
+        assert!(res.convertedObjects.is_empty());
+    }
+}
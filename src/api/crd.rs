@@ -0,0 +1,267 @@
+//! Lifecycle helpers for CustomResourceDefinitions
+//!
+//! Covers the usual operator bootstrap: apply the CRD, wait for it to be served,
+//! and tear it down along with its instances again.
+use std::time::{Duration, Instant};
+use serde_json::Value;
+
+use crate::api::{Api, RawApi, Object, Void, ObjectList, PostParams, DeleteParams, ListParams, Conditions, ConditionLike, WatchEvent};
+use crate::api::wait::poll_until;
+use crate::{Result, Error, ErrorKind};
+
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1beta1::{
+    CustomResourceDefinitionSpec as CrdSpec,
+    CustomResourceDefinitionStatus as CrdStatus,
+};
+
+type Crd = Object<CrdSpec, CrdStatus>;
+
+/// Whether a CRD has the `Established` condition set to `True`
+pub fn is_crd_established(crd: &Crd) -> bool {
+    crd.status.as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .map(|conds| conds.is_condition_true("Established"))
+        .unwrap_or(false)
+}
+
+/// The `RawApi` for instances of a CRD
+///
+/// Uses the storage version when `spec.versions` is set, and `spec.version` otherwise.
+pub fn crd_instance_api(crd: &Crd) -> RawApi {
+    let version = crd.spec.versions.as_ref()
+        .and_then(|vs| vs.iter().find(|v| v.storage).map(|v| v.name.clone()))
+        .or_else(|| crd.spec.version.clone())
+        .unwrap_or_else(|| "v1".into());
+    RawApi::customResource(&crd.spec.names.plural)
+        .group(&crd.spec.group)
+        .version(&version)
+}
+
+/// Whether a CRD is fully set up: established, with its names accepted and storage version recorded
+///
+/// Returns what is still pending, or fails for problems that waiting will not fix:
+/// names conflicting with another CRD, a non structural schema, or not exactly one
+/// storage version.
+fn acceptance(crd: &Crd) -> Result<Option<String>> {
+    let name = &crd.metadata.name;
+    let rejected = |what: String| Err(Error::from(ErrorKind::RequestValidation(format!("crd {} {}", name, what))));
+    let storage: Vec<&str> = match &crd.spec.versions {
+        Some(vs) => vs.iter().filter(|v| v.storage).map(|v| v.name.as_str()).collect(),
+        None => crd.spec.version.iter().map(String::as_str).collect(),
+    };
+    if storage.len() != 1 {
+        return rejected(format!("must have exactly one storage version, found {:?}", storage));
+    }
+    let (conds, stored) = match &crd.status {
+        Some(s) => (s.conditions.as_deref().unwrap_or_default(), &s.stored_versions),
+        None => return Ok(Some("no status yet".into())),
+    };
+    let detail = |c: &dyn ConditionLike| {
+        format!("{}: {}", c.reason().unwrap_or(c.type_()), c.message().unwrap_or_default())
+    };
+    if let Some(c) = conds.find_condition("NamesAccepted").filter(|c| c.status == "False") {
+        return rejected(format!("names are not accepted, {}", detail(c)));
+    }
+    if let Some(c) = conds.find_condition("NonStructuralSchema").filter(|c| c.status == "True") {
+        return rejected(format!("has a non structural schema, {}", detail(c)));
+    }
+    if let Some(retired) = stored.iter().find(|v| crd.spec.versions.iter().flatten().all(|sv| &sv.name != *v)) {
+        warn!("crd {} still has objects stored as {}, which is no longer listed", name, retired);
+    }
+    Ok(if !conds.is_condition_true("NamesAccepted") {
+        Some("names are not accepted yet".into())
+    } else if !is_crd_established(crd) {
+        Some("it is not established yet".into())
+    } else if !stored.iter().any(|v| v == storage[0]) {
+        Some(format!("storage version {} is not recorded yet", storage[0]))
+    } else {
+        None
+    })
+}
+
+/// How long each watch in `wait_for_accepted` may last
+const ACCEPTANCE_WATCH_WINDOW: Duration = Duration::from_secs(2);
+
+impl Api<Crd> {
+    /// Create a CRD, or replace it if it already exists
+    ///
+    /// The resourceVersion of the existing CRD is injected into `data` for the replace,
+    /// which must be in the `content_type` of `pp`.
+    pub fn create_or_update(&self, pp: &PostParams, data: Vec<u8>) -> Result<Crd> {
+        let req = self.api.create(pp, data.clone())?;
+        match self.client.request::<Crd>(req) {
+            Err(e) => {
+                if e.status_code() != Some(409) {
+                    return Err(e);
+                }
+            },
+            created => return created,
+        }
+        let mut manifest: Value = pp.content_type.deserialize(&data)?;
+        let name = manifest["metadata"]["name"].as_str().map(String::from).ok_or_else(|| {
+            ErrorKind::RequestValidation("CRD manifest is missing metadata.name".into())
+        })?;
+        let existing = self.get(&name)?;
+        debug!("Replacing existing CRD {}", name);
+        manifest["metadata"]["resourceVersion"] = existing.metadata.resourceVersion.into();
+        let data = pp.content_type.serialize(&manifest)?;
+        self.replace(&name, pp, data)
+    }
+
+    /// Block until the named CRD is `Established`, or fail after `timeout`
+    pub fn wait_for_established(&self, name: &str, timeout: Duration) -> Result<Crd> {
+        poll_until(&format!("crd {} to be established", name), timeout, || {
+            let crd = self.get(name)?;
+            Ok(if is_crd_established(&crd) { Some(crd) } else { None })
+        })
+    }
+
+    /// Block until the named CRD is established, its names are accepted and its storage
+    /// version is recorded, or fail after `timeout`
+    ///
+    /// Safer than `wait_for_established` when rolling out new versions: name conflicts,
+    /// structural schema violations and storage version mistakes reported by the server
+    /// fail right away, with the server's explanation. Follows the CRD with a watch.
+    pub fn wait_for_accepted(&self, name: &str, timeout: Duration) -> Result<Crd> {
+        let deadline = Instant::now() + timeout;
+        let mut crd = self.get(name)?;
+        loop {
+            let pending = match acceptance(&crd)? {
+                Some(pending) => pending,
+                None => return Ok(crd),
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Err(Error::from(ErrorKind::Timeout(format!("crd {} to be accepted, but {}", name, pending))));
+            }
+            debug!("Waiting for crd {}: {}", name, pending);
+            // watches are read to their end, so keep each one short to notice the deadline
+            let window = remaining.min(ACCEPTANCE_WATCH_WINDOW);
+            let lp = ListParams { timeout: Some(window.as_secs().max(1) as u32), ..Default::default() };
+            let version = crd.metadata.resourceVersion.clone().unwrap_or_default();
+            for event in self.watch_object(name, &lp, &version)? {
+                match event {
+                    WatchEvent::Added(c) | WatchEvent::Modified(c) => crd = c,
+                    WatchEvent::Deleted(_) => {
+                        return Err(Error::from(ErrorKind::RequestValidation(format!("crd {} was deleted", name))));
+                    },
+                    WatchEvent::Error(ref e) if e.code == 410 => crd = self.get(name)?,
+                    WatchEvent::Error(e) => return Err(Error::from(ErrorKind::Api(e))),
+                }
+            }
+        }
+    }
+
+    /// Delete a CRD after deleting all of its instances, and wait for it to disappear
+    ///
+    /// Instances are deleted one by one across all namespaces so that their
+    /// finalizers run before the apiserver's own instance cleanup kicks in.
+    pub fn delete_with_instances(&self, name: &str, dp: &DeleteParams, timeout: Duration) -> Result<()> {
+        let crd = self.get(name)?;
+        let instances = crd_instance_api(&crd);
+        let req = instances.list(&ListParams::default())?;
+        for o in self.client.request::<ObjectList<Object<Void, Void>>>(req)?.items {
+            let api = match &o.metadata.namespace {
+                Some(ns) => instances.clone().within(ns),
+                None => instances.clone(),
+            };
+            debug!("Deleting {} {}", instances.resource, o.metadata.name);
+            self.client.request_text(api.delete(&o.metadata.name, dp)?)?;
+        }
+        self.delete_and_wait(name, dp, timeout)
+    }
+}
+
+#[test]
+fn crd_instance_api_uses_storage_version() {
+    let crd: Crd = serde_json::from_value(serde_json::json!({
+        "metadata": { "name": "foos.clux.dev" },
+        "spec": {
+            "group": "clux.dev",
+            "scope": "Namespaced",
+            "names": { "kind": "Foo", "plural": "foos" },
+            "versions": [
+                { "name": "v1", "served": true, "storage": false },
+                { "name": "v2", "served": true, "storage": true },
+            ],
+        },
+        "status": {
+            "acceptedNames": { "kind": "Foo", "plural": "foos" },
+            "storedVersions": ["v2"],
+            "conditions": [{ "type": "Established", "status": "True" }],
+        }
+    })).unwrap();
+    assert!(is_crd_established(&crd));
+    let req = crd_instance_api(&crd).within("ns").list(&ListParams::default()).unwrap();
+    assert_eq!(req.uri(), "/apis/clux.dev/v2/namespaces/ns/foos");
+}
+
+#[test]
+fn crd_acceptance_waits_and_rejects() {
+    let crd = |conditions: Value, stored: &[&str]| -> Crd {
+        serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "scope": "Namespaced",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "versions": [
+                    { "name": "v1", "served": true, "storage": false },
+                    { "name": "v2", "served": true, "storage": true },
+                ],
+            },
+            "status": { "acceptedNames": { "kind": "", "plural": "" }, "storedVersions": stored, "conditions": conditions },
+        })).unwrap()
+    };
+    let accepted = serde_json::json!([
+        { "type": "NamesAccepted", "status": "True" },
+        { "type": "Established", "status": "True" },
+    ]);
+    assert_eq!(acceptance(&crd(accepted.clone(), &["v2"])).unwrap(), None);
+    assert_eq!(acceptance(&crd(accepted, &["v1"])).unwrap().unwrap(), "storage version v2 is not recorded yet");
+    assert_eq!(acceptance(&crd(serde_json::json!([]), &[])).unwrap().unwrap(), "names are not accepted yet");
+
+    let conflict = serde_json::json!([{ "type": "NamesAccepted", "status": "False",
+        "reason": "NameConflict", "message": "\"foo\" is already in use" }]);
+    let err = acceptance(&crd(conflict, &[])).unwrap_err();
+    assert!(err.to_string().ends_with("names are not accepted, NameConflict: \"foo\" is already in use"));
+    let nonstructural = serde_json::json!([{ "type": "NonStructuralSchema", "status": "True",
+        "reason": "Violations", "message": "spec.validation.openAPIV3Schema.type: Required value" }]);
+    assert!(acceptance(&crd(nonstructural, &[])).unwrap_err().to_string().contains("type: Required value"));
+
+    let mut two_storage = crd(serde_json::json!([]), &[]);
+    two_storage.spec.versions.as_mut().unwrap()[0].storage = true;
+    assert!(acceptance(&two_storage).is_err());
+}
+
+#[test]
+fn acceptance_is_followed_in_short_watches() {
+    use crate::client::fake::Fake;
+    let crd = |accepted: &str, rv: &str| serde_json::json!({
+        "metadata": { "name": "foos.clux.dev", "resourceVersion": rv },
+        "spec": {
+            "group": "clux.dev", "scope": "Namespaced", "names": { "kind": "Foo", "plural": "foos" },
+            "versions": [{ "name": "v1", "served": true, "storage": true }],
+        },
+        "status": {
+            "acceptedNames": { "kind": "Foo", "plural": "foos" },
+            "storedVersions": ["v1"],
+            "conditions": [
+                { "type": "NamesAccepted", "status": accepted },
+                { "type": "Established", "status": accepted },
+            ],
+        }
+    });
+    let crds = "/apis/apiextensions.k8s.io/v1beta1/customresourcedefinitions";
+    let fake = Fake::new()
+        .on("GET", &format!("{}/foos.clux.dev", crds), 200, crd("Unknown", "1").to_string())
+        .once("GET", &format!("{}?watch=true", crds), 200, "")
+        .once("GET", &format!("{}?watch=true", crds), 200,
+            serde_json::json!({ "type": "MODIFIED", "object": crd("True", "2") }).to_string() + "\n");
+    let api = Api::v1beta1CustomResourceDefinition(fake.client());
+    let crd = api.wait_for_accepted("foos.clux.dev", Duration::from_secs(60)).unwrap();
+    assert_eq!(crd.metadata.resourceVersion.as_deref(), Some("2"));
+    let watches: Vec<_> = fake.requests().into_iter().filter(|r| r.query().contains("watch=true")).collect();
+    assert_eq!(watches.len(), 2);
+    assert!(watches.iter().all(|r| r.query().contains("timeoutSeconds=2")));
+}
@@ -0,0 +1,217 @@
+//! Decoding objects of many kinds from one dynamic watch into typed values
+use std::collections::BTreeMap;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::api::{GroupVersionKind, KubeObject, ObjectMeta, Resource, TypeMeta, WatchEvent};
+use crate::{ApiError, ErrorKind, Result};
+use failure::ResultExt;
+
+/// An object of any kind, keeping every field
+///
+/// What dynamic informers and reflectors over arbitrary resources hold, e.g.
+/// `Informer::<DynamicObject>::raw(client, api)`.
+#[derive(Clone)]
+pub struct DynamicObject {
+    pub types: TypeMeta,
+    pub metadata: ObjectMeta,
+    /// The whole object, including `apiVersion`, `kind` and `metadata`
+    pub data: Value,
+}
+
+impl DynamicObject {
+    /// Its type, from `apiVersion` and `kind`
+    pub fn gvk(&self) -> Result<GroupVersionKind> {
+        self.types.gvk()
+    }
+}
+
+impl<'de> Deserialize<'de> for DynamicObject {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let data = Value::deserialize(d)?;
+        let types = serde_json::from_value(data.clone()).map_err(de::Error::custom)?;
+        let metadata = serde_json::from_value(data["metadata"].clone()).map_err(de::Error::custom)?;
+        Ok(DynamicObject { types, metadata, data })
+    }
+}
+
+impl Serialize for DynamicObject {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        self.data.serialize(s)
+    }
+}
+
+impl KubeObject for DynamicObject {
+    fn meta(&self) -> &ObjectMeta {
+        &self.metadata
+    }
+    fn types(&self) -> Option<&TypeMeta> {
+        Some(&self.types)
+    }
+}
+
+/// A watch event with its object decoded
+#[derive(Clone, Debug)]
+pub enum DecodedEvent<T> {
+    Added(T),
+    Modified(T),
+    Deleted(T),
+    Error(ApiError),
+}
+
+type DecodeFn<T> = Box<dyn Fn(DynamicObject) -> Result<T> + Send + Sync>;
+type UnknownFn<T> = Box<dyn Fn(&GroupVersionKind, DynamicObject) -> Result<Option<T>> + Send + Sync>;
+
+/// Decodes dynamic objects into `T`, by their group, version and kind
+///
+/// Registered kinds are decoded into their types. Objects of other kinds go to
+/// the `on_unknown` hook, then to the `dynamic` fallback, and are skipped with a
+/// debug log when neither takes them.
+///
+/// ```
+/// use kube::api::{Decoder, DynamicObject, GroupVersionKind};
+///
+/// // a garbage collector that looks closer at leases, and at nothing else
+/// enum Owned {
+///     Lease(String),
+///     Other(DynamicObject),
+/// }
+/// let lease = GroupVersionKind::new("coordination.k8s.io", "v1", "Lease");
+/// let decoder = Decoder::new()
+///     .register_gvk(lease, |o| Ok(Owned::Lease(o.data["spec"]["holderIdentity"].to_string())))
+///     .dynamic(Owned::Other);
+/// ```
+pub struct Decoder<T> {
+    typed: BTreeMap<GroupVersionKind, DecodeFn<T>>,
+    unknown: Option<UnknownFn<T>>,
+    dynamic: Option<DecodeFn<T>>,
+}
+
+impl<T> Default for Decoder<T> {
+    fn default() -> Self {
+        Decoder { typed: BTreeMap::new(), unknown: None, dynamic: None }
+    }
+}
+
+impl<T> Decoder<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode objects of the `Resource` `K`, and wrap them with `f`
+    pub fn register<K, F>(self, f: F) -> Self
+    where
+        K: Resource + DeserializeOwned,
+        F: Fn(K) -> T + Send + Sync + 'static,
+    {
+        self.register_gvk(GroupVersionKind::of::<K>(), move |o| {
+            Ok(f(serde_json::from_value(o.data).context(ErrorKind::SerdeParse)?))
+        })
+    }
+
+    /// Decode objects of a kind with `f`
+    pub fn register_gvk<F>(mut self, gvk: GroupVersionKind, f: F) -> Self
+    where
+        F: Fn(DynamicObject) -> Result<T> + Send + Sync + 'static,
+    {
+        self.typed.insert(gvk, Box::new(f));
+        self
+    }
+
+    /// Offer objects of kinds not registered to `f`, which may decode them or pass
+    pub fn on_unknown<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&GroupVersionKind, DynamicObject) -> Result<Option<T>> + Send + Sync + 'static,
+    {
+        self.unknown = Some(Box::new(f));
+        self
+    }
+
+    /// Keep every object no decoder or hook took as it is
+    pub fn dynamic<F>(mut self, f: F) -> Self
+    where
+        F: Fn(DynamicObject) -> T + Send + Sync + 'static,
+    {
+        self.dynamic = Some(Box::new(move |o| Ok(f(o))));
+        self
+    }
+
+    /// Decode an object, `None` if nothing takes its kind
+    pub fn decode(&self, object: DynamicObject) -> Result<Option<T>> {
+        let gvk = object.gvk()?;
+        if let Some(f) = self.typed.get(&gvk) {
+            return f(object).map(Some);
+        }
+        let object = match &self.unknown {
+            Some(hook) => {
+                // the hook may only want to look, so let the fallback have it too
+                if let Some(t) = hook(&gvk, object.clone())? {
+                    return Ok(Some(t));
+                }
+                object
+            },
+            None => object,
+        };
+        match &self.dynamic {
+            Some(f) => f(object).map(Some),
+            None => {
+                debug!("No decoder for {}, skipping {}", gvk, object.metadata.name);
+                Ok(None)
+            },
+        }
+    }
+
+    /// Decode the object of a watch event, `None` if nothing takes its kind
+    pub fn decode_event(&self, event: WatchEvent<DynamicObject>) -> Result<Option<DecodedEvent<T>>> {
+        Ok(match event {
+            WatchEvent::Added(o) => self.decode(o)?.map(DecodedEvent::Added),
+            WatchEvent::Modified(o) => self.decode(o)?.map(DecodedEvent::Modified),
+            WatchEvent::Deleted(o) => self.decode(o)?.map(DecodedEvent::Deleted),
+            WatchEvent::Error(e) => Some(DecodedEvent::Error(e)),
+        })
+    }
+}
+
+#[test]
+fn kinds_are_decoded_by_type_hook_or_fallback() {
+    use crate::api::{NamespaceResourceScope, Object};
+    #[derive(Clone, Deserialize)]
+    struct FooSpec {
+        size: u32,
+    }
+    impl Resource for Object<FooSpec, ()> {
+        const GROUP: &'static str = "example.com";
+        const VERSION: &'static str = "v1";
+        const KIND: &'static str = "Foo";
+        const PLURAL: &'static str = "foos";
+        type Scope = NamespaceResourceScope;
+    }
+    #[derive(Debug, PartialEq)]
+    enum Seen {
+        Foo(u32),
+        Secret(String),
+        Other(String),
+    }
+    let decoder = Decoder::new()
+        .register(|foo: Object<FooSpec, ()>| Seen::Foo(foo.spec.size))
+        .on_unknown(|gvk, o| Ok(if gvk.kind == "Secret" { Some(Seen::Secret(o.metadata.name)) } else { None }))
+        .dynamic(|o| Seen::Other(o.types.kind.unwrap_or_default()));
+    let event = |json: &str| -> WatchEvent<DynamicObject> { serde_json::from_str(json).unwrap() };
+    let decoded = |json: &str| match decoder.decode_event(event(json)).unwrap() {
+        Some(DecodedEvent::Added(s)) | Some(DecodedEvent::Modified(s)) | Some(DecodedEvent::Deleted(s)) => Some(s),
+        _ => None,
+    };
+    assert_eq!(decoded(r#"{"type":"ADDED","object":{"apiVersion":"example.com/v1","kind":"Foo",
+        "metadata":{"name":"a"},"spec":{"size":3}}}"#), Some(Seen::Foo(3)));
+    assert_eq!(decoded(r#"{"type":"MODIFIED","object":{"apiVersion":"v1","kind":"Secret",
+        "metadata":{"name":"creds"}}}"#), Some(Seen::Secret("creds".into())));
+    assert_eq!(decoded(r#"{"type":"DELETED","object":{"apiVersion":"v1","kind":"ConfigMap",
+        "metadata":{"name":"cm"},"data":{"a":"b"}}}"#), Some(Seen::Other("ConfigMap".into())));
+
+    let strict = Decoder::new().register(|foo: Object<FooSpec, ()>| foo.spec.size);
+    let cm: DynamicObject = serde_json::from_str(r#"{"apiVersion":"v1","kind":"ConfigMap","metadata":{"name":"cm"}}"#).unwrap();
+    assert!(strict.decode(cm.clone()).unwrap().is_none());
+    assert_eq!(serde_json::to_value(&cm).unwrap(), cm.data);
+}
@@ -0,0 +1,240 @@
+//! Finding objects that use API versions deprecated or removed in a Kubernetes release
+//!
+//! ```
+//! use kube::api::deprecations::{check_deprecations, Severity};
+//! use serde_json::json;
+//!
+//! let ingress = json!({
+//!     "apiVersion": "extensions/v1beta1", "kind": "Ingress",
+//!     "metadata": { "name": "blog", "namespace": "default" },
+//! });
+//! let hints = check_deprecations(&[ingress], "v1.22.3").unwrap();
+//! assert_eq!(hints[0].severity, Severity::Removed);
+//! assert_eq!(hints[0].replacement, Some("networking.k8s.io/v1"));
+//! ```
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::client::APIClient;
+use crate::{Result, Error, ErrorKind};
+use failure::ResultExt;
+
+/// A Kubernetes release, ordered by major and minor version
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct KubeVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl KubeVersion {
+    pub const fn new(major: u32, minor: u32) -> Self {
+        KubeVersion { major, minor }
+    }
+
+    /// The version of the apiserver a client talks to, from `/version`
+    pub fn of_server(client: &APIClient) -> Result<Self> {
+        #[allow(non_snake_case)]
+        #[derive(Deserialize)]
+        struct Info {
+            gitVersion: String,
+        }
+        let req = http::Request::get("/version").body(vec![]).context(ErrorKind::RequestBuild)?;
+        let info: Info = client.request(req)?;
+        info.gitVersion.parse()
+    }
+}
+
+impl FromStr for KubeVersion {
+    type Err = Error;
+
+    /// Parse `1.22`, `v1.22.3` or a vendor version like `v1.22.3-gke.1500`
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::from(ErrorKind::RequestValidation(format!("invalid Kubernetes version {:?}", s)));
+        // providers like EKS report minors such as `22+`
+        let mut parts = s.trim_start_matches('v').split(['.', '-', '+']);
+        let major = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+        let minor = parts.next().and_then(|p| p.parse().ok()).ok_or_else(invalid)?;
+        Ok(KubeVersion { major, minor })
+    }
+}
+
+impl fmt::Display for KubeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// A deprecated API version of a kind
+#[derive(Clone, Debug)]
+pub struct Deprecation {
+    pub api_version: &'static str,
+    pub kind: &'static str,
+    pub deprecated_in: KubeVersion,
+    pub removed_in: KubeVersion,
+    /// The `apiVersion` to move to, if there is one
+    pub replacement: Option<&'static str>,
+    /// What else changes when migrating
+    pub note: Option<&'static str>,
+}
+
+const fn v(minor: u32) -> KubeVersion {
+    KubeVersion::new(1, minor)
+}
+
+macro_rules! deprecations {
+    ($( $av:expr, [$($kind:expr),+], $dep:expr, $rem:expr, $repl:expr, $note:expr; )*) => {
+        &[ $( $( Deprecation {
+            api_version: $av,
+            kind: $kind,
+            deprecated_in: v($dep),
+            removed_in: v($rem),
+            replacement: $repl,
+            note: $note,
+        }, )+ )* ]
+    };
+}
+
+/// Deprecated API versions of the built in kinds, from the Kubernetes deprecation guide
+pub const DEPRECATIONS: &[Deprecation] = deprecations![
+    "extensions/v1beta1", ["Deployment", "DaemonSet", "ReplicaSet"], 9, 16, Some("apps/v1"),
+        Some("spec.selector is required and immutable in apps/v1");
+    "apps/v1beta1", ["Deployment", "StatefulSet"], 9, 16, Some("apps/v1"),
+        Some("spec.selector is required and immutable in apps/v1");
+    "apps/v1beta2", ["Deployment", "StatefulSet", "DaemonSet", "ReplicaSet"], 9, 16, Some("apps/v1"), None;
+    "extensions/v1beta1", ["NetworkPolicy"], 9, 16, Some("networking.k8s.io/v1"), None;
+    "extensions/v1beta1", ["PodSecurityPolicy"], 10, 16, Some("policy/v1beta1"), None;
+    "extensions/v1beta1", ["Ingress"], 14, 22, Some("networking.k8s.io/v1"),
+        Some("backend.serviceName and servicePort become backend.service.name and port, and pathType is required");
+    "networking.k8s.io/v1beta1", ["Ingress"], 19, 22, Some("networking.k8s.io/v1"),
+        Some("backend.serviceName and servicePort become backend.service.name and port, and pathType is required");
+    "networking.k8s.io/v1beta1", ["IngressClass"], 19, 22, Some("networking.k8s.io/v1"), None;
+    "apiextensions.k8s.io/v1beta1", ["CustomResourceDefinition"], 16, 22, Some("apiextensions.k8s.io/v1"),
+        Some("a structural schema is required for each version, and spec.validation moves into spec.versions");
+    "admissionregistration.k8s.io/v1beta1", ["ValidatingWebhookConfiguration", "MutatingWebhookConfiguration"],
+        16, 22, Some("admissionregistration.k8s.io/v1"),
+        Some("sideEffects and admissionReviewVersions are required, and failurePolicy defaults to Fail");
+    "apiregistration.k8s.io/v1beta1", ["APIService"], 19, 22, Some("apiregistration.k8s.io/v1"), None;
+    "authentication.k8s.io/v1beta1", ["TokenReview"], 19, 22, Some("authentication.k8s.io/v1"), None;
+    "authorization.k8s.io/v1beta1",
+        ["SubjectAccessReview", "LocalSubjectAccessReview", "SelfSubjectAccessReview", "SelfSubjectRulesReview"],
+        19, 22, Some("authorization.k8s.io/v1"), Some("spec.group is renamed to spec.groups");
+    "certificates.k8s.io/v1beta1", ["CertificateSigningRequest"], 19, 22, Some("certificates.k8s.io/v1"),
+        Some("spec.signerName is required");
+    "coordination.k8s.io/v1beta1", ["Lease"], 19, 22, Some("coordination.k8s.io/v1"), None;
+    "rbac.authorization.k8s.io/v1beta1", ["Role", "ClusterRole", "RoleBinding", "ClusterRoleBinding"],
+        17, 22, Some("rbac.authorization.k8s.io/v1"), None;
+    "scheduling.k8s.io/v1beta1", ["PriorityClass"], 14, 22, Some("scheduling.k8s.io/v1"), None;
+    "storage.k8s.io/v1beta1", ["CSIDriver", "CSINode", "StorageClass", "VolumeAttachment"], 19, 22,
+        Some("storage.k8s.io/v1"), None;
+    "batch/v1beta1", ["CronJob"], 21, 25, Some("batch/v1"), None;
+    "discovery.k8s.io/v1beta1", ["EndpointSlice"], 21, 25, Some("discovery.k8s.io/v1"),
+        Some("topology[\"kubernetes.io/hostname\"] becomes nodeName");
+    "events.k8s.io/v1beta1", ["Event"], 19, 25, Some("events.k8s.io/v1"), None;
+    "autoscaling/v2beta1", ["HorizontalPodAutoscaler"], 22, 25, Some("autoscaling/v2"),
+        Some("targetAverageUtilization becomes target.averageUtilization");
+    "policy/v1beta1", ["PodDisruptionBudget"], 21, 25, Some("policy/v1"),
+        Some("an empty spec.selector selects all pods in the namespace");
+    "policy/v1beta1", ["PodSecurityPolicy"], 21, 25, None,
+        Some("use Pod Security Admission or a policy engine instead");
+    "node.k8s.io/v1beta1", ["RuntimeClass"], 20, 25, Some("node.k8s.io/v1"), None;
+    "autoscaling/v2beta2", ["HorizontalPodAutoscaler"], 23, 26, Some("autoscaling/v2"), None;
+    "flowcontrol.apiserver.k8s.io/v1beta1", ["FlowSchema", "PriorityLevelConfiguration"], 23, 26,
+        Some("flowcontrol.apiserver.k8s.io/v1"), None;
+    "storage.k8s.io/v1beta1", ["CSIStorageCapacity"], 24, 27, Some("storage.k8s.io/v1"), None;
+    "flowcontrol.apiserver.k8s.io/v1beta2", ["FlowSchema", "PriorityLevelConfiguration"], 26, 29,
+        Some("flowcontrol.apiserver.k8s.io/v1"), None;
+    "flowcontrol.apiserver.k8s.io/v1beta3", ["FlowSchema", "PriorityLevelConfiguration"], 29, 32,
+        Some("flowcontrol.apiserver.k8s.io/v1"), None;
+];
+
+/// The deprecation of a kind at an api version, if any
+pub fn find_deprecation(api_version: &str, kind: &str) -> Option<&'static Deprecation> {
+    DEPRECATIONS.iter().find(|d| d.api_version == api_version && d.kind == kind)
+}
+
+/// How urgent a migration is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Still served, but going away
+    Deprecated,
+    /// No longer served, the object cannot be applied
+    Removed,
+}
+
+/// An object that needs migrating
+#[derive(Clone, Debug)]
+pub struct Hint {
+    pub api_version: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub severity: Severity,
+    pub removed_in: KubeVersion,
+    /// The `apiVersion` to move to, if there is one
+    pub replacement: Option<&'static str>,
+    /// What to do, for printing
+    pub message: String,
+}
+
+/// Hints for each object using an API version deprecated or removed in `server_version`
+///
+/// Objects are json manifests as loaded by `manifest::split_documents`, or listed from a cluster.
+pub fn check_deprecations(objects: &[Value], server_version: &str) -> Result<Vec<Hint>> {
+    let server: KubeVersion = server_version.parse()?;
+    let mut hints = vec![];
+    for obj in objects {
+        let (av, kind) = (obj["apiVersion"].as_str().unwrap_or(""), obj["kind"].as_str().unwrap_or(""));
+        let d = match find_deprecation(av, kind) {
+            Some(d) if server >= d.deprecated_in => d,
+            _ => continue,
+        };
+        let severity = if server >= d.removed_in { Severity::Removed } else { Severity::Deprecated };
+        let status = match severity {
+            Severity::Removed => format!("{} {} was removed in {}", av, kind, d.removed_in),
+            Severity::Deprecated => format!("{} {} is deprecated and will be removed in {}", av, kind, d.removed_in),
+        };
+        let action = match d.replacement {
+            Some(r) => format!("migrate to {}", r),
+            None => "it has no replacement".into(),
+        };
+        let message = match d.note {
+            Some(note) => format!("{}; {}: {}", status, action, note),
+            None => format!("{}; {}", status, action),
+        };
+        hints.push(Hint {
+            api_version: av.into(),
+            kind: kind.into(),
+            namespace: obj["metadata"]["namespace"].as_str().map(String::from),
+            name: obj["metadata"]["name"].as_str().unwrap_or("").into(),
+            severity,
+            removed_in: d.removed_in,
+            replacement: d.replacement,
+            message,
+        });
+    }
+    Ok(hints)
+}
+
+#[test]
+fn deprecations_by_server_version() {
+    use serde_json::json;
+    assert_eq!("v1.22.3-gke.1500".parse::<KubeVersion>().unwrap(), KubeVersion::new(1, 22));
+    assert_eq!("1.23+".parse::<KubeVersion>().unwrap(), KubeVersion::new(1, 23));
+    assert!("latest".parse::<KubeVersion>().is_err());
+
+    let objects = vec![
+        json!({ "apiVersion": "batch/v1beta1", "kind": "CronJob", "metadata": { "name": "backup" } }),
+        json!({ "apiVersion": "policy/v1beta1", "kind": "PodSecurityPolicy", "metadata": { "name": "restricted" } }),
+        json!({ "apiVersion": "apps/v1", "kind": "Deployment", "metadata": { "name": "blog" } }),
+    ];
+    assert!(check_deprecations(&objects, "1.20").unwrap().is_empty());
+    let hints = check_deprecations(&objects, "1.21").unwrap();
+    assert_eq!(hints.len(), 2);
+    assert_eq!(hints[0].severity, Severity::Deprecated);
+    assert_eq!(hints[0].message, "batch/v1beta1 CronJob is deprecated and will be removed in 1.25; migrate to batch/v1");
+    let hints = check_deprecations(&objects, "1.25").unwrap();
+    assert_eq!(hints[1].severity, Severity::Removed);
+    assert_eq!(hints[1].replacement, None);
+}
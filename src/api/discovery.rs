@@ -0,0 +1,221 @@
+//! Finding the resources an apiserver serves
+use crate::api::{GroupVersionKind, GroupVersionResource, RawApi};
+use crate::client::APIClient;
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+#[derive(Deserialize)]
+struct APIVersions {
+    versions: Vec<String>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct GroupVersionForDiscovery {
+    groupVersion: String,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct APIGroup {
+    name: String,
+    preferredVersion: Option<GroupVersionForDiscovery>,
+    #[serde(default)]
+    versions: Vec<GroupVersionForDiscovery>,
+}
+
+#[derive(Deserialize)]
+struct APIGroupList {
+    groups: Vec<APIGroup>,
+}
+
+#[allow(non_snake_case)]
+#[derive(Deserialize)]
+struct APIResource {
+    name: String,
+    namespaced: bool,
+    kind: String,
+    #[serde(default)]
+    verbs: Vec<String>,
+    #[serde(default)]
+    singularName: String,
+    #[serde(default)]
+    shortNames: Vec<String>,
+    #[serde(default)]
+    categories: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct APIResourceList {
+    resources: Vec<APIResource>,
+}
+
+/// A resource served by the apiserver, in the preferred version of its group
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiResource {
+    /// API group, empty for the core group
+    pub group: String,
+    pub version: String,
+    /// The plural name used in urls, e.g. `deployments`
+    pub resource: String,
+    pub kind: String,
+    pub namespaced: bool,
+    /// What can be done with it, e.g. `list` and `watch`
+    pub verbs: Vec<String>,
+    /// Lowercase singular name, e.g. `deployment`
+    pub singular: String,
+    /// Abbreviations like `deploy`
+    pub short_names: Vec<String>,
+    /// Groups of resources it belongs to, like `all`
+    pub categories: Vec<String>,
+}
+
+impl ApiResource {
+    /// The `apiVersion` of its objects
+    pub fn api_version(&self) -> String {
+        self.gvk().api_version()
+    }
+
+    /// The type of its objects
+    pub fn gvk(&self) -> GroupVersionKind {
+        GroupVersionKind::new(&self.group, &self.version, &self.kind)
+    }
+
+    pub fn gvr(&self) -> GroupVersionResource {
+        GroupVersionResource::new(&self.group, &self.version, &self.resource)
+    }
+
+    /// `resource.group` as kubectl accepts it, e.g. `deployments.apps`
+    pub fn qualified_name(&self) -> String {
+        if self.group.is_empty() {
+            self.resource.clone()
+        } else {
+            format!("{}.{}", self.resource, self.group)
+        }
+    }
+
+    pub fn supports(&self, verb: &str) -> bool {
+        self.verbs.iter().any(|v| v == verb)
+    }
+
+    /// Url data for requests on this resource, across all namespaces
+    pub fn raw_api(&self) -> RawApi {
+        self.gvr().raw_api()
+    }
+}
+
+fn get<T: serde::de::DeserializeOwned>(client: &APIClient, path: &str) -> Result<T> {
+    let req = http::Request::get(path).body(vec![]).context(ErrorKind::RequestBuild)?;
+    client.request(req)
+}
+
+fn resources_in(client: &APIClient, path: &str, group: &str, version: &str) -> Result<Vec<ApiResource>> {
+    let list: APIResourceList = get(client, path)?;
+    Ok(list.resources.into_iter()
+        // subresources like pods/log
+        .filter(|r| !r.name.contains('/'))
+        .map(|r| ApiResource {
+            group: group.into(),
+            version: version.into(),
+            resource: r.name,
+            namespaced: r.namespaced,
+            verbs: r.verbs,
+            // older apiservers leave it empty
+            singular: if r.singularName.is_empty() { r.kind.to_lowercase() } else { r.singularName },
+            kind: r.kind,
+            short_names: r.shortNames,
+            categories: r.categories,
+        })
+        .collect())
+}
+
+/// All resources the apiserver serves, in the preferred version of each group
+///
+/// Groups whose discovery fails, typically an unavailable aggregated apiserver like
+/// metrics-server, are skipped with a warning, as kubectl does.
+pub fn discover(client: &APIClient) -> Result<Vec<ApiResource>> {
+    let mut resources = vec![];
+    let core: APIVersions = get(client, "/api")?;
+    if let Some(v) = core.versions.first() {
+        resources.extend(resources_in(client, &format!("/api/{}", v), "", v)?);
+    }
+    let groups: APIGroupList = get(client, "/apis")?;
+    for g in groups.groups {
+        let APIGroup { name, preferredVersion: preferred, versions } = g;
+        let gv = match preferred.or_else(|| versions.into_iter().next()) {
+            Some(gv) => gv.groupVersion,
+            None => continue,
+        };
+        let version = gv.rsplit('/').next().unwrap_or("").to_string();
+        match resources_in(client, &format!("/apis/{}", gv), &name, &version) {
+            Ok(rs) => resources.extend(rs),
+            Err(e) => warn!("Skipping discovery of {}: {}", gv, e),
+        }
+    }
+    Ok(resources)
+}
+
+/// Resolve a resource the way kubectl does, from a name a user typed
+///
+/// Accepts plurals, singulars, kinds and short names, such as `deployments`, `deployment`,
+/// `Deployment` or `deploy`, optionally qualified by group (`deploy.apps`) or version and
+/// group (`deployments.v1.apps`). Otherwise the name is taken as a category like `all`,
+/// resolving to all its resources. Ambiguous names resolve to the first resource found,
+/// which for `discover` means the core group wins.
+pub fn resolve<'a>(resources: &'a [ApiResource], name: &str) -> Result<Vec<&'a ApiResource>> {
+    let lower = name.to_lowercase();
+    let (short, qualifier) = match lower.find('.') {
+        Some(i) => (&lower[..i], Some(&lower[i + 1..])),
+        None => (lower.as_str(), None),
+    };
+    let qualified = |r: &ApiResource| match qualifier {
+        None => true,
+        Some(q) => q == r.group || q == format!("{}.{}", r.version, r.group),
+    };
+    let named = |r: &&ApiResource| {
+        qualified(r) && (r.resource == short || r.singular == short || r.kind.to_lowercase() == short)
+    };
+    let abbreviated = |r: &&ApiResource| qualified(r) && r.short_names.iter().any(|s| s == short);
+    if let Some(r) = resources.iter().find(named).or_else(|| resources.iter().find(abbreviated)) {
+        return Ok(vec![r]);
+    }
+    let category: Vec<_> = resources.iter().filter(|r| r.categories.contains(&lower)).collect();
+    if category.is_empty() {
+        return Err(ErrorKind::RequestValidation(format!("the server doesn't have a resource type {:?}", name)).into());
+    }
+    Ok(category)
+}
+
+#[test]
+fn short_names_and_categories_resolve() {
+    let res = |group: &str, resource: &str, kind: &str, short: &[&str], categories: &[&str]| ApiResource {
+        group: group.into(),
+        version: "v1".into(),
+        resource: resource.into(),
+        kind: kind.into(),
+        namespaced: true,
+        verbs: vec![],
+        singular: kind.to_lowercase(),
+        short_names: short.iter().map(|s| s.to_string()).collect(),
+        categories: categories.iter().map(|s| s.to_string()).collect(),
+    };
+    let resources = vec![
+        res("", "services", "Service", &["svc"], &["all"]),
+        res("", "configmaps", "ConfigMap", &["cm"], &[]),
+        res("", "events", "Event", &["ev"], &[]),
+        res("apps", "deployments", "Deployment", &["deploy"], &["all"]),
+        res("events.k8s.io", "events", "Event", &["ev"], &[]),
+    ];
+    let names = |name: &str| -> Vec<String> {
+        resolve(&resources, name).unwrap().iter().map(|r| r.qualified_name()).collect()
+    };
+    assert_eq!(names("svc"), vec!["services"]);
+    assert_eq!(names("cm"), vec!["configmaps"]);
+    assert_eq!(names("deploy"), vec!["deployments.apps"]);
+    assert_eq!(names("Deployment"), vec!["deployments.apps"]);
+    assert_eq!(names("deployments.v1.apps"), vec!["deployments.apps"]);
+    assert_eq!(names("events"), vec!["events"]);
+    assert_eq!(names("ev.events.k8s.io"), vec!["events.events.k8s.io"]);
+    assert_eq!(names("all"), vec!["services", "deployments.apps"]);
+    assert!(resolve(&resources, "foo").is_err());
+}
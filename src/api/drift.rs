@@ -0,0 +1,353 @@
+//! Detecting objects that drift from their desired state
+use std::collections::BTreeMap;
+
+use serde::{de, Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::api::{
+    discover, three_way_merge_patch, ApiResource, GroupVersionKind, Informer, KubeObject, ObjectMeta, PatchParams,
+    PostParams, RawApi, WatchEvent,
+};
+use crate::client::APIClient;
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+/// A live object of any kind, keeping every field for comparison
+#[derive(Clone)]
+struct Live {
+    metadata: ObjectMeta,
+    value: Value,
+}
+
+impl<'de> Deserialize<'de> for Live {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let value = Value::deserialize(d)?;
+        let metadata = serde_json::from_value(value["metadata"].clone()).map_err(de::Error::custom)?;
+        Ok(Live { metadata, value })
+    }
+}
+
+impl KubeObject for Live {
+    fn meta(&self) -> &ObjectMeta {
+        &self.metadata
+    }
+}
+
+/// How a live object differs from its desired state
+#[derive(Clone, Debug, PartialEq)]
+pub enum DriftKind {
+    /// The object does not exist
+    Missing,
+    /// Fields set in the desired state have other values; the merge patch reverting them
+    Changed(Value),
+}
+
+/// A drifted object
+#[derive(Clone, Debug)]
+pub struct Drift {
+    pub api_version: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub drift: DriftKind,
+}
+
+struct Desired {
+    resource: ApiResource,
+    object: Value,
+}
+
+impl Desired {
+    fn api(&self) -> RawApi {
+        let mut api = self.resource.raw_api();
+        api.namespace = self.namespace().map(String::from);
+        api
+    }
+
+    fn namespace(&self) -> Option<&str> {
+        if self.resource.namespaced {
+            Some(self.object["metadata"]["namespace"].as_str().unwrap_or("default"))
+        } else {
+            None
+        }
+    }
+
+    fn name(&self) -> &str {
+        self.object["metadata"]["name"].as_str().unwrap_or("")
+    }
+
+    fn drift(&self, live: Option<&Value>) -> Option<Drift> {
+        let drift = match live {
+            None => DriftKind::Missing,
+            Some(live) => DriftKind::Changed(three_way_merge_patch(None, &self.object, &desired_fields(&self.object, live))?),
+        };
+        Some(Drift {
+            api_version: self.resource.api_version(),
+            kind: self.resource.kind.clone(),
+            namespace: self.namespace().map(String::from),
+            name: self.name().into(),
+            drift,
+        })
+    }
+}
+
+/// The fields of `live` that `desired` sets
+///
+/// List elements are matched by their `name` where they have one, and by position
+/// otherwise, so defaults the apiserver fills into each (like a port's `protocol`)
+/// are left out. A list of another length, or with an element that has no match,
+/// is kept whole.
+fn desired_fields(desired: &Value, live: &Value) -> Value {
+    match (desired, live) {
+        (Value::Object(d), Value::Object(l)) => Value::Object(
+            d.iter().filter_map(|(k, dv)| l.get(k).map(|lv| (k.clone(), desired_fields(dv, lv)))).collect(),
+        ),
+        (Value::Array(d), Value::Array(l)) if d.len() == l.len() => {
+            let matched = d.iter().enumerate().map(|(i, dv)| {
+                let lv = match dv["name"].as_str() {
+                    Some(name) => l.iter().find(|lv| lv["name"] == name),
+                    None => l.get(i),
+                };
+                lv.map(|lv| desired_fields(dv, lv))
+            });
+            matched.collect::<Option<Vec<_>>>().map(Value::Array).unwrap_or_else(|| live.clone())
+        },
+        _ => live.clone(),
+    }
+}
+
+/// (resource, namespace, name)
+type Key = (String, Option<String>, String);
+
+type DriftHandler = Box<dyn Fn(&Drift) + Send + Sync>;
+
+/// Watches a set of objects for changes away from their desired state
+///
+/// Only fields in the desired state are compared, so defaults and status filled in by
+/// the cluster are not drift. With `auto_correct`, missing objects are created again
+/// and changed fields patched back; either way each drift is passed to `on_drift`.
+///
+/// ```no_run
+/// use kube::{api::DriftDetector, client::APIClient, config};
+/// use serde_json::json;
+///
+/// let config = config::load_kube_config().unwrap();
+/// let client = APIClient::new(config);
+/// let mut detector = DriftDetector::new(client)
+///     .auto_correct(true)
+///     .on_drift(|d| println!("{} {} drifted: {:?}", d.kind, d.name, d.drift));
+/// detector.desire(json!({
+///     "apiVersion": "v1", "kind": "ConfigMap",
+///     "metadata": { "name": "blog", "namespace": "default" },
+///     "data": { "theme": "dark" },
+/// })).unwrap();
+/// detector.run().unwrap();
+/// ```
+pub struct DriftDetector {
+    client: APIClient,
+    resources: Option<Vec<ApiResource>>,
+    desired: BTreeMap<Key, Desired>,
+    correct: bool,
+    on_drift: Option<DriftHandler>,
+}
+
+impl DriftDetector {
+    pub fn new(client: APIClient) -> Self {
+        DriftDetector { client, resources: None, desired: BTreeMap::new(), correct: false, on_drift: None }
+    }
+
+    /// Revert drift instead of only reporting it
+    pub fn auto_correct(mut self, correct: bool) -> Self {
+        self.correct = correct;
+        self
+    }
+
+    /// Called with every drift found, before it is corrected
+    pub fn on_drift<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&Drift) + Send + Sync + 'static,
+    {
+        self.on_drift = Some(Box::new(f));
+        self
+    }
+
+    /// Track the desired state of an object, replacing any earlier one
+    ///
+    /// Fails for kinds the apiserver does not serve.
+    pub fn desire(&mut self, object: Value) -> Result<()> {
+        if self.resources.is_none() {
+            self.resources = Some(discover(&self.client)?);
+        }
+        let gvk = GroupVersionKind::from_value(&object)?;
+        let resource = self.resources.iter().flatten()
+            .find(|r| r.gvk() == gvk)
+            .cloned()
+            .ok_or_else(|| ErrorKind::RequestValidation(format!("{} is not served by the apiserver", gvk)))?;
+        let desired = Desired { resource, object };
+        let key = (desired.resource.qualified_name(), desired.namespace().map(String::from), desired.name().into());
+        self.desired.insert(key, desired);
+        Ok(())
+    }
+
+    /// Stop tracking an object
+    pub fn forget(&mut self, resource: &str, namespace: Option<&str>, name: &str) {
+        self.desired.remove(&(resource.into(), namespace.map(String::from), name.into()));
+    }
+
+    fn handle(&self, desired: &Desired, live: Option<&Value>) -> Result<Option<Drift>> {
+        let drift = match desired.drift(live) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        if let Some(f) = &self.on_drift {
+            f(&drift);
+        }
+        if self.correct {
+            let api = desired.api();
+            match &drift.drift {
+                DriftKind::Missing => {
+                    let data = serde_json::to_vec(&desired.object).context(ErrorKind::SerdeParse)?;
+                    self.client.request::<Value>(api.create(&PostParams::default(), data)?)?;
+                },
+                DriftKind::Changed(patch) => {
+                    let data = serde_json::to_vec(patch).context(ErrorKind::SerdeParse)?;
+                    self.client.request::<Value>(api.patch(desired.name(), &PatchParams::default(), data)?)?;
+                },
+            }
+        }
+        Ok(Some(drift))
+    }
+
+    /// Compare every tracked object against the cluster once
+    pub fn check(&self) -> Result<Vec<Drift>> {
+        let mut drifts = vec![];
+        for desired in self.desired.values() {
+            let live = match self.client.request::<Value>(desired.api().get(desired.name())?) {
+                Ok(live) => Some(live),
+                Err(ref e) if e.is_not_found() => None,
+                Err(e) => return Err(e),
+            };
+            drifts.extend(self.handle(desired, live.as_ref())?);
+        }
+        Ok(drifts)
+    }
+
+    fn watch(&self, informer: &Informer<Live>) -> Result<()> {
+        loop {
+            informer.poll()?;
+            while let Some(event) = informer.pop() {
+                let (live, deleted) = match &event {
+                    WatchEvent::Added(o) | WatchEvent::Modified(o) => (o, false),
+                    WatchEvent::Deleted(o) => (o, true),
+                    WatchEvent::Error(e) => {
+                        // events may have been missed, so compare everything again
+                        warn!("Drift watch failed: {}, resyncing", e);
+                        informer.reset()?;
+                        self.check()?;
+                        continue;
+                    },
+                };
+                let desired = self.desired.iter().find(|((_, ns, name), d)| {
+                    *name == live.metadata.name
+                        && ns.as_deref() == live.metadata.namespace.as_deref()
+                        && GroupVersionKind::from_value(&live.value).is_ok_and(|gvk| gvk == d.resource.gvk())
+                });
+                if let Some((_, desired)) = desired {
+                    self.handle(desired, if deleted { None } else { Some(&live.value) })?;
+                }
+            }
+        }
+    }
+
+    /// Check all tracked objects, then watch them until an error occurs
+    ///
+    /// Each tracked resource and namespace is watched on its own thread.
+    pub fn run(&self) -> Result<()> {
+        let mut apis: BTreeMap<String, RawApi> = BTreeMap::new();
+        for d in self.desired.values() {
+            let api = d.api();
+            apis.insert(format!("{}/{:?}", d.resource.qualified_name(), api.namespace), api);
+        }
+        let informers = apis.into_values()
+            .map(|api| Informer::raw(self.client.clone(), api).init())
+            .collect::<Result<Vec<Informer<Live>>>>()?;
+        self.check()?;
+        std::thread::scope(|s| {
+            let handles: Vec<_> = informers.iter().map(|i| s.spawn(move || self.watch(i))).collect();
+            handles.into_iter()
+                .map(|h| h.join().unwrap_or_else(|_| Err(ErrorKind::RequestValidation("drift watch panicked".into()).into())))
+                .collect::<Result<Vec<()>>>()
+                .map(|_| ())
+        })
+    }
+}
+
+#[test]
+fn drift_is_reported_and_corrected() {
+    use serde_json::json;
+    use crate::client::fake::Fake;
+    let fake = Fake::new()
+        .on("GET", "/api", 200, r#"{"versions":["v1"]}"#)
+        .on("GET", "/api/v1", 200, r#"{"resources":[
+            {"name":"configmaps","namespaced":true,"kind":"ConfigMap","verbs":["get"]}]}"#)
+        .on("GET", "/apis", 200, r#"{"groups":[]}"#)
+        .on("GET", "/api/v1/namespaces/blog/configmaps/theme", 200, r#"{"apiVersion":"v1","kind":"ConfigMap",
+            "metadata":{"name":"theme","namespace":"blog","uid":"1"},"data":{"color":"light","font":"serif"}}"#)
+        .on("POST", "*", 201, "{}")
+        .on("PATCH", "*", 200, "{}");
+    let client = fake.client();
+    let mut detector = DriftDetector::new(client).auto_correct(true);
+    for name in &["theme", "gone"] {
+        detector.desire(json!({
+            "apiVersion": "v1", "kind": "ConfigMap",
+            "metadata": { "name": name, "namespace": "blog" },
+            "data": { "color": "dark" },
+        })).unwrap();
+    }
+    let drifts = detector.check().unwrap();
+    assert_eq!(drifts[0].name, "gone");
+    assert_eq!(drifts[0].drift, DriftKind::Missing);
+    assert_eq!(drifts[1].drift, DriftKind::Changed(json!({ "data": { "color": "dark" } })));
+    let writes: Vec<_> = fake.requests().into_iter().filter(|r| r.method != "GET").collect();
+    assert_eq!(writes[0].to_string(), "POST /api/v1/namespaces/blog/configmaps");
+    assert_eq!(writes[1].to_string(), "PATCH /api/v1/namespaces/blog/configmaps/theme");
+    assert_eq!(writes[1].json(), json!({ "data": { "color": "dark" } }));
+}
+
+#[test]
+fn server_defaults_are_not_drift() {
+    use serde_json::json;
+    use crate::client::fake::Fake;
+    let fake = Fake::new()
+        .on("GET", "/api", 200, r#"{"versions":["v1"]}"#)
+        .on("GET", "/api/v1", 200, r#"{"resources":[
+            {"name":"pods","namespaced":true,"kind":"Pod","verbs":["get"]}]}"#)
+        .on("GET", "/apis", 200, r#"{"groups":[]}"#)
+        .on("GET", "/api/v1/namespaces/blog/pods/web", 200, r#"{"apiVersion":"v1","kind":"Pod",
+            "metadata":{"name":"web","namespace":"blog","uid":"1"},
+            "spec":{"containers":[
+                {"name":"sidecar","image":"envoy","imagePullPolicy":"IfNotPresent"},
+                {"name":"app","image":"nginx:1.17","imagePullPolicy":"IfNotPresent",
+                 "terminationMessagePath":"/dev/termination-log",
+                 "ports":[{"containerPort":80,"protocol":"TCP"}]}],
+             "restartPolicy":"Always"},
+            "status":{"phase":"Running"}}"#);
+    let mut detector = DriftDetector::new(fake.client());
+    let pod = |image: &str| json!({
+        "apiVersion": "v1", "kind": "Pod",
+        "metadata": { "name": "web", "namespace": "blog" },
+        "spec": { "containers": [
+            { "name": "app", "image": image, "ports": [{ "containerPort": 80 }] },
+            { "name": "sidecar", "image": "envoy" },
+        ] },
+    });
+    detector.desire(pod("nginx:1.17")).unwrap();
+    assert!(detector.check().unwrap().is_empty());
+
+    detector.desire(pod("nginx:1.18")).unwrap();
+    let drifts = detector.check().unwrap();
+    match &drifts[0].drift {
+        DriftKind::Changed(patch) => assert_eq!(patch["spec"]["containers"][0]["image"], "nginx:1.18"),
+        drift => panic!("unexpected drift {:?}", drift),
+    }
+}
@@ -0,0 +1,106 @@
+//! Ephemeral debug containers, as used by `kubectl debug`
+//!
+//! The `pods/{name}/ephemeralcontainers` subresource needs a 1.16+ apiserver with the
+//! `EphemeralContainers` feature gate. The types are defined here since the
+//! bundled openapi version predates them.
+#![allow(non_snake_case)]
+
+use crate::api::{Api, Object, ObjectMeta, TypeMeta, KubeObject, PatchParams, PostParams};
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+use k8s_openapi::api::core::v1::{Container, PodSpec, PodStatus};
+
+/// A container that is added to a running pod for debugging
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct EphemeralContainer {
+    /// Same fields as a normal container, though ports, probes and resources are disallowed
+    #[serde(flatten)]
+    pub container: Container,
+    /// Container whose process namespace to share, when the runtime supports it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub targetContainerName: Option<String>,
+}
+
+impl EphemeralContainer {
+    /// An interactive debug container running `image`
+    pub fn new(name: &str, image: &str) -> Self {
+        EphemeralContainer {
+            container: Container {
+                name: name.into(),
+                image: Some(image.into()),
+                stdin: Some(true),
+                tty: Some(true),
+                ..Default::default()
+            },
+            targetContainerName: None,
+        }
+    }
+
+    /// Share the process namespace of a container in the pod
+    pub fn target(mut self, container: &str) -> Self {
+        self.targetContainerName = Some(container.into());
+        self
+    }
+}
+
+/// The object served by the ephemeralcontainers subresource
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct EphemeralContainers {
+    #[serde(flatten)]
+    pub types: TypeMeta,
+    pub metadata: ObjectMeta,
+    #[serde(default)]
+    pub ephemeralContainers: Vec<EphemeralContainer>,
+}
+
+impl KubeObject for EphemeralContainers {
+    fn meta(&self) -> &ObjectMeta { &self.metadata }
+    fn types(&self) -> Option<&TypeMeta> { Some(&self.types) }
+}
+
+impl Api<Object<PodSpec, PodStatus>> {
+    /// Get the ephemeral containers of a pod
+    pub fn get_ephemeral_containers(&self, name: &str) -> Result<EphemeralContainers> {
+        let req = self.api.get_ephemeral_containers(name)?;
+        self.client.request::<EphemeralContainers>(req)
+    }
+
+    /// Patch the ephemeral containers of a pod
+    pub fn patch_ephemeral_containers(&self, name: &str, pp: &PatchParams, patch: Vec<u8>) -> Result<EphemeralContainers> {
+        let req = self.api.patch_ephemeral_containers(name, pp, patch)?;
+        self.client.request::<EphemeralContainers>(req)
+    }
+
+    /// Replace the ephemeral containers of a pod
+    pub fn replace_ephemeral_containers(&self, name: &str, pp: &PostParams, data: Vec<u8>) -> Result<EphemeralContainers> {
+        let req = self.api.replace_ephemeral_containers(name, pp, data)?;
+        self.client.request::<EphemeralContainers>(req)
+    }
+
+    /// Add a debug container to a running pod
+    ///
+    /// Ephemeral containers cannot be changed or removed once added, so this
+    /// fails if a container with the same name already exists.
+    pub fn add_ephemeral_container(&self, name: &str, container: EphemeralContainer) -> Result<EphemeralContainers> {
+        let mut ecs = self.get_ephemeral_containers(name)?;
+        if ecs.ephemeralContainers.iter().any(|c| c.container.name == container.container.name) {
+            return Err(ErrorKind::RequestValidation(format!(
+                "pod {} already has an ephemeral container named {}", name, container.container.name)).into());
+        }
+        ecs.ephemeralContainers.push(container);
+        let data = serde_json::to_vec(&ecs).context(ErrorKind::SerdeParse)?;
+        self.replace_ephemeral_containers(name, &PostParams::default(), data)
+    }
+}
+
+#[test]
+fn ephemeral_container_flattens_container_fields() {
+    let ec = EphemeralContainer::new("debugger", "busybox").target("app");
+    let json = serde_json::to_value(&ec).unwrap();
+    assert_eq!(json["name"], "debugger");
+    assert_eq!(json["image"], "busybox");
+    assert_eq!(json["targetContainerName"], "app");
+    let parsed: EphemeralContainer = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed.container.stdin, Some(true));
+}
@@ -0,0 +1,199 @@
+//! Following cluster events, as alerting agents do
+use std::collections::HashMap;
+
+use crate::api::{Informer, RawApi, WatchEvent, v1Event};
+use crate::client::APIClient;
+use crate::Result;
+
+/// An event passed on by an `EventWatcher`
+#[derive(Clone)]
+pub struct ObservedEvent {
+    pub event: v1Event,
+    /// How often it happened since last reported, from the growth of its `count`
+    pub occurrences: i32,
+    /// Whether it was reported before, and is only seen again
+    pub repeat: bool,
+}
+
+/// Watches Events, passing on those matching its filters once per new occurrence
+///
+/// The apiserver aggregates repeats of an event into one object, bumping its `count`
+/// and `lastTimestamp`. Those updates are reported as repeats with the number of new
+/// occurrences, while updates not adding any, like those replayed after a reconnect,
+/// are dropped. Events from before `init` are not reported.
+///
+/// ```no_run
+/// use kube::{api::EventWatcher, client::APIClient, config};
+///
+/// let config = config::load_kube_config().unwrap();
+/// let watcher = EventWatcher::new(APIClient::new(config))
+///     .warnings_only()
+///     .reason("Failed*")
+///     .reason("BackOff")
+///     .namespace("production")
+///     .init()
+///     .unwrap();
+/// loop {
+///     for e in watcher.poll().unwrap() {
+///         println!("{} {}: {}", e.event.reason, e.event.involvedObject.name.unwrap_or_default(), e.event.message);
+///     }
+/// }
+/// ```
+pub struct EventWatcher {
+    client: APIClient,
+    informer: Option<Informer<v1Event>>,
+    warnings_only: bool,
+    reasons: Vec<String>,
+    namespaces: Vec<String>,
+    repeats: bool,
+    /// last seen count by event uid
+    counts: std::sync::Mutex<HashMap<String, i32>>,
+}
+
+/// Whether `s` matches `pattern`, where `*` matches any run of characters
+fn wildcard_match(pattern: &str, s: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == s,
+        Some((prefix, rest)) => {
+            let s = match s.strip_prefix(prefix) {
+                Some(s) => s,
+                None => return false,
+            };
+            if rest.is_empty() {
+                return true;
+            }
+            s.char_indices().map(|(i, _)| i).chain(std::iter::once(s.len()))
+                .any(|i| wildcard_match(rest, &s[i..]))
+        },
+    }
+}
+
+impl EventWatcher {
+    pub fn new(client: APIClient) -> Self {
+        EventWatcher {
+            client,
+            informer: None,
+            warnings_only: false,
+            reasons: vec![],
+            namespaces: vec![],
+            repeats: true,
+            counts: Default::default(),
+        }
+    }
+
+    /// Only pass on events of type `Warning`
+    pub fn warnings_only(mut self) -> Self {
+        self.warnings_only = true;
+        self
+    }
+
+    /// Only pass on events with a reason matching one of these patterns, like `Failed*`
+    pub fn reason(mut self, pattern: &str) -> Self {
+        self.reasons.push(pattern.into());
+        self
+    }
+
+    /// Only pass on events from one of these namespaces
+    ///
+    /// Watches just that namespace when only one is given, and all otherwise.
+    pub fn namespace(mut self, ns: &str) -> Self {
+        self.namespaces.push(ns.into());
+        self
+    }
+
+    /// Drop updates of events already reported, however often they happened since
+    pub fn ignore_repeats(mut self) -> Self {
+        self.repeats = false;
+        self
+    }
+
+    /// Start watching from now
+    pub fn init(mut self) -> Result<Self> {
+        let mut api = RawApi::v1Event();
+        if let [ns] = self.namespaces.as_slice() {
+            api = api.within(ns);
+        }
+        let mut informer = Informer::raw(self.client.clone(), api);
+        if self.warnings_only {
+            informer = informer.fields("type=Warning");
+        }
+        self.informer = Some(informer.init()?);
+        Ok(self)
+    }
+
+    fn matches(&self, e: &v1Event) -> bool {
+        (!self.warnings_only || e.type_ == "Warning")
+            && (self.reasons.is_empty() || self.reasons.iter().any(|p| wildcard_match(p, &e.reason)))
+            && (self.namespaces.is_empty() || e.metadata.namespace.as_ref().is_some_and(|ns| self.namespaces.contains(ns)))
+    }
+
+    /// Watch once, returning the new occurrences of matching events
+    pub fn poll(&self) -> Result<Vec<ObservedEvent>> {
+        let informer = match &self.informer {
+            Some(i) => i,
+            None => return Ok(vec![]),
+        };
+        informer.poll()?;
+        let mut observed = vec![];
+        let mut counts = self.counts.lock().unwrap();
+        while let Some(event) = informer.pop() {
+            let e = match event {
+                WatchEvent::Added(e) | WatchEvent::Modified(e) => e,
+                WatchEvent::Deleted(e) => {
+                    // expired, so its uid will not come back
+                    counts.remove(e.metadata.uid.as_deref().unwrap_or_default());
+                    continue;
+                },
+                WatchEvent::Error(e) => {
+                    warn!("Event watch failed: {}, resuming from now", e);
+                    informer.reset()?;
+                    continue;
+                },
+            };
+            if !self.matches(&e) {
+                continue;
+            }
+            let count = e.count.max(1);
+            let key = e.metadata.uid.clone().unwrap_or_else(|| e.metadata.name.clone());
+            let previous = counts.insert(key, count);
+            let occurrences = count - previous.unwrap_or(0);
+            if occurrences <= 0 || (previous.is_some() && !self.repeats) {
+                continue;
+            }
+            observed.push(ObservedEvent { event: e, occurrences, repeat: previous.is_some() });
+        }
+        Ok(observed)
+    }
+}
+
+#[test]
+fn events_are_filtered_and_deduplicated() {
+    use crate::client::fake::Fake;
+    let event = |uid: &str, ns: &str, type_: &str, reason: &str, count: i32| format!(
+        r#"{{"type":"MODIFIED","object":{{"metadata":{{"name":"{uid}","namespace":"{ns}","uid":"{uid}"}},
+        "involvedObject":{{"name":"web"}},"type":"{type_}","reason":"{reason}","count":{count}}}}}"#,
+        uid = uid, ns = ns, type_ = type_, reason = reason, count = count,
+    ).replace('\n', "");
+    let fake = Fake::new()
+        .on("GET", "/api/v1/namespaces/prod/events?watch=true", 200, [
+            event("a", "prod", "Warning", "FailedMount", 1),
+            event("b", "prod", "Normal", "Scheduled", 1),
+            event("a", "prod", "Warning", "FailedMount", 3),
+            event("a", "prod", "Warning", "FailedMount", 3),
+            event("c", "prod", "Warning", "BackOff", 2),
+            event("d", "dev", "Warning", "FailedScheduling", 1),
+        ].join("\n"))
+        .on("GET", "/api/v1/namespaces/prod/events", 200, r#"{"metadata":{"resourceVersion":"10"},"items":[]}"#);
+    let client = fake.client();
+    let watcher = EventWatcher::new(client).warnings_only().reason("Failed*").namespace("prod").init().unwrap();
+    let observed: Vec<_> = watcher.poll().unwrap().into_iter()
+        .map(|o| (o.event.metadata.name, o.occurrences, o.repeat))
+        .collect();
+    assert_eq!(observed, vec![("a".to_string(), 1, false), ("a".to_string(), 2, true)]);
+    let watch = fake.requests().into_iter().find(|r| r.query().contains("watch=true")).unwrap();
+    assert!(watch.query().contains("fieldSelector=type%3DWarning"));
+
+    assert!(wildcard_match("*Failed*", "ImagePullFailedAgain"));
+    assert!(wildcard_match("Back*ff", "BackOff"));
+    assert!(!wildcard_match("Failed", "FailedMount"));
+}
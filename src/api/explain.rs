@@ -0,0 +1,233 @@
+//! Documentation of fields from their schemas, like `kubectl explain`
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::api::{discover, resolve, GroupVersion, GroupVersionKind, OpenApiV3, SchemaValidator};
+use crate::{ErrorKind, Result};
+
+/// A field, with its type, documentation and the fields within it
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldDoc {
+    pub name: String,
+    /// As kubectl names it, like `string`, `[]Container` or `map[string]string`
+    pub type_name: String,
+    pub description: String,
+    pub required: bool,
+    /// Its fields, or those of its items for arrays and maps
+    pub fields: Vec<FieldDoc>,
+}
+
+/// The documentation of a field of a kind
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    pub gvk: GroupVersionKind,
+    /// The path to the field, empty for the kind itself
+    pub path: Vec<String>,
+    pub field: FieldDoc,
+}
+
+/// As kubectl prints it, with nested fields indented when explained recursively
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "KIND:     {}", self.gvk.kind)?;
+        writeln!(f, "VERSION:  {}", self.gvk.api_version())?;
+        if let Some(name) = self.path.last() {
+            writeln!(f, "\nFIELD:    {} <{}>", name, self.field.type_name)?;
+        }
+        writeln!(f, "\nDESCRIPTION:")?;
+        for line in self.field.description.lines() {
+            writeln!(f, "     {}", line)?;
+        }
+        if self.field.fields.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "\nFIELDS:")?;
+        let recursive = self.field.fields.iter().any(|c| !c.fields.is_empty());
+        fn write_fields(f: &mut fmt::Formatter, fields: &[FieldDoc], depth: usize, recursive: bool) -> fmt::Result {
+            for c in fields {
+                let required = if c.required { " -required-" } else { "" };
+                writeln!(f, "{:indent$}{}\t<{}>{}", "", c.name, c.type_name, required, indent = 3 + depth * 3)?;
+                if recursive {
+                    write_fields(f, &c.fields, depth + 1, recursive)?;
+                } else {
+                    for line in c.description.lines() {
+                        writeln!(f, "     {}", line)?;
+                    }
+                    writeln!(f)?;
+                }
+            }
+            Ok(())
+        }
+        write_fields(f, &self.field.fields, 0, recursive)
+    }
+}
+
+/// The last part of a `$ref`'d definition name, like `DeploymentSpec`
+fn ref_type(schema: &Value) -> Option<&str> {
+    let r = schema["$ref"].as_str()
+        .or_else(|| schema["allOf"].as_array().and_then(|a| a.first()).and_then(|s| s["$ref"].as_str()))?;
+    r.rsplit(['/', '.']).next()
+}
+
+/// The type whose fields are within a schema, through arrays and maps
+fn inner_ref_type(schema: &Value) -> Option<&str> {
+    ref_type(schema)
+        .or_else(|| Some(&schema["items"]).filter(|s| s.is_object()).and_then(inner_ref_type))
+        .or_else(|| Some(&schema["additionalProperties"]).filter(|s| s.is_object()).and_then(inner_ref_type))
+}
+
+impl SchemaValidator {
+    fn type_name(&self, schema: &Value) -> String {
+        if let Some(name) = ref_type(schema) {
+            return name.to_string();
+        }
+        if schema["x-kubernetes-int-or-string"] == true {
+            return "IntOrString".into();
+        }
+        match schema["type"].as_str() {
+            Some("array") => format!("[]{}", self.type_name(&schema["items"])),
+            Some("object") | None if schema["additionalProperties"].is_object() => {
+                format!("map[string]{}", self.type_name(&schema["additionalProperties"]))
+            },
+            Some("object") | None => "Object".into(),
+            Some(t) => t.into(),
+        }
+    }
+
+    /// The schema whose properties are the fields within `schema`
+    fn fields_of<'a>(&'a self, schema: &'a Value) -> &'a Value {
+        let schema = self.resolve(schema);
+        match schema["type"].as_str() {
+            Some("array") => self.fields_of(&schema["items"]),
+            _ if schema["additionalProperties"].is_object() => self.fields_of(&schema["additionalProperties"]),
+            _ => schema,
+        }
+    }
+
+    /// Document `name` with `schema`, descending `depth` more levels while not recursing into a type again
+    fn document(&self, name: &str, schema: &Value, required: bool, depth: usize, seen: &mut Vec<String>) -> FieldDoc {
+        let resolved = self.resolve(schema);
+        // descriptions next to references win, as they are about this use of the type
+        let description = schema["description"].as_str().or_else(|| resolved["description"].as_str()).unwrap_or("");
+        let mut doc = FieldDoc {
+            name: name.into(),
+            type_name: self.type_name(schema),
+            description: description.into(),
+            required,
+            fields: vec![],
+        };
+        let inner = self.fields_of(schema);
+        let type_name = inner_ref_type(schema).map(String::from);
+        if depth == 0 || type_name.as_ref().is_some_and(|t| seen.contains(t)) {
+            return doc;
+        }
+        seen.extend(type_name.clone());
+        let required: Vec<&str> = inner["required"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+        for (field, s) in inner["properties"].as_object().into_iter().flatten() {
+            doc.fields.push(self.document(field, s, required.contains(&field.as_str()), depth - 1, seen));
+        }
+        if type_name.is_some() {
+            seen.pop();
+        }
+        doc
+    }
+
+    /// Document a field of a kind, like `spec.strategy` of `apps/v1 Deployment`
+    ///
+    /// An empty path documents the kind itself. Lists its fields, with all fields
+    /// below them when `recursive`.
+    pub fn explain(&self, gvk: &GroupVersionKind, path: &str, recursive: bool) -> Result<Explanation> {
+        let mut schema = self.schema_for(gvk).ok_or_else(|| ErrorKind::RequestValidation(format!("no schema for {}", gvk)))?;
+        let path: Vec<String> = path.split('.').filter(|p| !p.is_empty()).map(String::from).collect();
+        let mut required = false;
+        for (i, field) in path.iter().enumerate() {
+            let inner = self.fields_of(schema);
+            schema = inner["properties"].get(field.as_str()).ok_or_else(|| ErrorKind::RequestValidation(
+                format!("field {:?} does not exist in {}", path[..=i].join("."), gvk.kind)))?;
+            required = inner["required"].as_array().is_some_and(|r| r.iter().any(|f| f == field.as_str()));
+        }
+        let name = path.last().cloned().unwrap_or_else(|| gvk.kind.clone());
+        let depth = if recursive { usize::MAX } else { 1 };
+        let field = self.document(&name, schema, required, depth, &mut vec![]);
+        Ok(Explanation { gvk: gvk.clone(), path, field })
+    }
+}
+
+impl OpenApiV3 {
+    /// Document a resource or a field of it, like `kubectl explain deployment.spec.strategy`
+    ///
+    /// The resource may be named as kubectl takes it, e.g. `deploy` or `Deployment`,
+    /// and is looked up with `discover`. Its schemas are fetched, or read from the
+    /// cache directory.
+    pub fn explain(&self, target: &str, recursive: bool) -> Result<Explanation> {
+        let (resource, path) = match target.find('.') {
+            Some(i) => (&target[..i], &target[i + 1..]),
+            None => (target, ""),
+        };
+        let resources = discover(self.client())?;
+        let found = resolve(&resources, resource)?;
+        let gvk = match found.first() {
+            Some(r) => r.gvk(),
+            None => return Err(ErrorKind::RequestValidation(format!("the server does not have a resource {:?}", resource)).into()),
+        };
+        let validator = self.validator(&[GroupVersion::new(&gvk.group, &gvk.version)])?;
+        validator.explain(&gvk, path, recursive)
+    }
+}
+
+#[test]
+fn fields_are_explained_from_schemas() {
+    use serde_json::json;
+    let doc = json!({ "components": { "schemas": {
+        "io.k8s.api.apps.v1.Deployment": {
+            "description": "Deployment enables declarative updates for Pods and ReplicaSets.",
+            "x-kubernetes-group-version-kind": [{ "group": "apps", "version": "v1", "kind": "Deployment" }],
+            "properties": {
+                "apiVersion": { "type": "string", "description": "APIVersion defines the versioned schema." },
+                "spec": { "allOf": [{ "$ref": "#/components/schemas/io.k8s.api.apps.v1.DeploymentSpec" }],
+                    "description": "Specification of the desired behavior of the Deployment." },
+            },
+        },
+        "io.k8s.api.apps.v1.DeploymentSpec": {
+            "required": ["selector"],
+            "properties": {
+                "selector": { "allOf": [{ "$ref": "#/components/schemas/io.k8s.apimachinery.pkg.apis.meta.v1.LabelSelector" }] },
+                "strategy": { "allOf": [{ "$ref": "#/components/schemas/io.k8s.api.apps.v1.DeploymentStrategy" }],
+                    "description": "The deployment strategy to use to replace existing pods with new ones." },
+            },
+        },
+        "io.k8s.api.apps.v1.DeploymentStrategy": {
+            "properties": {
+                "rollingUpdate": { "properties": { "maxSurge": { "x-kubernetes-int-or-string": true } } },
+                "type": { "type": "string", "description": "Type of deployment.\nCan be \"Recreate\" or \"RollingUpdate\"." },
+            },
+        },
+        "io.k8s.apimachinery.pkg.apis.meta.v1.LabelSelector": {
+            "properties": {
+                "matchLabels": { "type": "object", "additionalProperties": { "type": "string" } },
+                "matchExpressions": { "type": "array", "items": { "allOf": [{ "$ref": "#/components/schemas/io.k8s.apimachinery.pkg.apis.meta.v1.LabelSelector" }] } },
+            },
+        },
+    }}});
+    let validator = SchemaValidator::from_openapi_v3(&[doc]);
+    let gvk = GroupVersionKind::new("apps", "v1", "Deployment");
+
+    let strategy = validator.explain(&gvk, "spec.strategy", false).unwrap();
+    assert_eq!(strategy.field.type_name, "DeploymentStrategy");
+    assert_eq!(strategy.to_string(), "KIND:     Deployment\nVERSION:  apps/v1\n\
+        \nFIELD:    strategy <DeploymentStrategy>\n\
+        \nDESCRIPTION:\n     The deployment strategy to use to replace existing pods with new ones.\n\
+        \nFIELDS:\n   rollingUpdate\t<Object>\n\n   type\t<string>\n     Type of deployment.\n     Can be \"Recreate\" or \"RollingUpdate\".\n\n");
+
+    let spec = validator.explain(&gvk, "spec", true).unwrap();
+    let selector = &spec.field.fields[0];
+    assert!(selector.required);
+    let types: Vec<_> = selector.fields.iter().map(|f| (f.name.as_str(), f.type_name.as_str(), f.fields.len())).collect();
+    assert_eq!(types, vec![("matchExpressions", "[]LabelSelector", 0), ("matchLabels", "map[string]string", 0)],
+        "recursive types are not expanded again");
+    assert!(spec.to_string().contains("   strategy\t<DeploymentStrategy>\n      rollingUpdate\t<Object>\n         maxSurge\t<IntOrString>\n"));
+    assert_eq!(validator.explain(&gvk, "", false).unwrap().field.fields.len(), 2);
+    let err = validator.explain(&gvk, "spec.strategy.kind", false).unwrap_err();
+    assert!(err.to_string().contains("\"spec.strategy.kind\" does not exist in Deployment"), "{}", err);
+}
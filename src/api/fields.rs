@@ -0,0 +1,134 @@
+//! Checking field selectors before the apiserver rejects them
+use crate::api::{GroupVersionResource, ListParams, RawApi};
+use crate::client::APIClient;
+use crate::{Error, ErrorKind, Result};
+
+/// Fields every resource can be selected by
+const COMMON_FIELDS: &[&str] = &["metadata.name", "metadata.namespace"];
+
+/// Fields beyond the common ones the apiserver supports, by group and resource
+const SUPPORTED_FIELDS: &[(&str, &str, &[&str])] = &[
+    ("", "pods", &[
+        "spec.nodeName", "spec.restartPolicy", "spec.schedulerName", "spec.serviceAccountName",
+        "spec.hostNetwork", "status.phase", "status.podIP", "status.nominatedNodeName",
+    ]),
+    ("", "events", &[
+        "involvedObject.kind", "involvedObject.namespace", "involvedObject.name", "involvedObject.uid",
+        "involvedObject.apiVersion", "involvedObject.resourceVersion", "involvedObject.fieldPath",
+        "reason", "reportingComponent", "source", "type",
+    ]),
+    ("", "secrets", &["type"]),
+    ("", "namespaces", &["status.phase"]),
+    ("", "nodes", &["spec.unschedulable"]),
+    ("", "replicationcontrollers", &["status.replicas"]),
+    ("apps", "replicasets", &["status.replicas"]),
+    ("batch", "jobs", &["status.successful"]),
+    ("certificates.k8s.io", "certificatesigningrequests", &["spec.signerName"]),
+];
+
+/// The fields a resource is known to support in field selectors
+///
+/// Custom resources may have more through `selectableFields` on 1.30+ apiservers;
+/// use `check_field_selector` to ask the apiserver about those.
+pub fn supported_fields(gvr: &GroupVersionResource) -> Vec<&'static str> {
+    let extra = SUPPORTED_FIELDS.iter()
+        .find(|(group, resource, _)| *group == gvr.group && *resource == gvr.resource)
+        .map(|(_, _, fields)| *fields)
+        .unwrap_or(&[]);
+    COMMON_FIELDS.iter().chain(extra).cloned().collect()
+}
+
+/// Split a selector into the fields it selects on, honouring `\` escapes in values
+fn selected_fields(selector: &str) -> Result<Vec<String>> {
+    let invalid = |term: &str| Error::from(ErrorKind::RequestValidation(format!(
+        "invalid field selector term {:?}, expected field=value, field==value or field!=value", term)));
+    let mut terms = vec![String::new()];
+    let mut chars = selector.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let last = terms.last_mut().unwrap();
+                last.push(c);
+                last.extend(chars.next());
+            },
+            ',' => terms.push(String::new()),
+            _ => terms.last_mut().unwrap().push(c),
+        }
+    }
+    terms.iter()
+        .filter(|t| !t.trim().is_empty())
+        .map(|term| {
+            let field = term.find(['=', '!']).map(|i| &term[..i]).ok_or_else(|| invalid(term))?;
+            let op = &term[field.len()..];
+            if !(op.starts_with("!=") || op.starts_with('=')) || field.trim().is_empty() {
+                return Err(invalid(term));
+            }
+            Ok(field.trim().to_string())
+        })
+        .collect()
+}
+
+/// Check a field selector against the fields `gvr` is known to support
+///
+/// Unsupported fields make the apiserver fail the list or watch with a `400`,
+/// or, through some proxies, answer with nothing at all; this says which field it is.
+pub fn validate_field_selector(gvr: &GroupVersionResource, selector: &str) -> Result<()> {
+    let supported = supported_fields(gvr);
+    for field in selected_fields(selector)? {
+        if !supported.contains(&field.as_str()) {
+            return Err(ErrorKind::RequestValidation(format!(
+                "field selector {:?} is not supported for {}, only {}", field, gvr.resource, supported.join(", "))).into());
+        }
+    }
+    Ok(())
+}
+
+/// Check a field selector, asking the apiserver about fields not known to be supported
+///
+/// Known fields are accepted without a request. Otherwise a list of at most one
+/// object is made, and the apiserver's rejection turned into a validation error.
+pub fn check_field_selector(client: &APIClient, api: &RawApi, selector: &str) -> Result<()> {
+    // malformed selectors are rejected without asking
+    selected_fields(selector)?;
+    let gvr = GroupVersionResource::new(&api.group, &api.version, &api.resource);
+    let known = match validate_field_selector(&gvr, selector) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+    let lp = ListParams { field_selector: Some(selector.into()), limit: Some(1), ..Default::default() };
+    match client.request::<serde_json::Value>(api.list(&lp)?) {
+        Ok(_) => Ok(()),
+        Err(e) if e.status_code() == Some(400) => {
+            let message = match e.api_error() {
+                Some(ae) => ae.message.clone(),
+                None => known.to_string(),
+            };
+            Err(ErrorKind::RequestValidation(message).into())
+        },
+        Err(e) => Err(e),
+    }
+}
+
+#[test]
+fn field_selectors_are_checked() {
+    use crate::client::fake::Fake;
+    let pods = GroupVersionResource::new("", "v1", "pods");
+    assert!(validate_field_selector(&pods, "status.phase=Running,spec.nodeName!=node-1").is_ok());
+    assert!(validate_field_selector(&pods, "metadata.name==blog").is_ok());
+    assert!(validate_field_selector(&pods, "").is_ok());
+    let err = validate_field_selector(&pods, "status.podIP=10.0.0.1,spec.priority=1").unwrap_err();
+    assert!(err.to_string().contains("\"spec.priority\" is not supported for pods"), "{}", err);
+    assert!(validate_field_selector(&pods, "status.phase").is_err(), "a value is needed");
+    assert_eq!(selected_fields(r"metadata.name=a\,b,type=x").unwrap(), vec!["metadata.name", "type"]);
+
+    let client = Fake::new()
+        .on("GET", "/apis/example.com/v1/foos?spec.color", 200, r#"{"items":[]}"#)
+        .on("GET", "/apis/example.com/v1/foos", 400, r#"{"status":"Failure","code":400,"reason":"BadRequest",
+            "message":"field label not supported: spec.size"}"#)
+        .client();
+    let foos = RawApi::customResource("foos").group("example.com");
+    assert!(check_field_selector(&client, &foos, "metadata.name=a").is_ok());
+    assert!(check_field_selector(&client, &foos, "spec.color=blue").is_ok(), "selectable fields of crds");
+    let err = check_field_selector(&client, &foos, "spec.size=large").unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::RequestValidation(m) if m.contains("spec.size")));
+}
@@ -0,0 +1,300 @@
+//! Running one informer against every cluster of a `ClusterSet`
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc, Mutex, RwLock, Weak};
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use crate::api::{Backoff, Informer, RawApi};
+use crate::api::resource::{KubeObject, WatchEvent};
+use crate::client::ClusterSet;
+use crate::{Error, Result};
+
+/// How long a cluster that failed to initialize waits before trying again
+const INIT_RETRY: Duration = Duration::from_secs(10);
+
+/// How long an initialized cluster whose polls keep failing waits between them
+const POLL_BACKOFF: Backoff = Backoff {
+    steps: usize::MAX,
+    duration: Duration::from_millis(100),
+    factor: 2.0,
+    cap: Some(INIT_RETRY),
+};
+
+/// A watch event, tagged with the cluster it happened in
+#[derive(Debug, Clone)]
+pub struct ClusterEvent<K> where
+    K: Clone + KubeObject
+{
+    pub cluster: String,
+    pub event: WatchEvent<K>,
+}
+
+/// What the thread polling a cluster reports
+enum Report<K> where
+    K: Clone + KubeObject
+{
+    Event(ClusterEvent<K>),
+    Failed(String, Error),
+}
+
+/// The same informer on several clusters, merged into one event queue
+///
+/// Every cluster is polled on its own thread, so a slow or unreachable cluster
+/// does not hold up the events of the others. Clusters that fail to initialize
+/// are reported, and tried again while the others are already watched; failing
+/// polls are retried with a growing backoff. A cluster is reported once for every
+/// run of failures.
+/// Clients are taken from the `ClusterSet` before every poll, so clients rebuilt
+/// by `ClusterSet::refresh` are picked up.
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::time::Duration;
+/// use kube::{api::{FleetInformer, RawApi, Object}, client::ClusterSet};
+/// use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+///
+/// let clusters = Arc::new(ClusterSet::from_contexts().unwrap());
+/// let pods: FleetInformer<Object<PodSpec, PodStatus>> = FleetInformer::new(clusters, RawApi::v1Pod()).init();
+/// loop {
+///     for (cluster, e) in pods.poll(Duration::from_secs(1)) {
+///         println!("cluster {} failed: {}", cluster, e);
+///     }
+///     while let Some(ev) = pods.pop() {
+///         println!("{}: {:?}", ev.cluster, ev.event);
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct FleetInformer<K> where
+    K: Clone + DeserializeOwned + KubeObject
+{
+    clusters: Arc<ClusterSet>,
+    informers: Vec<(String, Informer<K>)>,
+    events: Arc<RwLock<VecDeque<ClusterEvent<K>>>>,
+    reports: Arc<Mutex<Option<mpsc::Receiver<Report<K>>>>>,
+    /// Dropped with the last clone, which stops the polling threads
+    alive: Arc<()>,
+}
+
+impl<K> FleetInformer<K> where
+    K: Clone + DeserializeOwned + KubeObject + Send + Sync + 'static,
+{
+    /// Create an informer on `r` for every cluster in the set
+    ///
+    /// Clients are only built once `init` starts polling.
+    pub fn new(clusters: Arc<ClusterSet>, r: RawApi) -> Self {
+        // the clients are replaced with the ones from the set before every call
+        let placeholder = crate::client::APIClient::with_transport("", Unbuilt);
+        let informers = clusters.clusters().into_iter()
+            .map(|name| (name, Informer::raw(placeholder.clone(), r.clone())))
+            .collect();
+        FleetInformer {
+            clusters,
+            informers,
+            events: Arc::new(RwLock::new(VecDeque::new())),
+            reports: Arc::new(Mutex::new(None)),
+            alive: Arc::new(()),
+        }
+    }
+
+    fn map<F: Fn(Informer<K>) -> Informer<K>>(mut self, f: F) -> Self {
+        self.informers = self.informers.into_iter().map(|(c, i)| (c, f(i))).collect();
+        self
+    }
+
+    /// Configure the timeout for the list/watch call on every cluster
+    pub fn timeout(self, timeout_secs: u32) -> Self {
+        self.map(|i| i.timeout(timeout_secs))
+    }
+
+    /// Configure the field selector on every cluster
+    pub fn fields(self, field_selector: &str) -> Self {
+        self.map(|i| i.fields(field_selector))
+    }
+
+    /// Configure the label selector on every cluster
+    pub fn labels(self, label_selector: &str) -> Self {
+        self.map(|i| i.labels(label_selector))
+    }
+
+    /// Start initializing and polling every cluster on its own thread, until all clones are dropped
+    pub fn init(self) -> Self {
+        let (tx, rx) = mpsc::channel();
+        for (cluster, informer) in &self.informers {
+            let (cluster, informer, tx) = (cluster.clone(), informer.clone(), tx.clone());
+            let (clusters, alive) = (self.clusters.clone(), Arc::downgrade(&self.alive));
+            std::thread::spawn(move || watch_cluster(&cluster, informer, &clusters, alive, tx));
+        }
+        *self.reports.lock().unwrap() = Some(rx);
+        self
+    }
+
+    /// Queue the events of all clusters, waiting up to `timeout` for the first ones
+    ///
+    /// Returns the clusters that started failing since the last poll; they are retried on their own.
+    pub fn poll(&self, timeout: Duration) -> Vec<(String, Error)> {
+        let reports = self.reports.lock().unwrap();
+        let rx = match reports.as_ref() {
+            Some(rx) => rx,
+            None => {
+                warn!("FleetInformer polled before init");
+                return vec![];
+            },
+        };
+        let mut failed = vec![];
+        let first = rx.recv_timeout(timeout).ok();
+        for report in first.into_iter().chain(rx.try_iter()) {
+            match report {
+                Report::Event(event) => self.events.write().unwrap().push_back(event),
+                Report::Failed(cluster, e) => failed.push((cluster, e)),
+            }
+        }
+        failed
+    }
+
+    /// Pop an event from the front of the merged queue
+    pub fn pop(&self) -> Option<ClusterEvent<K>> {
+        self.events.write().unwrap().pop_front()
+    }
+
+    /// The informer for a single cluster, to check its version and sync state
+    ///
+    /// It is polled by the fleet; its events are only found through `pop`.
+    pub fn informer(&self, cluster: &str) -> Option<&Informer<K>> {
+        self.informers.iter().find(|(c, _)| c == cluster).map(|(_, i)| i)
+    }
+
+    /// Names of the clusters being watched
+    pub fn clusters(&self) -> Vec<&str> {
+        self.informers.iter().map(|(c, _)| c.as_str()).collect()
+    }
+}
+
+/// Initialize and poll the informer of one cluster, reporting its events and failures
+fn watch_cluster<K>(
+    cluster: &str,
+    informer: Informer<K>,
+    clusters: &ClusterSet,
+    alive: Weak<()>,
+    tx: mpsc::Sender<Report<K>>,
+) where
+    K: Clone + DeserializeOwned + KubeObject,
+{
+    // failures in a row; only the first of them is reported
+    let mut failures = 0;
+    let fail = |e: Error, failures: usize| {
+        if failures > 1 {
+            debug!("Cluster {} is still failing: {}", cluster, e);
+            return true;
+        }
+        warn!("Cluster {} failed: {}", cluster, e);
+        tx.send(Report::Failed(cluster.into(), e)).is_ok()
+    };
+    while alive.upgrade().is_some() {
+        // taken from the set every time, to pick up clients it rebuilt
+        let ready = clusters.client(cluster).and_then(|client| {
+            let inf = informer.clone().with_client(client);
+            if inf.has_synced() { Ok(inf) } else { inf.init() }
+        });
+        let inf = match ready {
+            Ok(inf) => inf,
+            Err(e) => {
+                failures += 1;
+                if !fail(e, failures) {
+                    return;
+                }
+                std::thread::sleep(INIT_RETRY);
+                continue;
+            },
+        };
+        match inf.poll() {
+            Ok(()) => failures = 0,
+            Err(e) => {
+                failures += 1;
+                if !fail(e, failures) {
+                    return;
+                }
+                std::thread::sleep(POLL_BACKOFF.wait(failures));
+            },
+        }
+        while let Some(event) = inf.pop() {
+            if tx.send(Report::Event(ClusterEvent { cluster: cluster.into(), event })).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The transport of informers that have not been given a client from the set yet
+struct Unbuilt;
+
+impl crate::client::Transport for Unbuilt {
+    fn execute(&self, _: http::Request<Vec<u8>>) -> Result<http::Response<crate::client::Body>> {
+        Err(crate::ErrorKind::RequestSend("a cluster without a client".into()).into())
+    }
+}
+
+#[test]
+fn clusters_are_polled_independently() {
+    use crate::api::Void;
+    use crate::client::fake::{respond, Fake};
+    use std::time::Instant;
+    // answers lists with a resourceVersion and every watch with an added object, after `delay`
+    let cluster = |delay| Fake::new().handle("GET", "*", move |req| {
+        std::thread::sleep(delay);
+        respond(200, if req.query().contains("watch=true") {
+            r#"{"type":"ADDED","object":{"metadata":{"name":"a","resourceVersion":"2"},"spec":{}}}"#
+        } else {
+            r#"{"metadata":{"resourceVersion":"1"},"items":[]}"#
+        })
+    }).client();
+    let mut clusters = ClusterSet::new(None);
+    clusters.add_client("fast", cluster(Duration::from_millis(0)));
+    clusters.add_client("slow", cluster(Duration::from_secs(30)));
+    clusters.add_client("broken", Fake::new().unreachable("*", "*").client());
+    let started = Instant::now();
+    let fleet: FleetInformer<crate::api::Object<Void, Void>> =
+        FleetInformer::new(Arc::new(clusters), RawApi::v1Pod()).init();
+
+    let (mut events, mut failed) = (vec![], vec![]);
+    while (events.is_empty() || failed.is_empty()) && started.elapsed() < Duration::from_secs(5) {
+        failed.extend(fleet.poll(Duration::from_millis(100)).into_iter().map(|(c, _)| c));
+        while let Some(ev) = fleet.pop() {
+            events.push(ev.cluster);
+        }
+    }
+    // neither the slow nor the broken cluster holds up the events of the fast one
+    assert_eq!(events.first().map(String::as_str), Some("fast"));
+    assert!(events.iter().all(|c| c == "fast"));
+    assert_eq!(failed, vec!["broken"]);
+    assert!(fleet.informer("fast").unwrap().has_synced());
+    assert!(!fleet.informer("broken").unwrap().has_synced());
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn failing_polls_back_off_and_are_reported_once() {
+    use crate::api::Void;
+    use crate::client::fake::{status, Fake};
+    // watches are throttled without a wait, and relists fail after the first
+    let fake = Fake::new()
+        .handle("GET", "/api/v1/pods?watch=true", |_| http::Response::builder()
+            .status(429)
+            .header("Retry-After", "0")
+            .body(Box::new(std::io::Cursor::new(status(429, "TooManyRequests"))) as crate::client::Body)
+            .unwrap())
+        .once("GET", "/api/v1/pods", 200, r#"{"metadata":{"resourceVersion":"1"},"items":[]}"#)
+        .unreachable("GET", "/api/v1/pods");
+    let mut clusters = ClusterSet::new(None);
+    clusters.add_client("flaky", fake.client());
+    let fleet: FleetInformer<crate::api::Object<Void, Void>> =
+        FleetInformer::new(Arc::new(clusters), RawApi::v1Pod()).init();
+    let mut failed = vec![];
+    for _ in 0..10 {
+        failed.extend(fleet.poll(Duration::from_millis(100)).into_iter().map(|(c, _)| c));
+    }
+    assert_eq!(failed, vec!["flaky"]);
+    let relists = fake.requests().iter().filter(|r| !r.query().contains("watch=true")).count();
+    assert!((3..10).contains(&relists), "{} lists", relists);
+}
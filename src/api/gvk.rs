@@ -0,0 +1,224 @@
+//! Group, version, kind and resource names, as found in `apiVersion` fields and urls
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::api::{RawApi, Resource, TypeMeta};
+use crate::{Error, ErrorKind, Result};
+
+fn invalid(what: &str, value: &str) -> Error {
+    Error::from(ErrorKind::RequestValidation(format!("invalid {} {:?}", what, value)))
+}
+
+/// An API group at a version, written as the `apiVersion` of objects: `apps/v1`, or `v1` for core
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GroupVersion {
+    /// Empty for the core group
+    pub group: String,
+    pub version: String,
+}
+
+impl GroupVersion {
+    pub fn new(group: &str, version: &str) -> Self {
+        GroupVersion { group: group.into(), version: version.into() }
+    }
+
+    pub fn with_kind(&self, kind: &str) -> GroupVersionKind {
+        GroupVersionKind { group: self.group.clone(), version: self.version.clone(), kind: kind.into() }
+    }
+
+    pub fn with_resource(&self, resource: &str) -> GroupVersionResource {
+        GroupVersionResource { group: self.group.clone(), version: self.version.clone(), resource: resource.into() }
+    }
+
+    /// The `apiVersion` of objects in it
+    pub fn api_version(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for GroupVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.group.is_empty() {
+            f.write_str(&self.version)
+        } else {
+            write!(f, "{}/{}", self.group, self.version)
+        }
+    }
+}
+
+impl FromStr for GroupVersion {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (group, version) = match s.split_once('/') {
+            Some((g, v)) => (g, v),
+            None => ("", s),
+        };
+        if version.is_empty() || version.contains('/') || (s.contains('/') && group.is_empty()) {
+            return Err(invalid("apiVersion", s));
+        }
+        Ok(GroupVersion::new(group, version))
+    }
+}
+
+/// The type of an object, as named by its `apiVersion` and `kind`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GroupVersionKind {
+    /// Empty for the core group
+    pub group: String,
+    pub version: String,
+    pub kind: String,
+}
+
+impl GroupVersionKind {
+    pub fn new(group: &str, version: &str, kind: &str) -> Self {
+        GroupVersionKind { group: group.into(), version: version.into(), kind: kind.into() }
+    }
+
+    /// From an `apiVersion` like `apps/v1` and a kind
+    pub fn from_api_version(api_version: &str, kind: &str) -> Result<Self> {
+        Ok(api_version.parse::<GroupVersion>()?.with_kind(kind))
+    }
+
+    /// The type of a `Resource`
+    pub fn of<K: Resource>() -> Self {
+        GroupVersionKind::new(K::GROUP, K::VERSION, K::KIND)
+    }
+
+    /// The type of a dynamic object, from its `apiVersion` and `kind` fields
+    pub fn from_value(obj: &Value) -> Result<Self> {
+        match (obj["apiVersion"].as_str(), obj["kind"].as_str()) {
+            (Some(av), Some(kind)) if !kind.is_empty() => Self::from_api_version(av, kind),
+            _ => Err(Error::from(ErrorKind::RequestValidation("object without apiVersion and kind".into()))),
+        }
+    }
+
+    pub fn group_version(&self) -> GroupVersion {
+        GroupVersion::new(&self.group, &self.version)
+    }
+
+    pub fn api_version(&self) -> String {
+        self.group_version().to_string()
+    }
+
+    pub fn type_meta(&self) -> TypeMeta {
+        TypeMeta { apiVersion: Some(self.api_version()), kind: Some(self.kind.clone()) }
+    }
+
+    /// Set the `apiVersion` and `kind` of a dynamic object
+    ///
+    /// Needed for items of lists, which the apiserver sends without them.
+    pub fn apply_to(&self, obj: &mut Value) {
+        if let Some(o) = obj.as_object_mut() {
+            o.insert("apiVersion".into(), self.api_version().into());
+            o.insert("kind".into(), self.kind.clone().into());
+        }
+    }
+}
+
+/// As client-go formats it: `apps/v1, Kind=Deployment`
+impl fmt::Display for GroupVersionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, Kind={}", self.group_version(), self.kind)
+    }
+}
+
+/// A resource as addressed in urls, like `deployments` in `apps/v1`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GroupVersionResource {
+    /// Empty for the core group
+    pub group: String,
+    pub version: String,
+    /// The plural name, e.g. `deployments`
+    pub resource: String,
+}
+
+impl GroupVersionResource {
+    pub fn new(group: &str, version: &str, resource: &str) -> Self {
+        GroupVersionResource { group: group.into(), version: version.into(), resource: resource.into() }
+    }
+
+    /// The urls of a `Resource`
+    pub fn of<K: Resource>() -> Self {
+        GroupVersionResource::new(K::GROUP, K::VERSION, K::PLURAL)
+    }
+
+    pub fn group_version(&self) -> GroupVersion {
+        GroupVersion::new(&self.group, &self.version)
+    }
+
+    /// Url data for requests on this resource, across all namespaces
+    pub fn raw_api(&self) -> RawApi {
+        RawApi {
+            resource: self.resource.clone(),
+            group: self.group.clone(),
+            namespace: None,
+            version: self.version.clone(),
+            prefix: if self.group.is_empty() { "api".into() } else { "apis".into() },
+        }
+    }
+}
+
+/// As client-go formats it: `apps/v1, Resource=deployments`
+impl fmt::Display for GroupVersionResource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, Resource={}", self.group_version(), self.resource)
+    }
+}
+
+/// Parses the fully qualified form kubectl accepts: `deployments.v1.apps`, or `pods.v1` for core
+impl FromStr for GroupVersionResource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, '.');
+        let (resource, version, group) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+        let is_version = version.strip_prefix('v').is_some_and(|v| v.starts_with(|c: char| c.is_ascii_digit()));
+        if resource.is_empty() || !is_version {
+            return Err(invalid("resource", s));
+        }
+        Ok(GroupVersionResource::new(group, version, resource))
+    }
+}
+
+impl TypeMeta {
+    /// The type meta of a `Resource`
+    pub fn of<K: Resource>() -> Self {
+        GroupVersionKind::of::<K>().type_meta()
+    }
+
+    /// The type it names, unless `apiVersion` or `kind` are missing
+    pub fn gvk(&self) -> Result<GroupVersionKind> {
+        match (&self.apiVersion, &self.kind) {
+            (Some(av), Some(kind)) => GroupVersionKind::from_api_version(av, kind),
+            _ => Err(Error::from(ErrorKind::RequestValidation("type meta without apiVersion and kind".into()))),
+        }
+    }
+}
+
+#[test]
+fn names_parse_and_format() {
+    let gv: GroupVersion = "apps/v1".parse().unwrap();
+    assert_eq!(gv, GroupVersion::new("apps", "v1"));
+    assert_eq!("v1".parse::<GroupVersion>().unwrap().to_string(), "v1");
+    assert!("/v1".parse::<GroupVersion>().is_err());
+    assert!("a/b/c".parse::<GroupVersion>().is_err());
+
+    let gvk = GroupVersionKind::from_value(&serde_json::json!({ "apiVersion": "apps/v1", "kind": "Deployment" })).unwrap();
+    assert_eq!(gvk.to_string(), "apps/v1, Kind=Deployment");
+    assert_eq!(gvk.type_meta().gvk().unwrap(), gvk);
+    assert!(GroupVersionKind::from_value(&serde_json::json!({ "kind": "Deployment" })).is_err());
+    let mut item = serde_json::json!({ "metadata": { "name": "blog" } });
+    gvk.apply_to(&mut item);
+    assert_eq!(item["apiVersion"], "apps/v1");
+
+    let gvr: GroupVersionResource = "deployments.v1.apps".parse().unwrap();
+    assert_eq!(gvr, gv.with_resource("deployments"));
+    assert_eq!(gvr.to_string(), "apps/v1, Resource=deployments");
+    let pods: GroupVersionResource = "pods.v1".parse().unwrap();
+    assert_eq!(pods.raw_api().list(&Default::default()).unwrap().uri(), "/api/v1/pods");
+    assert_eq!("ingresses.v1beta1.networking.k8s.io".parse::<GroupVersionResource>().unwrap().group, "networking.k8s.io");
+    assert!("deployments.apps".parse::<GroupVersionResource>().is_err());
+}
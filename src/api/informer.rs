@@ -4,22 +4,51 @@ use crate::api::{
     ListParams,
     Void,
 };
+use crate::api::coalesce::Coalescer;
 use crate::api::resource::{
     ObjectList,
     WatchEvent,
     KubeObject,
 };
+use crate::api::wait::poll_until;
 use crate::client::APIClient;
 use crate::{Result};
 
 use serde::de::DeserializeOwned;
 use std::{
     collections::VecDeque,
-    sync::{Arc, RwLock},
+    sync::{atomic::{AtomicBool, Ordering}, mpsc, Arc, Mutex, RwLock},
+    time::Duration,
 };
 
 type WatchQueue<K> = VecDeque<WatchEvent<K>>;
 
+type WatchResult<K> = Result<(Vec<WatchEvent<K>>, String)>;
+
+/// Runs a watch on its own thread, so a stuck one can be abandoned
+type WatchSpawner<K> = Arc<dyn Fn(Informer<K>) -> mpsc::Receiver<WatchResult<K>> + Send + Sync>;
+
+/// Restarting watches that return no data, see `Informer::liveness_window`
+#[derive(Clone)]
+struct Liveness<K> where
+    K: Clone + DeserializeOwned + KubeObject
+{
+    window: Duration,
+    spawn: WatchSpawner<K>,
+    watches: Arc<Mutex<StalledWatches<K>>>,
+}
+
+/// The watches still running on their threads after a poll stopped waiting for them
+///
+/// At most one is abandoned for a fresh watch; while it is stuck, the next stalled
+/// watch is kept and waited for again, so stuck connections hold two threads at most.
+struct StalledWatches<K> where
+    K: Clone + DeserializeOwned + KubeObject
+{
+    abandoned: Option<mpsc::Receiver<WatchResult<K>>>,
+    waiting: Option<mpsc::Receiver<WatchResult<K>>>,
+}
+
 /// An event informer for a `Resource`
 ///
 /// This watches a `Resource<K>`, by:
@@ -29,6 +58,9 @@ type WatchQueue<K> = VecDeque<WatchEvent<K>>;
 ///
 /// It caches WatchEvent<K> internally in a queue when polling.
 /// A user should drain this queue periodically.
+///
+/// With `coalesce`, bursts of events for the same object are merged before
+/// they are popped, see `Coalescer`.
 #[derive(Clone)]
 pub struct Informer<K> where
     K: Clone + DeserializeOwned + KubeObject
@@ -38,6 +70,9 @@ pub struct Informer<K> where
     client: APIClient,
     resource: RawApi,
     params: ListParams,
+    coalescer: Option<Arc<Mutex<Coalescer<K>>>>,
+    liveness: Option<Liveness<K>>,
+    synced: Arc<AtomicBool>,
 }
 
 impl<K> Informer<K> where
@@ -51,6 +86,9 @@ impl<K> Informer<K> where
             params: ListParams::default(),
             events: Arc::new(RwLock::new(VecDeque::new())),
             version: Arc::new(RwLock::new(0.to_string())),
+            coalescer: None,
+            liveness: None,
+            synced: Arc::new(AtomicBool::new(false)),
         }
     }
 }
@@ -66,9 +104,18 @@ impl<K> Informer<K> where
             params: ListParams::default(),
             events: Arc::new(RwLock::new(VecDeque::new())),
             version: Arc::new(RwLock::new(0.to_string())),
+            coalescer: None,
+            liveness: None,
+            synced: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// The same informer, sharing its version and queue, making its calls through `client`
+    pub(crate) fn with_client(mut self, client: APIClient) -> Self {
+        self.client = client;
+        self
+    }
+
     // builders for GetParams
 
     /// Configure the timeout for the list/watch call.
@@ -105,6 +152,15 @@ impl<K> Informer<K> where
         self
     }
 
+    /// Merge events for the same object arriving within `window` of each other
+    ///
+    /// Events are only popped once their window has passed, which cuts down
+    /// on redundant handling of objects that change in quick succession.
+    pub fn coalesce(mut self, window: Duration) -> Self {
+        self.coalescer = Some(Arc::new(Mutex::new(Coalescer::new(window))));
+        self
+    }
+
     // finalizers:
 
     /// Initialize without a prior version
@@ -114,6 +170,7 @@ impl<K> Informer<K> where
         let initial = self.get_resource_version()?;
         info!("Starting Informer for {:?}", self.resource);
         *self.version.write().unwrap() = initial;
+        self.synced.store(true, Ordering::SeqCst);
         Ok(self)
     }
 
@@ -121,9 +178,24 @@ impl<K> Informer<K> where
     pub fn init_from(self, v: String) -> Self {
         info!("Recreating Informer for {:?} at {}", self.resource, v);
         *self.version.write().unwrap() = v;
+        self.synced.store(true, Ordering::SeqCst);
         self
     }
 
+    /// Whether a resourceVersion to watch from was set, by `init` or `init_from`
+    ///
+    /// Shared between clones, so one can be polled while others check this.
+    pub fn has_synced(&self) -> bool {
+        self.synced.load(Ordering::SeqCst)
+    }
+
+    /// Block until `has_synced`, or fail after `timeout`
+    pub fn wait_for_cache_sync(&self, timeout: Duration) -> Result<()> {
+        poll_until(&format!("{} informer to sync", self.resource.resource), timeout, || {
+            Ok(if self.has_synced() { Some(()) } else { None })
+        })
+    }
+
 
     /// Run a single watch poll
     ///
@@ -132,17 +204,52 @@ impl<K> Informer<K> where
     /// If handling all the events is too time consuming, you probably need a queue.
     pub fn poll(&self) -> Result<()> {
         trace!("Watching {:?}", self.resource);
-        match self.single_watch() {
+        let res = match &self.liveness {
+            None => self.single_watch(),
+            Some(liveness) => {
+                let limit = Duration::from_secs(u64::from(self.params.timeout.unwrap_or(10))) + liveness.window;
+                let waiting = liveness.watches.lock().unwrap().waiting.take();
+                let rx = waiting.unwrap_or_else(|| (liveness.spawn)(self.clone()));
+                match rx.recv_timeout(limit) {
+                    Ok(res) => res,
+                    Err(_) => {
+                        let mut watches = liveness.watches.lock().unwrap();
+                        // an abandoned watch that has returned since no longer holds a thread
+                        if watches.abandoned.as_ref().is_some_and(|old| !matches!(old.try_recv(), Err(mpsc::TryRecvError::Empty))) {
+                            watches.abandoned = None;
+                        }
+                        if watches.abandoned.is_none() {
+                            // the connection is most likely gone without a reset, e.g. dropped by a NAT;
+                            // leave it to time out on its own and watch again from the same version
+                            warn!("Watch of {} returned no data within {:?}, restarting it", self.resource.resource, limit);
+                            watches.abandoned = Some(rx);
+                        } else {
+                            warn!("Watch of {} returned no data within {:?}, still waiting for it", self.resource.resource, limit);
+                            watches.waiting = Some(rx);
+                        }
+                        return Ok(());
+                    },
+                }
+            },
+        };
+        match res {
             Ok((events, newver)) => {
                 *self.version.write().unwrap() = newver;
-                for e in events {
-                    self.events.write().unwrap().push_back(e);
+                match &self.coalescer {
+                    Some(c) => {
+                        let mut c = c.lock().unwrap();
+                        for e in events {
+                            c.push(e);
+                        }
+                    },
+                    None => self.events.write().unwrap().extend(events),
                 }
             },
             Err(e) => {
                 warn!("Poll error: {:?}", e);
                 // If desynched due to mismatching resourceVersion, retry in a bit
-                std::thread::sleep(std::time::Duration::from_secs(10));
+                // (or as long as the apiserver asks us to when throttled)
+                std::thread::sleep(e.retry_after().unwrap_or(std::time::Duration::from_secs(10)));
                 self.reset()?;
             }
         };
@@ -150,8 +257,13 @@ impl<K> Informer<K> where
     }
 
     /// Pop an event from the front of the WatchQueue
+    ///
+    /// When coalescing, only events whose window has passed are returned.
     pub fn pop(&self) -> Option<WatchEvent<K>> {
-        self.events.write().unwrap().pop_front()
+        match &self.coalescer {
+            Some(c) => c.lock().unwrap().pop(),
+            None => self.events.write().unwrap().pop_front(),
+        }
     }
 
     /// Reset the resourceVersion to current and clear the event queue
@@ -160,6 +272,9 @@ impl<K> Informer<K> where
         let initial = self.get_resource_version()?;
         *self.version.write().unwrap() = initial;
         self.events.write().unwrap().clear();
+        if let Some(c) = &self.coalescer {
+            c.lock().unwrap().clear();
+        }
         Ok(())
     }
 
@@ -182,7 +297,7 @@ impl<K> Informer<K> where
     }
 
     /// Watch helper
-    fn single_watch(&self) -> Result<(Vec<WatchEvent<K>>, String)> {
+    fn single_watch(&self) -> WatchResult<K> {
         let oldver = self.version();
         let req = self.resource.watch(&self.params, &oldver)?;
         let events = self.client.request_events::<WatchEvent<K>>(req)?;
@@ -202,3 +317,67 @@ impl<K> Informer<K> where
         Ok((events, newver))
     }
 }
+
+impl<K> Informer<K> where
+    K: Clone + DeserializeOwned + KubeObject + Send + Sync + 'static,
+{
+    /// Restart watches that stay open past their timeout plus `window` without returning
+    ///
+    /// A watch whose connection was silently dropped, e.g. by a NAT or load balancer
+    /// losing its state, otherwise blocks until the OS gives up on the socket, which can
+    /// take hours. The stuck watch is abandoned and restarted from the last resourceVersion,
+    /// so no events are lost. Abandoned watches keep their thread until the transport
+    /// gives up on them; while one still does, a second stuck watch is waited for on
+    /// later polls instead of being abandoned too.
+    pub fn liveness_window(mut self, window: Duration) -> Self {
+        let spawn = |informer: Informer<K>| {
+            let (tx, rx) = mpsc::channel();
+            std::thread::spawn(move || {
+                // the receiver is gone if the watch was abandoned
+                let _ = tx.send(informer.single_watch());
+            });
+            rx
+        };
+        let watches = Arc::new(Mutex::new(StalledWatches { abandoned: None, waiting: None }));
+        self.liveness = Some(Liveness { window, spawn: Arc::new(spawn), watches });
+        self
+    }
+}
+
+#[test]
+fn stale_watches_are_restarted() {
+    use std::sync::atomic::AtomicUsize;
+    use crate::api::Object;
+    use crate::client::fake::{respond, Fake};
+    // the first two watches hang until released, as on connections dropped without a reset
+    let (watches, released) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicBool::new(false)));
+    let (counted, release) = (watches.clone(), released.clone());
+    let fake = Fake::new().handle("GET", "*", move |_| {
+        if counted.fetch_add(1, Ordering::SeqCst) < 2 {
+            while !release.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+        respond(200, "{\"type\":\"ADDED\",\"object\":{\"metadata\":{\"name\":\"a\",\"resourceVersion\":\"6\"},\"spec\":{}}}\n")
+    });
+    let inf: Informer<Object<Void, Void>> = Informer::raw(fake.client(), RawApi::customResource("foos").group("example.com"))
+        .timeout(0)
+        .liveness_window(Duration::from_millis(100))
+        .init_from("5".into());
+    // the first stuck watch is abandoned for a new one, which is waited for while the first is still stuck
+    for _ in 0..3 {
+        inf.poll().unwrap();
+        assert!(inf.pop().is_none());
+    }
+    assert_eq!(inf.version(), "5");
+    assert_eq!(watches.load(Ordering::SeqCst), 2);
+
+    released.store(true, Ordering::SeqCst);
+    inf.poll().unwrap();
+    assert_eq!(watches.load(Ordering::SeqCst), 2);
+    assert!(inf.pop().is_some());
+    assert_eq!(inf.version(), "6");
+    inf.poll().unwrap();
+    assert_eq!(watches.load(Ordering::SeqCst), 3);
+    assert!(fake.requests()[..2].iter().all(|r| r.query().contains("resourceVersion=5")));
+}
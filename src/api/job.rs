@@ -0,0 +1,157 @@
+//! Convenience operations for Jobs and CronJobs
+use std::collections::BTreeMap;
+use std::time::Duration;
+use serde_json::json;
+use chrono::Utc;
+
+use crate::api::{Api, RawApi, Object, ObjectList, ListParams, LogParams, PostParams, OwnerReference, Conditions};
+use crate::api::wait::poll_until;
+use crate::{Result, Error, ErrorKind};
+use failure::ResultExt;
+
+use k8s_openapi::api::batch::v1::{JobSpec, JobStatus};
+use k8s_openapi::api::batch::v1beta1::{CronJobSpec, CronJobStatus};
+use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+
+type Job = Object<JobSpec, JobStatus>;
+type CronJob = Object<CronJobSpec, CronJobStatus>;
+
+/// Find a condition of a type with status `True` on a Job
+fn true_condition<'a>(job: &'a Job, type_: &str) -> Option<&'a k8s_openapi::api::batch::v1::JobCondition> {
+    job.status.as_ref()
+        .and_then(|s| s.conditions.as_ref())
+        .and_then(|conds| conds.find_condition(type_))
+        .filter(|c| c.status == "True")
+}
+
+impl Object<JobSpec, JobStatus> {
+    /// Whether the Job has the `Complete` condition
+    pub fn is_complete(&self) -> bool {
+        true_condition(self, "Complete").is_some()
+    }
+
+    /// Whether the Job has the `Failed` condition
+    pub fn is_failed(&self) -> bool {
+        true_condition(self, "Failed").is_some()
+    }
+}
+
+impl Api<Job> {
+    /// Block until the Job completes, or fail after `timeout`
+    ///
+    /// A Job reaching the `Failed` condition results in `ErrorKind::JobFailed`.
+    pub fn wait_for_completion(&self, name: &str, timeout: Duration) -> Result<Job> {
+        poll_until(&format!("job {} to complete", name), timeout, || {
+            let job = self.get(name)?;
+            if let Some(c) = true_condition(&job, "Failed") {
+                let reason = c.reason.clone().unwrap_or_default();
+                let msg = c.message.clone().unwrap_or_default();
+                return Err(Error::from(ErrorKind::JobFailed(format!("{}: {} {}", name, reason, msg))));
+            }
+            Ok(if job.is_complete() { Some(job) } else { None })
+        })
+    }
+
+    /// Collect logs from every pod created by the Job
+    ///
+    /// Pods are found through the `job-name` label set by the job controller.
+    /// Returns a map of pod name to log output.
+    pub fn logs(&self, name: &str, lp: &LogParams) -> Result<BTreeMap<String, String>> {
+        let mut pods = RawApi::v1Pod();
+        pods.namespace = self.api.namespace.clone();
+        let selector = ListParams {
+            label_selector: Some(format!("job-name={}", name)),
+            ..Default::default()
+        };
+        let req = pods.list(&selector)?;
+        let podlist = self.client.request::<ObjectList<Object<PodSpec, PodStatus>>>(req)?;
+        let mut logs = BTreeMap::new();
+        for p in podlist.items {
+            let req = pods.log(&p.metadata.name, lp)?;
+            logs.insert(p.metadata.name, self.client.request_text(req)?);
+        }
+        Ok(logs)
+    }
+}
+
+impl Api<CronJob> {
+    /// Create a Job from the CronJob's template right now
+    ///
+    /// Equivalent to `kubectl create job --from=cronjob/<name>`. The Job is owned by the CronJob.
+    pub fn trigger_now(&self, name: &str) -> Result<Job> {
+        let cj = self.get(name)?;
+        let template = &cj.spec.job_template;
+        let jobname = format!("{}-manual-{}", name, Utc::now().timestamp());
+        let owner = OwnerReference::controller_for(&cj)?;
+        let mut annotations = template.metadata.as_ref()
+            .and_then(|m| m.annotations.clone())
+            .unwrap_or_default();
+        annotations.insert("cronjob.kubernetes.io/instantiate".into(), "manual".into());
+        let labels = template.metadata.as_ref()
+            .and_then(|m| m.labels.clone())
+            .unwrap_or_default();
+        let job = json!({
+            "apiVersion": "batch/v1",
+            "kind": "Job",
+            "metadata": {
+                "name": jobname,
+                "labels": labels,
+                "annotations": annotations,
+                "ownerReferences": [owner],
+            },
+            "spec": template.spec,
+        });
+        let mut jobs = RawApi::v1Job();
+        jobs.namespace = self.api.namespace.clone();
+        let data = serde_json::to_vec(&job).context(ErrorKind::SerdeParse)?;
+        let req = jobs.create(&PostParams::default(), data)?;
+        self.client.request::<Job>(req)
+    }
+}
+
+#[test]
+fn jobs_are_waited_for_logged_and_triggered() {
+    use crate::client::fake::{respond, Fake};
+    let fake = Fake::new()
+        .on("GET", "/apis/batch/v1/namespaces/ns/jobs/migrate", 200, json!({
+            "metadata": { "name": "migrate" }, "spec": { "template": {} },
+            "status": { "conditions": [{ "type": "Failed", "status": "True", "reason": "BackoffLimitExceeded", "message": "gave up" }] },
+        }).to_string())
+        .on("GET", "/api/v1/namespaces/ns/pods", 200, json!({
+            "metadata": {}, "items": [{ "metadata": { "name": "migrate-abc" }, "spec": { "containers": [] } }],
+        }).to_string())
+        .on("GET", "/api/v1/namespaces/ns/pods/migrate-abc/log", 200, "migrating\n")
+        .on("GET", "/apis/batch/v1beta1/namespaces/ns/cronjobs/nightly", 200, json!({
+            "apiVersion": "batch/v1beta1", "kind": "CronJob",
+            "metadata": { "name": "nightly", "uid": "cj-1" },
+            "spec": {
+                "schedule": "@daily",
+                "jobTemplate": {
+                    "metadata": { "labels": { "app": "nightly" }, "annotations": { "team": "data" } },
+                    "spec": { "template": { "spec": { "containers": [{ "name": "run" }] } } },
+                },
+            },
+        }).to_string())
+        .handle("POST", "/apis/batch/v1/namespaces/ns/jobs", |req| respond(201, req.body.clone()));
+    let client = fake.client();
+    let jobs = Api::v1Job(client.clone()).within("ns");
+    let err = jobs.wait_for_completion("migrate", Duration::from_secs(5)).err().expect("a failed job is an error");
+    match err.kind() {
+        ErrorKind::JobFailed(msg) => assert_eq!(msg, "migrate: BackoffLimitExceeded gave up"),
+        kind => panic!("unexpected error {}", kind),
+    }
+
+    let logs = jobs.logs("migrate", &LogParams::default()).unwrap();
+    assert_eq!(logs["migrate-abc"], "migrating\n");
+    assert!(fake.lines().contains(&"GET /api/v1/namespaces/ns/pods?labelSelector=job-name%3Dmigrate".to_string()));
+
+    let job = Api::v1beta1CronJob(client).within("ns").trigger_now("nightly").unwrap();
+    assert!(job.metadata.name.starts_with("nightly-manual-"));
+    let created = fake.requests().last().unwrap().json();
+    assert_eq!(created["kind"], "Job");
+    assert_eq!(created["metadata"]["labels"], json!({ "app": "nightly" }));
+    assert_eq!(created["metadata"]["annotations"], json!({ "team": "data", "cronjob.kubernetes.io/instantiate": "manual" }));
+    assert_eq!(created["metadata"]["ownerReferences"][0]["uid"], "cj-1");
+    assert_eq!(created["metadata"]["ownerReferences"][0]["controller"], true);
+    assert_eq!(created["spec"]["template"]["spec"]["containers"][0]["name"], "run");
+}
@@ -0,0 +1,160 @@
+//! Building RFC 6902 JSON Patches, for use with `PatchStrategy::JSON`
+//!
+//! ```
+//! use kube::api::json_patch::JsonPatch;
+//! use serde_json::json;
+//!
+//! let original = json!({ "metadata": { "labels": { "app.kubernetes.io/name": "blog" } } });
+//! let patch = JsonPatch::new()
+//!     .replace(&["metadata", "labels", "app.kubernetes.io/name"], json!("wiki"))
+//!     .guard(&original);
+//! assert_eq!(patch.ops()[0].path(), "/metadata/labels/app.kubernetes.io~1name");
+//! ```
+use serde_json::Value;
+
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+/// A single JSON Patch operation
+///
+/// Paths are JSON pointers; build them with `pointer` to get the escaping right.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum PatchOp {
+    Add { path: String, value: Value },
+    Remove { path: String },
+    Replace { path: String, value: Value },
+    Test { path: String, value: Value },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+}
+
+impl PatchOp {
+    /// The location the operation acts on
+    pub fn path(&self) -> &str {
+        match self {
+            PatchOp::Add { path, .. } | PatchOp::Remove { path } | PatchOp::Replace { path, .. }
+            | PatchOp::Test { path, .. } | PatchOp::Move { path, .. } | PatchOp::Copy { path, .. } => path,
+        }
+    }
+}
+
+/// Escape a path segment for a JSON pointer: `~` becomes `~0` and `/` becomes `~1`
+pub fn escape(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+/// A JSON pointer from unescaped path segments, e.g. a label key containing a `/`
+///
+/// Use `"-"` as the last segment to append to an array.
+pub fn pointer(segments: &[&str]) -> String {
+    segments.iter().map(|s| format!("/{}", escape(s))).collect()
+}
+
+/// A list of `PatchOp`s, built up in order
+#[derive(Clone, Debug, Default)]
+pub struct JsonPatch {
+    ops: Vec<PatchOp>,
+    guard: Option<Value>,
+}
+
+impl JsonPatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append any operation
+    pub fn op(mut self, op: PatchOp) -> Self {
+        self.ops.push(op);
+        self
+    }
+
+    /// Add a value, replacing one already there or inserting into an array
+    pub fn add(self, path: &[&str], value: Value) -> Self {
+        self.op(PatchOp::Add { path: pointer(path), value })
+    }
+
+    /// Remove the value at a path, which must exist
+    pub fn remove(self, path: &[&str]) -> Self {
+        self.op(PatchOp::Remove { path: pointer(path) })
+    }
+
+    /// Replace the value at a path, which must exist
+    pub fn replace(self, path: &[&str], value: Value) -> Self {
+        self.op(PatchOp::Replace { path: pointer(path), value })
+    }
+
+    /// Fail the whole patch unless the value at a path equals `value`
+    pub fn test(self, path: &[&str], value: Value) -> Self {
+        self.op(PatchOp::Test { path: pointer(path), value })
+    }
+
+    /// Move a value to another path
+    pub fn move_from(self, from: &[&str], path: &[&str]) -> Self {
+        self.op(PatchOp::Move { from: pointer(from), path: pointer(path) })
+    }
+
+    /// Copy a value to another path
+    pub fn copy_from(self, from: &[&str], path: &[&str]) -> Self {
+        self.op(PatchOp::Copy { from: pointer(from), path: pointer(path) })
+    }
+
+    /// Guard the patch against concurrent changes to the values it overwrites
+    ///
+    /// Every operation changing or removing a value that exists in `original` is preceded by
+    /// a `test` for that value, so the patch fails with `422` if someone else changed it since.
+    /// This is a finer grained alternative to sending a resourceVersion.
+    pub fn guard(mut self, original: &Value) -> Self {
+        self.guard = Some(original.clone());
+        self
+    }
+
+    /// The operations to send, including any guards
+    pub fn ops(&self) -> Vec<PatchOp> {
+        let original = match &self.guard {
+            Some(o) => o,
+            None => return self.ops.clone(),
+        };
+        let mut ops = vec![];
+        for op in &self.ops {
+            let overwritten = match op {
+                PatchOp::Add { path, .. } | PatchOp::Remove { path } | PatchOp::Replace { path, .. }
+                | PatchOp::Copy { path, .. } => vec![path],
+                PatchOp::Move { from, path } => vec![from, path],
+                PatchOp::Test { .. } => vec![],
+            };
+            for path in overwritten {
+                // appending to an array does not overwrite anything
+                if let Some(value) = original.pointer(path).filter(|_| !path.ends_with("/-")) {
+                    ops.push(PatchOp::Test { path: path.clone(), value: value.clone() });
+                }
+            }
+            ops.push(op.clone());
+        }
+        ops
+    }
+
+    /// Serialize for `Api::patch` with `PatchStrategy::JSON`
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self.ops()).context(ErrorKind::SerdeParse)?)
+    }
+}
+
+#[test]
+fn ops_are_escaped_and_guarded() {
+    use serde_json::json;
+    assert_eq!(pointer(&["metadata", "annotations", "a~b/c"]), "/metadata/annotations/a~0b~1c");
+    let original = json!({ "spec": { "replicas": 2, "args": ["a"] } });
+    let patch = JsonPatch::new()
+        .replace(&["spec", "replicas"], json!(3))
+        .add(&["spec", "args", "-"], json!("b"))
+        .remove(&["spec", "paused"])
+        .guard(&original);
+    let json: Value = serde_json::from_slice(&patch.to_vec().unwrap()).unwrap();
+    assert_eq!(json, json!([
+        { "op": "test", "path": "/spec/replicas", "value": 2 },
+        { "op": "replace", "path": "/spec/replicas", "value": 3 },
+        { "op": "add", "path": "/spec/args/-", "value": "b" },
+        { "op": "remove", "path": "/spec/paused" },
+    ]));
+}
@@ -0,0 +1,462 @@
+//! Kubernetes flavoured JSONPath, as used by `kubectl get -o jsonpath=...`
+//!
+//! Templates mix literal text with expressions in braces:
+//! - fields `{.status.podIP}`, wildcards `{.items[*].metadata.name}`, and `{..image}` recursion
+//! - indexes `{.items[0]}`, `{.items[-1]}`, unions `{.items[0,2]}` and slices `{.items[1:3]}`
+//! - quoted names for keys with dots `{.metadata.labels['app.kubernetes.io/name']}`
+//! - filters `{.items[?(@.status.phase=="Running")].metadata.name}`
+//! - iteration `{range .items[*]}{.metadata.name}{"\n"}{end}`
+//!
+//! ```
+//! use kube::api::jsonpath::JsonPath;
+//! use serde_json::json;
+//!
+//! let pods = json!({ "items": [
+//!     { "metadata": { "name": "a" }, "status": { "phase": "Running" } },
+//!     { "metadata": { "name": "b" }, "status": { "phase": "Pending" } },
+//! ]});
+//! let tpl = JsonPath::parse(r#"{range .items[*]}{.metadata.name}={.status.phase}{"\n"}{end}"#).unwrap();
+//! assert_eq!(tpl.render(&pods), "a=Running\nb=Pending\n");
+//! ```
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{Result, Error, ErrorKind};
+use failure::ResultExt;
+
+#[derive(Clone, Debug)]
+enum Step {
+    Field(String),
+    Names(Vec<String>),
+    Wildcard,
+    Indexes(Vec<i64>),
+    Slice(Option<i64>, Option<i64>, Option<i64>),
+    Recurse,
+    Filter(Box<Filter>),
+}
+
+#[derive(Clone, Debug)]
+struct Path {
+    /// Starts at `$` rather than the current object
+    root: bool,
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug)]
+enum Operand {
+    Path(Path),
+    Literal(Value),
+}
+
+#[derive(Clone, Debug)]
+struct Filter {
+    left: Operand,
+    /// Without a comparison, the filter checks the left path exists
+    cmp: Option<(String, Operand)>,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Text(String),
+    Expr(Path),
+    Range(Path, Vec<Node>),
+}
+
+fn invalid(msg: String) -> Error {
+    ErrorKind::JsonPath(msg).into()
+}
+
+/// Index of the first `close` at nesting depth zero, skipping quoted strings
+fn find_closing(chars: &[char], open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some(_) if c == '\\' => i += 1,
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == open => depth += 1,
+            None if c == close && depth == 0 => return Some(i),
+            None if c == close => depth -= 1,
+            None => {},
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Split on a separator outside of quotes
+fn split_top(s: &str, sep: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut quote = None;
+    for c in s.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == sep => { parts.push(String::new()); continue; },
+            _ => {},
+        }
+        parts.last_mut().unwrap().push(c);
+    }
+    parts
+}
+
+fn unquote(s: &str) -> Option<String> {
+    let s = s.trim();
+    let q = s.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    if s.len() < 2 || !s.ends_with(q) {
+        return None;
+    }
+    let mut out = String::new();
+    let mut chars = s[1..s.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => out.push('\n'),
+            't' => out.push('\t'),
+            'r' => out.push('\r'),
+            c => out.push(c),
+        }
+    }
+    Some(out)
+}
+
+fn parse_bracket(inner: &str) -> Result<Step> {
+    let inner = inner.trim();
+    if inner == "*" {
+        return Ok(Step::Wildcard);
+    }
+    if inner.starts_with("?(") && inner.ends_with(')') {
+        return Ok(Step::Filter(Box::new(parse_filter(&inner[2..inner.len() - 1])?)));
+    }
+    let int = |s: &str| -> Result<Option<i64>> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(None);
+        }
+        s.parse().map(Some).map_err(|_| invalid(format!("invalid index {:?}", s)))
+    };
+    let slice = split_top(inner, ':');
+    if slice.len() > 1 {
+        if slice.len() > 3 {
+            return Err(invalid(format!("invalid slice [{}]", inner)));
+        }
+        let step = match slice.get(2) { Some(s) => int(s)?, None => None };
+        if step.unwrap_or(1) < 1 {
+            return Err(invalid(format!("slice step must be positive in [{}]", inner)));
+        }
+        return Ok(Step::Slice(int(&slice[0])?, int(&slice[1])?, step));
+    }
+    let parts = split_top(inner, ',');
+    if parts.iter().all(|p| unquote(p).is_some()) {
+        return Ok(Step::Names(parts.iter().filter_map(|p| unquote(p)).collect()));
+    }
+    let mut indexes = vec![];
+    for p in parts {
+        indexes.push(int(&p)?.ok_or_else(|| invalid(format!("empty index in [{}]", inner)))?);
+    }
+    Ok(Step::Indexes(indexes))
+}
+
+fn parse_operand(s: &str) -> Result<Operand> {
+    let s = s.trim();
+    if let Some(text) = unquote(s) {
+        return Ok(Operand::Literal(Value::String(text)));
+    }
+    if s.starts_with('@') || s.starts_with('$') || s.starts_with('.') {
+        return Ok(Operand::Path(parse_path(s)?));
+    }
+    let lit = serde_json::from_str::<Value>(s).map_err(|_| invalid(format!("invalid literal {:?}", s)))?;
+    Ok(Operand::Literal(lit))
+}
+
+fn parse_filter(s: &str) -> Result<Filter> {
+    // two character operators first, so `<=` is not read as `<`
+    for op in &["==", "!=", "<=", ">=", "<", ">"] {
+        let mut quote = None;
+        for (i, c) in s.char_indices() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => {},
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if s[i..].starts_with(op) => {
+                    return Ok(Filter {
+                        left: parse_operand(&s[..i])?,
+                        cmp: Some((op.to_string(), parse_operand(&s[i + op.len()..])?)),
+                    });
+                },
+                None => {},
+            }
+        }
+    }
+    Ok(Filter { left: parse_operand(s)?, cmp: None })
+}
+
+fn parse_path(src: &str) -> Result<Path> {
+    let chars: Vec<char> = src.trim().chars().collect();
+    let mut path = Path { root: false, steps: vec![] };
+    let mut i = 0;
+    match chars.first() {
+        Some('$') => { path.root = true; i = 1; },
+        Some('@') => i = 1,
+        _ => {},
+    }
+    while i < chars.len() {
+        match chars[i] {
+            '.' => {
+                i += 1;
+                if chars.get(i) == Some(&'.') {
+                    path.steps.push(Step::Recurse);
+                    i += 1;
+                }
+                let start = i;
+                while i < chars.len() && chars[i] != '.' && chars[i] != '[' {
+                    i += 1;
+                }
+                let name: String = chars[start..i].iter().collect();
+                match name.trim() {
+                    "" => {},
+                    "*" => path.steps.push(Step::Wildcard),
+                    n => path.steps.push(Step::Field(n.to_string())),
+                }
+            },
+            '[' => {
+                let len = find_closing(&chars[i + 1..], '[', ']')
+                    .ok_or_else(|| invalid(format!("unclosed [ in {}", src)))?;
+                let inner: String = chars[i + 1..i + 1 + len].iter().collect();
+                path.steps.push(parse_bracket(&inner)?);
+                i += len + 2;
+            },
+            c => return Err(invalid(format!("unexpected {:?} in {}", c, src))),
+        }
+    }
+    Ok(path)
+}
+
+fn parse_template(src: &str) -> Result<Vec<Node>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut nodes = vec![];
+    let mut ranges: Vec<(Path, Vec<Node>)> = vec![];
+    let mut text = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '{' {
+            text.push(chars[i]);
+            i += 1;
+            continue;
+        }
+        if !text.is_empty() {
+            nodes.push(Node::Text(std::mem::take(&mut text)));
+        }
+        let len = find_closing(&chars[i + 1..], '{', '}')
+            .ok_or_else(|| invalid(format!("unclosed {{ in {}", src)))?;
+        let expr: String = chars[i + 1..i + 1 + len].iter().collect();
+        let expr = expr.trim();
+        i += len + 2;
+        if let Some(lit) = unquote(expr) {
+            nodes.push(Node::Text(lit));
+        } else if expr == "end" {
+            let (path, parent) = ranges.pop().ok_or_else(|| invalid("{end} without {range}".into()))?;
+            let body = std::mem::replace(&mut nodes, parent);
+            nodes.push(Node::Range(path, body));
+        } else if let Some(path) = expr.strip_prefix("range ") {
+            ranges.push((parse_path(path)?, std::mem::take(&mut nodes)));
+        } else {
+            nodes.push(Node::Expr(parse_path(expr)?));
+        }
+    }
+    if !ranges.is_empty() {
+        return Err(invalid("{range} without {end}".into()));
+    }
+    if !text.is_empty() {
+        nodes.push(Node::Text(text));
+    }
+    Ok(nodes)
+}
+
+fn descendants<'a>(v: &'a Value, out: &mut Vec<&'a Value>) {
+    out.push(v);
+    match v {
+        Value::Object(map) => map.values().for_each(|c| descendants(c, out)),
+        Value::Array(xs) => xs.iter().for_each(|c| descendants(c, out)),
+        _ => {},
+    }
+}
+
+fn resolve(len: usize, i: i64) -> Option<usize> {
+    let i = if i < 0 { len as i64 + i } else { i };
+    if i >= 0 && (i as usize) < len { Some(i as usize) } else { None }
+}
+
+fn compare(a: &Value, op: &str, b: &Value) -> bool {
+    let ord = match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64().and_then(|x| y.as_f64().and_then(|y| x.partial_cmp(&y))),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    };
+    match (op, ord) {
+        ("==", _) => a == b,
+        ("!=", _) => a != b,
+        ("<", Some(o)) => o == std::cmp::Ordering::Less,
+        ("<=", Some(o)) => o != std::cmp::Ordering::Greater,
+        (">", Some(o)) => o == std::cmp::Ordering::Greater,
+        (">=", Some(o)) => o != std::cmp::Ordering::Less,
+        _ => false,
+    }
+}
+
+fn operand<'a>(o: &'a Operand, root: &'a Value, current: &'a Value) -> Option<&'a Value> {
+    match o {
+        Operand::Literal(v) => Some(v),
+        Operand::Path(p) => eval(p, root, current).into_iter().next(),
+    }
+}
+
+fn matches_filter(f: &Filter, root: &Value, v: &Value) -> bool {
+    let left = operand(&f.left, root, v);
+    match (&f.cmp, left) {
+        (None, left) => left.is_some(),
+        (Some((op, right)), Some(left)) => operand(right, root, v).map(|r| compare(left, op, r)).unwrap_or(false),
+        (Some(_), None) => false,
+    }
+}
+
+fn apply<'a>(step: &Step, v: &'a Value, root: &'a Value, out: &mut Vec<&'a Value>) {
+    match (step, v) {
+        (Step::Field(name), Value::Object(map)) => out.extend(map.get(name)),
+        (Step::Names(names), Value::Object(map)) => out.extend(names.iter().filter_map(|n| map.get(n))),
+        (Step::Wildcard, Value::Object(map)) => out.extend(map.values()),
+        (Step::Wildcard, Value::Array(xs)) => out.extend(xs.iter()),
+        (Step::Indexes(is), Value::Array(xs)) => {
+            out.extend(is.iter().filter_map(|i| resolve(xs.len(), *i)).map(|i| &xs[i]))
+        },
+        (Step::Slice(start, end, step), Value::Array(xs)) => {
+            let len = xs.len() as i64;
+            let clamp = |i: i64| if i < 0 { std::cmp::max(len + i, 0) } else { std::cmp::min(i, len) };
+            let start = clamp(start.unwrap_or(0)) as usize;
+            let end = clamp(end.unwrap_or(len)) as usize;
+            if start < end {
+                out.extend(xs[start..end].iter().step_by(step.unwrap_or(1) as usize));
+            }
+        },
+        (Step::Recurse, v) => descendants(v, out),
+        (Step::Filter(f), Value::Array(xs)) => out.extend(xs.iter().filter(|x| matches_filter(f, root, x))),
+        (Step::Filter(f), v) if matches_filter(f, root, v) => out.push(v),
+        _ => {},
+    }
+}
+
+fn eval<'a>(path: &Path, root: &'a Value, current: &'a Value) -> Vec<&'a Value> {
+    let mut values = vec![if path.root { root } else { current }];
+    for step in &path.steps {
+        let mut next = vec![];
+        for v in values {
+            apply(step, v, root, &mut next);
+        }
+        values = next;
+    }
+    values
+}
+
+/// How kubectl prints a result: strings bare, anything else as json
+fn display(v: &Value) -> String {
+    match v {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn render_nodes(nodes: &[Node], root: &Value, current: &Value, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(t) => out.push_str(t),
+            Node::Expr(p) => {
+                let values: Vec<_> = eval(p, root, current).into_iter().map(display).collect();
+                out.push_str(&values.join(" "));
+            },
+            Node::Range(p, body) => {
+                for v in eval(p, root, current) {
+                    render_nodes(body, root, v, out);
+                }
+            },
+        }
+    }
+}
+
+/// A parsed JSONPath template
+#[derive(Clone, Debug)]
+pub struct JsonPath {
+    nodes: Vec<Node>,
+}
+
+impl JsonPath {
+    /// Parse a template, e.g. `{.metadata.name}`
+    ///
+    /// A bare expression without braces, like `.metadata.name`, is accepted as well.
+    pub fn parse(template: &str) -> Result<Self> {
+        let nodes = if template.contains('{') {
+            parse_template(template)?
+        } else {
+            vec![Node::Expr(parse_path(template)?)]
+        };
+        Ok(JsonPath { nodes })
+    }
+
+    /// Render the template against a json value
+    ///
+    /// Several results of one expression are separated by spaces; missing fields render as nothing.
+    pub fn render(&self, value: &Value) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, value, value, &mut out);
+        out
+    }
+
+    /// Render the template against a typed object
+    pub fn render_object<T: Serialize>(&self, obj: &T) -> Result<String> {
+        let value = serde_json::to_value(obj).context(ErrorKind::SerdeParse)?;
+        Ok(self.render(&value))
+    }
+
+    /// The values all expressions in the template evaluate to, ignoring text and ranges
+    pub fn query<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        self.nodes.iter()
+            .filter_map(|n| match n { Node::Expr(p) => Some(eval(p, value, value)), _ => None })
+            .flatten()
+            .collect()
+    }
+}
+
+#[test]
+fn kubectl_style_expressions() {
+    use serde_json::json;
+    let pods = json!({ "kind": "List", "items": [
+        { "metadata": { "name": "a", "labels": { "app.kubernetes.io/name": "blog" } },
+          "spec": { "containers": [{ "image": "nginx" }, { "image": "envoy" }] },
+          "status": { "phase": "Running", "restarts": 3 } },
+        { "metadata": { "name": "b" },
+          "spec": { "containers": [{ "image": "redis" }] },
+          "status": { "phase": "Pending", "restarts": 0 } },
+    ]});
+    let render = |t: &str| JsonPath::parse(t).unwrap().render(&pods);
+    assert_eq!(render("{.kind}"), "List");
+    assert_eq!(render(".items[*].metadata.name"), "a b");
+    assert_eq!(render("{.items[-1].metadata.name}"), "b");
+    assert_eq!(render("{.items[0:1].metadata.name}"), "a");
+    assert_eq!(render("{..image}"), "nginx envoy redis");
+    assert_eq!(render("{.items[0].metadata.labels['app.kubernetes.io/name']}"), "blog");
+    assert_eq!(render(r#"{.items[?(@.status.phase=="Running")].metadata.name}"#), "a");
+    assert_eq!(render("{.items[?(@.status.restarts > 1)].metadata.name}"), "a");
+    assert_eq!(render("{.items[?(@.metadata.labels)].metadata.name}"), "a");
+    assert_eq!(render("{.items[0].status}"), r#"{"phase":"Running","restarts":3}"#);
+    assert_eq!(render("{.missing}"), "");
+    assert_eq!(render(r#"{range .items[*]}[{.metadata.name}:{range .spec.containers[*]}{.image},{end}]{end}"#),
+        "[a:nginx,envoy,][b:redis,]");
+    assert!(JsonPath::parse("{range .items[*]}").is_err());
+    assert!(JsonPath::parse("{.items[a]}").is_err());
+}
@@ -0,0 +1,271 @@
+//! Reading and changing labels and annotations on any object
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::api::{Object, ObjectMeta};
+use crate::{ErrorKind, Result};
+use failure::ResultExt;
+
+/// Label and annotation helpers for typed objects, metadata and dynamic json objects
+///
+/// Implementors only provide lookups and single key updates; `MetaPatch` implements it
+/// too, recording changes as a merge patch instead of applying them.
+///
+/// ```
+/// use kube::api::ObjectMetaExt;
+/// use serde_json::json;
+///
+/// let mut pod = json!({ "metadata": { "name": "web", "labels": { "app": "blog" } } });
+/// assert_eq!(pod.label("app"), Some("blog"));
+/// pod.set_label("tier", "frontend");
+/// pod.remove_label("app");
+/// assert_eq!(pod["metadata"]["labels"], json!({ "tier": "frontend" }));
+/// ```
+pub trait ObjectMetaExt {
+    fn label(&self, key: &str) -> Option<&str>;
+
+    fn annotation(&self, key: &str) -> Option<&str>;
+
+    /// Set a label, or remove it for `None`
+    fn update_label(&mut self, key: &str, value: Option<&str>);
+
+    /// Set an annotation, or remove it for `None`
+    fn update_annotation(&mut self, key: &str, value: Option<&str>);
+
+    fn has_label(&self, key: &str) -> bool {
+        self.label(key).is_some()
+    }
+
+    fn has_annotation(&self, key: &str) -> bool {
+        self.annotation(key).is_some()
+    }
+
+    fn set_label(&mut self, key: &str, value: &str) {
+        self.update_label(key, Some(value))
+    }
+
+    fn set_annotation(&mut self, key: &str, value: &str) {
+        self.update_annotation(key, Some(value))
+    }
+
+    fn remove_label(&mut self, key: &str) {
+        self.update_label(key, None)
+    }
+
+    fn remove_annotation(&mut self, key: &str) {
+        self.update_annotation(key, None)
+    }
+
+    /// Set all these labels, keeping others
+    fn merge_labels(&mut self, labels: &BTreeMap<String, String>) {
+        for (k, v) in labels {
+            self.set_label(k, v);
+        }
+    }
+
+    /// Set all these annotations, keeping others
+    fn merge_annotations(&mut self, annotations: &BTreeMap<String, String>) {
+        for (k, v) in annotations {
+            self.set_annotation(k, v);
+        }
+    }
+}
+
+fn update(map: &mut BTreeMap<String, String>, key: &str, value: Option<&str>) {
+    match value {
+        Some(v) => map.insert(key.into(), v.into()),
+        None => map.remove(key),
+    };
+}
+
+impl ObjectMetaExt for ObjectMeta {
+    fn label(&self, key: &str) -> Option<&str> {
+        self.labels.get(key).map(String::as_str)
+    }
+
+    fn annotation(&self, key: &str) -> Option<&str> {
+        self.annotations.get(key).map(String::as_str)
+    }
+
+    fn update_label(&mut self, key: &str, value: Option<&str>) {
+        update(&mut self.labels, key, value)
+    }
+
+    fn update_annotation(&mut self, key: &str, value: Option<&str>) {
+        update(&mut self.annotations, key, value)
+    }
+}
+
+impl<P: Clone, U: Clone> ObjectMetaExt for Object<P, U> {
+    fn label(&self, key: &str) -> Option<&str> {
+        self.metadata.label(key)
+    }
+
+    fn annotation(&self, key: &str) -> Option<&str> {
+        self.metadata.annotation(key)
+    }
+
+    fn update_label(&mut self, key: &str, value: Option<&str>) {
+        self.metadata.update_label(key, value)
+    }
+
+    fn update_annotation(&mut self, key: &str, value: Option<&str>) {
+        self.metadata.update_annotation(key, value)
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl ObjectMetaExt for k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta {
+    fn label(&self, key: &str) -> Option<&str> {
+        self.labels.as_ref()?.get(key).map(String::as_str)
+    }
+
+    fn annotation(&self, key: &str) -> Option<&str> {
+        self.annotations.as_ref()?.get(key).map(String::as_str)
+    }
+
+    fn update_label(&mut self, key: &str, value: Option<&str>) {
+        update(self.labels.get_or_insert_with(BTreeMap::new), key, value)
+    }
+
+    fn update_annotation(&mut self, key: &str, value: Option<&str>) {
+        update(self.annotations.get_or_insert_with(BTreeMap::new), key, value)
+    }
+}
+
+fn value_entry<'a>(object: &'a Value, map: &str, key: &str) -> Option<&'a str> {
+    object.get("metadata")?.get(map)?.get(key)?.as_str()
+}
+
+fn update_value(object: &mut Value, map: &str, key: &str, value: Option<&str>) {
+    if !object.is_object() {
+        return;
+    }
+    let metadata = object.as_object_mut().unwrap().entry("metadata").or_insert_with(|| json!({}));
+    let entries = match metadata.as_object_mut() {
+        Some(m) => m.entry(map).or_insert_with(|| json!({})),
+        None => return,
+    };
+    if entries.is_null() {
+        *entries = json!({});
+    }
+    if let Some(entries) = entries.as_object_mut() {
+        match value {
+            Some(v) => entries.insert(key.into(), v.into()),
+            None => entries.remove(key),
+        };
+    }
+}
+
+/// Dynamic objects; non objects are left alone
+impl ObjectMetaExt for Value {
+    fn label(&self, key: &str) -> Option<&str> {
+        value_entry(self, "labels", key)
+    }
+
+    fn annotation(&self, key: &str) -> Option<&str> {
+        value_entry(self, "annotations", key)
+    }
+
+    fn update_label(&mut self, key: &str, value: Option<&str>) {
+        update_value(self, "labels", key, value)
+    }
+
+    fn update_annotation(&mut self, key: &str, value: Option<&str>) {
+        update_value(self, "annotations", key, value)
+    }
+}
+
+/// Label and annotation changes, sent as a merge patch
+///
+/// Removals become `null`, so other labels and annotations on the object are kept.
+///
+/// ```no_run
+/// use kube::{api::{Api, MetaPatch, ObjectMetaExt, PatchParams}, client::APIClient, config};
+///
+/// let config = config::load_kube_config().unwrap();
+/// let client = APIClient::new(config);
+/// let mut patch = MetaPatch::new();
+/// patch.set_label("release", "stable");
+/// patch.remove_annotation("deprecated");
+/// Api::v1Pod(client).within("default").patch("web", &PatchParams::default(), patch.to_vec().unwrap()).unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetaPatch {
+    labels: BTreeMap<String, Option<String>>,
+    annotations: BTreeMap<String, Option<String>>,
+}
+
+impl MetaPatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty() && self.annotations.is_empty()
+    }
+
+    /// The merge patch, like `{"metadata":{"labels":{"app":"blog","old":null}}}`
+    pub fn to_value(&self) -> Value {
+        let mut metadata = serde_json::Map::new();
+        if !self.labels.is_empty() {
+            metadata.insert("labels".into(), json!(self.labels));
+        }
+        if !self.annotations.is_empty() {
+            metadata.insert("annotations".into(), json!(self.annotations));
+        }
+        json!({ "metadata": metadata })
+    }
+
+    /// The merge patch, ready for `Api::patch`
+    pub fn to_vec(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(&self.to_value()).context(ErrorKind::SerdeParse)?)
+    }
+}
+
+impl ObjectMetaExt for MetaPatch {
+    /// The label this patch sets, if any
+    fn label(&self, key: &str) -> Option<&str> {
+        self.labels.get(key)?.as_deref()
+    }
+
+    fn annotation(&self, key: &str) -> Option<&str> {
+        self.annotations.get(key)?.as_deref()
+    }
+
+    fn update_label(&mut self, key: &str, value: Option<&str>) {
+        self.labels.insert(key.into(), value.map(String::from));
+    }
+
+    fn update_annotation(&mut self, key: &str, value: Option<&str>) {
+        self.annotations.insert(key.into(), value.map(String::from));
+    }
+}
+
+#[test]
+fn labels_apply_and_patch_alike() {
+    use crate::api::{TypeMeta, Void};
+    let mut obj: Object<Void, Void> = Object {
+        types: TypeMeta::default(),
+        metadata: ObjectMeta { name: "web".into(), ..Default::default() },
+        spec: Void {},
+        status: None,
+    };
+    let mut patch = MetaPatch::new();
+    let mut dynamic = json!({ "metadata": { "name": "web", "labels": null } });
+    let labels: BTreeMap<_, _> = vec![("app".to_string(), "blog".to_string())].into_iter().collect();
+    for target in &mut [&mut obj as &mut dyn ObjectMetaExt, &mut patch, &mut dynamic] {
+        target.merge_labels(&labels);
+        target.set_annotation("note", "x");
+        target.remove_annotation("old");
+        assert_eq!(target.label("app"), Some("blog"));
+        assert!(target.has_annotation("note") && !target.has_annotation("old"));
+    }
+    assert_eq!(obj.metadata.labels, labels);
+    assert_eq!(dynamic["metadata"]["labels"], json!({ "app": "blog" }));
+    assert_eq!(patch.to_value(), json!({ "metadata": {
+        "labels": { "app": "blog" },
+        "annotations": { "note": "x", "old": null },
+    }}));
+}
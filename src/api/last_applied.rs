@@ -0,0 +1,131 @@
+//! The `kubectl.kubernetes.io/last-applied-configuration` annotation of client side apply
+//!
+//! `kubectl apply` records what it applied in this annotation, so that the next apply
+//! can tell fields removed from the manifest (which are deleted) from fields set by
+//! someone else (which are kept). Writing it the same way lets Rust tools and kubectl
+//! apply to the same objects.
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+
+use crate::api::KubeObject;
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+/// The annotation holding the last applied configuration
+pub const LAST_APPLIED_ANNOTATION: &str = "kubectl.kubernetes.io/last-applied-configuration";
+
+/// The apiserver's limit on the total size of all annotations of an object
+pub const MAX_ANNOTATIONS_SIZE: usize = 256 * 1024;
+
+fn parse(config: &str) -> Result<Value> {
+    Ok(serde_json::from_str(config).context(ErrorKind::SerdeParse)?)
+}
+
+/// The last applied configuration of an object, if it has one
+pub fn last_applied<K: KubeObject>(obj: &K) -> Result<Option<Value>> {
+    obj.meta().annotations.get(LAST_APPLIED_ANNOTATION).map(|c| parse(c)).transpose()
+}
+
+/// The last applied configuration of a json object, if it has one
+pub fn last_applied_value(obj: &Value) -> Result<Option<Value>> {
+    obj["metadata"]["annotations"][LAST_APPLIED_ANNOTATION].as_str().map(parse).transpose()
+}
+
+/// Record `obj` in its own last applied annotation, as `kubectl apply` does
+///
+/// The recorded configuration leaves out the annotation itself. Fails if the
+/// annotations would exceed `MAX_ANNOTATIONS_SIZE`, which the apiserver rejects;
+/// kubectl users hit this with very large ConfigMaps or CRDs.
+pub fn set_last_applied(obj: &mut Value) -> Result<()> {
+    let mut config = obj.clone();
+    if let Some(meta) = config.get_mut("metadata").and_then(Value::as_object_mut) {
+        if let Some(annotations) = meta.get_mut("annotations").and_then(Value::as_object_mut) {
+            annotations.remove(LAST_APPLIED_ANNOTATION);
+            if annotations.is_empty() {
+                meta.remove("annotations");
+            }
+        }
+    }
+    // kubectl's encoder ends the document with a newline, keep that for byte equality
+    let encoded = serde_json::to_string(&config).context(ErrorKind::SerdeParse)? + "\n";
+
+    let meta = obj.get_mut("metadata").and_then(Value::as_object_mut)
+        .ok_or_else(|| ErrorKind::RequestValidation("object without metadata".into()))?;
+    let annotations = meta.entry("annotations").or_insert_with(|| Value::Object(Map::new()));
+    let annotations = annotations.as_object_mut()
+        .ok_or_else(|| ErrorKind::RequestValidation("metadata.annotations is not a map".into()))?;
+    annotations.insert(LAST_APPLIED_ANNOTATION.into(), Value::String(encoded));
+    let size: usize = annotations.iter().map(|(k, v)| k.len() + v.as_str().map(str::len).unwrap_or(0)).sum();
+    if size > MAX_ANNOTATIONS_SIZE {
+        return Err(ErrorKind::RequestValidation(format!(
+            "annotations would be {} bytes, more than the limit of {}", size, MAX_ANNOTATIONS_SIZE)).into());
+    }
+    Ok(())
+}
+
+/// A typed object with its last applied annotation set
+pub fn with_last_applied<K: Serialize + DeserializeOwned>(obj: &K) -> Result<K> {
+    let mut value = serde_json::to_value(obj).context(ErrorKind::SerdeParse)?;
+    set_last_applied(&mut value)?;
+    Ok(serde_json::from_value(value).context(ErrorKind::SerdeParse)?)
+}
+
+/// The json merge patch `kubectl apply` would send to turn `live` into `desired`
+///
+/// Fields in `last` (the previous last applied configuration) that are no longer in
+/// `desired` are deleted; fields only in `live` are left alone. Lists are replaced
+/// whole, as a merge patch cannot merge them by key. `desired` should already carry
+/// its new annotation from `set_last_applied`. Returns `None` when nothing changes.
+pub fn three_way_merge_patch(last: Option<&Value>, desired: &Value, live: &Value) -> Option<Value> {
+    match (desired, live) {
+        (Value::Object(d), Value::Object(l)) => {
+            let last = last.and_then(Value::as_object);
+            let mut patch = Map::new();
+            for key in last.into_iter().flat_map(|m| m.keys()) {
+                if !d.contains_key(key) && l.contains_key(key) {
+                    patch.insert(key.clone(), Value::Null);
+                }
+            }
+            for (key, dv) in d {
+                let change = match l.get(key) {
+                    None => Some(dv.clone()),
+                    Some(lv) => three_way_merge_patch(last.and_then(|m| m.get(key)), dv, lv),
+                };
+                if let Some(change) = change {
+                    patch.insert(key.clone(), change);
+                }
+            }
+            if patch.is_empty() { None } else { Some(Value::Object(patch)) }
+        },
+        (d, l) if d == l => None,
+        (d, _) => Some(d.clone()),
+    }
+}
+
+#[test]
+fn last_applied_roundtrip_and_patch() {
+    use serde_json::json;
+    let mut desired = json!({
+        "apiVersion": "v1", "kind": "ConfigMap",
+        "metadata": { "name": "cfg" },
+        "data": { "a": "1" },
+    });
+    set_last_applied(&mut desired).unwrap();
+    let recorded = last_applied_value(&desired).unwrap().unwrap();
+    assert!(desired["metadata"]["annotations"][LAST_APPLIED_ANNOTATION].as_str().unwrap().ends_with("}\n"));
+    assert!(recorded["metadata"].get("annotations").is_none());
+    assert_eq!(recorded["data"]["a"], "1");
+
+    let last = json!({ "data": { "a": "0", "removed": "x" } });
+    let live = json!({
+        "metadata": { "name": "cfg", "uid": "123" },
+        "data": { "a": "0", "removed": "x", "theirs": "y" },
+    });
+    let patch = three_way_merge_patch(Some(&last), &desired, &live).unwrap();
+    assert_eq!(patch["data"], json!({ "a": "1", "removed": null }));
+    assert!(patch["metadata"].get("uid").is_none());
+    assert!(three_way_merge_patch(None, &live, &live).is_none());
+
+    let mut huge = json!({ "metadata": { "name": "big" }, "data": { "blob": "x".repeat(MAX_ANNOTATIONS_SIZE) } });
+    assert!(set_last_applied(&mut huge).is_err());
+}
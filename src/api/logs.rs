@@ -0,0 +1,291 @@
+//! Following the logs of every pod matching a selector
+use std::collections::HashSet;
+use std::fmt;
+use std::io::{BufRead, BufReader};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::api::{Informer, ListParams, LogParams, Object, ObjectList, RawApi, WatchEvent};
+use crate::client::APIClient;
+use crate::Result;
+
+use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+
+type Pod = Object<PodSpec, PodStatus>;
+
+/// A line of output from a container
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogLine {
+    pub pod: String,
+    pub container: String,
+    /// Without the trailing newline
+    pub line: String,
+}
+
+/// Prefixed with pod and container, as `stern` prints it
+impl fmt::Display for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.pod, self.container, self.line)
+    }
+}
+
+type LineHandler = Arc<dyn Fn(&LogLine) + Send + Sync>;
+
+/// (pod, container, container id)
+type Tail = (String, String, String);
+
+/// Follows the logs of all containers in pods matching a label selector
+///
+/// Pods are watched, so the logs of pods created later are followed as they start,
+/// as are those of restarted containers. Each container is followed on its own
+/// thread, which ends once the container does.
+///
+/// ```no_run
+/// use kube::{api::LogTailer, client::APIClient, config};
+///
+/// let config = config::load_kube_config().unwrap();
+/// let client = APIClient::new(config);
+/// LogTailer::new(client, "app=blog")
+///     .namespace("default")
+///     .tail_lines(10)
+///     .on_line(|l| println!("{}", l))
+///     .run()
+///     .unwrap();
+/// ```
+pub struct LogTailer {
+    client: APIClient,
+    pods: RawApi,
+    selector: String,
+    container: Option<String>,
+    params: LogParams,
+    on_line: LineHandler,
+    informer: Option<Informer<Pod>>,
+    tailing: Arc<Mutex<HashSet<Tail>>>,
+}
+
+impl LogTailer {
+    /// Follow pods matching `selector`, e.g. `app=blog`, across all namespaces
+    pub fn new(client: APIClient, selector: &str) -> Self {
+        LogTailer {
+            client,
+            pods: RawApi::v1Pod(),
+            selector: selector.into(),
+            container: None,
+            params: LogParams::default(),
+            on_line: Arc::new(|l: &LogLine| info!("{}", l)),
+            informer: None,
+            tailing: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Only follow pods in this namespace
+    pub fn namespace(mut self, ns: &str) -> Self {
+        self.pods = self.pods.within(ns);
+        self
+    }
+
+    /// Only follow containers of this name
+    pub fn container(mut self, name: &str) -> Self {
+        self.container = Some(name.into());
+        self
+    }
+
+    /// Start with the last `n` lines of containers running at `init`
+    pub fn tail_lines(mut self, n: i64) -> Self {
+        self.params.tail_lines = Some(n);
+        self
+    }
+
+    /// Start with the lines of the last `secs` seconds of containers running at `init`
+    pub fn since_seconds(mut self, secs: i64) -> Self {
+        self.params.since_seconds = Some(secs);
+        self
+    }
+
+    /// Have the kubelet prefix each line with its RFC3339 timestamp
+    pub fn timestamps(mut self) -> Self {
+        self.params.timestamps = true;
+        self
+    }
+
+    /// Called from the container threads with every line, instead of logging it at info
+    pub fn on_line<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&LogLine) + Send + Sync + 'static,
+    {
+        self.on_line = Arc::new(f);
+        self
+    }
+
+    /// Start following the containers running now, and watch for new ones
+    pub fn init(mut self) -> Result<Self> {
+        let version = self.follow_all(true)?;
+        let informer = Informer::raw(self.client.clone(), self.pods.clone())
+            .labels(&self.selector)
+            .init_from(version);
+        self.informer = Some(informer);
+        Ok(self)
+    }
+
+    /// Wait for pod changes once, following any containers that started
+    pub fn poll(&self) -> Result<()> {
+        let informer = match &self.informer {
+            Some(i) => i,
+            None => return Ok(()),
+        };
+        informer.poll()?;
+        while let Some(event) = informer.pop() {
+            match event {
+                WatchEvent::Added(pod) | WatchEvent::Modified(pod) => self.follow(&pod, false),
+                // followed containers end with their pod
+                WatchEvent::Deleted(_) => {},
+                WatchEvent::Error(e) => {
+                    warn!("Pod watch failed: {}, relisting", e);
+                    informer.reset()?;
+                    self.follow_all(false)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// `init`, then `poll` until an error occurs
+    pub fn run(self) -> Result<()> {
+        let tailer = self.init()?;
+        loop {
+            tailer.poll()?;
+        }
+    }
+
+    /// Follow all matching pods, returning the resourceVersion of the list
+    fn follow_all(&self, initial: bool) -> Result<String> {
+        let lp = ListParams { label_selector: Some(self.selector.clone()), ..Default::default() };
+        let pods = self.client.request::<ObjectList<Pod>>(self.pods.list(&lp)?)?;
+        for pod in &pods.items {
+            self.follow(pod, initial);
+        }
+        Ok(pods.metadata.resourceVersion.unwrap_or_default())
+    }
+
+    /// Start a thread for every running container of the pod not followed yet
+    fn follow(&self, pod: &Pod, initial: bool) {
+        for (container, id) in running_containers(pod) {
+            if self.container.as_ref().is_some_and(|c| *c != container) {
+                continue;
+            }
+            let tail = (pod.metadata.name.clone(), container, id);
+            if !self.tailing.lock().unwrap().insert(tail.clone()) {
+                continue;
+            }
+            // containers that started since are followed from their first line
+            let lp = if initial {
+                self.params.clone()
+            } else {
+                LogParams { timestamps: self.params.timestamps, ..Default::default() }
+            };
+            let mut pods = self.pods.clone();
+            pods.namespace = pod.metadata.namespace.clone();
+            let (client, on_line, tailing) = (self.client.clone(), self.on_line.clone(), self.tailing.clone());
+            std::thread::spawn(move || {
+                if let Err(e) = follow_container(&client, &pods, &tail, lp, &*on_line) {
+                    warn!("Stopped following {} {}: {}", tail.0, tail.1, e);
+                }
+                tailing.lock().unwrap().remove(&tail);
+            });
+        }
+    }
+}
+
+/// (name, container id) of the running containers and init containers of a pod
+fn running_containers(pod: &Pod) -> Vec<(String, String)> {
+    let status = match &pod.status {
+        Some(s) => s,
+        None => return vec![],
+    };
+    status.init_container_statuses.iter().chain(status.container_statuses.iter())
+        .flatten()
+        .filter(|cs| cs.state.as_ref().is_some_and(|s| s.running.is_some()))
+        .filter_map(|cs| Some((cs.name.clone(), cs.container_id.clone()?)))
+        .collect()
+}
+
+/// Pass on every line of a container until it is no longer running
+///
+/// Streams also end when the client's request timeout hits, and are then resumed
+/// from shortly before the last line received, so a few lines may repeat.
+fn follow_container(client: &APIClient, pods: &RawApi, tail: &Tail, mut lp: LogParams, on_line: &dyn Fn(&LogLine)) -> Result<()> {
+    let (pod, container, id) = tail;
+    lp.follow = true;
+    lp.container = Some(container.clone());
+    loop {
+        let mut last = Instant::now();
+        match client.request_stream(pods.log(pod, &lp)?) {
+            Ok(body) => {
+                for line in BufReader::new(body).lines() {
+                    let line = match line {
+                        Ok(l) => l,
+                        Err(e) => {
+                            debug!("Log stream of {} {} ended: {}", pod, container, e);
+                            break;
+                        },
+                    };
+                    on_line(&LogLine { pod: pod.clone(), container: container.clone(), line });
+                    last = Instant::now();
+                }
+            },
+            Err(e) => {
+                debug!("Failed to follow {} {}: {}", pod, container, e);
+                std::thread::sleep(Duration::from_secs(1));
+            },
+        }
+        let live = match client.request::<Pod>(pods.get(pod)?) {
+            Ok(p) => p,
+            Err(ref e) if e.is_not_found() => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if !running_containers(&live).iter().any(|(c, i)| c == container && i == id) {
+            return Ok(());
+        }
+        lp.tail_lines = None;
+        lp.since_seconds = Some(last.elapsed().as_secs() as i64 + 1);
+    }
+}
+
+#[test]
+fn logs_of_new_pods_are_followed() {
+    use crate::client::fake::Fake;
+    let pod = |name: &str, id: &str| format!(
+        r#"{{"metadata":{{"name":"{}","namespace":"blog","resourceVersion":"2"}},"spec":{{"containers":[]}},
+        "status":{{"containerStatuses":[{{"name":"app","image":"nginx","imageID":"","ready":true,"restartCount":0,
+        "containerID":"{}","state":{{"running":{{}}}}}}]}}}}"#, name, id);
+    let fake = Fake::new()
+        .on("GET", "/api/v1/namespaces/blog/pods?watch=true", 200,
+            format!(r#"{{"type":"ADDED","object":{}}}"#, pod("b", "docker://2")).replace('\n', "") + "\n")
+        .on("GET", "/api/v1/namespaces/blog/pods", 200, format!(r#"{{"metadata":{{"resourceVersion":"1"}},"items":[{}]}}"#, pod("a", "docker://1")))
+        .on("GET", "/api/v1/namespaces/blog/pods/a/log", 200, "hello\nworld\n")
+        .on("GET", "/api/v1/namespaces/blog/pods/b/log", 200, "started\n");
+    let client = fake.client();
+    let lines = Arc::new(Mutex::new(vec![]));
+    let seen = lines.clone();
+    let tailer = LogTailer::new(client, "app=web")
+        .namespace("blog")
+        .tail_lines(5)
+        .on_line(move |l| seen.lock().unwrap().push(l.to_string()))
+        .init()
+        .unwrap();
+    tailer.poll().unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !tailer.tailing.lock().unwrap().is_empty() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut lines = lines.lock().unwrap().clone();
+    lines.sort();
+    assert_eq!(lines, vec!["a app hello", "a app world", "b app started"]);
+    let queries = |path: &str| fake.requests().into_iter()
+        .filter(|r| r.path() == path).map(|r| r.query().to_string()).collect::<Vec<_>>();
+    let pods = queries("/api/v1/namespaces/blog/pods");
+    assert!(pods.iter().any(|q| q.contains("labelSelector=app%3Dweb") && !q.contains("watch=true")));
+    assert!(pods.iter().filter(|q| q.contains("watch=true")).all(|q| q.contains("resourceVersion=1")));
+    assert!(queries("/api/v1/namespaces/blog/pods/a/log").iter().all(|q| q.contains("tailLines=5") && q.contains("container=app")));
+    assert!(queries("/api/v1/namespaces/blog/pods/b/log").iter().all(|q| !q.contains("tailLines")));
+}
@@ -0,0 +1,159 @@
+//! Loading manifests from multi document YAML or JSON, as `kubectl apply -f` does
+//!
+//! ```
+//! use kube::api::manifest::{Document, Loader};
+//! use kube::api::{Object, Void};
+//!
+//! enum Known {
+//!     Namespace(Object<Void, Void>),
+//! }
+//!
+//! let yaml = "apiVersion: v1\nkind: Namespace\nmetadata:\n  name: blog\nspec: {}\n---\n\
+//!             apiVersion: example.com/v1\nkind: Foo\nmetadata:\n  name: foo\n";
+//! let docs = Loader::new()
+//!     .register("v1", "Namespace", Known::Namespace)
+//!     .load_str(yaml)
+//!     .unwrap();
+//! assert!(matches!(docs[0], Document::Typed(Known::Namespace(_))));
+//! assert!(matches!(docs[1], Document::Dynamic(_)));
+//! ```
+use std::collections::HashMap;
+use std::io::Read;
+
+use serde::de::{Deserialize, DeserializeOwned};
+use serde_json::Value;
+
+use crate::api::GroupVersionKind;
+use crate::{Result, Error, ErrorKind};
+use failure::ResultExt;
+
+/// Split a stream of YAML or JSON documents into json values, in order
+///
+/// YAML documents are separated by `---`; JSON documents may simply follow each other.
+/// Empty documents are skipped, and `List` objects are expanded into their items.
+pub fn split_documents(input: &str) -> Result<Vec<Value>> {
+    let mut docs = vec![];
+    let trimmed = input.trim_start();
+    if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        for doc in serde_json::Deserializer::from_str(trimmed).into_iter::<Value>() {
+            docs.push(doc.context(ErrorKind::SerdeParse)?);
+        }
+    } else {
+        for doc in serde_yaml::Deserializer::from_str(input) {
+            docs.push(Value::deserialize(doc).context(ErrorKind::SerdeParse)?);
+        }
+    }
+    let mut out = vec![];
+    for doc in docs {
+        expand(doc, &mut out);
+    }
+    Ok(out)
+}
+
+fn expand(doc: Value, out: &mut Vec<Value>) {
+    match doc {
+        Value::Null => {},
+        Value::Array(items) => items.into_iter().for_each(|i| expand(i, out)),
+        Value::Object(mut obj) if obj.get("kind").and_then(Value::as_str).map(|k| k.ends_with("List")).unwrap_or(false)
+            && obj.get("items").map(Value::is_array).unwrap_or(false) => {
+            expand(obj.remove("items").unwrap(), out)
+        },
+        doc => out.push(doc),
+    }
+}
+
+/// A loaded document
+#[derive(Clone, Debug)]
+pub enum Document<T> {
+    /// A document of a registered kind
+    Typed(T),
+    /// Anything else
+    Dynamic(Value),
+}
+
+type Parser<T> = Box<dyn Fn(Value) -> Result<T> + Send + Sync>;
+
+/// Parses documents into typed objects by their `apiVersion` and `kind`
+///
+/// Kinds are registered with a constructor for a type of your choosing, usually an enum
+/// of the kinds a tool knows about; all other documents are kept as json.
+pub struct Loader<T> {
+    parsers: HashMap<GroupVersionKind, Parser<T>>,
+}
+
+impl<T> Default for Loader<T> {
+    fn default() -> Self {
+        Loader { parsers: HashMap::new() }
+    }
+}
+
+impl<T> Loader<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse documents of `apiVersion` and `kind` as `K`, and wrap them with `f`
+    pub fn register<K, F>(mut self, api_version: &str, kind: &str, f: F) -> Self
+    where
+        K: DeserializeOwned,
+        F: Fn(K) -> T + Send + Sync + 'static,
+    {
+        let parser = move |v: Value| -> Result<T> {
+            Ok(f(serde_json::from_value(v).context(ErrorKind::SerdeParse)?))
+        };
+        // an invalid apiVersion matches no documents
+        let gvk = GroupVersionKind::from_api_version(api_version, kind)
+            .unwrap_or_else(|_| GroupVersionKind::new("", api_version, kind));
+        self.parsers.insert(gvk, Box::new(parser));
+        self
+    }
+
+    /// Parse a single json value
+    ///
+    /// Fails for documents without an `apiVersion` and `kind`, or that a registered type rejects.
+    pub fn parse(&self, doc: Value) -> Result<Document<T>> {
+        let gvk = GroupVersionKind::from_value(&doc).map_err(|_| Error::from(ErrorKind::RequestValidation(
+            format!("document without apiVersion and kind: {}", doc))))?;
+        match self.parsers.get(&gvk) {
+            Some(parse) => parse(doc).map(Document::Typed),
+            None => Ok(Document::Dynamic(doc)),
+        }
+    }
+
+    /// Load all documents in a YAML or JSON stream, in order
+    pub fn load_str(&self, input: &str) -> Result<Vec<Document<T>>> {
+        split_documents(input)?.into_iter().map(|d| self.parse(d)).collect()
+    }
+
+    /// Load all documents from a reader, e.g. a file or stdin
+    pub fn load_reader<R: Read>(&self, mut reader: R) -> Result<Vec<Document<T>>> {
+        let mut input = String::new();
+        reader.read_to_string(&mut input).context(ErrorKind::RequestParse)?;
+        self.load_str(&input)
+    }
+}
+
+#[test]
+fn documents_are_split_in_order() {
+    let yaml = "---\napiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: a\ndata: {}\n---\n# empty\n---\n\
+        apiVersion: v1\nkind: List\nitems:\n- apiVersion: v1\n  kind: Secret\n  metadata:\n    name: b\n\
+        - apiVersion: v1\n  kind: Service\n  metadata:\n    name: c\n";
+    let names: Vec<_> = split_documents(yaml).unwrap().into_iter()
+        .map(|d| d["metadata"]["name"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(names, vec!["a", "b", "c"]);
+
+    let json = r#"{"apiVersion":"v1","kind":"ConfigMap","metadata":{"name":"a"}} {"apiVersion":"v1","kind":"Secret","metadata":{"name":"b"}}"#;
+    assert_eq!(split_documents(json).unwrap().len(), 2);
+
+    #[derive(Deserialize)]
+    struct ConfigMap {
+        metadata: crate::api::ObjectMeta,
+    }
+    let loader: Loader<String> = Loader::new()
+        .register("v1", "ConfigMap", |cm: ConfigMap| cm.metadata.name);
+    let docs = loader.load_str(yaml).unwrap();
+    assert!(matches!(&docs[0], Document::Typed(name) if name == "a"));
+    assert!(matches!(&docs[1], Document::Dynamic(_)));
+    assert!(loader.load_str("foo: bar").is_err());
+}
@@ -2,6 +2,9 @@
 
 use std::collections::BTreeMap;
 
+use crate::api::resource::KubeObject;
+use crate::{Result, Error, ErrorKind};
+
 #[derive(Deserialize, Serialize, Clone, Default)]
 pub struct TypeMeta {
     /// The version of the API
@@ -20,6 +23,7 @@ pub struct TypeMeta {
 
 #[derive(Deserialize, Serialize, Clone, Default)]
 pub struct ListMeta {
+    #[serde(rename = "continue")]
     pub continue_: Option<String>,
     pub resourceVersion: Option<String>,
     pub selfLink: Option<String>,
@@ -100,6 +104,82 @@ pub struct OwnerReference {
     pub uid: String,
 }
 
+impl OwnerReference {
+    /// Build a non-controlling reference to an owner object
+    ///
+    /// The owner must have been read from the apiserver, so that apiVersion, kind and uid are set.
+    pub fn for_owner<K: KubeObject>(owner: &K) -> Result<OwnerReference> {
+        let meta = owner.meta();
+        let types = owner.types().cloned().unwrap_or_default();
+        let missing = |field: &str| Error::from(ErrorKind::RequestValidation(
+            format!("owner {} is missing {}", meta.name, field)
+        ));
+        Ok(OwnerReference {
+            controller: false,
+            blockOwnerDeletion: false,
+            name: meta.name.clone(),
+            apiVersion: types.apiVersion.ok_or_else(|| missing("apiVersion"))?,
+            kind: types.kind.ok_or_else(|| missing("kind"))?,
+            uid: meta.uid.clone().ok_or_else(|| missing("uid"))?,
+        })
+    }
+
+    /// Build a controlling reference to an owner object
+    ///
+    /// Sets both `controller` and `blockOwnerDeletion`, like `metav1.NewControllerRef`.
+    pub fn controller_for<K: KubeObject>(owner: &K) -> Result<OwnerReference> {
+        let mut oref = Self::for_owner(owner)?;
+        oref.controller = true;
+        oref.blockOwnerDeletion = true;
+        Ok(oref)
+    }
+}
+
+impl ObjectMeta {
+    /// Add an owner reference, replacing any existing reference to the same owner uid
+    pub fn add_owner_reference(&mut self, oref: OwnerReference) {
+        self.ownerReferences.retain(|o| o.uid != oref.uid);
+        self.ownerReferences.push(oref);
+    }
+
+    /// Make `owner` the controller of this object
+    ///
+    /// Fails if the object is already controlled by a different owner.
+    pub fn set_controller_reference<K: KubeObject>(&mut self, owner: &K) -> Result<()> {
+        let oref = OwnerReference::controller_for(owner)?;
+        if let Some(existing) = self.controller_reference() {
+            if existing.uid != oref.uid {
+                return Err(Error::from(ErrorKind::RequestValidation(format!(
+                    "{} is already controlled by {} {}", self.name, existing.kind, existing.name
+                ))));
+            }
+        }
+        self.add_owner_reference(oref);
+        Ok(())
+    }
+
+    /// The owner reference marked as the managing controller, if any
+    pub fn controller_reference(&self) -> Option<&OwnerReference> {
+        self.ownerReferences.iter().find(|o| o.controller)
+    }
+
+    /// Whether `owner` is among the owners of this object
+    pub fn is_owned_by<K: KubeObject>(&self, owner: &K) -> bool {
+        match &owner.meta().uid {
+            Some(uid) => self.ownerReferences.iter().any(|o| &o.uid == uid),
+            None => false,
+        }
+    }
+
+    /// Whether `owner` is the managing controller of this object
+    pub fn is_controlled_by<K: KubeObject>(&self, owner: &K) -> bool {
+        match (&owner.meta().uid, self.controller_reference()) {
+            (Some(uid), Some(c)) => &c.uid == uid,
+            _ => false,
+        }
+    }
+}
+
 /// Initializers tracks the progress of initialization
 #[derive(Deserialize, Serialize, Clone, Default)]
 pub struct Initializers {
@@ -127,3 +207,39 @@ pub struct Status {
     pub reason: Option<String>,
     pub status: Option<String>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{Object, Void};
+
+    fn object(name: &str, uid: &str) -> Object<Void, Void> {
+        Object {
+            types: TypeMeta { apiVersion: Some("apps/v1".into()), kind: Some("Deployment".into()) },
+            metadata: ObjectMeta { name: name.into(), uid: Some(uid.into()), ..Default::default() },
+            spec: Void {},
+            status: None,
+        }
+    }
+
+    #[test]
+    fn controller_references() {
+        let parent = object("parent", "1234");
+        let other = object("other", "5678");
+        let mut child = ObjectMeta::default();
+        child.set_controller_reference(&parent).unwrap();
+        child.set_controller_reference(&parent).unwrap(); // idempotent
+        assert_eq!(child.ownerReferences.len(), 1);
+        assert!(child.is_controlled_by(&parent));
+        assert!(!child.is_controlled_by(&other));
+        assert!(child.set_controller_reference(&other).is_err());
+
+        child.add_owner_reference(OwnerReference::for_owner(&other).unwrap());
+        assert!(child.is_owned_by(&other));
+        assert!(!child.is_controlled_by(&other));
+
+        let mut untyped = object("untyped", "9");
+        untyped.types = TypeMeta::default();
+        assert!(OwnerReference::for_owner(&untyped).is_err());
+    }
+}
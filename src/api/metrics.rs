@@ -0,0 +1,268 @@
+//! kube-state-metrics style gauges from reflector caches
+//!
+//! Enabled with the `metrics` feature. Gauges are computed from the reflector
+//! caches when scraped, so they are as fresh as the last `poll` of each reflector.
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::time::Duration;
+use failure::ResultExt;
+
+use serde::de::DeserializeOwned;
+
+use crate::api::{Object, Reflector, KubeObject};
+use crate::client::ReconnectingTransport;
+use crate::{Result, ErrorKind};
+
+use k8s_openapi::api::apps::v1::{DeploymentSpec, DeploymentStatus};
+use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+
+/// How long a metrics connection may take to send its request, or read the answer
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const POD_PHASES: [&str; 5] = ["Pending", "Running", "Succeeded", "Failed", "Unknown"];
+
+/// A single sample of a gauge
+struct Sample {
+    labels: Vec<(&'static str, String)>,
+    value: f64,
+}
+
+/// A gauge family with its help text, computed on every scrape
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    collect: Box<dyn Fn() -> Vec<Sample> + Send + Sync>,
+}
+
+/// Gauges derived from reflectors, rendered in the Prometheus text format
+///
+/// ```no_run
+/// use kube::{api::{Api, Reflector, Metrics}, client::APIClient, config};
+///
+/// let client = APIClient::new(config::load_kube_config().unwrap());
+/// let pods = Reflector::new(Api::v1Pod(client)).init().unwrap();
+/// let mut metrics = Metrics::default();
+/// metrics.pods(pods.clone());
+/// std::thread::spawn(move || metrics.serve("0.0.0.0:8080"));
+/// loop {
+///     pods.poll().unwrap();
+/// }
+/// ```
+#[derive(Default)]
+pub struct Metrics {
+    families: Vec<Family>,
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn namespace_of<K: KubeObject>(obj: &K) -> String {
+    obj.meta().namespace.clone().unwrap_or_default()
+}
+
+impl Metrics {
+    /// Count the objects of a kind in a reflector, by namespace
+    pub fn object_counts<K>(&mut self, kind: &str, reflector: Reflector<K>)
+    where
+        K: Clone + DeserializeOwned + KubeObject + Send + Sync + 'static,
+    {
+        let kind = kind.to_string();
+        self.families.push(Family {
+            name: "kube_objects",
+            help: "Number of objects by kind and namespace",
+            collect: Box::new(move || {
+                let mut counts = BTreeMap::new();
+                for obj in reflector.read().unwrap_or_default() {
+                    *counts.entry(namespace_of(&obj)).or_insert(0) += 1;
+                }
+                counts.into_iter().map(|(ns, n)| Sample {
+                    labels: vec![("kind", kind.clone()), ("namespace", ns)],
+                    value: f64::from(n),
+                }).collect()
+            }),
+        });
+    }
+
+    /// Desired, updated and available replicas of Deployments
+    pub fn deployments(&mut self, reflector: Reflector<Object<DeploymentSpec, DeploymentStatus>>) {
+        type Getter = fn(&Object<DeploymentSpec, DeploymentStatus>) -> Option<i32>;
+        let gauges: [(&'static str, &'static str, Getter); 4] = [
+            ("kube_deployment_spec_replicas", "Desired replicas of a deployment",
+                |d| d.spec.replicas),
+            ("kube_deployment_status_replicas", "Replicas of a deployment",
+                |d| d.status.as_ref().and_then(|s| s.replicas)),
+            ("kube_deployment_status_replicas_updated", "Updated replicas of a deployment",
+                |d| d.status.as_ref().and_then(|s| s.updated_replicas)),
+            ("kube_deployment_status_replicas_available", "Available replicas of a deployment",
+                |d| d.status.as_ref().and_then(|s| s.available_replicas)),
+        ];
+        for (name, help, get) in gauges.iter().cloned() {
+            let reflector = reflector.clone();
+            self.families.push(Family {
+                name,
+                help,
+                collect: Box::new(move || {
+                    reflector.read().unwrap_or_default().iter().map(|d| Sample {
+                        labels: vec![("namespace", namespace_of(d)), ("deployment", d.metadata.name.clone())],
+                        value: f64::from(get(d).unwrap_or(0)),
+                    }).collect()
+                }),
+            });
+        }
+    }
+
+    /// The phase of every pod, as one series per phase that is 1 for the current one
+    pub fn pods(&mut self, reflector: Reflector<Object<PodSpec, PodStatus>>) {
+        self.families.push(Family {
+            name: "kube_pod_status_phase",
+            help: "The current phase of a pod",
+            collect: Box::new(move || {
+                let mut samples = vec![];
+                for pod in reflector.read().unwrap_or_default() {
+                    let current = pod.status.as_ref().and_then(|s| s.phase.clone()).unwrap_or_else(|| "Unknown".into());
+                    for phase in POD_PHASES.iter() {
+                        samples.push(Sample {
+                            labels: vec![
+                                ("namespace", namespace_of(&pod)),
+                                ("pod", pod.metadata.name.clone()),
+                                ("phase", phase.to_string()),
+                            ],
+                            value: if current == *phase { 1.0 } else { 0.0 },
+                        });
+                    }
+                }
+                samples
+            }),
+        });
+    }
+
+    /// How often the client of a `ReconnectingTransport` was rebuilt, and its current connection failures
+    pub fn connection(&mut self, transport: ReconnectingTransport) {
+        type Getter = fn(&ReconnectingTransport) -> f64;
+        let gauges: [(&'static str, &'static str, Getter); 2] = [
+            ("kube_client_rebuilds", "Times the client was built again after connection failures",
+                |t| t.rebuilds() as f64),
+            ("kube_client_connection_failures", "Connection failures in a row",
+                |t| f64::from(t.consecutive_failures())),
+        ];
+        for (name, help, get) in gauges.iter().cloned() {
+            let transport = transport.clone();
+            self.families.push(Family {
+                name,
+                help,
+                collect: Box::new(move || vec![Sample { labels: vec![("server", transport.base_path())], value: get(&transport) }]),
+            });
+        }
+    }
+
+    /// Render all gauges in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        // families registered more than once (e.g. per kind) are rendered as one,
+        // since the samples of a family may not be split up
+        let mut names: Vec<&'static str> = vec![];
+        for family in &self.families {
+            if !names.contains(&family.name) {
+                names.push(family.name);
+            }
+        }
+        for name in names {
+            let mut families = self.families.iter().filter(|f| f.name == name).peekable();
+            let help = families.peek().map(|f| f.help).unwrap_or_default();
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name));
+            for family in families {
+                for sample in (family.collect)() {
+                    let labels: Vec<String> = sample.labels.iter()
+                        .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+                        .collect();
+                    out.push_str(&format!("{}{{{}}} {}\n", name, labels.join(","), sample.value));
+                }
+            }
+        }
+        out
+    }
+
+    /// Serve the gauges over http on `addr`, blocking forever
+    ///
+    /// Connections are answered one at a time; one that stalls is dropped after ten
+    /// seconds, so it cannot hold up the next scrape for longer.
+    pub fn serve<A: ToSocketAddrs>(&self, addr: A) -> Result<()> {
+        let listener = TcpListener::bind(addr).context(ErrorKind::Io("metrics listener".into()))?;
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(s) => s,
+                Err(e) => { warn!("Metrics connection failed: {}", e); continue; },
+            };
+            if let Err(e) = stream.set_read_timeout(Some(SCRAPE_TIMEOUT))
+                .and_then(|_| stream.set_write_timeout(Some(SCRAPE_TIMEOUT)))
+            {
+                warn!("Metrics connection failed: {}", e);
+                continue;
+            }
+            // drain the request head; every path serves the metrics
+            let mut reader = BufReader::new(match stream.try_clone() {
+                Ok(s) => s,
+                Err(_) => continue,
+            });
+            let mut line = String::new();
+            while reader.read_line(&mut line).map(|n| n > 0).unwrap_or(false) && line != "\r\n" {
+                line.clear();
+            }
+            let body = self.render();
+            let res = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(), body);
+            if let Err(e) = stream.write_all(res.as_bytes()) {
+                warn!("Failed to write metrics: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn render_text_format() {
+    let mut m = Metrics::default();
+    m.families.push(Family {
+        name: "kube_objects",
+        help: "Number of objects by kind and namespace",
+        collect: Box::new(|| vec![Sample { labels: vec![("kind", "Pod".into()), ("namespace", "a\"b".into())], value: 3.0 }]),
+    });
+    assert_eq!(m.render(), "# HELP kube_objects Number of objects by kind and namespace\n\
+        # TYPE kube_objects gauge\n\
+        kube_objects{kind=\"Pod\",namespace=\"a\\\"b\"} 3\n");
+}
+
+#[test]
+fn families_are_rendered_together() {
+    use crate::api::Api;
+    use crate::client::fake::Fake;
+    let client = Fake::new()
+        .on("GET", "/api/v1/pods", 200, r#"{"metadata":{"resourceVersion":"1"},"items":[
+            {"metadata":{"name":"web-1","namespace":"prod"},"spec":{"containers":[]},"status":{"phase":"Running"}}]}"#)
+        .on("GET", "/apis/apps/v1/deployments", 200, r#"{"metadata":{"resourceVersion":"1"},"items":[
+            {"metadata":{"name":"web","namespace":"prod"},"spec":{"replicas":2,"selector":{},"template":{}},
+             "status":{"replicas":2,"updatedReplicas":1,"availableReplicas":1}}]}"#)
+        .client();
+    let pods = Reflector::new(Api::v1Pod(client.clone())).init().unwrap();
+    let deploys = Reflector::new(Api::v1Deployment(client)).init().unwrap();
+    let mut m = Metrics::default();
+    m.object_counts("Pod", pods.clone());
+    m.deployments(deploys.clone());
+    m.object_counts("Deployment", deploys);
+    m.pods(pods);
+
+    let text = m.render();
+    let lines: Vec<&str> = text.lines().collect();
+    let objects: Vec<usize> = lines.iter().enumerate()
+        .filter(|(_, l)| l.starts_with("kube_objects{")).map(|(i, _)| i).collect();
+    assert_eq!(objects.len(), 2);
+    assert_eq!(objects[1], objects[0] + 1, "samples of a family are not split up:\n{}", text);
+    assert_eq!(lines.iter().filter(|l| l.starts_with("# TYPE kube_objects ")).count(), 1);
+    assert!(lines.contains(&"kube_objects{kind=\"Deployment\",namespace=\"prod\"} 1"));
+    assert!(lines.contains(&"kube_deployment_status_replicas_updated{namespace=\"prod\",deployment=\"web\"} 1"));
+    assert!(lines.contains(&"kube_pod_status_phase{namespace=\"prod\",pod=\"web-1\",phase=\"Running\"} 1"));
+    assert!(lines.contains(&"kube_pod_status_phase{namespace=\"prod\",pod=\"web-1\",phase=\"Pending\"} 0"));
+}
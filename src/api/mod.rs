@@ -5,28 +5,45 @@
 pub struct Void {}
 
 mod reflector;
-pub use self::reflector::Reflector;
+pub use self::reflector::{Reflector, NAMESPACE_INDEX, OWNER_INDEX};
 
 mod informer;
 pub use self::informer::{
     Informer,
 };
 
+mod coalesce;
+pub use self::coalesce::Coalescer;
+
+mod fleet;
+pub use self::fleet::{FleetInformer, ClusterEvent};
+
 mod raw;
 pub use raw::{
     RawApi,
     ListParams,
+    ResourceVersionMatch,
     PostParams,
+    ContentType,
     PatchParams,
+    FieldValidation,
     DeleteParams,
     PropagationPolicy,
     PatchStrategy,
     LogParams
 };
 
+mod request;
+pub use request::RequestBuilder;
+
 mod typed;
 pub use typed::{
     Api,
+    Resource,
+    ResourceScope,
+    NamespaceResourceScope,
+    ClusterResourceScope,
+    ResourceRequest,
     // well, ok:
     Scale,
     ScaleSpec,
@@ -38,16 +55,180 @@ mod resource;
 pub use self::resource::{
     Object,
     ObjectList,
+    PartialList,
+    ItemError,
     WatchEvent,
     KubeObject,
 };
 
+pub(crate) mod wait;
+
+pub mod json_patch;
+pub mod jsonpath;
+pub mod output;
+pub mod manifest;
+pub mod backup;
+pub mod deprecations;
+
+mod retry;
+pub use self::retry::{Backoff, retry_on_conflict, retry_on_error};
+
+mod last_applied;
+pub use self::last_applied::{
+    LAST_APPLIED_ANNOTATION, MAX_ANNOTATIONS_SIZE,
+    last_applied, last_applied_value, set_last_applied, with_last_applied, three_way_merge_patch,
+};
+
+mod sanitize;
+pub use self::sanitize::sanitize_for_create;
+
+mod labels;
+pub use self::labels::{ObjectMetaExt, MetaPatch};
+
+mod units;
+pub use self::units::{Quantity, QuantityFormat, IntOrString, parse_duration, format_duration};
+
+mod preserve;
+pub use self::preserve::Preserved;
+
+mod gvk;
+pub use self::gvk::{GroupVersion, GroupVersionKind, GroupVersionResource};
+mod fields;
+pub use self::fields::{supported_fields, validate_field_selector, check_field_selector};
+mod schema;
+pub use self::schema::{SchemaValidator, FieldError};
+mod openapi_v3;
+pub use self::openapi_v3::OpenApiV3;
+mod explain;
+pub use self::explain::{Explanation, FieldDoc};
+
+mod discovery;
+pub use self::discovery::{ApiResource, discover, resolve};
+
+mod drift;
+pub use self::drift::{DriftDetector, Drift, DriftKind};
+
+mod conditions;
+pub use self::conditions::{
+    Condition,
+    ConditionLike,
+    Conditions,
+    ConditionsMut,
+};
+
+mod conversion;
+pub use self::conversion::{
+    ConversionReview,
+    ConversionRequest,
+    ConversionResponse,
+    ConversionDispatcher,
+    Converter,
+};
+
+mod decoder;
+pub use self::decoder::{DynamicObject, Decoder, DecodedEvent};
+
 #[cfg(feature = "openapi")]
 mod openapi;
 #[cfg(feature = "openapi")]
 mod snowflake;
 #[cfg(feature = "openapi")]
-pub use snowflake::{v1Event, v1Secret, v1ConfigMap};
+mod crd;
+#[cfg(feature = "openapi")]
+pub use crd::{is_crd_established, crd_instance_api};
+#[cfg(feature = "openapi")]
+mod rollout;
+#[cfg(feature = "openapi")]
+pub use rollout::{RolloutStatus, RESTARTED_AT_ANNOTATION, REVISION_ANNOTATION};
+#[cfg(feature = "openapi")]
+mod job;
+#[cfg(feature = "openapi")]
+mod networking;
+#[cfg(feature = "openapi")]
+pub use networking::{
+    ServiceBuilder, IngressBuilder, NetworkPolicyBuilder,
+    pods_peer, namespaces_peer, cidr_peer,
+};
+#[cfg(feature = "openapi")]
+mod autoscaling;
+#[cfg(feature = "openapi")]
+pub use autoscaling::{
+    DisruptionBudget,
+    resource_utilization_metric, cpu_utilization_metric, pods_metric,
+};
+#[cfg(feature = "openapi")]
+mod ephemeral;
+#[cfg(feature = "openapi")]
+pub use ephemeral::{EphemeralContainer, EphemeralContainers};
+#[cfg(feature = "openapi")]
+mod binding;
+#[cfg(feature = "openapi")]
+mod namespace;
+#[cfg(feature = "openapi")]
+mod pod;
+#[cfg(feature = "openapi")]
+pub use pod::{PodStuck, NODE_INDEX};
+#[cfg(feature = "openapi")]
+mod logs;
+#[cfg(feature = "openapi")]
+pub use logs::{LogTailer, LogLine};
+#[cfg(feature = "openapi")]
+mod events;
+#[cfg(feature = "openapi")]
+pub use events::{EventWatcher, ObservedEvent};
+#[cfg(feature = "openapi")]
+mod storage;
+#[cfg(feature = "openapi")]
+pub use storage::{
+    ResizeStatus, DEFAULT_STORAGE_CLASS_ANNOTATION,
+    VolumeSnapshotSpec, VolumeSnapshotSource, VolumeSnapshotStatus, VolumeSnapshotError,
+    VolumeSnapshotContentSpec, VolumeSnapshotContentSource, VolumeSnapshotContentStatus,
+};
+#[cfg(feature = "openapi")]
+mod quota;
+#[cfg(feature = "openapi")]
+pub use quota::Resources;
+#[cfg(feature = "openapi")]
+mod capacity;
+#[cfg(feature = "openapi")]
+pub use capacity::NodeUsage;
+#[cfg(feature = "openapi")]
+mod ancestry;
+#[cfg(feature = "openapi")]
+pub use ancestry::Ancestry;
+#[cfg(feature = "openapi")]
+mod apiservice;
+#[cfg(feature = "openapi")]
+mod webhook;
+#[cfg(feature = "openapi")]
+pub use webhook::{WebhookCerts, WebhookCertManager, CA_CERT_KEY, CA_KEY_KEY};
+#[cfg(feature = "openapi")]
+mod review;
+#[cfg(feature = "openapi")]
+pub use review::{TokenReview, SubjectAccessReview};
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "metrics")]
+pub use metrics::Metrics;
+#[cfg(feature = "openshift")]
+mod openshift;
+#[cfg(feature = "openshift")]
+pub use openshift::{
+    Route, RouteSpec, RouteStatus, RouteTargetReference, RoutePort, RouteIngress, TLSConfig,
+    DeploymentConfig, DeploymentConfigSpec, DeploymentConfigStatus,
+    Project, ProjectSpec, ProjectStatus,
+    ImageStream, ImageStreamSpec, ImageStreamStatus, TagReference, TagEvent, NamedTagEventList,
+};
+#[cfg(feature = "openapi")]
+mod rbac;
+#[cfg(feature = "openapi")]
+pub use rbac::{
+    v1Role, v1ClusterRole, v1RoleBinding, v1ClusterRoleBinding,
+    Rule, PolicyRule, RoleRef, Subject,
+    rules_for_watches, service_account_subject, READ_VERBS,
+};
+#[cfg(feature = "openapi")]
+pub use snowflake::{v1Event, v1Secret, v1ConfigMap, v1StorageClass};
 
 mod metadata;
 pub use self::metadata::{
@@ -0,0 +1,104 @@
+//! Creating and tearing down namespaces, as test suites do for every run
+use std::time::Duration;
+use serde_json::{json, Value};
+
+use crate::api::{Api, Object, PostParams, DeleteParams};
+use crate::api::wait::poll_until_explained;
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+use k8s_openapi::api::core::v1::{NamespaceSpec, NamespaceStatus};
+
+type Namespace = Object<NamespaceSpec, NamespaceStatus>;
+
+/// Conditions set by the namespace controller while it cannot finish a deletion
+const DELETION_CONDITIONS: [&str; 5] = [
+    "NamespaceDeletionDiscoveryFailure",
+    "NamespaceDeletionGroupVersionParsingFailure",
+    "NamespaceDeletionContentFailure",
+    "NamespaceContentRemaining",
+    "NamespaceFinalizersRemaining",
+];
+
+/// Why a terminating namespace is not gone yet, from its conditions and finalizers
+///
+/// Read from the raw object, as the conditions are newer than the generated types.
+fn stuck_reason(ns: &Value) -> Option<String> {
+    let mut reasons: Vec<String> = ns["status"]["conditions"].as_array().into_iter().flatten()
+        .filter(|c| c["status"] == "True" && DELETION_CONDITIONS.iter().any(|t| c["type"] == *t))
+        .map(|c| c["message"].as_str().or_else(|| c["type"].as_str()).unwrap_or_default().to_string())
+        .collect();
+    let finalizers: Vec<&str> = ns["spec"]["finalizers"].as_array().into_iter().flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    if !finalizers.is_empty() {
+        reasons.push(format!("finalizers {} remain", finalizers.join(", ")));
+    }
+    if reasons.is_empty() { None } else { Some(reasons.join("; ")) }
+}
+
+impl Api<Namespace> {
+    /// Create a namespace with a unique name starting with `prefix`, like `e2e-x7k2p`
+    ///
+    /// The apiserver picks the suffix through `generateName`; clashes are retried.
+    pub fn create_random_namespace(&self, prefix: &str) -> Result<Namespace> {
+        let prefix = if prefix.ends_with('-') { prefix.to_string() } else { format!("{}-", prefix) };
+        let data = serde_json::to_vec(&json!({
+            "apiVersion": "v1", "kind": "Namespace",
+            "metadata": { "generateName": prefix },
+        })).context(ErrorKind::SerdeParse)?;
+        let mut attempts = 0;
+        loop {
+            match self.create(&PostParams::default(), data.clone()) {
+                Err(ref e) if e.status_code() == Some(409) && attempts < 5 => attempts += 1,
+                res => return res,
+            }
+        }
+    }
+
+    /// Delete a namespace and block until it is gone, or fail after `timeout`
+    ///
+    /// Namespaces stay `Terminating` until all their content is deleted, which hangs
+    /// when finalizers are never removed, e.g. because their controller was uninstalled
+    /// first. The timeout error then says which finalizers or conditions hold it up.
+    /// A namespace that is already gone counts as deleted.
+    pub fn delete_namespace_and_wait(&self, name: &str, timeout: Duration) -> Result<()> {
+        if self.delete_opt(name, &DeleteParams::default())?.is_none() {
+            return Ok(());
+        }
+        poll_until_explained(&format!("namespace {} to be deleted", name), timeout, |stuck| {
+            match self.client.request::<Value>(self.api.get(name)?) {
+                Ok(ns) => {
+                    *stuck = stuck_reason(&ns);
+                    Ok(None)
+                },
+                Err(ref e) if e.is_not_found() => Ok(Some(())),
+                Err(e) => Err(e),
+            }
+        })
+    }
+}
+
+#[test]
+fn stuck_namespaces_are_explained() {
+    use crate::client::fake::{respond, Fake};
+    let fake = Fake::new()
+        .handle("POST", "/api/v1/namespaces", |req| {
+            let name = format!("{}abcde", req.json()["metadata"]["generateName"].as_str().unwrap());
+            respond(201, json!({ "metadata": { "name": name }, "spec": {}, "status": { "phase": "Active" } }).to_string())
+        })
+        .on("*", "/api/v1/namespaces/e2e-abcde", 200, json!({
+            "metadata": { "name": "e2e-abcde", "deletionTimestamp": "2019-07-01T00:00:00Z" },
+            "spec": { "finalizers": ["kubernetes"] },
+            "status": { "phase": "Terminating", "conditions": [
+                { "type": "NamespaceDeletionDiscoveryFailure", "status": "False" },
+                { "type": "NamespaceFinalizersRemaining", "status": "True",
+                  "message": "Some content in the namespace has finalizers remaining: example.com/cleanup in 1 resource instances" },
+            ]},
+        }).to_string());
+    let namespaces = Api::v1Namespace(fake.client());
+    assert_eq!(namespaces.create_random_namespace("e2e").unwrap().metadata.name, "e2e-abcde");
+    let err = namespaces.delete_namespace_and_wait("e2e-abcde", Duration::from_millis(10)).unwrap_err();
+    assert_eq!(err.to_string(), "Timed out waiting for namespace e2e-abcde to be deleted, but Some content in the \
+        namespace has finalizers remaining: example.com/cleanup in 1 resource instances; finalizers kubernetes remain");
+}
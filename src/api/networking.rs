@@ -0,0 +1,379 @@
+//! Builders for Services, Ingresses and NetworkPolicies
+//!
+//! These produce typed objects that can be serialized straight into `Api::create`,
+//! and catch the common mistakes the apiserver would otherwise reject.
+use std::collections::BTreeMap;
+
+use crate::api::{Object, ObjectMeta, TypeMeta, Void};
+use crate::{Result, Error, ErrorKind};
+
+use k8s_openapi::api::core::v1::{ServiceSpec, ServiceStatus, ServicePort};
+use k8s_openapi::api::networking::v1::{
+    NetworkPolicySpec, NetworkPolicyIngressRule, NetworkPolicyEgressRule,
+    NetworkPolicyPeer, NetworkPolicyPort, IPBlock,
+};
+use k8s_openapi::api::networking::v1beta1::{
+    IngressSpec, IngressStatus, IngressRule, IngressBackend, IngressTLS,
+    HTTPIngressRuleValue, HTTPIngressPath,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::LabelSelector;
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+
+fn labels(pairs: &[(&str, &str)]) -> BTreeMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+fn selector(pairs: &[(&str, &str)]) -> LabelSelector {
+    LabelSelector {
+        match_labels: Some(labels(pairs)),
+        ..Default::default()
+    }
+}
+
+fn validate_port(port: i32) -> Result<()> {
+    if !(1..=65535).contains(&port) {
+        return Err(Error::from(ErrorKind::RequestValidation(format!("invalid port {}", port))));
+    }
+    Ok(())
+}
+
+fn object_meta(name: &str, annotations: BTreeMap<String, String>) -> ObjectMeta {
+    ObjectMeta { name: name.into(), annotations, ..Default::default() }
+}
+
+/// Builder for a Service
+///
+/// ```
+/// use kube::api::ServiceBuilder;
+/// let svc = ServiceBuilder::new("blog")
+///     .selector(&[("app", "blog")])
+///     .port("http", 80, 8080)
+///     .build()
+///     .unwrap();
+/// assert_eq!(svc.spec.ports.unwrap()[0].port, 80);
+/// ```
+#[derive(Clone, Default)]
+pub struct ServiceBuilder {
+    name: String,
+    annotations: BTreeMap<String, String>,
+    spec: ServiceSpec,
+}
+
+impl ServiceBuilder {
+    pub fn new(name: &str) -> Self {
+        ServiceBuilder { name: name.into(), ..Default::default() }
+    }
+
+    /// Route to pods with these labels
+    pub fn selector(mut self, pairs: &[(&str, &str)]) -> Self {
+        self.spec.selector = Some(labels(pairs));
+        self
+    }
+
+    /// Expose a named TCP port, forwarding to a port number on the pods
+    pub fn port(mut self, name: &str, port: i32, target_port: i32) -> Self {
+        self.spec.ports.get_or_insert_with(Vec::new).push(ServicePort {
+            name: Some(name.into()),
+            port,
+            target_port: Some(IntOrString::Int(target_port)),
+            protocol: Some("TCP".into()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Expose a named port, forwarding to a named container port
+    pub fn named_target_port(mut self, name: &str, port: i32, target_port: &str) -> Self {
+        self.spec.ports.get_or_insert_with(Vec::new).push(ServicePort {
+            name: Some(name.into()),
+            port,
+            target_port: Some(IntOrString::String(target_port.into())),
+            protocol: Some("TCP".into()),
+            ..Default::default()
+        });
+        self
+    }
+
+    /// Set the service type, e.g. `NodePort` or `LoadBalancer`
+    pub fn type_(mut self, type_: &str) -> Self {
+        self.spec.type_ = Some(type_.into());
+        self
+    }
+
+    /// Make a headless service (`clusterIP: None`)
+    pub fn headless(mut self) -> Self {
+        self.spec.cluster_ip = Some("None".into());
+        self
+    }
+
+    pub fn annotation(mut self, key: &str, value: &str) -> Self {
+        self.annotations.insert(key.into(), value.into());
+        self
+    }
+
+    /// Validate and produce the Service
+    pub fn build(self) -> Result<Object<ServiceSpec, ServiceStatus>> {
+        let ports = self.spec.ports.clone().unwrap_or_default();
+        let external = self.spec.type_.as_deref() == Some("ExternalName");
+        let headless = self.spec.cluster_ip.as_deref() == Some("None");
+        if ports.is_empty() && !external && !headless {
+            return Err(Error::from(ErrorKind::RequestValidation(format!("service {} has no ports", self.name))));
+        }
+        for p in &ports {
+            validate_port(p.port)?;
+            if let Some(IntOrString::Int(tp)) = p.target_port {
+                validate_port(tp)?;
+            }
+        }
+        if ports.len() > 1 && ports.iter().any(|p| p.name.as_deref().unwrap_or("").is_empty()) {
+            return Err(Error::from(ErrorKind::RequestValidation(
+                format!("service {} needs names on all ports when exposing several", self.name))));
+        }
+        Ok(Object {
+            types: TypeMeta { apiVersion: Some("v1".into()), kind: Some("Service".into()) },
+            metadata: object_meta(&self.name, self.annotations),
+            spec: self.spec,
+            status: None,
+        })
+    }
+}
+
+/// Builder for an Ingress
+///
+/// ```
+/// use kube::api::IngressBuilder;
+/// let ing = IngressBuilder::new("blog")
+///     .rule("blog.clux.dev", "/", "blog", 80)
+///     .tls(&["blog.clux.dev"], "blog-tls")
+///     .build()
+///     .unwrap();
+/// assert_eq!(ing.spec.rules.unwrap().len(), 1);
+/// ```
+#[derive(Clone, Default)]
+pub struct IngressBuilder {
+    name: String,
+    annotations: BTreeMap<String, String>,
+    spec: IngressSpec,
+}
+
+impl IngressBuilder {
+    pub fn new(name: &str) -> Self {
+        IngressBuilder { name: name.into(), ..Default::default() }
+    }
+
+    /// Route a host and path prefix to a service port
+    ///
+    /// Paths for the same host are grouped into one rule.
+    pub fn rule(mut self, host: &str, path: &str, service: &str, port: i32) -> Self {
+        let path = HTTPIngressPath {
+            path: Some(path.into()),
+            backend: IngressBackend {
+                service_name: service.into(),
+                service_port: IntOrString::Int(port),
+            },
+        };
+        let rules = self.spec.rules.get_or_insert_with(Vec::new);
+        let host = if host.is_empty() { None } else { Some(host.to_string()) };
+        match rules.iter_mut().find(|r| r.host == host) {
+            Some(r) => r.http.get_or_insert_with(Default::default).paths.push(path),
+            None => rules.push(IngressRule {
+                host,
+                http: Some(HTTPIngressRuleValue { paths: vec![path] }),
+            }),
+        }
+        self
+    }
+
+    /// Send requests matching no rule to a service port
+    pub fn default_backend(mut self, service: &str, port: i32) -> Self {
+        self.spec.backend = Some(IngressBackend {
+            service_name: service.into(),
+            service_port: IntOrString::Int(port),
+        });
+        self
+    }
+
+    /// Terminate TLS for hosts with the certificate in a secret
+    pub fn tls(mut self, hosts: &[&str], secret: &str) -> Self {
+        self.spec.tls.get_or_insert_with(Vec::new).push(IngressTLS {
+            hosts: Some(hosts.iter().map(|h| h.to_string()).collect()),
+            secret_name: Some(secret.into()),
+        });
+        self
+    }
+
+    pub fn annotation(mut self, key: &str, value: &str) -> Self {
+        self.annotations.insert(key.into(), value.into());
+        self
+    }
+
+    /// Validate and produce the Ingress
+    pub fn build(self) -> Result<Object<IngressSpec, IngressStatus>> {
+        if self.spec.rules.is_none() && self.spec.backend.is_none() {
+            return Err(Error::from(ErrorKind::RequestValidation(
+                format!("ingress {} needs a rule or a default backend", self.name))));
+        }
+        let backends = self.spec.rules.iter().flatten()
+            .filter_map(|r| r.http.as_ref())
+            .flat_map(|h| h.paths.iter().map(|p| &p.backend))
+            .chain(self.spec.backend.iter());
+        for b in backends {
+            if let IntOrString::Int(p) = b.service_port {
+                validate_port(p)?;
+            }
+        }
+        for p in self.spec.rules.iter().flatten().filter_map(|r| r.http.as_ref()).flat_map(|h| &h.paths) {
+            if !p.path.as_deref().unwrap_or("/").starts_with('/') {
+                return Err(Error::from(ErrorKind::RequestValidation(
+                    format!("ingress {} path {:?} must be absolute", self.name, p.path))));
+            }
+        }
+        Ok(Object {
+            types: TypeMeta { apiVersion: Some("networking.k8s.io/v1beta1".into()), kind: Some("Ingress".into()) },
+            metadata: object_meta(&self.name, self.annotations),
+            spec: self.spec,
+            status: None,
+        })
+    }
+}
+
+/// A NetworkPolicy peer matching pods by label in the policy's namespace
+pub fn pods_peer(pairs: &[(&str, &str)]) -> NetworkPolicyPeer {
+    NetworkPolicyPeer { pod_selector: Some(selector(pairs)), ..Default::default() }
+}
+
+/// A NetworkPolicy peer matching all pods in namespaces by label
+pub fn namespaces_peer(pairs: &[(&str, &str)]) -> NetworkPolicyPeer {
+    NetworkPolicyPeer { namespace_selector: Some(selector(pairs)), ..Default::default() }
+}
+
+/// A NetworkPolicy peer matching an ip range
+pub fn cidr_peer(cidr: &str) -> NetworkPolicyPeer {
+    NetworkPolicyPeer {
+        ip_block: Some(IPBlock { cidr: cidr.into(), except: None }),
+        ..Default::default()
+    }
+}
+
+fn tcp_ports(ports: &[i32]) -> Option<Vec<NetworkPolicyPort>> {
+    if ports.is_empty() {
+        return None;
+    }
+    Some(ports.iter().map(|p| NetworkPolicyPort {
+        port: Some(IntOrString::Int(*p)),
+        protocol: Some("TCP".into()),
+    }).collect())
+}
+
+/// Builder for a NetworkPolicy
+///
+/// A policy without any `allow_*` rule for a direction denies all traffic in that
+/// direction. Ingress is always isolated; egress only once `allow_egress` or
+/// `isolate_egress` is called.
+///
+/// ```
+/// use kube::api::{NetworkPolicyBuilder, pods_peer};
+/// let np = NetworkPolicyBuilder::new("blog")
+///     .pod_selector(&[("app", "blog")])
+///     .allow_ingress(vec![pods_peer(&[("app", "proxy")])], &[8080])
+///     .build()
+///     .unwrap();
+/// assert_eq!(np.spec.policy_types.unwrap(), vec!["Ingress"]);
+/// ```
+#[derive(Clone, Default)]
+pub struct NetworkPolicyBuilder {
+    name: String,
+    spec: NetworkPolicySpec,
+    egress_isolated: bool,
+}
+
+impl NetworkPolicyBuilder {
+    pub fn new(name: &str) -> Self {
+        NetworkPolicyBuilder { name: name.into(), ..Default::default() }
+    }
+
+    /// The pods the policy applies to. Defaults to every pod in the namespace.
+    pub fn pod_selector(mut self, pairs: &[(&str, &str)]) -> Self {
+        self.spec.pod_selector = selector(pairs);
+        self
+    }
+
+    /// Allow traffic from peers to the given ports (all ports if empty)
+    pub fn allow_ingress(mut self, from: Vec<NetworkPolicyPeer>, ports: &[i32]) -> Self {
+        self.spec.ingress.get_or_insert_with(Vec::new).push(NetworkPolicyIngressRule {
+            from: if from.is_empty() { None } else { Some(from) },
+            ports: tcp_ports(ports),
+        });
+        self
+    }
+
+    /// Allow traffic to peers on the given ports (all ports if empty)
+    pub fn allow_egress(mut self, to: Vec<NetworkPolicyPeer>, ports: &[i32]) -> Self {
+        self.egress_isolated = true;
+        self.spec.egress.get_or_insert_with(Vec::new).push(NetworkPolicyEgressRule {
+            to: if to.is_empty() { None } else { Some(to) },
+            ports: tcp_ports(ports),
+        });
+        self
+    }
+
+    /// Deny all egress not explicitly allowed
+    pub fn isolate_egress(mut self) -> Self {
+        self.egress_isolated = true;
+        self
+    }
+
+    /// Validate and produce the NetworkPolicy
+    pub fn build(mut self) -> Result<Object<NetworkPolicySpec, Void>> {
+        let rule_ports = self.spec.ingress.iter().flatten().filter_map(|r| r.ports.as_ref())
+            .chain(self.spec.egress.iter().flatten().filter_map(|r| r.ports.as_ref()));
+        for p in rule_ports.flatten() {
+            if let Some(IntOrString::Int(p)) = p.port {
+                validate_port(p)?;
+            }
+        }
+        let mut types = vec!["Ingress".to_string()];
+        if self.egress_isolated {
+            types.push("Egress".into());
+        }
+        self.spec.policy_types = Some(types);
+        Ok(Object {
+            types: TypeMeta { apiVersion: Some("networking.k8s.io/v1".into()), kind: Some("NetworkPolicy".into()) },
+            metadata: object_meta(&self.name, BTreeMap::new()),
+            spec: self.spec,
+            status: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builders_validate() {
+        assert!(ServiceBuilder::new("empty").build().is_err());
+        assert!(ServiceBuilder::new("headless").headless().build().is_ok());
+        assert!(ServiceBuilder::new("bad").port("http", 80, 70000).build().is_err());
+        assert!(ServiceBuilder::new("unnamed").port("", 80, 80).port("metrics", 9090, 9090).build().is_err());
+
+        let ing = IngressBuilder::new("blog")
+            .rule("blog.clux.dev", "/", "blog", 80)
+            .rule("blog.clux.dev", "/api", "api", 80)
+            .build().unwrap();
+        let rules = ing.spec.rules.unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].http.as_ref().unwrap().paths.len(), 2);
+        assert!(IngressBuilder::new("nothing").build().is_err());
+        assert!(IngressBuilder::new("rel").rule("", "api", "api", 80).build().is_err());
+
+        let np = NetworkPolicyBuilder::new("db")
+            .allow_ingress(vec![namespaces_peer(&[("team", "blog")])], &[5432])
+            .allow_egress(vec![cidr_peer("10.0.0.0/8")], &[])
+            .build().unwrap();
+        let json = serde_json::to_value(&np).unwrap();
+        assert_eq!(json["spec"]["policyTypes"], serde_json::json!(["Ingress", "Egress"]));
+        assert_eq!(json["spec"]["ingress"][0]["ports"][0]["port"], 5432);
+        assert_eq!(json["kind"], "NetworkPolicy");
+    }
+}
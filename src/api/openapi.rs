@@ -1,7 +1,7 @@
 #![allow(non_snake_case)]
 use std::marker::PhantomData;
 
-use crate::api::{RawApi, Api, Object, Log, Void};
+use crate::api::{RawApi, Api, Object, Log, Void, Resource, NamespaceResourceScope, ClusterResourceScope};
 use crate::client::{
     APIClient,
 };
@@ -187,6 +187,38 @@ impl Api<Object<ResourceQuotaSpec, ResourceQuotaStatus>> {
     }
 }
 
+use k8s_openapi::api::core::v1::LimitRangeSpec;
+impl Api<Object<LimitRangeSpec, Void>> {
+    pub fn v1LimitRange(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1LimitRange(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+use crate::api::storage::{VolumeSnapshotSpec, VolumeSnapshotStatus, VolumeSnapshotContentSpec, VolumeSnapshotContentStatus};
+impl Api<Object<VolumeSnapshotSpec, VolumeSnapshotStatus>> {
+    pub fn v1VolumeSnapshot(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1VolumeSnapshot(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl Api<Object<VolumeSnapshotContentSpec, VolumeSnapshotContentStatus>> {
+    pub fn v1VolumeSnapshotContent(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1VolumeSnapshotContent(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
 use k8s_openapi::api::networking::v1::{NetworkPolicySpec};
 impl Api<Object<NetworkPolicySpec, Void>> {
     pub fn v1NetworkPolicy(client: APIClient) -> Self {
@@ -198,6 +230,17 @@ impl Api<Object<NetworkPolicySpec, Void>> {
     }
 }
 
+use k8s_openapi::api::networking::v1beta1::{IngressSpec, IngressStatus};
+impl Api<Object<IngressSpec, IngressStatus>> {
+    pub fn v1beta1Ingress(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1beta1Ingress(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
 use k8s_openapi::api::autoscaling::v1::{HorizontalPodAutoscalerSpec, HorizontalPodAutoscalerStatus};
 impl Api<Object<HorizontalPodAutoscalerSpec, HorizontalPodAutoscalerStatus>> {
     pub fn v1HorizontalPodAutoscaler(client: APIClient) -> Self {
@@ -208,3 +251,139 @@ impl Api<Object<HorizontalPodAutoscalerSpec, HorizontalPodAutoscalerStatus>> {
         }
     }
 }
+
+use k8s_openapi::api::autoscaling::v2beta2::{
+    HorizontalPodAutoscalerSpec as HorizontalPodAutoscalerV2Spec,
+    HorizontalPodAutoscalerStatus as HorizontalPodAutoscalerV2Status,
+};
+impl Api<Object<HorizontalPodAutoscalerV2Spec, HorizontalPodAutoscalerV2Status>> {
+    pub fn v2beta2HorizontalPodAutoscaler(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v2beta2HorizontalPodAutoscaler(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+use k8s_openapi::api::policy::v1beta1::{PodDisruptionBudgetSpec, PodDisruptionBudgetStatus};
+impl Api<Object<PodDisruptionBudgetSpec, PodDisruptionBudgetStatus>> {
+    pub fn v1beta1PodDisruptionBudget(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1beta1PodDisruptionBudget(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+use k8s_openapi::kube_aggregator::pkg::apis::apiregistration::v1::{APIServiceSpec, APIServiceStatus};
+impl Api<Object<APIServiceSpec, APIServiceStatus>> {
+    pub fn v1APIService(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1APIService(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+use k8s_openapi::api::coordination::v1::LeaseSpec;
+impl Api<Object<LeaseSpec, Void>> {
+    pub fn v1Lease(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1Lease(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+use k8s_openapi::api::authentication::v1::{TokenReviewSpec, TokenReviewStatus};
+impl Api<Object<TokenReviewSpec, TokenReviewStatus>> {
+    pub fn v1TokenReview(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1TokenReview(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+use k8s_openapi::api::authorization::v1::{SubjectAccessReviewSpec, SubjectAccessReviewStatus};
+impl Api<Object<SubjectAccessReviewSpec, SubjectAccessReviewStatus>> {
+    pub fn v1SubjectAccessReview(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1SubjectAccessReview(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Reviews within the namespace set with `within`, for users who may only ask there
+    ///
+    /// Send these as `SubjectAccessReview::local_for_resource`, in the same namespace.
+    pub fn v1LocalSubjectAccessReview(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1LocalSubjectAccessReview(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+macro_rules! resource_impl {
+    ($spec:ty, $status:ty, $group:expr, $version:expr, $kind:expr, $plural:expr, $scope:ty) => {
+        impl Resource for Object<$spec, $status> {
+            const GROUP: &'static str = $group;
+            const VERSION: &'static str = $version;
+            const KIND: &'static str = $kind;
+            const PLURAL: &'static str = $plural;
+            type Scope = $scope;
+        }
+    };
+}
+resource_impl!(CrdSpec, CrdStatus, "apiextensions.k8s.io", "v1beta1", "CustomResourceDefinition", "customresourcedefinitions", ClusterResourceScope);
+resource_impl!(CronJobSpec, CronJobStatus, "batch", "v1beta1", "CronJob", "cronjobs", NamespaceResourceScope);
+resource_impl!(NodeSpec, NodeStatus, "", "v1", "Node", "nodes", ClusterResourceScope);
+resource_impl!(DeploymentSpec, DeploymentStatus, "apps", "v1", "Deployment", "deployments", NamespaceResourceScope);
+resource_impl!(PodSpec, PodStatus, "", "v1", "Pod", "pods", NamespaceResourceScope);
+resource_impl!(ServiceSpec, ServiceStatus, "", "v1", "Service", "services", NamespaceResourceScope);
+resource_impl!(JobSpec, JobStatus, "batch", "v1", "Job", "jobs", NamespaceResourceScope);
+resource_impl!(NamespaceSpec, NamespaceStatus, "", "v1", "Namespace", "namespaces", ClusterResourceScope);
+resource_impl!(DaemonSetSpec, DaemonSetStatus, "apps", "v1", "DaemonSet", "daemonsets", NamespaceResourceScope);
+resource_impl!(StatefulSetSpec, StatefulSetStatus, "apps", "v1", "StatefulSet", "statefulsets", NamespaceResourceScope);
+resource_impl!(ReplicaSetSpec, ReplicaSetStatus, "apps", "v1", "ReplicaSet", "replicasets", NamespaceResourceScope);
+resource_impl!(ReplicationControllerSpec, ReplicationControllerStatus, "", "v1", "ReplicationController", "replicationcontrollers", NamespaceResourceScope);
+resource_impl!(PersistentVolumeClaimSpec, PersistentVolumeClaimStatus, "", "v1", "PersistentVolumeClaim", "persistentvolumeclaims", NamespaceResourceScope);
+resource_impl!(PersistentVolumeSpec, PersistentVolumeStatus, "", "v1", "PersistentVolume", "persistentvolumes", ClusterResourceScope);
+resource_impl!(VolumeAttachmentSpec, VolumeAttachmentStatus, "storage.k8s.io", "v1", "VolumeAttachment", "volumeattachments", ClusterResourceScope);
+resource_impl!(ResourceQuotaSpec, ResourceQuotaStatus, "", "v1", "ResourceQuota", "resourcequotas", NamespaceResourceScope);
+resource_impl!(LimitRangeSpec, Void, "", "v1", "LimitRange", "limitranges", NamespaceResourceScope);
+resource_impl!(VolumeSnapshotSpec, VolumeSnapshotStatus, "snapshot.storage.k8s.io", "v1", "VolumeSnapshot", "volumesnapshots", NamespaceResourceScope);
+resource_impl!(VolumeSnapshotContentSpec, VolumeSnapshotContentStatus, "snapshot.storage.k8s.io", "v1", "VolumeSnapshotContent", "volumesnapshotcontents", ClusterResourceScope);
+resource_impl!(NetworkPolicySpec, Void, "networking.k8s.io", "v1", "NetworkPolicy", "networkpolicies", NamespaceResourceScope);
+resource_impl!(IngressSpec, IngressStatus, "networking.k8s.io", "v1beta1", "Ingress", "ingresses", NamespaceResourceScope);
+resource_impl!(HorizontalPodAutoscalerV2Spec, HorizontalPodAutoscalerV2Status, "autoscaling", "v2beta2", "HorizontalPodAutoscaler", "horizontalpodautoscalers", NamespaceResourceScope);
+resource_impl!(PodDisruptionBudgetSpec, PodDisruptionBudgetStatus, "policy", "v1beta1", "PodDisruptionBudget", "poddisruptionbudgets", NamespaceResourceScope);
+resource_impl!(APIServiceSpec, APIServiceStatus, "apiregistration.k8s.io", "v1", "APIService", "apiservices", ClusterResourceScope);
+resource_impl!(LeaseSpec, Void, "coordination.k8s.io", "v1", "Lease", "leases", NamespaceResourceScope);
+resource_impl!(HorizontalPodAutoscalerSpec, HorizontalPodAutoscalerStatus, "autoscaling", "v1", "HorizontalPodAutoscaler", "horizontalpodautoscalers", NamespaceResourceScope);
+
+#[test]
+fn resources_match_constructors() {
+    use crate::api::ListParams;
+    fn url<K: Resource>() -> String {
+        K::raw_api().list(&ListParams::default()).unwrap().uri().to_string()
+    }
+    let url_of = |api: RawApi| api.list(&ListParams::default()).unwrap().uri().to_string();
+    assert_eq!(url::<Object<PodSpec, PodStatus>>(), url_of(RawApi::v1Pod()));
+    assert_eq!(url::<Object<DeploymentSpec, DeploymentStatus>>(), url_of(RawApi::v1Deployment()));
+    assert_eq!(url::<Object<CrdSpec, CrdStatus>>(), url_of(RawApi::v1beta1CustomResourceDefinition()));
+    assert_eq!(url::<Object<IngressSpec, IngressStatus>>(), url_of(RawApi::v1beta1Ingress()));
+    assert_eq!(Object::<LeaseSpec, Void>::api_version(), "coordination.k8s.io/v1");
+    assert_eq!(url::<Object<LimitRangeSpec, Void>>(), url_of(RawApi::v1LimitRange()));
+    assert_eq!(url::<Object<VolumeSnapshotSpec, VolumeSnapshotStatus>>(), url_of(RawApi::v1VolumeSnapshot()));
+    assert_eq!(url::<crate::api::v1StorageClass>(), url_of(RawApi::v1StorageClass()));
+    assert_eq!(<Object<NodeSpec, NodeStatus> as Resource>::KIND, "Node");
+}
@@ -0,0 +1,225 @@
+//! Fetching the OpenAPI v3 schemas of each group version, cached on disk
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use failure::ResultExt;
+use serde_json::Value;
+
+use crate::api::{GroupVersion, SchemaValidator};
+use crate::client::APIClient;
+use crate::{ErrorKind, Result};
+
+/// A schema document as cached on disk
+#[derive(Deserialize, Serialize)]
+struct Cached {
+    /// The url it was fetched from, whose `hash` changes with the document
+    url: String,
+    etag: Option<String>,
+    document: Value,
+}
+
+/// The OpenAPI v3 schemas of an apiserver, one document per group version
+///
+/// The apiserver lists a url for each group version at `/openapi/v3`, carrying a
+/// hash of the document. With a cache directory, documents are kept on disk by
+/// server version, and fetched again only once their hash changes, and even then
+/// with the cached `ETag`, so tools need not download megabytes on every run.
+///
+/// ```no_run
+/// use kube::{api::{GroupVersion, OpenApiV3}, client::APIClient, config};
+///
+/// let config = config::load_kube_config().expect("failed to load kubeconfig");
+/// let mut schemas = OpenApiV3::new(APIClient::new(config));
+/// if let Some(dir) = OpenApiV3::default_cache_dir() {
+///     schemas = schemas.cache_dir(dir);
+/// }
+/// let validator = schemas.validator(&[GroupVersion::new("apps", "v1")]).unwrap();
+/// ```
+pub struct OpenApiV3 {
+    client: APIClient,
+    cache_dir: Option<PathBuf>,
+    server_version: Mutex<Option<String>>,
+}
+
+impl OpenApiV3 {
+    pub fn new(client: APIClient) -> Self {
+        OpenApiV3 { client, cache_dir: None, server_version: Mutex::new(None) }
+    }
+
+    /// Keep documents in `dir`, under a directory for each server version
+    pub fn cache_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.cache_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// `~/.kube/cache/openapi`, next to kubectl's caches
+    pub fn default_cache_dir() -> Option<PathBuf> {
+        dirs::home_dir().map(|h| h.join(".kube").join("cache").join("openapi"))
+    }
+
+    pub(crate) fn client(&self) -> &APIClient {
+        &self.client
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(&self, path: &str) -> Result<T> {
+        let req = http::Request::get(path).body(vec![]).context(ErrorKind::RequestBuild)?;
+        self.client.request(req)
+    }
+
+    /// The `gitVersion` of the apiserver, asked for once
+    fn server_version(&self) -> Result<String> {
+        let mut version = self.server_version.lock().unwrap();
+        if let Some(v) = &*version {
+            return Ok(v.clone());
+        }
+        let info: Value = self.get("/version")?;
+        let v = info["gitVersion"].as_str().unwrap_or("unknown").to_string();
+        *version = Some(v.clone());
+        Ok(v)
+    }
+
+    /// The url of each group version's document, like `apis/apps/v1` or `api/v1`
+    pub fn paths(&self) -> Result<BTreeMap<String, String>> {
+        let discovery: Value = self.get("/openapi/v3")?;
+        Ok(discovery["paths"].as_object().into_iter().flatten()
+            .filter_map(|(path, p)| Some((path.clone(), p["serverRelativeURL"].as_str()?.to_string())))
+            .collect())
+    }
+
+    fn cache_file(&self, path: &str) -> Result<Option<PathBuf>> {
+        let dir = match &self.cache_dir {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        let version = self.server_version()?.replace(['/', '\\'], "_");
+        Ok(Some(dir.join(version).join(format!("{}.json", path.replace('/', "_")))))
+    }
+
+    fn read_cache(&self, file: &Path) -> Option<Cached> {
+        let data = std::fs::read(file).ok()?;
+        match serde_json::from_slice(&data) {
+            Ok(cached) => Some(cached),
+            Err(e) => {
+                warn!("Ignoring unreadable schema cache {}: {}", file.display(), e);
+                None
+            },
+        }
+    }
+
+    fn write_cache(&self, file: &Path, cached: &Cached) {
+        // write next to it and rename, so a crash never leaves a partial document
+        let tmp = file.with_extension("tmp");
+        let res = serde_json::to_vec(cached).map_err(std::io::Error::from)
+            .and_then(|data| {
+                std::fs::create_dir_all(file.parent().unwrap_or(file))?;
+                std::fs::write(&tmp, data)
+            })
+            .and_then(|_| std::fs::rename(&tmp, file));
+        if let Err(e) = res {
+            warn!("Failed to cache schema in {}: {}", file.display(), e);
+        }
+    }
+
+    /// The document of a group version at a discovery path like `apis/apps/v1`
+    pub fn document(&self, path: &str) -> Result<Value> {
+        let url = self.paths()?.remove(path).ok_or_else(|| ErrorKind::RequestValidation(
+            format!("the apiserver has no OpenAPI v3 schemas for {}", path)))?;
+        let file = self.cache_file(path)?;
+        let cached = file.as_ref().and_then(|f| self.read_cache(f));
+        if let Some(c) = cached.as_ref().filter(|c| c.url == url) {
+            trace!("Using cached schemas for {}", path);
+            return Ok(c.document.clone());
+        }
+        let mut req = http::Request::get(url.as_str());
+        if let Some(etag) = cached.as_ref().and_then(|c| c.etag.as_ref()) {
+            req.header(http::header::IF_NONE_MATCH, etag.as_str());
+        }
+        let req = req.body(vec![]).context(ErrorKind::RequestBuild)?;
+        let (status, headers, text) = self.client.request_with_headers(req)?;
+        let cached = match cached {
+            Some(c) if status == http::StatusCode::NOT_MODIFIED => Cached { url, ..c },
+            _ => {
+                let etag = headers.get(http::header::ETAG).and_then(|e| e.to_str().ok()).map(String::from);
+                let document = serde_json::from_str(&text).context(ErrorKind::SerdeParse)?;
+                Cached { url, etag, document }
+            },
+        };
+        if let Some(file) = &file {
+            self.write_cache(file, &cached);
+        }
+        Ok(cached.document)
+    }
+
+    /// The document of a group version
+    pub fn group_version(&self, gv: &GroupVersion) -> Result<Value> {
+        if gv.group.is_empty() {
+            self.document(&format!("api/{}", gv.version))
+        } else {
+            self.document(&format!("apis/{}/{}", gv.group, gv.version))
+        }
+    }
+
+    /// A validator for the kinds of these group versions
+    pub fn validator(&self, gvs: &[GroupVersion]) -> Result<SchemaValidator> {
+        let docs = gvs.iter().map(|gv| self.group_version(gv)).collect::<Result<Vec<_>>>()?;
+        Ok(SchemaValidator::from_openapi_v3(&docs))
+    }
+}
+
+#[test]
+fn documents_are_cached_by_hash_and_etag() {
+    use crate::client::fake::{respond, Fake};
+    use std::sync::Arc;
+    let hash = Arc::new(Mutex::new("A".to_string()));
+    let served = hash.clone();
+    let fake = Fake::new()
+        .on("GET", "/version", 200, r#"{"gitVersion":"v1.27.3"}"#)
+        .handle("GET", "/openapi/v3", move |_| respond(200, serde_json::json!({ "paths": {
+            "apis/apps/v1": { "serverRelativeURL": format!("/openapi/v3/apis/apps/v1?hash={}", served.lock().unwrap()) },
+        }}).to_string()))
+        .handle("GET", "/openapi/v3/*", |req| {
+            if req.header("If-None-Match") == "\"etag-1\"" {
+                return respond(304, "");
+            }
+            let mut res = respond(200, serde_json::json!({ "components": { "schemas": {
+                "io.k8s.api.apps.v1.Deployment": {
+                    "x-kubernetes-group-version-kind": [{ "group": "apps", "version": "v1", "kind": "Deployment" }],
+                    "properties": { "spec": { "allOf": [{ "$ref": "#/components/schemas/io.k8s.api.apps.v1.DeploymentSpec" }] } },
+                },
+                "io.k8s.api.apps.v1.DeploymentSpec": { "properties": { "replicas": { "type": "integer" } } },
+            }}}).to_string());
+            res.headers_mut().insert("ETag", "\"etag-1\"".parse().unwrap());
+            res
+        });
+    let dir = tempfile::tempdir().unwrap();
+    let schemas = || OpenApiV3::new(fake.client()).cache_dir(dir.path());
+    let apps = [GroupVersion::new("apps", "v1")];
+    let validator = schemas().validator(&apps).unwrap();
+    let deployment = serde_json::json!({ "apiVersion": "apps/v1", "kind": "Deployment", "spec": { "replicas": "2" } });
+    assert_eq!(validator.validate(&deployment).unwrap()[0].to_string(), "spec.replicas: expected integer, got string");
+    assert!(dir.path().join("v1.27.3").join("apis_apps_v1.json").exists());
+
+    let seen = Mutex::new(0);
+    // the documents fetched since the last call, with the etag they were fetched with
+    let fetches = || {
+        let requests = fake.requests();
+        let mut seen = seen.lock().unwrap();
+        let fetches: Vec<String> = requests[*seen..].iter()
+            .filter(|r| r.path().starts_with("/openapi/v3/"))
+            .map(|r| format!("{} {}", r.uri, r.header("If-None-Match")))
+            .collect();
+        *seen = requests.len();
+        fetches
+    };
+    assert_eq!(fetches(), vec!["/openapi/v3/apis/apps/v1?hash=A "]);
+    schemas().validator(&apps).unwrap();
+    assert!(fetches().is_empty(), "an unchanged hash is served from disk");
+    *hash.lock().unwrap() = "B".into();
+    let validator = schemas().validator(&apps).unwrap();
+    assert_eq!(fetches(), vec!["/openapi/v3/apis/apps/v1?hash=B \"etag-1\""]);
+    assert_eq!(validator.validate(&deployment).unwrap().len(), 1, "a 304 keeps the cached document");
+    schemas().validator(&apps).unwrap();
+    assert!(fetches().is_empty());
+    assert!(schemas().group_version(&GroupVersion::new("batch", "v1")).is_err());
+}
@@ -0,0 +1,326 @@
+//! Common OpenShift resources
+//!
+//! Enabled with the `openshift` feature. The spec and status structs cover the
+//! commonly used fields; anything more exotic is kept as raw json.
+#![allow(non_snake_case)]
+
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use serde_json::{json, Value};
+
+use crate::api::{RawApi, Api, Object, Resource, NamespaceResourceScope, ClusterResourceScope, PostParams};
+use crate::client::APIClient;
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+use k8s_openapi::api::core::v1::{ObjectReference, PodTemplateSpec};
+
+/// Constructors for OpenShift resources
+#[allow(non_snake_case)]
+impl RawApi {
+    /// Route constructor
+    pub fn v1Route() -> Self {
+        Self {
+            group: "route.openshift.io".into(),
+            resource: "routes".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// DeploymentConfig constructor
+    pub fn v1DeploymentConfig() -> Self {
+        Self {
+            group: "apps.openshift.io".into(),
+            resource: "deploymentconfigs".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Project constructor
+    pub fn v1Project() -> Self {
+        Self {
+            group: "project.openshift.io".into(),
+            resource: "projects".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// ImageStream constructor
+    pub fn v1ImageStream() -> Self {
+        Self {
+            group: "image.openshift.io".into(),
+            resource: "imagestreams".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// The service a Route points at
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RouteTargetReference {
+    pub kind: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<i32>,
+}
+
+/// The service port a Route targets, by name or number
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RoutePort {
+    pub targetPort: Value,
+}
+
+/// TLS termination for a Route
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct TLSConfig {
+    /// One of `edge`, `passthrough` or `reencrypt`
+    pub termination: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insecureEdgeTerminationPolicy: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub caCertificate: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destinationCACertificate: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RouteSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub to: RouteTargetReference,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alternateBackends: Vec<RouteTargetReference>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<RoutePort>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TLSConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wildcardPolicy: Option<String>,
+}
+
+/// A router that has admitted the Route
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RouteIngress {
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub routerName: String,
+    #[serde(default)]
+    pub conditions: Vec<Value>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct RouteStatus {
+    #[serde(default)]
+    pub ingress: Vec<RouteIngress>,
+}
+
+pub type Route = Object<RouteSpec, RouteStatus>;
+
+impl Route {
+    /// Hosts the Route has been admitted on by a router
+    pub fn admitted_hosts(&self) -> Vec<&str> {
+        self.status.iter()
+            .flat_map(|s| s.ingress.iter())
+            .filter(|i| i.conditions.iter().any(|c| c["type"] == "Admitted" && c["status"] == "True"))
+            .map(|i| i.host.as_str())
+            .collect()
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct DeploymentConfigSpec {
+    #[serde(default)]
+    pub replicas: i32,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub selector: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<PodTemplateSpec>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub triggers: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub paused: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revisionHistoryLimit: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct DeploymentConfigStatus {
+    #[serde(default)]
+    pub latestVersion: i64,
+    #[serde(default)]
+    pub observedGeneration: i64,
+    #[serde(default)]
+    pub replicas: i32,
+    #[serde(default)]
+    pub updatedReplicas: i32,
+    #[serde(default)]
+    pub availableReplicas: i32,
+    #[serde(default)]
+    pub readyReplicas: i32,
+    #[serde(default)]
+    pub conditions: Vec<Value>,
+}
+
+pub type DeploymentConfig = Object<DeploymentConfigSpec, DeploymentConfigStatus>;
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ProjectSpec {
+    #[serde(default)]
+    pub finalizers: Vec<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ProjectStatus {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phase: Option<String>,
+}
+
+pub type Project = Object<ProjectSpec, ProjectStatus>;
+
+/// A tag in an ImageStream spec
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct TagReference {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<ObjectReference>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub importPolicy: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub referencePolicy: Option<Value>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ImageStreamSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lookupPolicy: Option<Value>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<TagReference>,
+}
+
+/// An image a tag has pointed at
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct TagEvent {
+    #[serde(default)]
+    pub created: String,
+    #[serde(default)]
+    pub dockerImageReference: String,
+    #[serde(default)]
+    pub image: String,
+    #[serde(default)]
+    pub generation: i64,
+}
+
+/// The history of a tag, newest first
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct NamedTagEventList {
+    pub tag: String,
+    #[serde(default)]
+    pub items: Vec<TagEvent>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ImageStreamStatus {
+    #[serde(default)]
+    pub dockerImageRepository: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publicDockerImageRepository: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<NamedTagEventList>,
+}
+
+pub type ImageStream = Object<ImageStreamSpec, ImageStreamStatus>;
+
+impl ImageStream {
+    /// The image reference the tag currently resolves to
+    pub fn resolve_tag(&self, tag: &str) -> Option<&str> {
+        self.status.as_ref()?
+            .tags.iter()
+            .find(|t| t.tag == tag)?
+            .items.first()
+            .map(|e| e.dockerImageReference.as_str())
+    }
+}
+
+macro_rules! openshift_impl {
+    ($t:ty, $ctor:ident, $group:expr, $kind:expr, $plural:expr, $scope:ty) => {
+        impl Resource for $t {
+            const GROUP: &'static str = $group;
+            const VERSION: &'static str = "v1";
+            const KIND: &'static str = $kind;
+            const PLURAL: &'static str = $plural;
+            type Scope = $scope;
+        }
+
+        impl Api<$t> {
+            pub fn $ctor(client: APIClient) -> Self {
+                Api {
+                    api: RawApi::$ctor(),
+                    client,
+                    phantom: PhantomData,
+                }
+            }
+        }
+    };
+}
+openshift_impl!(Route, v1Route, "route.openshift.io", "Route", "routes", NamespaceResourceScope);
+openshift_impl!(DeploymentConfig, v1DeploymentConfig, "apps.openshift.io", "DeploymentConfig", "deploymentconfigs",
+    NamespaceResourceScope);
+openshift_impl!(Project, v1Project, "project.openshift.io", "Project", "projects", ClusterResourceScope);
+openshift_impl!(ImageStream, v1ImageStream, "image.openshift.io", "ImageStream", "imagestreams", NamespaceResourceScope);
+
+impl Api<Project> {
+    /// Create a project through a ProjectRequest, like `oc new-project`
+    ///
+    /// Regular users cannot create Projects directly; a ProjectRequest also
+    /// sets up the default role bindings for the requesting user.
+    pub fn request_project(&self, name: &str, display_name: Option<&str>, description: Option<&str>) -> Result<Project> {
+        let req = json!({
+            "apiVersion": "project.openshift.io/v1",
+            "kind": "ProjectRequest",
+            "metadata": { "name": name },
+            "displayName": display_name,
+            "description": description,
+        });
+        let data = serde_json::to_vec(&req).context(ErrorKind::SerdeParse)?;
+        let mut api = self.api.clone();
+        api.resource = "projectrequests".into();
+        let req = api.create(&PostParams::default(), data)?;
+        self.client.request::<Project>(req)
+    }
+}
+
+#[test]
+fn route_admitted_hosts() {
+    let route: Route = serde_json::from_value(json!({
+        "metadata": { "name": "blog" },
+        "spec": { "to": { "kind": "Service", "name": "blog" }, "tls": { "termination": "edge" } },
+        "status": { "ingress": [
+            { "host": "blog.apps.example.com", "routerName": "default",
+              "conditions": [{ "type": "Admitted", "status": "True" }] },
+            { "host": "blog.internal", "routerName": "sharded",
+              "conditions": [{ "type": "Admitted", "status": "False" }] },
+        ]}
+    })).unwrap();
+    assert_eq!(route.admitted_hosts(), vec!["blog.apps.example.com"]);
+    let req = RawApi::v1Route().within("ns").get("blog").unwrap();
+    assert_eq!(req.uri(), "/apis/route.openshift.io/v1/namespaces/ns/routes/blog");
+}
@@ -0,0 +1,336 @@
+//! Printing objects the way `kubectl get -o ...` does
+//!
+//! ```
+//! use kube::api::output::{OutputFormat, Printer};
+//! use serde_json::json;
+//!
+//! let pods = vec![json!({
+//!     "apiVersion": "v1", "kind": "Pod",
+//!     "metadata": { "name": "blog", "creationTimestamp": "2019-08-01T10:00:00Z" },
+//! })];
+//! let printer = Printer::new("name".parse::<OutputFormat>().unwrap());
+//! assert_eq!(printer.print(&pods).unwrap(), "pod/blog\n");
+//! ```
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+
+use crate::api::jsonpath::JsonPath;
+use crate::{Result, Error, ErrorKind};
+use failure::ResultExt;
+
+/// A column of a server side `Table`
+#[allow(non_snake_case)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct TableColumnDefinition {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub type_: String,
+    #[serde(default)]
+    pub format: String,
+    #[serde(default)]
+    pub description: String,
+    /// Columns with a priority above 0 are only shown in wide output
+    #[serde(default)]
+    pub priority: i32,
+}
+
+/// A row of a server side `Table`
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct TableRow {
+    pub cells: Vec<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub object: Option<Value>,
+}
+
+/// The columns kubectl prints for a resource, as computed by the apiserver
+///
+/// Fetched with `Api::list_table` or `Api::get_table`.
+#[allow(non_snake_case)]
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct Table {
+    #[serde(default)]
+    pub columnDefinitions: Vec<TableColumnDefinition>,
+    #[serde(default)]
+    pub rows: Vec<TableRow>,
+}
+
+impl Table {
+    /// A Table from what a `list_table` or `get_table` request answered
+    ///
+    /// Apiservers without Tables answer with the plain list or object instead, which is
+    /// turned into the `Name` and `Created At` columns they show for resources without
+    /// printer columns.
+    pub fn from_response(res: Value) -> Result<Self> {
+        if res["kind"] == "Table" {
+            return Ok(serde_json::from_value(res).context(ErrorKind::SerdeParse)?);
+        }
+        let objects = match res.get("items") {
+            Some(Value::Array(items)) => items.clone(),
+            Some(_) => return Err(ErrorKind::RequestValidation(format!("{} items are not a list", res["kind"])).into()),
+            None => vec![res],
+        };
+        let column = |name: &str, type_: &str, format: &str| TableColumnDefinition {
+            name: name.into(), type_: type_.into(), format: format.into(), ..Default::default()
+        };
+        Ok(Table {
+            columnDefinitions: vec![column("Name", "string", "name"), column("Created At", "date", "")],
+            rows: objects.into_iter().map(|o| TableRow {
+                cells: vec![o["metadata"]["name"].clone(), o["metadata"]["creationTimestamp"].clone()],
+                object: Some(o),
+            }).collect(),
+        })
+    }
+}
+
+/// The `-o` flag of kubectl
+#[derive(Clone, Debug)]
+pub enum OutputFormat {
+    /// Columns only, the default
+    Table,
+    /// Columns including the low priority ones
+    Wide,
+    Json,
+    Yaml,
+    /// `kind.group/name` per object
+    Name,
+    JsonPath(JsonPath),
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    /// Parse an `-o` value, e.g. `yaml` or `jsonpath={.metadata.name}`
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "" => OutputFormat::Table,
+            "wide" => OutputFormat::Wide,
+            "json" => OutputFormat::Json,
+            "yaml" => OutputFormat::Yaml,
+            "name" => OutputFormat::Name,
+            s if s.starts_with("jsonpath=") => OutputFormat::JsonPath(JsonPath::parse(&s["jsonpath=".len()..])?),
+            s => return Err(ErrorKind::RequestValidation(format!("unknown output format {:?}", s)).into()),
+        })
+    }
+}
+
+/// A client side column, computed from each object
+#[derive(Clone, Debug)]
+pub struct Column {
+    pub header: String,
+    value: ColumnValue,
+    /// Only shown in wide output
+    pub wide: bool,
+}
+
+#[derive(Clone, Debug)]
+enum ColumnValue {
+    Path(JsonPath),
+    Age,
+}
+
+impl Column {
+    /// A column showing a JSONPath expression, e.g. `{.spec.nodeName}`
+    pub fn new(header: &str, path: &str) -> Result<Self> {
+        Ok(Column { header: header.into(), value: ColumnValue::Path(JsonPath::parse(path)?), wide: false })
+    }
+
+    /// The time since the object was created, like `5m` or `3d2h`
+    pub fn age() -> Self {
+        Column { header: "AGE".into(), value: ColumnValue::Age, wide: false }
+    }
+
+    /// Only show this column in wide output
+    pub fn wide(mut self) -> Self {
+        self.wide = true;
+        self
+    }
+
+    fn cell(&self, obj: &Value) -> String {
+        match &self.value {
+            ColumnValue::Path(p) => p.render(obj),
+            ColumnValue::Age => obj["metadata"]["creationTimestamp"].as_str()
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| human_duration(Utc::now().signed_duration_since(t)))
+                .unwrap_or_else(|| "<unknown>".into()),
+        }
+    }
+}
+
+/// A duration the way kubectl shows ages: two significant units at most
+pub fn human_duration(d: chrono::Duration) -> String {
+    let secs = d.num_seconds();
+    let (mins, hours, days) = (secs / 60, secs / 3600, secs / 86400);
+    let years = days / 365;
+    if secs < 0 {
+        "<invalid>".into()
+    } else if secs < 120 {
+        format!("{}s", secs)
+    } else if mins < 10 {
+        match secs % 60 { 0 => format!("{}m", mins), s => format!("{}m{}s", mins, s) }
+    } else if hours < 3 {
+        format!("{}m", mins)
+    } else if hours < 8 {
+        match mins % 60 { 0 => format!("{}h", hours), m => format!("{}h{}m", hours, m) }
+    } else if hours < 48 {
+        format!("{}h", hours)
+    } else if days < 8 {
+        match hours % 24 { 0 => format!("{}d", days), h => format!("{}d{}h", days, h) }
+    } else if years < 2 {
+        format!("{}d", days)
+    } else if years < 8 {
+        match days % 365 { 0 => format!("{}y", years), d => format!("{}y{}d", years, d) }
+    } else {
+        format!("{}y", years)
+    }
+}
+
+/// `kind.group/name`, as printed by `-o name`
+fn object_name(obj: &Value) -> String {
+    let kind = obj["kind"].as_str().unwrap_or("").to_lowercase();
+    let group = obj["apiVersion"].as_str()
+        .and_then(|av| av.rfind('/').map(|i| &av[..i]))
+        .map(|g| format!(".{}", g))
+        .unwrap_or_default();
+    format!("{}{}/{}", kind, group, obj["metadata"]["name"].as_str().unwrap_or(""))
+}
+
+fn table_cell(v: &Value) -> String {
+    match v {
+        Value::Null => "<none>".into(),
+        Value::String(s) if s.is_empty() => "<none>".into(),
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// Pad columns to a common width, separated by three spaces like kubectl
+fn align(rows: Vec<Vec<String>>) -> String {
+    let cols = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..cols)
+        .map(|i| rows.iter().filter_map(|r| r.get(i)).map(|c| c.chars().count()).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for row in rows {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            line.push_str(cell);
+            if i + 1 < row.len() {
+                line.push_str(&" ".repeat(widths[i] - cell.chars().count() + 3));
+            }
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Render a server side `Table`, with the low priority columns when `wide`
+pub fn format_table(table: &Table, wide: bool) -> String {
+    let shown: Vec<usize> = table.columnDefinitions.iter().enumerate()
+        .filter(|(_, c)| wide || c.priority == 0)
+        .map(|(i, _)| i)
+        .collect();
+    let mut rows = vec![shown.iter().map(|i| table.columnDefinitions[*i].name.to_uppercase()).collect()];
+    for row in &table.rows {
+        rows.push(shown.iter().map(|i| row.cells.get(*i).map(table_cell).unwrap_or_default()).collect());
+    }
+    align(rows)
+}
+
+/// Prints lists of objects in one output format
+///
+/// Tables without a server side `Table` use the configured columns,
+/// by default `NAME` and `AGE`.
+#[derive(Clone, Debug)]
+pub struct Printer {
+    format: OutputFormat,
+    columns: Vec<Column>,
+}
+
+impl Printer {
+    pub fn new(format: OutputFormat) -> Self {
+        let columns = vec![Column::new("NAME", "{.metadata.name}").unwrap(), Column::age()];
+        Printer { format, columns }
+    }
+
+    /// Use these columns for table output instead of `NAME` and `AGE`
+    pub fn columns(mut self, columns: Vec<Column>) -> Self {
+        self.columns = columns;
+        self
+    }
+
+    /// Print objects, as a `List` for json and yaml unless there is exactly one
+    pub fn print(&self, objects: &[Value]) -> Result<String> {
+        let single_or_list = || match objects {
+            [obj] => obj.clone(),
+            objs => json!({ "apiVersion": "v1", "kind": "List", "items": objs, "metadata": {} }),
+        };
+        Ok(match &self.format {
+            OutputFormat::Json => {
+                serde_json::to_string_pretty(&single_or_list()).context(ErrorKind::SerdeParse)? + "\n"
+            },
+            OutputFormat::Yaml => serde_yaml::to_string(&single_or_list()).context(ErrorKind::SerdeParse)? + "\n",
+            OutputFormat::Name => objects.iter().map(|o| object_name(o) + "\n").collect(),
+            OutputFormat::JsonPath(p) => objects.iter().map(|o| p.render(o)).collect(),
+            OutputFormat::Table | OutputFormat::Wide => {
+                let wide = matches!(self.format, OutputFormat::Wide);
+                let columns: Vec<&Column> = self.columns.iter().filter(|c| wide || !c.wide).collect();
+                let mut rows = vec![columns.iter().map(|c| c.header.to_uppercase()).collect()];
+                for obj in objects {
+                    rows.push(columns.iter().map(|c| c.cell(obj)).collect());
+                }
+                align(rows)
+            },
+        })
+    }
+
+    /// Print a server side `Table`, falling back to the row objects for non table formats
+    ///
+    /// Row objects are only included when the table was requested with them.
+    pub fn print_table(&self, table: &Table) -> Result<String> {
+        match self.format {
+            OutputFormat::Table => Ok(format_table(table, false)),
+            OutputFormat::Wide => Ok(format_table(table, true)),
+            _ => {
+                let objects: Vec<Value> = table.rows.iter().filter_map(|r| r.object.clone()).collect();
+                self.print(&objects)
+            },
+        }
+    }
+}
+
+#[test]
+fn ages_and_tables() {
+    use chrono::Duration;
+    assert_eq!(human_duration(Duration::seconds(59)), "59s");
+    assert_eq!(human_duration(Duration::seconds(5 * 60 + 3)), "5m3s");
+    assert_eq!(human_duration(Duration::minutes(90)), "90m");
+    assert_eq!(human_duration(Duration::minutes(5 * 60 + 30)), "5h30m");
+    assert_eq!(human_duration(Duration::hours(30)), "30h");
+    assert_eq!(human_duration(Duration::hours(3 * 24 + 2)), "3d2h");
+    assert_eq!(human_duration(Duration::days(400)), "400d");
+    assert_eq!(human_duration(Duration::days(3 * 365 + 10)), "3y10d");
+
+    let table: Table = serde_json::from_value(json!({
+        "columnDefinitions": [
+            { "name": "Name", "type": "string", "priority": 0 },
+            { "name": "Status", "type": "string", "priority": 0 },
+            { "name": "Node", "type": "string", "priority": 1 },
+        ],
+        "rows": [
+            { "cells": ["blog", "Running", "node-1"] },
+            { "cells": ["database", "Pending", null] },
+        ],
+    })).unwrap();
+    assert_eq!(format_table(&table, false), "NAME       STATUS\nblog       Running\ndatabase   Pending\n");
+    assert_eq!(format_table(&table, true),
+        "NAME       STATUS    NODE\nblog       Running   node-1\ndatabase   Pending   <none>\n");
+
+    let deploy = json!({ "apiVersion": "apps/v1", "kind": "Deployment", "metadata": { "name": "blog" } });
+    let printer = Printer::new(OutputFormat::Table)
+        .columns(vec![Column::new("NAME", "{.metadata.name}").unwrap(), Column::age()]);
+    assert_eq!(printer.print(std::slice::from_ref(&deploy)).unwrap(), "NAME   AGE\nblog   <unknown>\n");
+    assert_eq!(Printer::new(OutputFormat::Name).print(&[deploy]).unwrap(), "deployment.apps/blog\n");
+}
@@ -0,0 +1,181 @@
+//! Waiting on pods to start, and finding out why they do not
+use std::fmt;
+use std::time::Duration;
+
+use crate::api::{Api, Object, Conditions, Reflector};
+use crate::api::wait::{poll_until, poll_until_explained};
+use crate::{Result, Error, ErrorKind};
+
+use k8s_openapi::api::core::v1::{ContainerStatus, PodSpec, PodStatus};
+
+type Pod = Object<PodSpec, PodStatus>;
+
+/// The index `Reflector::index_by_node` adds, with pods keyed by the node they are bound to
+pub const NODE_INDEX: &str = "node";
+
+/// Why a pod is not making progress towards running
+#[derive(Clone, Debug, PartialEq)]
+pub enum PodStuck {
+    /// The image of a container cannot be pulled, e.g. `ImagePullBackOff`
+    ImagePull { container: String, reason: String, message: String },
+    /// A container keeps exiting and is restarted with a growing delay
+    CrashLoop { container: String, restarts: i32, message: String },
+    /// A container cannot be created, e.g. for a missing ConfigMap or Secret
+    ContainerConfig { container: String, reason: String, message: String },
+    /// No node can fit the pod, with the scheduler's explanation
+    Unschedulable(String),
+}
+
+impl fmt::Display for PodStuck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PodStuck::ImagePull { container, reason, message } |
+            PodStuck::ContainerConfig { container, reason, message } => {
+                write!(f, "container {} is in {}: {}", container, reason, message)
+            },
+            PodStuck::CrashLoop { container, restarts, message } => {
+                write!(f, "container {} is crash looping after {} restarts: {}", container, restarts, message)
+            },
+            PodStuck::Unschedulable(message) => write!(f, "pod is unschedulable: {}", message),
+        }
+    }
+}
+
+fn stuck_container(cs: &ContainerStatus) -> Option<PodStuck> {
+    let waiting = cs.state.as_ref()?.waiting.as_ref()?;
+    let reason = waiting.reason.clone().unwrap_or_default();
+    let mut message = waiting.message.clone().unwrap_or_default();
+    let container = cs.name.clone();
+    Some(match reason.as_str() {
+        "ErrImagePull" | "ImagePullBackOff" | "InvalidImageName" | "ErrImageNeverPull" => {
+            PodStuck::ImagePull { container, reason, message }
+        },
+        "CrashLoopBackOff" => {
+            // the back-off message only names the container, so say how it last exited
+            let last = cs.last_state.as_ref().and_then(|s| s.terminated.as_ref());
+            if let Some(t) = last {
+                message = format!("last exited with code {} ({})", t.exit_code, t.reason.as_deref().unwrap_or("Unknown"));
+            }
+            PodStuck::CrashLoop { container, restarts: cs.restart_count, message }
+        },
+        "CreateContainerConfigError" | "CreateContainerError" | "RunContainerError" => {
+            PodStuck::ContainerConfig { container, reason, message }
+        },
+        _ => return None,
+    })
+}
+
+impl Object<PodSpec, PodStatus> {
+    /// The phase, e.g. `Pending` or `Running`
+    pub fn phase(&self) -> Option<&str> {
+        self.status.as_ref().and_then(|s| s.phase.as_deref())
+    }
+
+    /// Whether the pod has the `Ready` condition, so is receiving traffic from services
+    pub fn is_ready(&self) -> bool {
+        self.status.as_ref()
+            .and_then(|s| s.conditions.as_ref())
+            .is_some_and(|c| c.is_condition_true("Ready"))
+    }
+
+    /// The status of a container or init container by name
+    pub fn container_status(&self, container: &str) -> Option<&ContainerStatus> {
+        let status = self.status.as_ref()?;
+        status.container_statuses.iter().chain(status.init_container_statuses.iter())
+            .flatten()
+            .find(|cs| cs.name == container)
+    }
+
+    /// Why the pod is stuck, if it is in a state that does not resolve on its own
+    ///
+    /// Init containers are checked first, as they hold up the rest of the pod.
+    pub fn stuck_reason(&self) -> Option<PodStuck> {
+        let status = self.status.as_ref()?;
+        let stuck = status.init_container_statuses.iter().chain(status.container_statuses.iter())
+            .flatten()
+            .find_map(stuck_container);
+        if stuck.is_some() {
+            return stuck;
+        }
+        status.conditions.as_ref()?
+            .find_condition("PodScheduled")
+            .filter(|c| c.status == "False" && c.reason.as_deref() == Some("Unschedulable"))
+            .map(|c| PodStuck::Unschedulable(c.message.clone().unwrap_or_default()))
+    }
+}
+
+impl Reflector<Pod> {
+    /// Index the cached pods as `NODE_INDEX`, leaving out pods not scheduled yet
+    pub fn index_by_node(self) -> Self {
+        self.index(NODE_INDEX, |p| p.spec.node_name.iter().cloned().collect())
+    }
+}
+
+impl Api<Pod> {
+    /// Block until the pod is `Ready`, or fail after `timeout`
+    ///
+    /// A pod that finished instead results in `ErrorKind::PodFailed`. On timeout, the
+    /// error includes the `stuck_reason` of the pod, if any.
+    pub fn wait_for_pod_ready(&self, name: &str, timeout: Duration) -> Result<Pod> {
+        poll_until_explained(&format!("pod {} to be ready", name), timeout, |stuck| {
+            let pod = self.get(name)?;
+            if let Some(phase @ "Failed") | Some(phase @ "Succeeded") = pod.phase() {
+                return Err(Error::from(ErrorKind::PodFailed(format!("{} is {} and will not become ready", name, phase))));
+            }
+            *stuck = pod.stuck_reason().map(|s| s.to_string());
+            Ok(if pod.is_ready() { Some(pod) } else { None })
+        })
+    }
+
+    /// Block until a container of the pod is running, or fail after `timeout`
+    ///
+    /// Works for init containers too, and does not wait for readiness probes.
+    pub fn wait_for_container_started(&self, name: &str, container: &str, timeout: Duration) -> Result<Pod> {
+        poll_until(&format!("container {} of pod {} to start", container, name), timeout, || {
+            let pod = self.get(name)?;
+            let running = pod.container_status(container)
+                .and_then(|cs| cs.state.as_ref())
+                .is_some_and(|s| s.running.is_some());
+            if !running && pod.phase().is_some_and(|p| p == "Failed" || p == "Succeeded") {
+                return Err(Error::from(ErrorKind::PodFailed(format!("{} finished before {} started", name, container))));
+            }
+            Ok(if running { Some(pod) } else { None })
+        })
+    }
+}
+
+#[test]
+fn stuck_pods_explain_themselves() {
+    use serde_json::json;
+    let pod = |status: serde_json::Value| -> Pod {
+        serde_json::from_value(json!({ "metadata": { "name": "web" }, "spec": { "containers": [] }, "status": status })).unwrap()
+    };
+    let waiting = |name: &str, reason: &str| json!({
+        "name": name, "image": "nginx", "imageID": "", "ready": false, "restartCount": 3,
+        "state": { "waiting": { "reason": reason, "message": "Back-off pulling image" } },
+        "lastState": { "terminated": { "exitCode": 137, "reason": "OOMKilled" } },
+    });
+
+    let p = pod(json!({ "phase": "Pending", "containerStatuses": [waiting("app", "ImagePullBackOff")] }));
+    assert!(!p.is_ready());
+    assert_eq!(p.stuck_reason(), Some(PodStuck::ImagePull {
+        container: "app".into(), reason: "ImagePullBackOff".into(), message: "Back-off pulling image".into(),
+    }));
+    let p = pod(json!({
+        "phase": "Running",
+        "initContainerStatuses": [waiting("migrate", "CrashLoopBackOff")],
+        "containerStatuses": [waiting("app", "PodInitializing")],
+    }));
+    assert_eq!(p.stuck_reason().unwrap().to_string(),
+        "container migrate is crash looping after 3 restarts: last exited with code 137 (OOMKilled)");
+    assert!(p.container_status("migrate").is_some());
+    let p = pod(json!({
+        "phase": "Pending",
+        "conditions": [{ "type": "PodScheduled", "status": "False", "reason": "Unschedulable",
+            "message": "0/3 nodes are available: 3 Insufficient cpu." }],
+    }));
+    assert_eq!(p.stuck_reason(), Some(PodStuck::Unschedulable("0/3 nodes are available: 3 Insufficient cpu.".into())));
+    let p = pod(json!({ "phase": "Running", "conditions": [{ "type": "Ready", "status": "True" }] }));
+    assert!(p.is_ready());
+    assert_eq!(p.stuck_reason(), None);
+}
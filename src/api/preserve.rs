@@ -0,0 +1,201 @@
+//! Keeping fields a type does not know about through read-modify-write cycles
+use std::collections::BTreeMap;
+use std::ops::{Deref, DerefMut};
+
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::ser::{self, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::api::{KubeObject, ObjectMeta, Resource, TypeMeta};
+
+/// What a type dropped when parsing a value
+#[derive(Clone, Debug)]
+enum Unknown {
+    /// A field the type has no place for, with its value
+    Field(Value),
+    /// Fields dropped inside an object the type does know
+    Fields(BTreeMap<String, Unknown>),
+    /// Fields dropped inside the items of a list the type does know
+    Items(Vec<Option<Unknown>>),
+}
+
+/// The parts of `raw` missing from `known`, its round trip through a type
+fn unknown(raw: &Value, known: &Value) -> Option<Unknown> {
+    match (raw, known) {
+        (Value::Object(r), Value::Object(k)) => {
+            let fields: BTreeMap<_, _> = r.iter().filter_map(|(key, rv)| {
+                let u = match k.get(key) {
+                    None if rv.is_null() => None,
+                    None => Some(Unknown::Field(rv.clone())),
+                    Some(kv) => unknown(rv, kv),
+                };
+                u.map(|u| (key.clone(), u))
+            }).collect();
+            if fields.is_empty() { None } else { Some(Unknown::Fields(fields)) }
+        },
+        (Value::Array(r), Value::Array(k)) if r.len() == k.len() => {
+            let items: Vec<_> = r.iter().zip(k).map(|(rv, kv)| unknown(rv, kv)).collect();
+            if items.iter().all(Option::is_none) { None } else { Some(Unknown::Items(items)) }
+        },
+        // scalars, or values the type reshaped; what the type holds wins
+        _ => None,
+    }
+}
+
+/// Put dropped fields back into a serialized value
+fn restore(value: &mut Value, unknown: &Unknown) {
+    match (value, unknown) {
+        (Value::Object(v), Unknown::Fields(fields)) => {
+            for (key, u) in fields {
+                match (v.get_mut(key), u) {
+                    (None, Unknown::Field(raw)) => {
+                        v.insert(key.clone(), raw.clone());
+                    },
+                    // the type now sets it, or it was removed through the type
+                    (Some(_), Unknown::Field(_)) | (None, _) => {},
+                    (Some(inner), u) => restore(inner, u),
+                }
+            }
+        },
+        // items were added or removed, so can no longer be matched up
+        (Value::Array(v), Unknown::Items(items)) if v.len() == items.len() => {
+            for (inner, u) in v.iter_mut().zip(items) {
+                if let Some(u) = u {
+                    restore(inner, u);
+                }
+            }
+        },
+        _ => {},
+    }
+}
+
+/// A typed object that serializes back with the fields its type does not know
+///
+/// Typed reads drop fields missing from the type, such as those added by newer API
+/// versions or by other controllers, and writing the object back would then remove
+/// them. Wrapping the type keeps them:
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// use kube::api::{Object, Preserved, Void};
+/// use serde_json::json;
+///
+/// #[derive(Clone, Deserialize, Serialize)]
+/// struct FooSpec { replicas: i32 }
+///
+/// let raw = json!({ "metadata": { "name": "a" }, "spec": { "replicas": 1, "paused": true } });
+/// let mut foo: Preserved<Object<FooSpec, Void>> = serde_json::from_value(raw).unwrap();
+/// foo.spec.replicas = 2;
+/// let written = serde_json::to_value(&foo).unwrap();
+/// assert_eq!(written["spec"], json!({ "replicas": 2, "paused": true }));
+/// ```
+///
+/// Fields removed through the type stay removed. Unknown fields inside list items
+/// are only kept while the list keeps its length.
+#[derive(Clone)]
+pub struct Preserved<K> {
+    inner: K,
+    unknown: Option<Unknown>,
+}
+
+impl<K> Preserved<K> {
+    /// Wrap an object that was not read from anywhere, so has nothing to preserve
+    pub fn new(inner: K) -> Self {
+        Preserved { inner, unknown: None }
+    }
+
+    pub fn into_inner(self) -> K {
+        self.inner
+    }
+
+    /// Whether parsing dropped any fields
+    pub fn has_unknown_fields(&self) -> bool {
+        self.unknown.is_some()
+    }
+}
+
+impl<K> Deref for Preserved<K> {
+    type Target = K;
+
+    fn deref(&self) -> &K {
+        &self.inner
+    }
+}
+
+impl<K> DerefMut for Preserved<K> {
+    fn deref_mut(&mut self) -> &mut K {
+        &mut self.inner
+    }
+}
+
+impl<'de, K: DeserializeOwned + Serialize> Deserialize<'de> for Preserved<K> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let raw = Value::deserialize(d)?;
+        let inner: K = serde_json::from_value(raw.clone()).map_err(de::Error::custom)?;
+        let known = serde_json::to_value(&inner).map_err(de::Error::custom)?;
+        Ok(Preserved { unknown: unknown(&raw, &known), inner })
+    }
+}
+
+impl<K: Serialize> Serialize for Preserved<K> {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut value = serde_json::to_value(&self.inner).map_err(ser::Error::custom)?;
+        if let Some(u) = &self.unknown {
+            restore(&mut value, u);
+        }
+        value.serialize(s)
+    }
+}
+
+impl<K: KubeObject> KubeObject for Preserved<K> {
+    fn meta(&self) -> &ObjectMeta {
+        self.inner.meta()
+    }
+
+    fn types(&self) -> Option<&TypeMeta> {
+        self.inner.types()
+    }
+}
+
+impl<K: Resource> Resource for Preserved<K> {
+    const GROUP: &'static str = K::GROUP;
+    const VERSION: &'static str = K::VERSION;
+    const KIND: &'static str = K::KIND;
+    const PLURAL: &'static str = K::PLURAL;
+    type Scope = K::Scope;
+}
+
+#[test]
+fn unknown_fields_survive_round_trips() {
+    use serde_json::json;
+    use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+    use crate::api::Object;
+    let raw = json!({
+        "apiVersion": "v1", "kind": "Pod",
+        "metadata": { "name": "web", "creationTimestamp": "2019-10-01T00:00:00Z", "labels": { "app": "web" } },
+        "spec": {
+            "nodeName": "node-1",
+            "hostUsers": false,
+            "containers": [{ "name": "app", "image": "nginx:1.16", "resizePolicy": [{ "resourceName": "cpu" }] }],
+        },
+    });
+    let mut pod: Preserved<Object<PodSpec, PodStatus>> = serde_json::from_value(raw).unwrap();
+    assert!(pod.has_unknown_fields());
+    pod.metadata.labels.insert("tier".into(), "frontend".into());
+    pod.spec.containers[0].image = Some("nginx:1.17".into());
+    pod.spec.node_name = None;
+    let written = serde_json::to_value(&pod).unwrap();
+    assert_eq!(written["metadata"]["creationTimestamp"], "2019-10-01T00:00:00Z");
+    assert_eq!(written["metadata"]["labels"]["tier"], "frontend");
+    assert_eq!(written["spec"]["hostUsers"], false);
+    assert_eq!(written["spec"]["containers"][0]["image"], "nginx:1.17");
+    assert_eq!(written["spec"]["containers"][0]["resizePolicy"][0]["resourceName"], "cpu");
+    assert!(written["spec"].get("nodeName").is_none());
+
+    let sidecar = pod.spec.containers[0].clone();
+    pod.spec.containers.push(sidecar);
+    let written = serde_json::to_value(&pod).unwrap();
+    assert!(written["spec"]["containers"][0].get("resizePolicy").is_none());
+    assert_eq!(written["spec"]["hostUsers"], false);
+}
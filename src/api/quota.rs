@@ -0,0 +1,177 @@
+//! ResourceQuota and LimitRange calculators, for multi tenancy controllers
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+use crate::api::{Api, ListParams, Object, Quantity, Void};
+use crate::Result;
+
+use k8s_openapi::api::core::v1::{LimitRangeItem, LimitRangeSpec, ResourceQuotaSpec, ResourceQuotaStatus};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity as RawQuantity;
+
+type ResourceQuota = Object<ResourceQuotaSpec, ResourceQuotaStatus>;
+type LimitRange = Object<LimitRangeSpec, Void>;
+
+/// Amounts by resource name, like `requests.cpu` or `memory`
+pub type Resources = BTreeMap<String, Quantity>;
+
+/// Parse a resource list, skipping quantities the apiserver would not have accepted
+pub(crate) fn parse(list: Option<&BTreeMap<String, RawQuantity>>) -> Resources {
+    list.into_iter().flatten()
+        .filter_map(|(name, q)| match Quantity::try_from(q) {
+            Ok(q) => Some((name.clone(), q)),
+            Err(e) => {
+                warn!("Ignoring {}: {}", name, e);
+                None
+            },
+        })
+        .collect()
+}
+
+impl ResourceQuota {
+    /// The enforced limits
+    ///
+    /// From the status, which the quota controller copies the spec to once it
+    /// enforces it, falling back to the spec for quotas it has not seen yet.
+    pub fn hard(&self) -> Resources {
+        match self.status.as_ref().and_then(|s| s.hard.as_ref()) {
+            Some(hard) => parse(Some(hard)),
+            None => parse(self.spec.hard.as_ref()),
+        }
+    }
+
+    /// The current usage, as last observed by the quota controller
+    pub fn used(&self) -> Resources {
+        parse(self.status.as_ref().and_then(|s| s.used.as_ref()))
+    }
+
+    /// What is left of each limit, zero when used up or over
+    pub fn remaining(&self) -> Resources {
+        let used = self.used();
+        self.hard().into_iter()
+            .map(|(name, hard)| {
+                let left = match used.get(&name) {
+                    Some(u) => hard - *u,
+                    None => hard,
+                };
+                (name, left.max(Quantity::from_millis(0)))
+            })
+            .collect()
+    }
+
+    /// The resources for which `requests` would exceed what is left
+    pub fn exceeded_by(&self, requests: &Resources) -> Vec<String> {
+        let remaining = self.remaining();
+        requests.iter()
+            .filter(|(name, q)| remaining.get(*name).is_some_and(|left| *q > left))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+impl Api<ResourceQuota> {
+    /// What is left in the namespace, the least of all quotas for each resource
+    ///
+    /// Quotas with `scopes` only count some pods, e.g. `BestEffort` ones, but are
+    /// included regardless.
+    pub fn remaining(&self) -> Result<Resources> {
+        let mut remaining = Resources::new();
+        for quota in self.list(&ListParams::default())?.items {
+            for (name, left) in quota.remaining() {
+                match remaining.get(&name) {
+                    Some(least) if *least <= left => {},
+                    _ => { remaining.insert(name, left); },
+                }
+            }
+        }
+        Ok(remaining)
+    }
+}
+
+impl LimitRange {
+    /// The limits for a type of object, e.g. `Container`, `Pod` or `PersistentVolumeClaim`
+    pub fn limits_for(&self, type_: &str) -> Option<&LimitRangeItem> {
+        self.spec.limits.iter().find(|l| l.type_.as_deref() == Some(type_))
+    }
+
+    /// The requests and limits given to containers that set none
+    pub fn container_defaults(&self) -> (Resources, Resources) {
+        match self.limits_for("Container") {
+            Some(item) => (parse(item.default_request.as_ref()), parse(item.default.as_ref())),
+            None => (Resources::new(), Resources::new()),
+        }
+    }
+
+    /// Why the apiserver would reject an object of `type_` with these requests and limits
+    ///
+    /// Checks `min`, `max` and `maxLimitRequestRatio` like the LimitRanger admission
+    /// plugin does, before defaults are applied.
+    pub fn violations(&self, type_: &str, requests: &Resources, limits: &Resources) -> Vec<String> {
+        let item = match self.limits_for(type_) {
+            Some(item) => item,
+            None => return vec![],
+        };
+        let mut violations = vec![];
+        for (name, min) in parse(item.min.as_ref()) {
+            for (what, q) in &[("request", requests.get(&name)), ("limit", limits.get(&name))] {
+                if let Some(q) = q.filter(|q| **q < min) {
+                    violations.push(format!("minimum {} usage per {} is {}, but {} is {}", name, type_, min, what, q));
+                }
+            }
+        }
+        for (name, max) in parse(item.max.as_ref()) {
+            for (what, q) in &[("request", requests.get(&name)), ("limit", limits.get(&name))] {
+                if let Some(q) = q.filter(|q| **q > max) {
+                    violations.push(format!("maximum {} usage per {} is {}, but {} is {}", name, type_, max, what, q));
+                }
+            }
+        }
+        for (name, ratio) in parse(item.max_limit_request_ratio.as_ref()) {
+            if let (Some(req), Some(lim)) = (requests.get(&name), limits.get(&name)) {
+                if !req.is_zero() && lim.as_f64() / req.as_f64() > ratio.as_f64() {
+                    violations.push(format!("{} max limit to request ratio per {} is {}, but provided ratio is {:.6}",
+                        name, type_, ratio, lim.as_f64() / req.as_f64()));
+                }
+            }
+        }
+        violations
+    }
+}
+
+#[test]
+fn quota_and_limits_are_calculated() {
+    let quota: ResourceQuota = serde_json::from_value(serde_json::json!({
+        "metadata": { "name": "team", "namespace": "blog" },
+        "spec": { "hard": { "requests.cpu": "2", "requests.memory": "4Gi", "pods": "10" } },
+        "status": {
+            "hard": { "requests.cpu": "2", "requests.memory": "4Gi", "pods": "10" },
+            "used": { "requests.cpu": "1500m", "requests.memory": "5Gi", "pods": "3" },
+        },
+    })).unwrap();
+    let q = |s: &str| s.parse::<Quantity>().unwrap();
+    let remaining = quota.remaining();
+    assert_eq!(remaining["requests.cpu"], q("500m"));
+    assert!(remaining["requests.memory"].is_zero(), "over quota leaves nothing");
+    assert_eq!(remaining["pods"], q("7"));
+    let requests: Resources = vec![("requests.cpu".to_string(), q("600m")), ("pods".to_string(), q("1"))]
+        .into_iter().collect();
+    assert_eq!(quota.exceeded_by(&requests), vec!["requests.cpu"]);
+
+    let limits: LimitRange = serde_json::from_value(serde_json::json!({
+        "metadata": { "name": "defaults", "namespace": "blog" },
+        "spec": { "limits": [{
+            "type": "Container",
+            "default": { "cpu": "500m" }, "defaultRequest": { "cpu": "100m" },
+            "min": { "cpu": "50m" }, "max": { "memory": "1Gi" }, "maxLimitRequestRatio": { "cpu": "4" },
+        }] },
+    })).unwrap();
+    let (requests, defaults) = limits.container_defaults();
+    assert_eq!((requests["cpu"], defaults["cpu"]), (q("100m"), q("500m")));
+    let set = |pairs: &[(&str, &str)]| -> Resources { pairs.iter().map(|(n, v)| (n.to_string(), q(v))).collect() };
+    assert!(limits.violations("Container", &set(&[("cpu", "100m")]), &set(&[("cpu", "400m")])).is_empty());
+    assert_eq!(limits.violations("Container", &set(&[("cpu", "10m")]), &set(&[("cpu", "1"), ("memory", "2Gi")])), vec![
+        "minimum cpu usage per Container is 50m, but request is 10m",
+        "maximum memory usage per Container is 1Gi, but limit is 2Gi",
+        "cpu max limit to request ratio per Container is 4, but provided ratio is 100.000000",
+    ]);
+    assert!(limits.violations("Pod", &set(&[("cpu", "10m")]), &Resources::new()).is_empty());
+}
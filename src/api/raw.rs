@@ -1,3 +1,4 @@
+use crate::api::RequestBuilder;
 use crate::{Result, ErrorKind};
 use failure::ResultExt;
 
@@ -44,13 +45,20 @@ impl RawApi {
     /// Set as namespaced resource within a specified namespace
     pub fn within(mut self, ns: &str) -> Self {
         match self.resource.as_ref() {
-            "nodes" | "namespaces" | "customresourcedefinitions" =>
+            "nodes" | "namespaces" | "customresourcedefinitions" |
+            "clusterroles" | "clusterrolebindings" | "projects" | "apiservices" |
+            "storageclasses" | "volumesnapshotcontents" | "volumesnapshotclasses" =>
                 panic!("{} is not a namespace scoped resource", self.resource),
             _ => {},
         }
         self.namespace = Some(ns.to_string());
         self
     }
+    /// Undo `within`, for requests across all namespaces
+    pub fn all_namespaces(mut self) -> Self {
+        self.namespace = None;
+        self
+    }
     /// Set the api group of a resource manually
     ///
     /// Can be used to set legacy versions like "extensions" for old Deployments
@@ -221,6 +229,39 @@ impl RawApi {
         }
     }
 
+    // Stable StorageClass resource constructor
+    pub fn v1StorageClass() -> Self {
+        Self {
+            group: "storage.k8s.io".into(),
+            resource: "storageclasses".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// CSI VolumeSnapshot resource constructor, needs the external snapshotter CRDs
+    pub fn v1VolumeSnapshot() -> Self {
+        Self {
+            group: "snapshot.storage.k8s.io".into(),
+            resource: "volumesnapshots".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// CSI VolumeSnapshotContent resource constructor, needs the external snapshotter CRDs
+    pub fn v1VolumeSnapshotContent() -> Self {
+        Self {
+            group: "snapshot.storage.k8s.io".into(),
+            resource: "volumesnapshotcontents".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
     // Stable NetworkPolicy resource constructor
     pub fn v1NetworkPolicy() -> Self {
         Self {
@@ -243,6 +284,17 @@ impl RawApi {
         }
     }
 
+    /// Stable LimitRange resource constructor
+    pub fn v1LimitRange() -> Self {
+        Self {
+            group: "".into(),
+            resource: "limitranges".into(),
+            prefix: "api".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
     // Stable HorizontalPodAutoscaler resource constructor
     pub fn v1HorizontalPodAutoscaler() -> Self {
         Self {
@@ -254,6 +306,138 @@ impl RawApi {
         }
     }
 
+    // Stable Role resource constructor
+    pub fn v1Role() -> Self {
+        Self {
+            group: "rbac.authorization.k8s.io".into(),
+            resource: "roles".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    // Stable ClusterRole resource constructor
+    pub fn v1ClusterRole() -> Self {
+        Self {
+            group: "rbac.authorization.k8s.io".into(),
+            resource: "clusterroles".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    // Stable RoleBinding resource constructor
+    pub fn v1RoleBinding() -> Self {
+        Self {
+            group: "rbac.authorization.k8s.io".into(),
+            resource: "rolebindings".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    // Stable ClusterRoleBinding resource constructor
+    pub fn v1ClusterRoleBinding() -> Self {
+        Self {
+            group: "rbac.authorization.k8s.io".into(),
+            resource: "clusterrolebindings".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// Ingress constructor
+    pub fn v1beta1Ingress() -> Self {
+        Self {
+            group: "networking.k8s.io".into(),
+            resource: "ingresses".into(),
+            prefix: "apis".into(),
+            version: "v1beta1".into(), // latest available in 1.15.0
+            ..Default::default()
+        }
+    }
+
+    /// HorizontalPodAutoscaler constructor with support for multiple and custom metrics
+    pub fn v2beta2HorizontalPodAutoscaler() -> Self {
+        Self {
+            group: "autoscaling".into(),
+            resource: "horizontalpodautoscalers".into(),
+            prefix: "apis".into(),
+            version: "v2beta2".into(), // latest available in 1.15.0
+            ..Default::default()
+        }
+    }
+
+    /// PodDisruptionBudget constructor
+    pub fn v1beta1PodDisruptionBudget() -> Self {
+        Self {
+            group: "policy".into(),
+            resource: "poddisruptionbudgets".into(),
+            prefix: "apis".into(),
+            version: "v1beta1".into(), // latest available in 1.15.0
+            ..Default::default()
+        }
+    }
+
+    // Stable APIService resource constructor
+    pub fn v1APIService() -> Self {
+        Self {
+            group: "apiregistration.k8s.io".into(),
+            resource: "apiservices".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// TokenReview constructor, for asking who a token belongs to
+    pub fn v1TokenReview() -> Self {
+        Self {
+            group: "authentication.k8s.io".into(),
+            resource: "tokenreviews".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// SubjectAccessReview constructor, for asking what a user may do
+    pub fn v1SubjectAccessReview() -> Self {
+        Self {
+            group: "authorization.k8s.io".into(),
+            resource: "subjectaccessreviews".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    /// LocalSubjectAccessReview constructor, for asking within a namespace
+    pub fn v1LocalSubjectAccessReview() -> Self {
+        Self {
+            group: "authorization.k8s.io".into(),
+            resource: "localsubjectaccessreviews".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
+    // Stable Lease resource constructor
+    pub fn v1Lease() -> Self {
+        Self {
+            group: "coordination.k8s.io".into(),
+            resource: "leases".into(),
+            prefix: "apis".into(),
+            version: "v1".into(),
+            ..Default::default()
+        }
+    }
+
     /// CronJob constructor
     pub fn v1beta1CronJob() -> Self {
         Self {
@@ -276,6 +460,17 @@ impl RawApi {
         }
     }
 
+    /// MutatingWebhookConfiguration constructor
+    pub fn v1beta1MutatingWebhookConfiguration() -> Self {
+        Self {
+            group: "admissionregistration.k8s.io".into(),
+            resource: "mutatingwebhookconfigurations".into(),
+            prefix: "apis".into(),
+            version: "v1beta1".into(),
+            ..Default::default()
+        }
+    }
+
     /// Custom resource definition constructor
     pub fn v1beta1CustomResourceDefinition() -> Self {
         Self {
@@ -307,21 +502,20 @@ impl RawApi {
 // -------------------------------------------------------
 
 impl RawApi {
-    fn make_url(&self) -> String {
-        let pref = if self.prefix == "" { "".into() } else { format!("{}/", self.prefix) };
-        let g = if self.group == "" { "".into() } else { format!("{}/", self.group) };
-        let n = if let Some(ns) = &self.namespace { format!("namespaces/{}/", ns) } else { "".into() };
-
-        format!("/{prefix}{group}{version}/{namespaces}{resource}",
-            prefix = pref,
-            group = g,
-            version = self.version,
-            namespaces = n,
-            resource = self.resource,
-        )
+    /// A request for this resource, to add a name, subresource and parameters to
+    pub fn request(&self) -> RequestBuilder {
+        let rb = RequestBuilder::new(&self.group, &self.version, &self.resource).prefix(&self.prefix);
+        match &self.namespace {
+            Some(ns) => rb.namespace(ns),
+            None => rb,
+        }
     }
 }
 
+/// What kubectl accepts when asking for server side printing
+const TABLE_ACCEPT: &str = "application/json;as=Table;v=v1;g=meta.k8s.io,\
+    application/json;as=Table;v=v1beta1;g=meta.k8s.io,application/json";
+
 /// Common query parameters used in watch/list/delete calls on collections
 ///
 /// Constructed internally with a builder on Informer and Reflector,
@@ -331,13 +525,122 @@ pub struct ListParams {
     pub field_selector: Option<String>,
     pub include_uninitialized: bool,
     pub label_selector: Option<String>,
-    pub timeout: Option<u32>
+    pub timeout: Option<u32>,
+    /// The resourceVersion a list should be served at. Ignored by watch calls.
+    ///
+    /// `"0"` lets the apiserver answer from its watch cache, which is much cheaper
+    /// than a quorum read from etcd, at the cost of possibly stale data.
+    pub resource_version: Option<String>,
+    /// How `resource_version` is interpreted. Requires `resource_version` to be set.
+    pub resource_version_match: Option<ResourceVersionMatch>,
+    /// Return at most this many items, along with a `continue` token for the rest
+    ///
+    /// Ignored by the apiserver for lists served from its cache (`resourceVersion=0`).
+    pub limit: Option<u32>,
+    /// The `continue` token of the previous page of a paginated list
+    pub continue_token: Option<String>,
+}
+
+/// Semantics for the resourceVersion of a list call
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResourceVersionMatch {
+    /// Return data at exactly the given resourceVersion
+    Exact,
+    /// Return data at least as new as the given resourceVersion
+    NotOlderThan,
+}
+
+impl ListParams {
+    pub(crate) fn validate(&self) -> Result<()> {
+        match (&self.resource_version, &self.resource_version_match) {
+            (None, Some(_)) => Err(ErrorKind::RequestValidation(
+                "resource_version_match requires a resource_version".to_owned()).into()),
+            (Some(rv), Some(ResourceVersionMatch::Exact)) if rv == "0" => Err(ErrorKind::RequestValidation(
+                "resource_version_match Exact cannot be used with resource_version 0".to_owned()).into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// A list served from the apiserver cache (`resourceVersion=0`)
+    ///
+    /// Cheap, but the result may be arbitrarily stale.
+    pub fn cached() -> Self {
+        ListParams {
+            resource_version: Some("0".into()),
+            resource_version_match: Some(ResourceVersionMatch::NotOlderThan),
+            ..Default::default()
+        }
+    }
+
+    /// A list served at exactly the given resourceVersion
+    pub fn at_version(rv: &str) -> Self {
+        ListParams {
+            resource_version: Some(rv.into()),
+            resource_version_match: Some(ResourceVersionMatch::Exact),
+            ..Default::default()
+        }
+    }
+}
+
+/// How the apiserver treats unknown or duplicate fields in a write
+///
+/// Needs a 1.25+ apiserver; older ones ignore the parameter and drop such fields silently.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValidation {
+    /// Drop unknown fields silently, as older apiservers do
+    Ignore,
+    /// Drop unknown fields, but answer with a `Warning` header for each
+    Warn,
+    /// Reject the request when it has unknown fields
+    Strict,
+}
+
+/// The format of the body of put/post calls
+///
+/// The apiserver accepts yaml just as json, so manifests a user wrote can be
+/// sent as they are, comments and all, instead of being converted first.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ContentType {
+    #[default]
+    Json,
+    Yaml,
+}
+
+impl std::fmt::Display for ContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Json => "application/json",
+            Self::Yaml => "application/yaml",
+        })
+    }
+}
+
+impl ContentType {
+    /// Serialize a body in this format
+    pub fn serialize<T: serde::Serialize>(&self, value: &T) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Json => serde_json::to_vec(value).context(ErrorKind::SerdeParse)?,
+            Self::Yaml => serde_yaml::to_vec(value).context(ErrorKind::SerdeParse)?,
+        })
+    }
+
+    /// Parse a body in this format
+    pub fn deserialize<T: serde::de::DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        Ok(match self {
+            Self::Json => serde_json::from_slice(data).context(ErrorKind::SerdeParse)?,
+            Self::Yaml => serde_yaml::from_slice(data).context(ErrorKind::SerdeParse)?,
+        })
+    }
 }
 
 /// Common query parameters for put/post calls
 #[derive(Default, Clone)]
 pub struct PostParams {
     pub dry_run: bool,
+    /// How to treat unknown fields, e.g. a typo'd `replica:`. Defaults to the apiserver's choice.
+    pub field_validation: Option<FieldValidation>,
+    /// The format the body is in. Responses are json regardless.
+    pub content_type: ContentType,
 }
 
 /// Common query parameters for patch calls
@@ -350,11 +653,13 @@ pub struct PatchParams {
     pub force: bool,
     /// fieldManager is a name of the actor that is making changes. Required for `PatchStrategy::Apply`
     /// optional for everything else
-    pub field_manager: Option<String>
+    pub field_manager: Option<String>,
+    /// How to treat unknown fields in the patch. Defaults to the apiserver's choice.
+    pub field_validation: Option<FieldValidation>,
 }
 
 impl PatchParams {
-    fn validate(&self) -> Result<()> {
+    pub(crate) fn validate(&self) -> Result<()> {
         if let Some(field_manager) = &self.field_manager {
             // Implement the easy part of validation, in future this may be extended to provide validation as in go code
             // For now it's fine, because k8s API server will return an error
@@ -370,18 +675,6 @@ impl PatchParams {
             Ok(())
         }
     }
-
-    fn populate_qp(&self, qp: &mut url::form_urlencoded::Serializer<String>) {
-        if self.dry_run {
-            qp.append_pair("dryRun", "true");
-        }
-        if self.force {
-            qp.append_pair("force", "true");
-        }
-        if let Some(ref field_manager) = self.field_manager {
-            qp.append_pair("fieldManager", &field_manager);
-        }
-    }
 }
 
 
@@ -474,259 +767,154 @@ pub struct LogParams {
 impl RawApi {
     /// List a collection of a resource
     pub fn list(&self, lp: &ListParams) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
+        self.request().list_params(lp)?.get()
+    }
 
-        if let Some(fields) = &lp.field_selector {
-            qp.append_pair("fieldSelector", &fields);
-        }
-        if lp.include_uninitialized {
-            qp.append_pair("includeUninitialized", "true");
-        }
-        if let Some(labels) = &lp.label_selector {
-            qp.append_pair("labelSelector", &labels);
-        }
+    /// List a resource as a `Table` of the columns kubectl would print
+    ///
+    /// Falls back to the beta Table, then to a plain list, on apiservers without them;
+    /// `Table::from_response` reads any of these.
+    pub fn list_table(&self, lp: &ListParams) -> Result<http::Request<Vec<u8>>> {
+        self.request().list_params(lp)?.header("Accept", TABLE_ACCEPT).get()
+    }
 
-        let urlstr = qp.finish();
-        let mut req = http::Request::get(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+    /// Get a single instance as a `Table`
+    pub fn get_table(&self, name: &str) -> Result<http::Request<Vec<u8>>> {
+        self.request().name(name).header("Accept", TABLE_ACCEPT).get()
     }
 
     /// Create a minimial list request to seed an initial resourceVersion
     pub(crate) fn list_zero_resource_entries(&self, lp: &ListParams) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        qp.append_pair("limit", "1"); // can't have 0..
-        if lp.include_uninitialized {
-            qp.append_pair("includeUninitialized", "true");
-        }
         // rest of lp doesn't matter here - we just need a resourceVersion
-        let urlstr = qp.finish();
-        let mut req = http::Request::get(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+        self.request()
+            .param("limit", "1") // can't have 0..
+            .flag("includeUninitialized", lp.include_uninitialized)
+            .get()
     }
 
     /// Watch a resource at a given version
     pub fn watch(&self, lp: &ListParams, ver: &str) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
+        self.request().watch_params(lp, ver).get()
+    }
 
-        qp.append_pair("watch", "true");
-        qp.append_pair("resourceVersion", ver);
+    /// List a namespaced resource in all namespaces, regardless of `within`
+    pub fn list_all_namespaces(&self, lp: &ListParams) -> Result<http::Request<Vec<u8>>> {
+        self.clone().all_namespaces().list(lp)
+    }
 
-        qp.append_pair("timeoutSeconds", &lp.timeout.unwrap_or(10).to_string());
-        if let Some(fields) = &lp.field_selector {
-            qp.append_pair("fieldSelector", &fields);
-        }
-        if lp.include_uninitialized {
-            qp.append_pair("includeUninitialized", "true");
-        }
-        if let Some(labels) = &lp.label_selector {
-            qp.append_pair("labelSelector", &labels);
-        }
+    /// Watch a namespaced resource in all namespaces, regardless of `within`
+    ///
+    /// The namespace of each object is in its metadata, see `WatchEvent::namespace`.
+    pub fn watch_all_namespaces(&self, lp: &ListParams, ver: &str) -> Result<http::Request<Vec<u8>>> {
+        self.clone().all_namespaces().watch(lp, ver)
+    }
 
-        let urlstr = qp.finish();
-        let mut req = http::Request::get(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+    /// Watch a single named instance at a given version
+    ///
+    /// Adds a `metadata.name=<name>` field selector to any selector already in `lp`.
+    pub fn watch_object(&self, name: &str, lp: &ListParams, ver: &str) -> Result<http::Request<Vec<u8>>> {
+        let name_selector = format!("metadata.name={}", name);
+        let lp = ListParams {
+            field_selector: Some(match &lp.field_selector {
+                Some(fields) => format!("{},{}", fields, name_selector),
+                None => name_selector,
+            }),
+            ..lp.clone()
+        };
+        self.watch(&lp, ver)
     }
 
     /// Get a single instance
     pub fn get(&self, name: &str) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "/" + name;
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        let urlstr = qp.finish();
-        let mut req = http::Request::get(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).get()
     }
 
     /// Create an instance of a resource
     pub fn create(&self, pp: &PostParams, data: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        if pp.dry_run {
-            qp.append_pair("dryRun", "All");
-        }
-        let urlstr = qp.finish();
-        let mut req = http::Request::post(urlstr);
-        Ok(req.body(data).context(ErrorKind::RequestBuild)?)
+        self.request().post_params(pp).post(data)
     }
 
     /// Delete an instance of a resource
     pub fn delete(&self, name: &str, dp: &DeleteParams) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "/"+ name + "?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        if dp.dry_run {
-            qp.append_pair("dryRun", "All");
-        }
-        if let Some(grace) = dp.grace_period_seconds {
-            qp.append_pair("gracePeriodSeconds", &grace.to_string());
-        }
-        if let Some(ref prop) = dp.propagation_policy {
-            qp.append_pair("propagationPolicy", &format!("{:?}", prop));
-        }
-        let urlstr = qp.finish();
-        let mut req = http::Request::delete(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).delete_params(dp).delete()
     }
 
     /// Delete a collection of a resource
     pub fn delete_collection(&self, lp: &ListParams) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        if let Some(fields) = &lp.field_selector {
-            qp.append_pair("fieldSelector", &fields);
-        }
-        if lp.include_uninitialized {
-            qp.append_pair("includeUninitialized", "true");
-        }
-        if let Some(labels) = &lp.label_selector {
-            qp.append_pair("labelSelector", &labels);
-        }
-        let urlstr = qp.finish();
-        let mut req = http::Request::delete(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+        self.request().selectors(lp).delete()
     }
 
     /// Patch an instance of a resource
     ///
     /// Requires a serialized merge-patch+json at the moment.
     pub fn patch(&self, name: &str, pp: &PatchParams, patch: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
-        pp.validate()?;
-        let base_url = self.make_url() + "/" + name + "?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        pp.populate_qp(&mut qp);
-        let urlstr = qp.finish();
-
-        Ok(http::Request::patch(urlstr)
-            .header("Accept", "application/json")
-            .header("Content-Type", pp.patch_strategy.to_string())
-            .body(patch).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).patch_params(pp)?.patch(patch)
     }
 
     /// Replace an instance of a resource
     ///
     /// Requires metadata.resourceVersion set in data
     pub fn replace(&self, name: &str, pp: &PostParams, data: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "/" + name + "?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        if pp.dry_run {
-            qp.append_pair("dryRun", "All");
-        }
-        let urlstr = qp.finish();
-        let mut req = http::Request::put(urlstr);
-        Ok(req.body(data).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).post_params(pp).put(data)
     }
 
     /// Get an instance of the scale subresource
     pub fn get_scale(&self, name: &str) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "/" + name + "/scale";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        let urlstr = qp.finish();
-        let mut req = http::Request::get(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).subresource("scale").get()
     }
 
     /// Patch an instance of the scale subresource
     pub fn patch_scale(&self, name: &str, pp: &PatchParams, patch: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
-        pp.validate()?;
-        let base_url = self.make_url() + "/" + name + "/scale?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        pp.populate_qp(&mut qp);
-        let urlstr = qp.finish();
-        Ok(http::Request::patch(urlstr)
-            .header("Accept", "application/json")
-            .header("Content-Type", pp.patch_strategy.to_string())
-            .body(patch).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).subresource("scale").patch_params(pp)?.patch(patch)
     }
 
     /// Replace an instance of the scale subresource
     pub fn replace_scale(&self, name: &str, pp: &PostParams, data: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "/" + name + "/scale?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        if pp.dry_run {
-            qp.append_pair("dryRun", "All");
-        }
-        let urlstr = qp.finish();
-        let mut req = http::Request::put(urlstr);
-        Ok(req.body(data).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).subresource("scale").post_params(pp).put(data)
     }
 
     /// Get an instance of the status subresource
     pub fn get_status(&self, name: &str) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "/" + name + "/status";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        let urlstr = qp.finish();
-        let mut req = http::Request::get(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).subresource("status").get()
     }
 
     /// Patch an instance of the status subresource
     pub fn patch_status(&self, name: &str, pp: &PatchParams, patch: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
-        pp.validate()?;
-        let base_url = self.make_url() + "/" + name + "/status?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        pp.populate_qp(&mut qp);
-        let urlstr = qp.finish();
-        Ok(http::Request::patch(urlstr)
-            .header("Accept", "application/json")
-            .header("Content-Type", pp.patch_strategy.to_string())
-            .body(patch).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).subresource("status").patch_params(pp)?.patch(patch)
     }
 
     /// Replace an instance of the status subresource
     pub fn replace_status(&self, name: &str, pp: &PostParams, data: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "/" + name + "/status?";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-        if pp.dry_run {
-            qp.append_pair("dryRun", "All");
-        }
-        let urlstr = qp.finish();
-        let mut req = http::Request::put(urlstr);
-        Ok(req.body(data).context(ErrorKind::RequestBuild)?)
+        self.request().name(name).subresource("status").post_params(pp).put(data)
     }
 }
 
 impl RawApi {
-    /// Get a pod logs
-    pub fn log(&self, name: &str, lp: &LogParams) -> Result<http::Request<Vec<u8>>> {
-        let base_url = self.make_url() + "/" + name + "/" + "log";
-        let mut qp = url::form_urlencoded::Serializer::new(base_url);
-
-        if let Some(container) = &lp.container {
-            qp.append_pair("container", &container);
-        }
-
-        if lp.follow {
-            qp.append_pair("follow", "true");
-        }
-
-        if let Some(lb) = &lp.limit_bytes {
-            qp.append_pair("limitBytes", &lb.to_string());
-        }
-
-        if lp.pretty {
-            qp.append_pair("pretty", "true");
-        }
-
-        if lp.previous {
-            qp.append_pair("previous", "true");
-        }
+    /// Get the ephemeral containers subresource of a pod
+    pub fn get_ephemeral_containers(&self, name: &str) -> Result<http::Request<Vec<u8>>> {
+        self.request().name(name).subresource("ephemeralcontainers").get()
+    }
 
-        if let Some(ss) = &lp.since_seconds {
-            qp.append_pair("sinceSeconds", &ss.to_string());
-        }
+    /// Patch the ephemeral containers subresource of a pod
+    pub fn patch_ephemeral_containers(&self, name: &str, pp: &PatchParams, patch: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
+        self.request().name(name).subresource("ephemeralcontainers").patch_params(pp)?.patch(patch)
+    }
 
-        if let Some(tl) = &lp.tail_lines {
-            qp.append_pair("tailLines", &tl.to_string());
-        }
+    /// Replace the ephemeral containers subresource of a pod
+    pub fn replace_ephemeral_containers(&self, name: &str, pp: &PostParams, data: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
+        self.request().name(name).subresource("ephemeralcontainers").post_params(pp).put(data)
+    }
 
-        if lp.timestamps {
-            qp.append_pair("timestamps", "true");
-        }
+    /// Bind a pod to a node through the binding subresource
+    ///
+    /// The body is a serialized `v1.Binding`; usually only needed by schedulers.
+    pub fn create_binding(&self, name: &str, pp: &PostParams, data: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
+        self.request().name(name).subresource("binding").post_params(pp).post(data)
+    }
 
-        let urlstr = qp.finish();
-        let mut req = http::Request::get(urlstr);
-        Ok(req.body(vec![]).context(ErrorKind::RequestBuild)?)
+    /// Get a pod logs
+    pub fn log(&self, name: &str, lp: &LogParams) -> Result<http::Request<Vec<u8>>> {
+        self.request().name(name).subresource("log").log_params(lp).get()
     }
 }
 
@@ -742,21 +930,65 @@ fn watch_path() {
     let r = RawApi::v1Pod().within("ns");
     let gp = ListParams::default();
     let req = r.watch(&gp, "0").unwrap();
-    assert_eq!(req.uri(), "/api/v1/namespaces/ns/pods?&watch=true&resourceVersion=0&timeoutSeconds=10");
+    assert_eq!(req.uri(), "/api/v1/namespaces/ns/pods?watch=true&resourceVersion=0&timeoutSeconds=10");
+}
+#[test]
+fn list_resource_version_params() {
+    let r = RawApi::v1Pod().within("ns");
+    let req = r.list(&ListParams::cached()).unwrap();
+    assert_eq!(req.uri(), "/api/v1/namespaces/ns/pods?resourceVersion=0&resourceVersionMatch=NotOlderThan");
+    let req = r.list(&ListParams::at_version("123")).unwrap();
+    assert_eq!(req.uri(), "/api/v1/namespaces/ns/pods?resourceVersion=123&resourceVersionMatch=Exact");
+
+    let lp = ListParams { resource_version_match: Some(ResourceVersionMatch::Exact), ..Default::default() };
+    assert!(r.list(&lp).is_err(), "match without a version is rejected");
+    assert!(r.list(&ListParams::at_version("0")).is_err(), "exact match at 0 is rejected");
+}
+#[test]
+fn watch_object_path() {
+    let r = RawApi::v1Pod().within("ns");
+    let lp = ListParams { field_selector: Some("status.phase=Running".into()), ..Default::default() };
+    let req = r.watch_object("blog", &lp, "0").unwrap();
+    assert_eq!(req.uri(), "/api/v1/namespaces/ns/pods?watch=true&resourceVersion=0&timeoutSeconds=10&fieldSelector=status.phase%3DRunning%2Cmetadata.name%3Dblog");
 }
 #[test]
 fn replace_path(){
     let r = RawApi::v1DaemonSet();
     let pp = PostParams { dry_run: true, ..Default::default() };
     let req = r.replace("myds", &pp, vec![]).unwrap();
-    assert_eq!(req.uri(), "/apis/apps/v1/daemonsets/myds?&dryRun=All");
+    assert_eq!(req.uri(), "/apis/apps/v1/daemonsets/myds?dryRun=All");
+}
+#[test]
+fn field_validation_params() {
+    let r = RawApi::v1Deployment().within("ns");
+    let pp = PostParams { field_validation: Some(FieldValidation::Strict), ..Default::default() };
+    let req = r.create(&pp, vec![]).unwrap();
+    assert_eq!(req.uri(), "/apis/apps/v1/namespaces/ns/deployments?fieldValidation=Strict");
+    let pp = PatchParams { field_validation: Some(FieldValidation::Warn), ..Default::default() };
+    let req = r.patch("blog", &pp, vec![]).unwrap();
+    assert_eq!(req.uri(), "/apis/apps/v1/namespaces/ns/deployments/blog?fieldValidation=Warn");
+}
+#[test]
+fn yaml_bodies_are_sent_as_yaml() {
+    let r = RawApi::v1ConfigMap().within("ns");
+    let req = r.create(&PostParams::default(), vec![]).unwrap();
+    assert_eq!(req.headers()["Content-Type"], "application/json");
+    let pp = PostParams { content_type: ContentType::Yaml, ..Default::default() };
+    let manifest = b"# keep me\nmetadata:\n  name: blog\n".to_vec();
+    let req = r.replace("blog", &pp, manifest.clone()).unwrap();
+    assert_eq!(req.headers()["Content-Type"], "application/yaml");
+    assert_eq!(*req.body(), manifest);
+    let parsed: serde_json::Value = pp.content_type.deserialize(&manifest).unwrap();
+    assert_eq!(parsed["metadata"]["name"], "blog");
+    let data = pp.content_type.serialize(&parsed).unwrap();
+    assert_eq!(ContentType::Yaml.deserialize::<serde_json::Value>(&data).unwrap(), parsed);
 }
 #[test]
 fn create_path() {
     let r = RawApi::v1ReplicaSet().within("ns");
     let pp = PostParams::default();
     let req = r.create(&pp, vec![]).unwrap();
-    assert_eq!(req.uri(), "/apis/apps/v1/namespaces/ns/replicasets?");
+    assert_eq!(req.uri(), "/apis/apps/v1/namespaces/ns/replicasets");
 }
 #[test]
 fn delete_path() {
@@ -776,6 +1008,21 @@ fn delete_collection_path() {
     assert_eq!(req.method(), "DELETE")
 }
 
+#[test]
+fn all_namespaces_path() {
+    use crate::api::{Object, ObjectMeta, TypeMeta, WatchEvent};
+    let r = RawApi::v1Pod().within("ns");
+    let lp = ListParams::default();
+    assert_eq!(r.list_all_namespaces(&lp).unwrap().uri(), "/api/v1/pods");
+    assert_eq!(r.watch_all_namespaces(&lp, "0").unwrap().uri(), "/api/v1/pods?watch=true&resourceVersion=0&timeoutSeconds=10");
+    assert_eq!(r.list(&lp).unwrap().uri(), "/api/v1/namespaces/ns/pods", "the namespace is kept");
+    assert_eq!(r.all_namespaces().list(&lp).unwrap().uri(), "/api/v1/pods");
+
+    let metadata = ObjectMeta { name: "blog".into(), namespace: Some("ns".into()), ..Default::default() };
+    let pod = Object { types: TypeMeta::default(), metadata, spec: (), status: None::<()> };
+    assert_eq!(WatchEvent::Added(pod).namespace(), Some("ns"));
+}
+
 #[test]
 fn namespace_path() { // weird object compared to other v1
     let r = RawApi::v1Namespace();
@@ -803,16 +1050,32 @@ fn patch_status_path(){
     let r = RawApi::v1Node();
     let pp = PatchParams::default();
     let req = r.patch_status("mynode", &pp, vec![]).unwrap();
-    assert_eq!(req.uri(), "/api/v1/nodes/mynode/status?");
+    assert_eq!(req.uri(), "/api/v1/nodes/mynode/status");
     assert_eq!(req.headers().get("Content-Type").unwrap().to_str().unwrap(), format!("{}", PatchStrategy::Merge));
     assert_eq!(req.method(), "PATCH");
 }
 #[test]
+fn ephemeral_containers_path(){
+    let r = RawApi::v1Pod().within("ns");
+    let req = r.get_ephemeral_containers("blog").unwrap();
+    assert_eq!(req.uri(), "/api/v1/namespaces/ns/pods/blog/ephemeralcontainers");
+    let req = r.replace_ephemeral_containers("blog", &PostParams::default(), vec![]).unwrap();
+    assert_eq!(req.uri(), "/api/v1/namespaces/ns/pods/blog/ephemeralcontainers");
+    assert_eq!(req.method(), "PUT");
+}
+#[test]
+fn create_binding_path(){
+    let r = RawApi::v1Pod().within("ns");
+    let req = r.create_binding("blog", &PostParams::default(), vec![]).unwrap();
+    assert_eq!(req.uri(), "/api/v1/namespaces/ns/pods/blog/binding");
+    assert_eq!(req.method(), "POST");
+}
+#[test]
 fn replace_status_path(){
     let r = RawApi::v1Node();
     let pp = PostParams::default();
     let req = r.replace_status("mynode", &pp, vec![]).unwrap();
-    assert_eq!(req.uri(), "/api/v1/nodes/mynode/status?");
+    assert_eq!(req.uri(), "/api/v1/nodes/mynode/status");
     assert_eq!(req.method(), "PUT");
 }
 
@@ -824,12 +1087,12 @@ fn create_custom_resource() {
     let pp = PostParams::default();
     let req = r.create(&pp, vec![]).unwrap();
     assert_eq!(req.uri(),
-        "/apis/clux.dev/v1/namespaces/myns/foos?"
+        "/apis/clux.dev/v1/namespaces/myns/foos"
     );
     let patch_params = PatchParams::default();
     let req = r.patch("baz", &patch_params, vec![]).unwrap();
     assert_eq!(req.uri(),
-        "/apis/clux.dev/v1/namespaces/myns/foos/baz?"
+        "/apis/clux.dev/v1/namespaces/myns/foos/baz"
     );
     assert_eq!(req.method(), "PATCH");
 }
@@ -840,7 +1103,7 @@ fn replace_status() {
     let pp = PostParams::default();
     let req = r.replace_status("mycrd.domain.io", &pp, vec![]).unwrap();
     assert_eq!(req.uri(),
-        "/apis/apiextensions.k8s.io/v1beta1/customresourcedefinitions/mycrd.domain.io/status?"
+        "/apis/apiextensions.k8s.io/v1beta1/customresourcedefinitions/mycrd.domain.io/status"
     );
 }
 #[test]
@@ -855,7 +1118,7 @@ fn patch_scale_path(){
     let r = RawApi::v1Node();
     let pp = PatchParams::default();
     let req = r.patch_scale("mynode", &pp, vec![]).unwrap();
-    assert_eq!(req.uri(), "/api/v1/nodes/mynode/scale?");
+    assert_eq!(req.uri(), "/api/v1/nodes/mynode/scale");
     assert_eq!(req.method(), "PATCH");
 }
 #[test]
@@ -863,7 +1126,7 @@ fn replace_scale_path(){
     let r = RawApi::v1Node();
     let pp = PostParams::default();
     let req = r.replace_scale("mynode", &pp, vec![]).unwrap();
-    assert_eq!(req.uri(), "/api/v1/nodes/mynode/scale?");
+    assert_eq!(req.uri(), "/api/v1/nodes/mynode/scale");
     assert_eq!(req.method(), "PUT");
 }
 
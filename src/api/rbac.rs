@@ -0,0 +1,366 @@
+//! RBAC objects, rule builders and idempotent bootstrapping
+//!
+//! Lets a controller declare the Roles and bindings it needs in code and
+//! ensure them at startup rather than shipping separate manifests.
+#![allow(non_snake_case, non_camel_case_types)]
+
+use std::collections::BTreeMap;
+use core::marker::PhantomData;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::client::APIClient;
+use crate::api::{
+    RawApi, Api, KubeObject, Resource, NamespaceResourceScope, ClusterResourceScope, ObjectMeta, TypeMeta, PostParams, DeleteParams};
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+pub use k8s_openapi::api::rbac::v1::{PolicyRule, RoleRef, Subject, AggregationRule};
+
+/// Verbs needed to read and follow a resource with a Reflector or Informer
+pub const READ_VERBS: &[&str] = &["get", "list", "watch"];
+
+/// Builder for a `PolicyRule`
+///
+/// ```
+/// use kube::api::{Rule, PolicyRule};
+/// let rule: PolicyRule = Rule::new("apps", &["deployments"]).verbs(&["get", "patch"]).into();
+/// assert_eq!(rule.verbs, vec!["get", "patch"]);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Rule {
+    rule: PolicyRule,
+}
+
+impl Rule {
+    /// A rule for resources in an api group ("" for the core group)
+    pub fn new(group: &str, resources: &[&str]) -> Self {
+        Rule {
+            rule: PolicyRule {
+                api_groups: Some(vec![group.into()]),
+                resources: Some(resources.iter().map(|r| r.to_string()).collect()),
+                ..Default::default()
+            }
+        }
+    }
+
+    /// A rule for the resource a `RawApi` points at
+    pub fn for_api(api: &RawApi) -> Self {
+        Rule::new(&api.group, &[&api.resource])
+    }
+
+    /// A rule for non-resource urls like `/healthz`
+    pub fn non_resource(urls: &[&str]) -> Self {
+        Rule {
+            rule: PolicyRule {
+                non_resource_urls: Some(urls.iter().map(|u| u.to_string()).collect()),
+                ..Default::default()
+            }
+        }
+    }
+
+    /// Add verbs to the rule
+    pub fn verbs(mut self, verbs: &[&str]) -> Self {
+        for v in verbs {
+            if !self.rule.verbs.iter().any(|x| x == v) {
+                self.rule.verbs.push(v.to_string());
+            }
+        }
+        self
+    }
+
+    /// Add the verbs needed to watch the resource
+    pub fn read_only(self) -> Self {
+        self.verbs(READ_VERBS)
+    }
+
+    /// Restrict the rule to named objects
+    pub fn resource_names(mut self, names: &[&str]) -> Self {
+        self.rule.resource_names = Some(names.iter().map(|n| n.to_string()).collect());
+        self
+    }
+}
+
+impl From<Rule> for PolicyRule {
+    fn from(r: Rule) -> PolicyRule {
+        r.rule
+    }
+}
+
+/// The minimal rules needed to watch a set of resources
+///
+/// Resources in the same api group are collapsed into one rule with `get`, `list` and `watch`.
+/// Pass the same `RawApi`s that the controller's Informers and Reflectors are built from.
+pub fn rules_for_watches(apis: &[&RawApi]) -> Vec<PolicyRule> {
+    let mut groups: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for api in apis {
+        let resources = groups.entry(&api.group).or_default();
+        if !resources.contains(&api.resource.as_str()) {
+            resources.push(&api.resource);
+        }
+    }
+    groups.into_iter()
+        .map(|(g, rs)| Rule::new(g, &rs).read_only().into())
+        .collect()
+}
+
+/// A subject for a service account
+pub fn service_account_subject(name: &str, namespace: &str) -> Subject {
+    Subject {
+        kind: "ServiceAccount".into(),
+        name: name.into(),
+        namespace: Some(namespace.into()),
+        api_group: None,
+    }
+}
+
+/// Role object
+///
+/// https://kubernetes.io/docs/reference/generated/kubernetes-api/v1.15/#role-v1-rbac-authorization-k8s-io
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct v1Role {
+    #[serde(flatten)]
+    pub types: TypeMeta,
+    pub metadata: ObjectMeta,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+}
+
+/// ClusterRole object
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct v1ClusterRole {
+    #[serde(flatten)]
+    pub types: TypeMeta,
+    pub metadata: ObjectMeta,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aggregationRule: Option<AggregationRule>,
+}
+
+/// RoleBinding object
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct v1RoleBinding {
+    #[serde(flatten)]
+    pub types: TypeMeta,
+    pub metadata: ObjectMeta,
+    pub roleRef: RoleRef,
+    #[serde(default)]
+    pub subjects: Vec<Subject>,
+}
+
+/// ClusterRoleBinding object
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct v1ClusterRoleBinding {
+    #[serde(flatten)]
+    pub types: TypeMeta,
+    pub metadata: ObjectMeta,
+    pub roleRef: RoleRef,
+    #[serde(default)]
+    pub subjects: Vec<Subject>,
+}
+
+fn rbac_types(kind: &str) -> TypeMeta {
+    TypeMeta {
+        apiVersion: Some("rbac.authorization.k8s.io/v1".into()),
+        kind: Some(kind.into()),
+    }
+}
+
+fn role_ref(kind: &str, name: &str) -> RoleRef {
+    RoleRef {
+        api_group: "rbac.authorization.k8s.io".into(),
+        kind: kind.into(),
+        name: name.into(),
+    }
+}
+
+impl v1Role {
+    pub fn new(name: &str, rules: Vec<PolicyRule>) -> Self {
+        v1Role {
+            types: rbac_types("Role"),
+            metadata: ObjectMeta { name: name.into(), ..Default::default() },
+            rules,
+        }
+    }
+}
+
+impl v1ClusterRole {
+    pub fn new(name: &str, rules: Vec<PolicyRule>) -> Self {
+        v1ClusterRole {
+            types: rbac_types("ClusterRole"),
+            metadata: ObjectMeta { name: name.into(), ..Default::default() },
+            rules,
+            aggregationRule: None,
+        }
+    }
+}
+
+impl v1RoleBinding {
+    /// Bind a Role in the same namespace to subjects
+    pub fn for_role(name: &str, role: &str, subjects: Vec<Subject>) -> Self {
+        v1RoleBinding {
+            types: rbac_types("RoleBinding"),
+            metadata: ObjectMeta { name: name.into(), ..Default::default() },
+            roleRef: role_ref("Role", role),
+            subjects,
+        }
+    }
+
+    /// Bind a ClusterRole to subjects within the binding's namespace
+    pub fn for_cluster_role(name: &str, role: &str, subjects: Vec<Subject>) -> Self {
+        v1RoleBinding {
+            roleRef: role_ref("ClusterRole", role),
+            ..v1RoleBinding::for_role(name, role, subjects)
+        }
+    }
+}
+
+impl v1ClusterRoleBinding {
+    pub fn new(name: &str, role: &str, subjects: Vec<Subject>) -> Self {
+        v1ClusterRoleBinding {
+            types: rbac_types("ClusterRoleBinding"),
+            metadata: ObjectMeta { name: name.into(), ..Default::default() },
+            roleRef: role_ref("ClusterRole", role),
+            subjects,
+        }
+    }
+}
+
+/// Create `desired`, or bring an existing object in line with it
+///
+/// `same` decides whether the existing object already matches.
+/// When `immutable_changed` is true the object is deleted and recreated,
+/// as the apiserver rejects updates to e.g. a binding's roleRef.
+fn ensure<K, S, I>(api: &Api<K>, mut desired: K, same: S, immutable_changed: I) -> Result<K>
+where
+    K: Clone + DeserializeOwned + Serialize + KubeObject + WithMetadata,
+    S: Fn(&K, &K) -> bool,
+    I: Fn(&K, &K) -> bool,
+{
+    let name = desired.meta().name.clone();
+    let pp = PostParams::default();
+    let existing = match api.get(&name) {
+        Ok(o) => o,
+        Err(ref e) if e.status_code() == Some(404) => {
+            let data = serde_json::to_vec(&desired).context(ErrorKind::SerdeParse)?;
+            return api.create(&pp, data);
+        },
+        Err(e) => return Err(e),
+    };
+    if same(&existing, &desired) {
+        return Ok(existing);
+    }
+    if immutable_changed(&existing, &desired) {
+        debug!("Recreating {} {}", api.api.resource, name);
+        match api.delete(&name, &DeleteParams::default()) {
+            Err(ref e) if e.status_code() == Some(404) => {},
+            Err(e) => return Err(e),
+            Ok(_) => {},
+        }
+        let data = serde_json::to_vec(&desired).context(ErrorKind::SerdeParse)?;
+        return api.create(&pp, data);
+    }
+    debug!("Updating {} {}", api.api.resource, name);
+    desired.metadata_mut().resourceVersion = existing.meta().resourceVersion.clone();
+    let data = serde_json::to_vec(&desired).context(ErrorKind::SerdeParse)?;
+    api.replace(&name, &pp, data)
+}
+
+/// Mutable metadata access for the rbac objects
+trait WithMetadata {
+    fn metadata_mut(&mut self) -> &mut ObjectMeta;
+}
+
+macro_rules! rbac_impl {
+    ($t:ident, $kind:expr, $plural:expr, $scope:ty) => {
+        impl KubeObject for $t {
+            fn meta(&self) -> &ObjectMeta { &self.metadata }
+            fn types(&self) -> Option<&TypeMeta> { Some(&self.types) }
+        }
+
+        impl WithMetadata for $t {
+            fn metadata_mut(&mut self) -> &mut ObjectMeta { &mut self.metadata }
+        }
+
+        impl Resource for $t {
+            const GROUP: &'static str = "rbac.authorization.k8s.io";
+            const VERSION: &'static str = "v1";
+            const KIND: &'static str = $kind;
+            const PLURAL: &'static str = $plural;
+            type Scope = $scope;
+        }
+
+        impl Api<$t> {
+            pub fn $t(client: APIClient) -> Self {
+                Api {
+                    api: RawApi::$t(),
+                    client,
+                    phantom: PhantomData,
+                }
+            }
+        }
+    };
+}
+rbac_impl!(v1Role, "Role", "roles", NamespaceResourceScope);
+rbac_impl!(v1ClusterRole, "ClusterRole", "clusterroles", ClusterResourceScope);
+rbac_impl!(v1RoleBinding, "RoleBinding", "rolebindings", NamespaceResourceScope);
+rbac_impl!(v1ClusterRoleBinding, "ClusterRoleBinding", "clusterrolebindings", ClusterResourceScope);
+
+impl Api<v1Role> {
+    /// Create the Role, or update its rules if they differ
+    pub fn ensure(&self, role: v1Role) -> Result<v1Role> {
+        ensure(self, role, |a, b| a.rules == b.rules, |_, _| false)
+    }
+}
+
+impl Api<v1ClusterRole> {
+    /// Create the ClusterRole, or update its rules if they differ
+    pub fn ensure(&self, role: v1ClusterRole) -> Result<v1ClusterRole> {
+        ensure(self, role,
+            |a, b| a.rules == b.rules && a.aggregationRule == b.aggregationRule,
+            |_, _| false)
+    }
+}
+
+impl Api<v1RoleBinding> {
+    /// Create the RoleBinding, or update it; recreated if the roleRef changed
+    pub fn ensure(&self, binding: v1RoleBinding) -> Result<v1RoleBinding> {
+        ensure(self, binding,
+            |a, b| a.roleRef == b.roleRef && a.subjects == b.subjects,
+            |a, b| a.roleRef != b.roleRef)
+    }
+}
+
+impl Api<v1ClusterRoleBinding> {
+    /// Create the ClusterRoleBinding, or update it; recreated if the roleRef changed
+    pub fn ensure(&self, binding: v1ClusterRoleBinding) -> Result<v1ClusterRoleBinding> {
+        ensure(self, binding,
+            |a, b| a.roleRef == b.roleRef && a.subjects == b.subjects,
+            |a, b| a.roleRef != b.roleRef)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_rules_are_grouped() {
+        let pods = RawApi::v1Pod();
+        let deploys = RawApi::v1Deployment();
+        let rs = RawApi::v1ReplicaSet();
+        let rules = rules_for_watches(&[&pods, &deploys, &rs, &pods]);
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].api_groups, Some(vec!["".to_string()]));
+        assert_eq!(rules[0].resources, Some(vec!["pods".to_string()]));
+        assert_eq!(rules[1].resources, Some(vec!["deployments".to_string(), "replicasets".to_string()]));
+        assert_eq!(rules[1].verbs, READ_VERBS);
+
+        let binding = v1RoleBinding::for_cluster_role("ctrl", "view", vec![service_account_subject("ctrl", "kube-system")]);
+        let json = serde_json::to_value(&binding).unwrap();
+        assert_eq!(json["roleRef"]["kind"], "ClusterRole");
+        assert_eq!(json["subjects"][0]["kind"], "ServiceAccount");
+        assert_eq!(json["kind"], "RoleBinding");
+    }
+}
@@ -1,31 +1,90 @@
 use crate::api::{RawApi, Api, ListParams, ObjectMeta};
+use crate::api::wait::poll_until;
 use crate::api::resource::{
-    ObjectList,
+    PartialList,
+    ItemError,
     WatchEvent,
     KubeObject,
 };
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 
 use crate::client::APIClient;
-use crate::{Result, ErrorKind};
+use crate::{Result, Error, ErrorKind};
+use failure::ResultExt;
 
 use std::{
-    collections::BTreeMap,
-    sync::{Arc, RwLock},
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, RwLock},
     time::{Duration},
 };
 
 /// Internal representation for Reflector
 type Cache<K> = BTreeMap<ObjectId, K>;
 
+/// Serializes a cache and its resourceVersion, where `K` is known to be `Serialize`
+type Snapshotter<K> = Arc<dyn Fn(&Cache<K>, &str, &str) -> Result<Vec<u8>> + Send + Sync>;
+
+/// The index `index_by_namespace` adds, keyed by namespace, or `""` for cluster scoped objects
+pub const NAMESPACE_INDEX: &str = "namespace";
+
+/// The index `index_by_owner` adds, keyed by the uids of owner references
+pub const OWNER_INDEX: &str = "owner";
+
+type IndexFn<K> = Arc<dyn Fn(&K) -> Vec<String> + Send + Sync>;
+
+/// Index name to index key to the objects with that key
+type Indices = BTreeMap<String, BTreeMap<String, BTreeSet<ObjectId>>>;
+
+type Handler<K> = Arc<dyn Fn(&K) + Send + Sync>;
+type UpdateHandler<K> = Arc<dyn Fn(&K, &K) + Send + Sync>;
+
+/// Callbacks registered with `on_add`, `on_update` and `on_delete`
+struct Handlers<K> {
+    add: Vec<Handler<K>>,
+    update: Vec<UpdateHandler<K>>,
+    delete: Vec<Handler<K>>,
+}
+
+impl<K> Clone for Handlers<K> {
+    fn clone(&self) -> Self {
+        Handlers { add: self.add.clone(), update: self.update.clone(), delete: self.delete.clone() }
+    }
+}
+
+impl<K> Handlers<K> {
+    fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.update.is_empty() && self.delete.is_empty()
+    }
+}
+
+/// A change to the cache, passed to handlers once the cache is unlocked
+enum Change<K> {
+    Added(K),
+    Updated(K, K),
+    Deleted(K),
+}
+
+/// The on disk form of a reflector cache
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize)]
+struct Snapshot<T> {
+    /// The list url the cache was filled from, to not restore a differently configured one
+    source: String,
+    resourceVersion: String,
+    items: Vec<T>,
+}
+
 /// A reflection of `Resource` state in kubernetes
 ///
 /// This watches and caches a `Resource<K>` by:
-/// - seeding the cache from a large initial list call
+/// - seeding the cache from a large initial list call, or several pages of one
 /// - keeping track of initial, and subsequent resourceVersions
 /// - recovering when resourceVersions get desynced
 ///
-/// It exposes it's internal state readably through a getter.
+/// It exposes it's internal state readably through a getter,
+/// and changes to it through `on_add`, `on_update` and `on_delete` handlers.
 #[derive(Clone)]
 pub struct Reflector<K> where
     K: Clone + DeserializeOwned,
@@ -35,6 +94,16 @@ pub struct Reflector<K> where
     client: APIClient,
     resource: RawApi,
     params: ListParams,
+    page_size: Option<u32>,
+    persist: Option<(PathBuf, Snapshotter<K>)>,
+    saved_version: Arc<RwLock<String>>,
+    handlers: Handlers<K>,
+    indexers: BTreeMap<String, IndexFn<K>>,
+    /// Only locked while holding `data`, to not deadlock
+    indices: Arc<RwLock<Indices>>,
+    synced: Arc<AtomicBool>,
+    skip_invalid: bool,
+    invalid: Arc<RwLock<Vec<ItemError>>>,
 }
 
 impl<K> Reflector<K> where
@@ -48,6 +117,15 @@ impl<K> Reflector<K> where
             params: ListParams::default(),
             data: Arc::new(RwLock::new(BTreeMap::new())),
             version: Arc::new(RwLock::new(0.to_string())),
+            page_size: None,
+            persist: None,
+            saved_version: Arc::new(RwLock::new(String::new())),
+            handlers: Handlers { add: vec![], update: vec![], delete: vec![] },
+            indexers: BTreeMap::new(),
+            indices: Arc::new(RwLock::new(BTreeMap::new())),
+            synced: Arc::new(AtomicBool::new(false)),
+            skip_invalid: false,
+            invalid: Arc::new(RwLock::new(vec![])),
         }
     }
 }
@@ -64,6 +142,15 @@ impl<K> Reflector<K> where
             params: ListParams::default(),
             data: Arc::new(RwLock::new(BTreeMap::new())),
             version: Arc::new(RwLock::new(0.to_string())),
+            page_size: None,
+            persist: None,
+            saved_version: Arc::new(RwLock::new(String::new())),
+            handlers: Handlers { add: vec![], update: vec![], delete: vec![] },
+            indexers: BTreeMap::new(),
+            indices: Arc::new(RwLock::new(BTreeMap::new())),
+            synced: Arc::new(AtomicBool::new(false)),
+            skip_invalid: false,
+            invalid: Arc::new(RwLock::new(vec![])),
         }
     }
 
@@ -104,32 +191,255 @@ impl<K> Reflector<K> where
         self
     }
 
+    /// Fill the cache with paginated list calls of at most `n` objects each
+    ///
+    /// Keeps the size of each response, and the memory needed to parse it, bounded on
+    /// large clusters. Pages are joined into one consistent snapshot by the apiserver.
+    /// Defaults to a single unpaginated list.
+    /// Streaming the initial state over a watch (`sendInitialEvents`) needs a much newer
+    /// apiserver than this crate targets, so is not used.
+    pub fn page_size(mut self, n: u32) -> Self {
+        self.page_size = Some(n);
+        self
+    }
+
+    /// Leave objects that fail to parse as `K` out of the cache, instead of failing the list
+    ///
+    /// Objects that a watch brings in a shape `K` does not fit are left out, and
+    /// removed from the cache, the same way; see `invalid_items` for what was left out.
+    pub fn skip_invalid(mut self) -> Self {
+        self.skip_invalid = true;
+        self
+    }
+
+    /// Call `f` with every object added to the cache, including those of the initial list
+    ///
+    /// Handlers run on the polling thread after the cache is updated, so they see the
+    /// change when reading the reflector, like client-go's `ResourceEventHandler`.
+    pub fn on_add<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K) + Send + Sync + 'static,
+    {
+        self.handlers.add.push(Arc::new(f));
+        self
+    }
+
+    /// Call `f` with the cached and the new state of every object that changes
+    ///
+    /// Relists after a desynced watch only report objects whose resourceVersion changed.
+    pub fn on_update<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &K) + Send + Sync + 'static,
+    {
+        self.handlers.update.push(Arc::new(f));
+        self
+    }
+
+    /// Call `f` with the final state of every object removed from the cache
+    ///
+    /// Deletions missed while a watch was desynced are found by the relist, and are
+    /// passed the last state that was cached.
+    pub fn on_delete<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K) + Send + Sync + 'static,
+    {
+        self.handlers.delete.push(Arc::new(f));
+        self
+    }
+
+    /// Index the cache by the keys `f` returns for each object, for lookups with `by_index`
+    ///
+    /// Indices are kept up to date as the cache changes, so reconcilers can find, say,
+    /// all objects referring to a secret without scanning the whole cache.
+    pub fn index<F>(mut self, name: &str, f: F) -> Self
+    where
+        F: Fn(&K) -> Vec<String> + Send + Sync + 'static,
+    {
+        self.indexers.insert(name.into(), Arc::new(f));
+        self
+    }
+
+    /// Index the cache as `NAMESPACE_INDEX`
+    pub fn index_by_namespace(self) -> Self {
+        self.index(NAMESPACE_INDEX, |o| vec![o.meta().namespace.clone().unwrap_or_default()])
+    }
+
+    /// Index the cache as `OWNER_INDEX`, to find the objects an owner uid owns
+    pub fn index_by_owner(self) -> Self {
+        self.index(OWNER_INDEX, |o| o.meta().ownerReferences.iter().map(|o| o.uid.clone()).collect())
+    }
+
     // finalizers:
 
     /// Initializes with a full list of data from a large initial LIST call
+    ///
+    /// With `persist`, a snapshot on disk is restored instead when there is one.
     pub fn init(self) -> Result<Self> {
         info!("Starting Reflector for {:?}", self.resource);
-        let (data, version) = self.get_full_resource_entries()?;
-        *self.data.write().unwrap() = data;
-        *self.version.write().unwrap() = version;
+        let (data, version) = match self.restore() {
+            Some(snapshot) => snapshot,
+            None => self.get_full_resource_entries()?,
+        };
+        self.replace(data, version);
         Ok(self)
     }
 
+    /// Swap in a freshly listed cache, telling handlers what changed
+    fn replace(&self, data: Cache<K>, version: String) {
+        let changes = {
+            let mut cache = self.data.write().unwrap();
+            let mut changes = vec![];
+            if !self.handlers.is_empty() {
+                for (id, o) in &data {
+                    match cache.get(id) {
+                        None => changes.push(Change::Added(o.clone())),
+                        Some(old) if old.meta().resourceVersion != o.meta().resourceVersion => {
+                            changes.push(Change::Updated(old.clone(), o.clone()))
+                        },
+                        Some(_) => {},
+                    }
+                }
+                changes.extend(cache.iter().filter(|(id, _)| !data.contains_key(id)).map(|(_, o)| Change::Deleted(o.clone())));
+            }
+            let mut indices = self.indices.write().unwrap();
+            indices.clear();
+            for (id, o) in &data {
+                self.add_to_indices(&mut indices, id, o);
+            }
+            *cache = data;
+            *self.version.write().unwrap() = version;
+            self.synced.store(true, Ordering::SeqCst);
+            changes
+        };
+        self.notify(changes);
+    }
+
+    fn add_to_indices(&self, indices: &mut Indices, id: &ObjectId, o: &K) {
+        for (name, f) in &self.indexers {
+            let index = indices.entry(name.clone()).or_default();
+            for key in f(o) {
+                index.entry(key).or_default().insert(id.clone());
+            }
+        }
+    }
+
+    fn remove_from_indices(&self, indices: &mut Indices, id: &ObjectId, o: &K) {
+        for (name, f) in &self.indexers {
+            if let Some(index) = indices.get_mut(name) {
+                for key in f(o) {
+                    if let Some(ids) = index.get_mut(&key) {
+                        ids.remove(id);
+                        if ids.is_empty() {
+                            index.remove(&key);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn notify(&self, changes: Vec<Change<K>>) {
+        for change in changes {
+            match change {
+                Change::Added(o) => self.handlers.add.iter().for_each(|f| f(&o)),
+                Change::Updated(old, new) => self.handlers.update.iter().for_each(|f| f(&old, &new)),
+                Change::Deleted(o) => self.handlers.delete.iter().for_each(|f| f(&o)),
+            }
+        }
+    }
+
     /// Run a single watch poll
     ///
     /// If this returns an error, it tries a full refresh.
     /// This is meant to be run continually in a thread. Spawn one.
     pub fn poll(&self) -> Result<()> {
         trace!("Watching {:?}", self.resource);
-        if let Err(_e) = self.single_watch() {
+        if let Err(e) = self.single_watch() {
             // If desynched due to mismatching resourceVersion, retry in a bit
-            std::thread::sleep(Duration::from_secs(10));
+            // (or as long as the apiserver asks us to when throttled)
+            std::thread::sleep(e.retry_after().unwrap_or(Duration::from_secs(10)));
             self.reset()?; // propagate error if this failed..
         }
+        if let Err(e) = self.save() {
+            warn!("Failed to snapshot {}: {}", self.resource.resource, e);
+        }
 
         Ok(())
     }
 
+    /// Write the cache to the `persist` path, if it changed since last written
+    ///
+    /// Done after every `poll`, but worth calling on shutdown too.
+    pub fn save(&self) -> Result<()> {
+        let (path, snapshotter) = match &self.persist {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let version = self.version.read().unwrap().clone();
+        if *self.saved_version.read().unwrap() == version {
+            return Ok(());
+        }
+        let data = snapshotter(&self.data.read().unwrap(), &self.source()?, &version)?;
+        // write next to it and rename, so a crash never leaves a partial snapshot
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, data)
+            .and_then(|_| std::fs::rename(&tmp, path))
+            .with_context(|_| ErrorKind::Io(path.display().to_string()))?;
+        debug!("Snapshotted {} at resourceVersion={}", self.resource.resource, version);
+        *self.saved_version.write().unwrap() = version;
+        Ok(())
+    }
+
+    fn source(&self) -> Result<String> {
+        Ok(self.resource.list(&self.params)?.uri().to_string())
+    }
+
+    /// The snapshot on disk, unless missing, unreadable, or from another configuration
+    fn restore(&self) -> Option<(Cache<K>, String)> {
+        let path = &self.persist.as_ref()?.0;
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                warn!("Ignoring unreadable snapshot {}: {}", path.display(), e);
+                return None;
+            },
+        };
+        let snapshot: Snapshot<K> = match serde_json::from_slice(&data) {
+            Ok(s) => s,
+            Err(e) => {
+                warn!("Ignoring corrupt snapshot {}: {}", path.display(), e);
+                return None;
+            },
+        };
+        if self.source().ok()? != snapshot.source {
+            warn!("Ignoring snapshot {} of {}", path.display(), snapshot.source);
+            return None;
+        }
+        info!("Restored {} {} from {} at resourceVersion={}",
+            snapshot.items.len(), self.resource.resource, path.display(), snapshot.resourceVersion);
+        *self.saved_version.write().unwrap() = snapshot.resourceVersion.clone();
+        let data = snapshot.items.into_iter().map(|o| (o.meta().into(), o)).collect();
+        Some((data, snapshot.resourceVersion))
+    }
+
+    /// Whether the cache was filled by a full list, or restored from a snapshot of one
+    ///
+    /// Shared between clones, so one can be polled while others check this.
+    pub fn has_synced(&self) -> bool {
+        self.synced.load(Ordering::SeqCst)
+    }
+
+    /// Block until `has_synced`, or fail after `timeout`
+    ///
+    /// For clones of a reflector that is initialized elsewhere, e.g. on another thread
+    /// or by a `Manager`, so decisions are not made on an empty cache at startup.
+    pub fn wait_for_cache_sync(&self, timeout: Duration) -> Result<()> {
+        poll_until(&format!("{} cache to sync", self.resource.resource), timeout, || {
+            Ok(if self.has_synced() { Some(()) } else { None })
+        })
+    }
+
     /// Read data for users of the reflector
     pub fn read(&self) -> Result<Vec<K>> {
         // unwrap for users because Poison errors are not great to deal with atm.
@@ -148,6 +458,11 @@ impl<K> Reflector<K> where
         )
     }
 
+    /// The objects left out with `skip_invalid` since the last list, and why
+    pub fn invalid_items(&self) -> Vec<ItemError> {
+        self.invalid.read().unwrap().clone()
+    }
+
     /// Read a single entry by name
     ///
     /// Will read in the configured namsepace, or globally on non-namespaced reflectors.
@@ -173,32 +488,70 @@ impl<K> Reflector<K> where
         Ok(self.data.read().unwrap().get(&id).map(Clone::clone))
     }
 
+    /// The cached objects with `key` in the index `name`
+    ///
+    /// Fails for indices that were not set up with `index` or the `index_by_*` builders.
+    pub fn by_index(&self, name: &str, key: &str) -> Result<Vec<K>> {
+        if !self.indexers.contains_key(name) {
+            return Err(Error::from(ErrorKind::RequestValidation(format!("no index named {}", name))));
+        }
+        let data = self.data.read().unwrap();
+        let indices = self.indices.read().unwrap();
+        let ids = indices.get(name).and_then(|index| index.get(key)).into_iter().flatten();
+        Ok(ids.filter_map(|id| data.get(id)).cloned().collect())
+    }
+
     /// Reset the state with a full LIST call
     ///
     /// Same as what is done in `State::new`.
     pub fn reset(&self) -> Result<()> {
         trace!("Refreshing {:?}", self.resource);
         let (data, version) = self.get_full_resource_entries()?;
-        *self.data.write().unwrap() = data;
-        *self.version.write().unwrap() = version;
+        self.replace(data, version);
         Ok(())
     }
 
 
     fn get_full_resource_entries(&self) -> Result<(Cache<K>, String)> {
-        let req = self.resource.list(&self.params)?;
-        // NB: Object isn't general enough here
-        let res = self.client.request::<ObjectList<K>>(req)?;
+        let mut lp = self.params.clone();
+        lp.limit = self.page_size;
         let mut data = BTreeMap::new();
-        let version = res.metadata.resourceVersion.unwrap_or_else(|| "".into());
-
-        trace!("Got {} {} at resourceVersion={:?}", res.items.len(), self.resource.resource, version);
-        for i in res.items {
-            // The non-generic parts we care about are spec + status
-            data.insert(i.meta().into(), i);
-        }
+        let mut invalid = vec![];
+        let version = loop {
+            let req = self.resource.list(&lp)?;
+            // NB: Object isn't general enough here
+            let res = match self.client.request::<PartialList<K>>(req) {
+                Err(ref e) if e.status_code() == Some(410) && lp.continue_token.is_some() => {
+                    // the snapshot we were paging through was compacted away, start over in one go
+                    warn!("Continue token for {} expired, relisting without pagination", self.resource.resource);
+                    lp.limit = None;
+                    lp.continue_token = None;
+                    data.clear();
+                    invalid.clear();
+                    continue;
+                },
+                res => res?,
+            };
+            trace!("Got {} {} at resourceVersion={:?}", res.items.len(), self.resource.resource, res.metadata.resourceVersion);
+            for e in res.errors {
+                warn!("Unparseable {} {}", self.resource.resource, e);
+                if !self.skip_invalid {
+                    return Err(failure::err_msg(e.to_string()).context(ErrorKind::SerdeParse).into());
+                }
+                invalid.push(e);
+            }
+            for i in res.items {
+                // The non-generic parts we care about are spec + status
+                data.insert(i.meta().into(), i);
+            }
+            match res.metadata.continue_.filter(|c| !c.is_empty()) {
+                Some(token) => lp.continue_token = Some(token),
+                None => break res.metadata.resourceVersion.unwrap_or_else(|| "".into()),
+            }
+        };
         let keys = data.keys().map(|key: &ObjectId| key.to_string()).collect::<Vec<_>>().join(", ");
         debug!("Initialized with: {}", keys);
+        *self.invalid.write().unwrap() = invalid;
         Ok((data, version))
     }
 
@@ -207,46 +560,109 @@ impl<K> Reflector<K> where
         let rg = &self.resource;
         let oldver = { self.version.read().unwrap().clone() };
         let req = rg.watch(&self.params, &oldver)?;
-        let res = self.client.request_events::<WatchEvent<K>>(req)?;
-
-        // Update in place:
-        let mut data = self.data.write().unwrap();
-        let mut ver = self.version.write().unwrap();
-
-        // Follow docs conventions and store the last resourceVersion
-        // https://kubernetes.io/docs/reference/using-api/api-concepts/#efficient-detection-of-changes
-        for ev in res {
-            match ev {
-                WatchEvent::Added(o) => {
-                    debug!("Adding {} to {}", o.meta().name, rg.resource);
-                    data.entry(o.meta().into())
-                        .or_insert_with(|| o.clone());
-                    if let Some(v) = &o.meta().resourceVersion {
-                        *ver = v.to_string();
-                    }
-                },
-                WatchEvent::Modified(o) => {
-                    debug!("Modifying {} in {}", o.meta().name, rg.resource);
-                    data.entry(o.meta().into())
-                        .and_modify(|e| *e = o.clone());
-                    if let Some(v) = &o.meta().resourceVersion {
-                        *ver = v.to_string();
+        // decoded one by one, so that objects K does not fit can be skipped like in lists
+        let frames = self.client.request_events::<serde_json::Value>(req)?;
+
+        // Update in place, telling handlers once the locks are released
+        let mut changes = vec![];
+        let res = {
+            let mut data = self.data.write().unwrap();
+            let mut indices = self.indices.write().unwrap();
+            let mut ver = self.version.write().unwrap();
+            let mut invalid = self.invalid.write().unwrap();
+
+            // Follow docs conventions and store the last resourceVersion
+            // https://kubernetes.io/docs/reference/using-api/api-concepts/#efficient-detection-of-changes
+            frames.into_iter().enumerate().try_for_each(|(index, frame)| {
+                let ev = match serde_json::from_value::<WatchEvent<K>>(frame.clone()) {
+                    Ok(ev) => ev,
+                    Err(e) => {
+                        let e = ItemError::new(index, &frame["object"], e.to_string());
+                        warn!("Unparseable {} event {}", rg.resource, e);
+                        if !self.skip_invalid {
+                            Err(failure::err_msg(e.to_string()).context(ErrorKind::SerdeParse))?
+                        }
+                        if let Some(v) = frame["object"]["metadata"]["resourceVersion"].as_str() {
+                            *ver = v.to_string();
+                        }
+                        // left out of the cache, as it would be by a list
+                        if let Some(name) = &e.name {
+                            let id = ObjectId { name: name.clone(), namespace: e.namespace.clone() };
+                            if let Some(old) = data.remove(&id) {
+                                self.remove_from_indices(&mut indices, &id, &old);
+                                changes.push(Change::Deleted(old));
+                            }
+                        }
+                        invalid.retain(|i| (&i.name, &i.namespace) != (&e.name, &e.namespace));
+                        invalid.push(e);
+                        return Ok(());
+                    },
+                };
+                if let WatchEvent::Added(o) | WatchEvent::Modified(o) | WatchEvent::Deleted(o) = &ev {
+                    let meta = o.meta();
+                    invalid.retain(|i| i.name.as_ref() != Some(&meta.name) || i.namespace != meta.namespace);
+                }
+                match ev {
+                    WatchEvent::Added(o) | WatchEvent::Modified(o) => {
+                        debug!("Updating {} in {}", o.meta().name, rg.resource);
+                        if let Some(v) = &o.meta().resourceVersion {
+                            *ver = v.to_string();
+                        }
+                        let id: ObjectId = o.meta().into();
+                        let old = data.insert(id.clone(), o.clone());
+                        if let Some(old) = &old {
+                            self.remove_from_indices(&mut indices, &id, old);
+                        }
+                        self.add_to_indices(&mut indices, &id, &o);
+                        changes.push(match old {
+                            Some(old) => Change::Updated(old, o),
+                            None => Change::Added(o),
+                        });
+                    },
+                    WatchEvent::Deleted(o) => {
+                        debug!("Removing {} from {}", o.meta().name, rg.resource);
+                        let id: ObjectId = o.meta().into();
+                        if let Some(old) = data.remove(&id) {
+                            self.remove_from_indices(&mut indices, &id, &old);
+                        }
+                        if let Some(v) = &o.meta().resourceVersion {
+                             *ver = v.to_string();
+                        }
+                        changes.push(Change::Deleted(o));
                     }
-                },
-                WatchEvent::Deleted(o) => {
-                    debug!("Removing {} from {}", o.meta().name, rg.resource);
-                    data.remove(&o.meta().into());
-                    if let Some(v) = &o.meta().resourceVersion {
-                         *ver = v.to_string();
+                    WatchEvent::Error(e) => {
+                        warn!("Failed to watch {}: {:?}", rg.resource, e);
+                        Err(ErrorKind::Api(e))?
                     }
                 }
-                WatchEvent::Error(e) => {
-                    warn!("Failed to watch {}: {:?}", rg.resource, e);
-                    Err(ErrorKind::Api(e))?
-                }
-            }
-        }
-        Ok(())
+                Ok(())
+            })
+        };
+        self.notify(changes);
+        res
+    }
+}
+
+impl<K> Reflector<K> where
+    K: Clone + DeserializeOwned + KubeObject + Serialize,
+{
+    /// Keep a snapshot of the cache in a file, to `init` from on restart
+    ///
+    /// Watching then resumes from the snapshot's resourceVersion rather than relisting,
+    /// which on large clusters saves minutes of warmup. Should that version be too old,
+    /// the apiserver rejects the watch and the cache is refreshed with a full list.
+    /// Snapshots of other resources, namespaces or selectors are ignored.
+    pub fn persist<P: AsRef<Path>>(mut self, path: P) -> Self {
+        let snapshotter = |cache: &Cache<K>, source: &str, version: &str| {
+            let snapshot = Snapshot {
+                source: source.into(),
+                resourceVersion: version.into(),
+                items: cache.values().collect(),
+            };
+            Ok(serde_json::to_vec(&snapshot).context(ErrorKind::SerdeParse)?)
+        };
+        self.persist = Some((path.as_ref().to_path_buf(), Arc::new(snapshotter)));
+        self
     }
 }
 
@@ -274,3 +690,185 @@ impl From<&ObjectMeta> for ObjectId {
         }
     }
 }
+
+#[test]
+fn init_follows_continue_tokens() {
+    use crate::client::fake::Fake;
+    use crate::api::{Object, Void};
+    let fake = Fake::new()
+        .on("GET", "/apis/example.com/v1/foos?continue=next", 200,
+            r#"{"metadata":{"resourceVersion":"10"},"items":[{"metadata":{"name":"b"},"spec":{}}]}"#)
+        .on("GET", "/apis/example.com/v1/foos", 200,
+            r#"{"metadata":{"resourceVersion":"10","continue":"next"},"items":[{"metadata":{"name":"a"},"spec":{}}]}"#);
+    let client = fake.client();
+    let api: Api<Object<Void, Void>> = Api::customResource(client, "foos").group("example.com");
+    let rf = Reflector::new(api).page_size(1).init().unwrap();
+    let names: Vec<_> = rf.read().unwrap().into_iter().map(|o| o.metadata.name).collect();
+    assert_eq!(names, vec!["a", "b"]);
+    assert_eq!(*rf.version.read().unwrap(), "10");
+    assert!(fake.requests().iter().all(|r| r.query().contains("limit=1")));
+}
+
+#[test]
+fn snapshots_are_restored() {
+    use crate::client::fake::{respond, Fake};
+    use crate::api::{Object, Void};
+    let fake = Fake::new().handle("GET", "/apis/example.com/v1/foos", |req| respond(200, if !req.query().contains("watch=true") {
+        r#"{"metadata":{"resourceVersion":"10"},"items":[{"metadata":{"name":"a"},"spec":{}}]}"#
+    } else if req.query().contains("resourceVersion=10") {
+        "{\"type\":\"ADDED\",\"object\":{\"metadata\":{\"name\":\"b\",\"resourceVersion\":\"11\"},\"spec\":{}}}\n"
+    } else {
+        assert!(req.query().contains("resourceVersion=11"));
+        ""
+    }));
+    let lists = || fake.requests().iter().filter(|r| !r.query().contains("watch=true")).count();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("foos.json");
+    let reflector = || {
+        let client = fake.client();
+        let api: Api<Object<Void, Void>> = Api::customResource(client, "foos").group("example.com");
+        Reflector::new(api).persist(&path)
+    };
+    let rf = reflector().init().unwrap();
+    assert!(!path.exists());
+    rf.poll().unwrap();
+    assert!(path.exists());
+
+    let rf = reflector().init().unwrap();
+    assert_eq!(lists(), 1);
+    assert_eq!(*rf.version.read().unwrap(), "11");
+    let names: Vec<_> = rf.read().unwrap().into_iter().map(|o| o.metadata.name).collect();
+    assert_eq!(names, vec!["a", "b"]);
+    rf.poll().unwrap();
+
+    let rf = reflector().labels("app=blog").init().unwrap();
+    assert_eq!(lists(), 2);
+    assert_eq!(*rf.version.read().unwrap(), "10");
+}
+
+#[test]
+fn handlers_see_old_and_final_states() {
+    use crate::client::fake::Fake;
+    use crate::api::{Object, Void};
+    use std::sync::Mutex;
+    let fake = Fake::new()
+        .on("GET", "/apis/example.com/v1/foos?watch=true", 200, r#"{"type":"MODIFIED","object":{"metadata":{"name":"a","resourceVersion":"11"},"spec":{}}}
+{"type":"DELETED","object":{"metadata":{"name":"b","resourceVersion":"12"},"spec":{}}}
+"#)
+        .on("GET", "/apis/example.com/v1/foos", 200, r#"{"metadata":{"resourceVersion":"10"},"items":[
+            {"metadata":{"name":"a","resourceVersion":"1"},"spec":{}},
+            {"metadata":{"name":"b","resourceVersion":"2"},"spec":{}}]}"#);
+    type Foo = Object<Void, Void>;
+    let seen = Arc::new(Mutex::new(vec![]));
+    let version = |o: &Foo| format!("{}@{}", o.metadata.name, o.metadata.resourceVersion.clone().unwrap_or_default());
+    let (adds, updates, deletes) = (seen.clone(), seen.clone(), seen.clone());
+    let client = fake.client();
+    let api: Api<Foo> = Api::customResource(client, "foos").group("example.com");
+    let rf = Reflector::new(api)
+        .on_add(move |o| adds.lock().unwrap().push(format!("add {}", version(o))))
+        .on_update(move |old, new| updates.lock().unwrap().push(format!("update {} {}", version(old), version(new))))
+        .on_delete(move |o| deletes.lock().unwrap().push(format!("delete {}", version(o))))
+        .init().unwrap();
+    rf.poll().unwrap();
+    // a relist brings back b and reverts a, as if the watch missed events
+    rf.reset().unwrap();
+    assert_eq!(*seen.lock().unwrap(), vec![
+        "add a@1", "add b@2", "update a@1 a@11", "delete b@12", "update a@11 a@1", "add b@2",
+    ]);
+}
+
+#[test]
+fn indices_follow_the_cache() {
+    use crate::client::fake::Fake;
+    use crate::api::{Object, Void};
+    let client = Fake::new()
+        .on("GET", "/apis/example.com/v1/foos?watch=true", 200, r#"{"type":"MODIFIED","object":{"metadata":{"name":"a","namespace":"x","resourceVersion":"11"},"spec":{}}}
+{"type":"DELETED","object":{"metadata":{"name":"b","namespace":"y","resourceVersion":"12"},"spec":{}}}
+{"type":"ADDED","object":{"metadata":{"name":"c","namespace":"x","resourceVersion":"13"},"spec":{}}}
+"#)
+        .on("GET", "/apis/example.com/v1/foos", 200, r#"{"metadata":{"resourceVersion":"10"},"items":[
+            {"metadata":{"name":"a","namespace":"x","ownerReferences":[{"name":"o","apiVersion":"v1","kind":"K","uid":"1"}]},"spec":{}},
+            {"metadata":{"name":"b","namespace":"y","ownerReferences":[{"name":"o","apiVersion":"v1","kind":"K","uid":"1"}]},"spec":{}}]}"#)
+        .client();
+    let api: Api<Object<Void, Void>> = Api::customResource(client, "foos").group("example.com");
+    let rf = Reflector::new(api)
+        .index_by_namespace()
+        .index_by_owner()
+        .index("initial", |o| vec![o.metadata.name[..1].to_string()])
+        .init().unwrap();
+    let names = |index: &str, key: &str| -> Vec<String> {
+        rf.by_index(index, key).unwrap().into_iter().map(|o| o.metadata.name).collect()
+    };
+    assert_eq!(names(OWNER_INDEX, "1"), vec!["a", "b"]);
+    assert_eq!(names(NAMESPACE_INDEX, "y"), vec!["b"]);
+    rf.poll().unwrap();
+    assert!(names(OWNER_INDEX, "1").is_empty());
+    assert!(names(NAMESPACE_INDEX, "y").is_empty());
+    assert_eq!(names(NAMESPACE_INDEX, "x"), vec!["a", "c"]);
+    assert_eq!(names("initial", "c"), vec!["c"]);
+    assert!(rf.by_index("node", "n1").is_err());
+}
+
+#[test]
+fn clones_see_the_cache_sync() {
+    use crate::client::fake::{respond, Fake};
+    use crate::api::{Object, Void};
+    let client = Fake::new().handle("GET", "*", |_| {
+        std::thread::sleep(Duration::from_millis(200));
+        respond(200, r#"{"metadata":{"resourceVersion":"10"},"items":[]}"#)
+    }).client();
+    let api: Api<Object<Void, Void>> = Api::customResource(client, "foos").group("example.com");
+    let rf = Reflector::new(api);
+    assert!(!rf.has_synced());
+    assert!(rf.wait_for_cache_sync(Duration::from_millis(10)).is_err());
+    let filler = rf.clone();
+    std::thread::spawn(move || filler.init().unwrap());
+    rf.wait_for_cache_sync(Duration::from_secs(5)).unwrap();
+    assert!(rf.has_synced());
+}
+
+#[test]
+fn invalid_items_are_skipped_when_asked() {
+    use crate::client::fake::Fake;
+    use crate::api::{Object, Void};
+    #[derive(Clone, Deserialize)]
+    struct FooSpec {
+        #[allow(dead_code)]
+        size: u32,
+    }
+    let event = |kind: &str, name: &str, size: &str, rv: u32| format!(
+        r#"{{"type":"{}","object":{{"metadata":{{"name":"{}","namespace":"ns","resourceVersion":"{}"}},"spec":{{"size":{}}}}}}}"#,
+        kind, name, rv, size);
+    let watch = [event("MODIFIED", "a", "2", 11), event("MODIFIED", "c", r#""huge""#, 12),
+        event("ADDED", "d", "null", 13), event("MODIFIED", "b", "2", 14)];
+    let fake = Fake::new()
+        .on("GET", "/apis/example.com/v1/namespaces/ns/foos?watch=true", 200, watch.join("\n") + "\n")
+        .on("GET", "/apis/example.com/v1/namespaces/ns/foos", 200, r#"{"metadata":{"resourceVersion":"10"},"items":[
+        {"metadata":{"name":"a","namespace":"ns"},"spec":{"size":1}},
+        {"metadata":{"name":"b","namespace":"ns"},"spec":{"size":"large"}},
+        {"metadata":{"name":"c","namespace":"ns"},"spec":{"size":3}}]}"#);
+    let api = || -> Api<Object<FooSpec, Void>> {
+        Api::customResource(fake.client(), "foos").group("example.com").within("ns")
+    };
+    assert!(api().list(&ListParams::default()).is_err());
+    let list = api().list_partial(&ListParams::default()).unwrap();
+    assert_eq!(list.items.len(), 2);
+    assert_eq!((list.errors[0].index, list.errors[0].name.as_deref()), (1, Some("b")));
+    assert!(list.errors[0].to_string().starts_with("item 1 (ns/b): invalid type"), "{}", list.errors[0]);
+
+    let err = Reflector::new(api()).init().err().unwrap();
+    assert!(matches!(err.kind(), ErrorKind::SerdeParse));
+    use failure::Fail;
+    assert!(err.cause().unwrap().to_string().contains("invalid type"), "{:?}", err.cause());
+    let rf = Reflector::new(api()).skip_invalid().init().unwrap();
+    let names = |rf: &Reflector<Object<FooSpec, Void>>| rf.read().unwrap().into_iter().map(|o| o.metadata.name).collect::<Vec<_>>();
+    assert_eq!(names(&rf), vec!["a", "c"]);
+    assert_eq!(rf.invalid_items()[0].name.as_deref(), Some("b"));
+
+    // watched objects are decoded one by one the same way, and fixed ones are taken back in
+    rf.poll().unwrap();
+    assert_eq!(names(&rf), vec!["a", "b"]);
+    let invalid: Vec<_> = rf.invalid_items().into_iter().map(|e| (e.index, e.name.unwrap())).collect();
+    assert_eq!(invalid, vec![(1, "c".to_string()), (2, "d".to_string())]);
+    assert_eq!(*rf.version.read().unwrap(), "14");
+}
@@ -0,0 +1,245 @@
+//! Requests composed from their parts, rather than formatted into paths
+use failure::ResultExt;
+use url::form_urlencoded;
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+
+use crate::api::{DeleteParams, ListParams, LogParams, PatchParams, PostParams};
+use crate::{ErrorKind, Result};
+
+/// A builder for requests to the apiserver
+///
+/// Composes the group, version, namespace, resource, name and subresource into a
+/// path, and appends query parameters, so any request can be made without format!
+/// strings. Names and parameters are percent encoded. `RawApi` builds all its
+/// requests this way; `RawApi::request` starts one for its resource.
+///
+/// ```
+/// use kube::api::{RequestBuilder, ListParams};
+///
+/// let req = RequestBuilder::new("apps", "v1", "deployments")
+///     .namespace("prod")
+///     .name("web")
+///     .subresource("scale")
+///     .get()
+///     .unwrap();
+/// assert_eq!(req.uri(), "/apis/apps/v1/namespaces/prod/deployments/web/scale");
+///
+/// let lp = ListParams { label_selector: Some("app=web".into()), ..Default::default() };
+/// let req = RequestBuilder::new("", "v1", "pods").list_params(&lp).unwrap().get().unwrap();
+/// assert_eq!(req.uri(), "/api/v1/pods?labelSelector=app%3Dweb");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct RequestBuilder {
+    prefix: String,
+    group: String,
+    version: String,
+    namespace: Option<String>,
+    resource: String,
+    name: Option<String>,
+    subresource: Option<String>,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+
+impl RequestBuilder {
+    /// Requests for a resource of a group, the empty string for the core group
+    pub fn new(group: &str, version: &str, resource: &str) -> Self {
+        RequestBuilder {
+            prefix: if group.is_empty() { "api" } else { "apis" }.into(),
+            group: group.into(),
+            version: version.into(),
+            resource: resource.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Use another path prefix than `api` or `apis`, which follow from the group
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Address resources within a namespace
+    pub fn namespace(mut self, ns: &str) -> Self {
+        self.namespace = Some(ns.into());
+        self
+    }
+
+    /// Address a single object
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Address a subresource of the object, like `status` or `log`
+    pub fn subresource(mut self, subresource: &str) -> Self {
+        self.subresource = Some(subresource.into());
+        self
+    }
+
+    /// Append a query parameter
+    pub fn param(mut self, key: &str, value: &str) -> Self {
+        self.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// Set a header, replacing any set before
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub(crate) fn flag(self, key: &str, set: bool) -> Self {
+        if set { self.param(key, "true") } else { self }
+    }
+
+    fn opt<T: ToString>(self, key: &str, value: &Option<T>) -> Self {
+        match value {
+            Some(v) => self.param(key, &v.to_string()),
+            None => self,
+        }
+    }
+
+    /// The selectors of a list, watch or delete collection call
+    pub(crate) fn selectors(self, lp: &ListParams) -> Self {
+        self.opt("fieldSelector", &lp.field_selector)
+            .flag("includeUninitialized", lp.include_uninitialized)
+            .opt("labelSelector", &lp.label_selector)
+    }
+
+    /// The parameters of a list call, checked to be consistent
+    pub fn list_params(self, lp: &ListParams) -> Result<Self> {
+        lp.validate()?;
+        Ok(self.selectors(lp)
+            .opt("resourceVersion", &lp.resource_version)
+            .opt("resourceVersionMatch", &lp.resource_version_match.as_ref().map(|m| format!("{:?}", m)))
+            .opt("limit", &lp.limit)
+            .opt("continue", &lp.continue_token))
+    }
+
+    /// The parameters of a watch from resourceVersion `ver`, timing out after 10s unless `lp` says otherwise
+    pub fn watch_params(self, lp: &ListParams, ver: &str) -> Self {
+        self.param("watch", "true")
+            .param("resourceVersion", ver)
+            .param("timeoutSeconds", &lp.timeout.unwrap_or(10).to_string())
+            .selectors(lp)
+    }
+
+    /// The parameters and content type of a create or replace call
+    pub fn post_params(self, pp: &PostParams) -> Self {
+        let rb = if pp.dry_run { self.param("dryRun", "All") } else { self };
+        rb.opt("fieldValidation", &pp.field_validation.as_ref().map(|f| format!("{:?}", f)))
+            .header("Content-Type", &pp.content_type.to_string())
+    }
+
+    /// The parameters and content type of a patch call, checked to be consistent
+    pub fn patch_params(self, pp: &PatchParams) -> Result<Self> {
+        pp.validate()?;
+        Ok(self.flag("dryRun", pp.dry_run)
+            .flag("force", pp.force)
+            .opt("fieldManager", &pp.field_manager)
+            .opt("fieldValidation", &pp.field_validation.as_ref().map(|f| format!("{:?}", f)))
+            .header("Accept", "application/json")
+            .header("Content-Type", &pp.patch_strategy.to_string()))
+    }
+
+    /// The parameters of a delete call
+    pub fn delete_params(self, dp: &DeleteParams) -> Self {
+        let rb = if dp.dry_run { self.param("dryRun", "All") } else { self };
+        rb.opt("gracePeriodSeconds", &dp.grace_period_seconds)
+            .opt("propagationPolicy", &dp.propagation_policy.as_ref().map(|p| format!("{:?}", p)))
+    }
+
+    /// The parameters of a log call
+    pub fn log_params(self, lp: &LogParams) -> Self {
+        self.opt("container", &lp.container)
+            .flag("follow", lp.follow)
+            .opt("limitBytes", &lp.limit_bytes)
+            .flag("pretty", lp.pretty)
+            .flag("previous", lp.previous)
+            .opt("sinceSeconds", &lp.since_seconds)
+            .opt("tailLines", &lp.tail_lines)
+            .flag("timestamps", lp.timestamps)
+    }
+
+    /// The path, without query parameters
+    pub fn path(&self) -> String {
+        let mut segments: Vec<&str> = vec![];
+        for s in &[&self.prefix, &self.group] {
+            if !s.is_empty() {
+                segments.push(s);
+            }
+        }
+        segments.push(&self.version);
+        if let Some(ns) = &self.namespace {
+            segments.extend(&["namespaces", ns.as_str()]);
+        }
+        segments.push(&self.resource);
+        segments.extend(self.name.as_deref());
+        segments.extend(self.subresource.as_deref());
+        let encoded: Vec<String> = segments.iter()
+            .map(|s| utf8_percent_encode(s, PATH_SEGMENT_ENCODE_SET).to_string())
+            .collect();
+        format!("/{}", encoded.join("/"))
+    }
+
+    /// The path and query parameters
+    pub fn uri(&self) -> String {
+        if self.query.is_empty() {
+            return self.path();
+        }
+        let query = form_urlencoded::Serializer::new(String::new()).extend_pairs(&self.query).finish();
+        format!("{}?{}", self.path(), query)
+    }
+
+    /// The request with `method` and `body`
+    pub fn build(&self, method: http::Method, body: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
+        let mut req = http::Request::builder();
+        req.method(method).uri(self.uri());
+        for (name, value) in &self.headers {
+            req.header(name.as_str(), value.as_str());
+        }
+        Ok(req.body(body).context(ErrorKind::RequestBuild)?)
+    }
+
+    pub fn get(&self) -> Result<http::Request<Vec<u8>>> {
+        self.build(http::Method::GET, vec![])
+    }
+
+    pub fn post(&self, body: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
+        self.build(http::Method::POST, body)
+    }
+
+    pub fn put(&self, body: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
+        self.build(http::Method::PUT, body)
+    }
+
+    pub fn patch(&self, body: Vec<u8>) -> Result<http::Request<Vec<u8>>> {
+        self.build(http::Method::PATCH, body)
+    }
+
+    pub fn delete(&self) -> Result<http::Request<Vec<u8>>> {
+        self.build(http::Method::DELETE, vec![])
+    }
+}
+
+#[test]
+fn requests_are_composed_and_encoded() {
+    let rb = RequestBuilder::new("rbac.authorization.k8s.io", "v1", "clusterroles").name("system:node");
+    assert_eq!(rb.path(), "/apis/rbac.authorization.k8s.io/v1/clusterroles/system:node");
+    let rb = RequestBuilder::new("", "v1", "configmaps").namespace("ns").name("a/b c");
+    assert_eq!(rb.get().unwrap().uri(), "/api/v1/namespaces/ns/configmaps/a%2Fb%20c");
+
+    let pp = PatchParams { field_manager: Some("me & you".into()), ..Default::default() };
+    let req = rb.patch_params(&pp).unwrap().param("pretty", "true").patch(b"{}".to_vec()).unwrap();
+    assert_eq!(req.uri(), "/api/v1/namespaces/ns/configmaps/a%2Fb%20c?fieldManager=me+%26+you&pretty=true");
+    assert_eq!(req.method(), "PATCH");
+    assert_eq!(req.headers()["Content-Type"], "application/merge-patch+json");
+
+    let req = RequestBuilder::new("", "v1", "users").prefix("oapi").header("Accept", "a").header("accept", "b").get().unwrap();
+    assert_eq!(req.uri(), "/oapi/v1/users");
+    assert_eq!(req.headers().get_all("Accept").iter().count(), 1);
+    let bad = PatchParams { force: true, ..Default::default() };
+    assert!(RequestBuilder::new("", "v1", "pods").patch_params(&bad).is_err());
+}
@@ -1,7 +1,8 @@
 #![allow(non_snake_case)]
 
-use std::fmt::Debug;
-use serde::{Deserialize};
+use std::fmt::{self, Debug};
+use serde::{Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
 
 use crate::api::metadata::{ObjectMeta, ListMeta, TypeMeta};
 use crate::ApiError;
@@ -10,7 +11,11 @@ use crate::ApiError;
 pub trait KubeObject {
     /// Every object must have ObjectMeta
     fn meta(&self) -> &ObjectMeta;
-    // NB: TypeMeta also required, but not used by abstractions yet
+
+    /// TypeMeta of the object, if the type carries it
+    ///
+    /// Generally only populated on objects returned from the apiserver.
+    fn types(&self) -> Option<&TypeMeta> { None }
 }
 
 
@@ -41,6 +46,23 @@ impl<K> Debug for WatchEvent<K> where
     }
 }
 
+impl<K> WatchEvent<K> where
+    K: Clone + KubeObject
+{
+    /// The object the event is about, unless it is an error
+    pub fn object(&self) -> Option<&K> {
+        match self {
+            WatchEvent::Added(o) | WatchEvent::Modified(o) | WatchEvent::Deleted(o) => Some(o),
+            WatchEvent::Error(_) => None,
+        }
+    }
+
+    /// The namespace of the object, telling events of a watch across namespaces apart
+    pub fn namespace(&self) -> Option<&str> {
+        self.object().and_then(|o| o.meta().namespace.as_deref())
+    }
+}
+
 // -------------------------------------------------------
 
 /// A standard kubernetes object with .spec and .status
@@ -85,6 +107,7 @@ pub struct Object<P, U> where
 /// Blanked implementation for standard objects that can use Object
 impl<P, U> KubeObject for Object<P, U> where P: Clone, U: Clone {
     fn meta(&self) -> &ObjectMeta { &self.metadata }
+    fn types(&self) -> Option<&TypeMeta> { Some(&self.types) }
 }
 
 /// A generic kubernetes object list
@@ -109,3 +132,62 @@ pub struct ObjectList<T> where
     #[serde(bound(deserialize = "Vec<T>: Deserialize<'de>"))]
     pub items: Vec<T>,
 }
+
+/// An item of a list that could not be parsed
+#[derive(Clone, Debug)]
+pub struct ItemError {
+    /// Its position in the list, or among the events of a watch
+    pub index: usize,
+    pub name: Option<String>,
+    pub namespace: Option<String>,
+    pub message: String,
+}
+
+impl ItemError {
+    pub(crate) fn new(index: usize, item: &serde_json::Value, message: String) -> Self {
+        let meta = &item["metadata"];
+        let (name, namespace) = (meta["name"].as_str().map(String::from), meta["namespace"].as_str().map(String::from));
+        ItemError { index, name, namespace, message }
+    }
+}
+
+impl fmt::Display for ItemError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.namespace, &self.name) {
+            (Some(ns), Some(name)) => write!(f, "item {} ({}/{}): {}", self.index, ns, name, self.message),
+            (None, Some(name)) => write!(f, "item {} ({}): {}", self.index, name, self.message),
+            _ => write!(f, "item {}: {}", self.index, self.message),
+        }
+    }
+}
+
+/// A list whose unparseable items are left out, and reported in `errors`
+///
+/// For lists of custom resources, where one object with an unexpected shape, e.g.
+/// from an older version of the CRD, should not make all others unreadable.
+#[derive(Clone)]
+pub struct PartialList<T> {
+    pub metadata: ListMeta,
+    pub items: Vec<T>,
+    pub errors: Vec<ItemError>,
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for PartialList<T> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Raw {
+            metadata: ListMeta,
+            #[serde(default)]
+            items: Vec<serde_json::Value>,
+        }
+        let raw = Raw::deserialize(d)?;
+        let (mut items, mut errors) = (vec![], vec![]);
+        for (index, item) in raw.items.into_iter().enumerate() {
+            match serde_json::from_value(item.clone()) {
+                Ok(o) => items.push(o),
+                Err(e) => errors.push(ItemError::new(index, &item, e.to_string())),
+            }
+        }
+        Ok(PartialList { metadata: raw.metadata, items, errors })
+    }
+}
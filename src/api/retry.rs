@@ -0,0 +1,136 @@
+//! Retrying read-modify-write cycles, like client-go's `retry.RetryOnConflict`
+use std::convert::TryFrom;
+use std::time::Duration;
+
+use crate::{Error, ErrorKind, Result};
+
+/// The longest wait between attempts, whatever the factor and cap
+const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// How often and how fast to retry
+///
+/// The default mirrors client-go's `DefaultRetry`: 5 attempts, starting 10ms apart.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    /// Maximum number of attempts, including the first
+    pub steps: usize,
+    /// Wait before the second attempt
+    pub duration: Duration,
+    /// Multiplier applied to the wait after every attempt; must be finite and not negative
+    pub factor: f64,
+    /// Upper bound for the wait between attempts, which never exceeds an hour
+    pub cap: Option<Duration>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            steps: 5,
+            duration: Duration::from_millis(10),
+            factor: 1.0,
+            cap: None,
+        }
+    }
+}
+
+impl Backoff {
+    pub(crate) fn validate(&self) -> Result<()> {
+        if !self.factor.is_finite() || self.factor < 0.0 {
+            return Err(ErrorKind::RequestValidation(
+                format!("backoff factor must be finite and not negative, got {}", self.factor)).into());
+        }
+        Ok(())
+    }
+
+    /// The wait before attempt `n + 1`
+    pub(crate) fn wait(&self, n: usize) -> Duration {
+        let exponent = i32::try_from(n).unwrap_or(i32::MAX) - 1;
+        let secs = self.duration.as_secs_f64() * self.factor.powi(exponent);
+        let max = self.cap.map_or(MAX_WAIT, |cap| cap.min(MAX_WAIT));
+        // NaN and infinities come from factors that were never validated
+        if !secs.is_finite() || secs >= max.as_secs_f64() {
+            return max;
+        }
+        Duration::from_secs_f64(secs.max(0.0))
+    }
+}
+
+/// Run `f` until it succeeds, fails with an error `retriable` rejects,
+/// or the backoff runs out of steps
+///
+/// An apiserver asking to back off through `Retry-After` is waited out instead
+/// when that is longer.
+pub fn retry_on_error<T, R, F>(backoff: &Backoff, retriable: R, mut f: F) -> Result<T>
+where
+    R: Fn(&Error) -> bool,
+    F: FnMut() -> Result<T>,
+{
+    backoff.validate()?;
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        match f() {
+            Err(ref e) if attempt < backoff.steps && retriable(e) => {
+                let wait = std::cmp::max(backoff.wait(attempt), e.retry_after().unwrap_or_default());
+                debug!("Attempt {} failed: {}, retrying in {:?}", attempt, e, wait);
+                std::thread::sleep(wait);
+            },
+            res => return res,
+        }
+    }
+}
+
+/// Run a read-modify-write `f` again while it fails with `409 Conflict`
+///
+/// `f` must re-read the object every time, so that each attempt
+/// writes with the latest resourceVersion.
+pub fn retry_on_conflict<T, F>(backoff: &Backoff, f: F) -> Result<T>
+where
+    F: FnMut() -> Result<T>,
+{
+    retry_on_error(backoff, |e| e.status_code() == Some(409), f)
+}
+
+#[test]
+fn retries_conflicts_only() {
+    use crate::{ApiError, ErrorKind};
+    let api = |code| Error::from(ErrorKind::Api(ApiError {
+        status: "Failure".into(),
+        message: String::new(),
+        reason: String::new(),
+        code,
+        throttle: None,
+    }));
+    let backoff = Backoff { duration: Duration::from_millis(1), ..Default::default() };
+    let mut calls = 0;
+    let res = retry_on_conflict(&backoff, || {
+        calls += 1;
+        if calls < 3 { Err(api(409)) } else { Ok(calls) }
+    });
+    assert_eq!(res.unwrap(), 3);
+    let mut calls = 0;
+    let res: Result<()> = retry_on_conflict(&backoff, || { calls += 1; Err(api(409)) });
+    assert!(res.is_err());
+    assert_eq!(calls, 5);
+    let mut calls = 0;
+    let res: Result<()> = retry_on_conflict(&backoff, || { calls += 1; Err(api(422)) });
+    assert!(res.is_err());
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn waits_stay_bounded() {
+    let backoff = Backoff { duration: Duration::from_secs(1), factor: 2.0, ..Default::default() };
+    assert_eq!(backoff.wait(1), Duration::from_secs(1));
+    assert_eq!(backoff.wait(3), Duration::from_secs(4));
+    assert_eq!(backoff.wait(5000), MAX_WAIT);
+    assert_eq!(backoff.wait(usize::MAX), MAX_WAIT);
+    let capped = Backoff { cap: Some(Duration::from_secs(10)), ..backoff.clone() };
+    assert_eq!(capped.wait(5000), Duration::from_secs(10));
+    for factor in &[-2.0, f64::NAN, f64::INFINITY] {
+        let bad = Backoff { factor: *factor, ..backoff.clone() };
+        bad.wait(3);
+        let res: Result<()> = retry_on_error(&bad, |_| true, || Ok(()));
+        assert!(res.is_err(), "factor {} was accepted", factor);
+    }
+}
@@ -0,0 +1,185 @@
+//! Token and access reviews, for authenticating and authorizing clients of a service
+//!
+//! A service can check the tokens its own clients present with a `TokenReview`,
+//! and what they may do with a `SubjectAccessReview`. Authentication and
+//! authorization webhooks receive these same objects from the apiserver, and answer
+//! them with their status set.
+use crate::api::{Api, Object, ObjectMeta, PostParams, TypeMeta};
+use crate::{ErrorKind, Result};
+use failure::ResultExt;
+
+use k8s_openapi::api::authentication::v1::{TokenReviewSpec, TokenReviewStatus, UserInfo};
+use k8s_openapi::api::authorization::v1::{
+    NonResourceAttributes, ResourceAttributes, SubjectAccessReviewSpec, SubjectAccessReviewStatus,
+};
+
+/// A TokenReview, asking who a token belongs to
+pub type TokenReview = Object<TokenReviewSpec, TokenReviewStatus>;
+
+/// A SubjectAccessReview, asking whether a user may do something
+pub type SubjectAccessReview = Object<SubjectAccessReviewSpec, SubjectAccessReviewStatus>;
+
+impl TokenReview {
+    /// A review of `token`, valid for any of `audiences`, or the apiserver's own when empty
+    pub fn for_token(token: &str, audiences: &[&str]) -> Self {
+        Object {
+            types: TypeMeta {
+                apiVersion: Some("authentication.k8s.io/v1".into()),
+                kind: Some("TokenReview".into()),
+            },
+            metadata: ObjectMeta::default(),
+            spec: TokenReviewSpec {
+                token: Some(token.into()),
+                audiences: Some(audiences.iter().map(|a| a.to_string()).collect()).filter(|a: &Vec<String>| !a.is_empty()),
+            },
+            status: None,
+        }
+    }
+
+    /// The answer of an authentication webhook that recognised the token as `user`
+    ///
+    /// The token is left out of the answer, which keeps the `apiVersion` it was asked with.
+    pub fn authenticated(mut self, user: UserInfo) -> Self {
+        let audiences = self.spec.audiences.clone();
+        self.spec.token = None;
+        self.status = Some(TokenReviewStatus { authenticated: Some(true), user: Some(user), audiences, error: None });
+        self
+    }
+
+    /// The answer of an authentication webhook that did not recognise the token
+    pub fn unauthenticated(mut self, error: &str) -> Self {
+        self.spec.token = None;
+        self.status = Some(TokenReviewStatus { authenticated: Some(false), error: Some(error.into()), ..Default::default() });
+        self
+    }
+}
+
+impl SubjectAccessReview {
+    fn for_user_with(user: &UserInfo, spec: SubjectAccessReviewSpec) -> Self {
+        Object {
+            types: TypeMeta {
+                apiVersion: Some("authorization.k8s.io/v1".into()),
+                kind: Some("SubjectAccessReview".into()),
+            },
+            metadata: ObjectMeta::default(),
+            spec: SubjectAccessReviewSpec {
+                user: user.username.clone(),
+                uid: user.uid.clone(),
+                groups: user.groups.clone(),
+                extra: user.extra.clone(),
+                ..spec
+            },
+            status: None,
+        }
+    }
+
+    /// Whether `user`, e.g. from a `TokenReview`, may act on a resource
+    pub fn for_resource(user: &UserInfo, attributes: ResourceAttributes) -> Self {
+        Self::for_user_with(user, SubjectAccessReviewSpec { resource_attributes: Some(attributes), ..Default::default() })
+    }
+
+    /// Whether `user` may act on a resource in the namespace of `attributes`, as a
+    /// LocalSubjectAccessReview for `Api::v1LocalSubjectAccessReview`
+    pub fn local_for_resource(user: &UserInfo, attributes: ResourceAttributes) -> Self {
+        let mut review = Self::for_resource(user, attributes);
+        review.types.kind = Some("LocalSubjectAccessReview".into());
+        review.metadata.namespace = review.spec.resource_attributes.as_ref().and_then(|a| a.namespace.clone());
+        review
+    }
+
+    /// Whether `user` may use `verb` on a path that is not a resource, like `/healthz`
+    pub fn for_path(user: &UserInfo, path: &str, verb: &str) -> Self {
+        let attributes = NonResourceAttributes { path: Some(path.into()), verb: Some(verb.into()) };
+        Self::for_user_with(user, SubjectAccessReviewSpec { non_resource_attributes: Some(attributes), ..Default::default() })
+    }
+
+    /// The answer of an authorization webhook allowing the request
+    pub fn allow(self) -> Self {
+        self.answer(SubjectAccessReviewStatus { allowed: true, ..Default::default() })
+    }
+
+    /// The answer of an authorization webhook denying the request, which no other authorizer may allow
+    pub fn deny(self, reason: &str) -> Self {
+        self.answer(SubjectAccessReviewStatus { denied: Some(true), reason: Some(reason.into()), ..Default::default() })
+    }
+
+    /// The answer of an authorization webhook with no opinion, leaving it to other authorizers
+    pub fn no_opinion(self, reason: &str) -> Self {
+        self.answer(SubjectAccessReviewStatus { reason: Some(reason.into()), ..Default::default() })
+    }
+
+    fn answer(mut self, status: SubjectAccessReviewStatus) -> Self {
+        self.status = Some(status);
+        self
+    }
+}
+
+impl Api<TokenReview> {
+    /// Ask the apiserver who `token` belongs to
+    ///
+    /// Tokens it does not recognise give a status with `authenticated` unset or false,
+    /// rather than an error.
+    pub fn review(&self, token: &str, audiences: &[&str]) -> Result<TokenReviewStatus> {
+        let data = serde_json::to_vec(&TokenReview::for_token(token, audiences)).context(ErrorKind::SerdeParse)?;
+        let review = self.create(&PostParams::default(), data)?;
+        Ok(review.status.unwrap_or_default())
+    }
+}
+
+impl Api<SubjectAccessReview> {
+    /// Ask the apiserver whether the user of `review` may do what it describes
+    pub fn review(&self, review: &SubjectAccessReview) -> Result<SubjectAccessReviewStatus> {
+        let data = serde_json::to_vec(review).context(ErrorKind::SerdeParse)?;
+        let review = self.create(&PostParams::default(), data)?;
+        Ok(review.status.unwrap_or_default())
+    }
+}
+
+#[test]
+fn reviews_are_created_and_answered() {
+    use crate::client::fake::{respond, Fake};
+    use serde_json::json;
+    let fake = Fake::new().handle("POST", "*", |req| {
+        let mut review = req.json();
+        review["status"] = match review["kind"].as_str() {
+            Some("TokenReview") if review["spec"]["token"] == "good" => json!({
+                "authenticated": true, "user": { "username": "jane", "groups": ["dev"] },
+            }),
+            Some("TokenReview") => json!({ "error": "invalid token" }),
+            _ => json!({ "allowed": review["spec"]["groups"][0] == "dev" }),
+        };
+        respond(201, review.to_string())
+    });
+    let client = fake.client();
+    let tokens = Api::v1TokenReview(client.clone());
+    let status = tokens.review("good", &["my-service"]).unwrap();
+    assert_eq!(status.authenticated, Some(true));
+    assert!(tokens.review("bad", &[]).unwrap().authenticated != Some(true));
+
+    let user = status.user.unwrap();
+    let attributes = ResourceAttributes { verb: Some("get".into()), resource: Some("widgets".into()), namespace: Some("ns".into()), ..Default::default() };
+    let reviews = Api::v1LocalSubjectAccessReview(client).within("ns");
+    assert!(reviews.review(&SubjectAccessReview::local_for_resource(&user, attributes)).unwrap().allowed);
+    assert_eq!(fake.lines(), vec![
+        "POST /apis/authentication.k8s.io/v1/tokenreviews",
+        "POST /apis/authentication.k8s.io/v1/tokenreviews",
+        "POST /apis/authorization.k8s.io/v1/namespaces/ns/localsubjectaccessreviews",
+    ]);
+    let local = fake.requests()[2].json();
+    assert_eq!(local["kind"], "LocalSubjectAccessReview");
+    assert_eq!(local["metadata"]["namespace"], "ns");
+    assert_eq!(local["spec"]["user"], "jane");
+
+    // answering reviews sent to a webhook
+    let asked: TokenReview = serde_json::from_value(json!({
+        "apiVersion": "authentication.k8s.io/v1beta1", "kind": "TokenReview", "metadata": {},
+        "spec": { "token": "secret" },
+    })).unwrap();
+    let answer = serde_json::to_value(asked.authenticated(user.clone())).unwrap();
+    assert_eq!(answer["apiVersion"], "authentication.k8s.io/v1beta1");
+    assert_eq!(answer["status"]["user"]["username"], "jane");
+    assert!(answer["spec"].get("token").is_none());
+    let denied = serde_json::to_value(SubjectAccessReview::for_path(&user, "/metrics", "get").deny("no")).unwrap();
+    assert_eq!(denied["status"], json!({ "allowed": false, "denied": true, "reason": "no" }));
+    assert_eq!(denied["spec"]["nonResourceAttributes"]["path"], "/metrics");
+}
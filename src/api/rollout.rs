@@ -0,0 +1,358 @@
+//! Rollout status and restarts for Deployments, StatefulSets and DaemonSets
+//!
+//! Status computations mirror `kubectl rollout status`.
+use serde_json::json;
+use chrono::Utc;
+
+use crate::api::{Api, RawApi, Object, ObjectMeta, ObjectList, ListParams, PatchParams, PostParams};
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+use k8s_openapi::api::apps::v1::{
+    DeploymentSpec, DeploymentStatus,
+    StatefulSetSpec, StatefulSetStatus,
+    DaemonSetSpec, DaemonSetStatus,
+    ReplicaSetSpec, ReplicaSetStatus,
+};
+
+/// Annotation set on the pod template by `rollout_restart`
+pub const RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+
+/// Annotation the deployment controller sets on Deployments and their ReplicaSets
+pub const REVISION_ANNOTATION: &str = "deployment.kubernetes.io/revision";
+
+type Deployment = Object<DeploymentSpec, DeploymentStatus>;
+type ReplicaSet = Object<ReplicaSetSpec, ReplicaSetStatus>;
+
+/// The progress of a rollout
+#[derive(Clone, Debug, PartialEq)]
+pub enum RolloutStatus {
+    /// Still rolling out, with a kubectl style explanation
+    Progressing(String),
+    /// Successfully rolled out
+    Complete(String),
+    /// The rollout cannot complete (e.g. progress deadline exceeded)
+    Failed(String),
+}
+
+impl RolloutStatus {
+    /// Whether the rollout has finished successfully
+    pub fn is_complete(&self) -> bool {
+        matches!(self, RolloutStatus::Complete(_))
+    }
+}
+
+/// Whether the controller has observed the latest generation of the object
+fn generation_observed(generation: Option<f64>, observed: Option<i64>) -> bool {
+    let generation = generation.unwrap_or(0.0) as i64;
+    generation <= observed.unwrap_or(0)
+}
+
+impl Object<DeploymentSpec, DeploymentStatus> {
+    /// Compute the rollout status the same way `kubectl rollout status` does
+    pub fn rollout_status(&self) -> RolloutStatus {
+        let name = &self.metadata.name;
+        let status = self.status.clone().unwrap_or_default();
+        if !generation_observed(self.metadata.generation, status.observed_generation) {
+            return RolloutStatus::Progressing(
+                "Waiting for deployment spec update to be observed".into());
+        }
+        let deadline_exceeded = status.conditions.unwrap_or_default().iter().any(|c| {
+            c.type_ == "Progressing" && c.reason.as_deref() == Some("ProgressDeadlineExceeded")
+        });
+        if deadline_exceeded {
+            return RolloutStatus::Failed(format!("deployment {} exceeded its progress deadline", name));
+        }
+        let updated = status.updated_replicas.unwrap_or(0);
+        let available = status.available_replicas.unwrap_or(0);
+        if let Some(desired) = self.spec.replicas {
+            if updated < desired {
+                return RolloutStatus::Progressing(format!(
+                    "Waiting for deployment {} rollout to finish: {} out of {} new replicas have been updated",
+                    name, updated, desired));
+            }
+        }
+        let replicas = status.replicas.unwrap_or(0);
+        if replicas > updated {
+            return RolloutStatus::Progressing(format!(
+                "Waiting for deployment {} rollout to finish: {} old replicas are pending termination",
+                name, replicas - updated));
+        }
+        if available < updated {
+            return RolloutStatus::Progressing(format!(
+                "Waiting for deployment {} rollout to finish: {} of {} updated replicas are available",
+                name, available, updated));
+        }
+        RolloutStatus::Complete(format!("deployment {} successfully rolled out", name))
+    }
+}
+
+impl Object<StatefulSetSpec, StatefulSetStatus> {
+    /// Compute the rollout status the same way `kubectl rollout status` does
+    pub fn rollout_status(&self) -> RolloutStatus {
+        let strategy = self.spec.update_strategy.clone().unwrap_or_default();
+        if strategy.type_.as_deref().unwrap_or("RollingUpdate") != "RollingUpdate" {
+            return RolloutStatus::Failed(
+                "rollout status is only available for RollingUpdate strategy type".into());
+        }
+        let status = match &self.status {
+            Some(s) if s.observed_generation.is_some() => s.clone(),
+            _ => return RolloutStatus::Progressing(
+                "Waiting for statefulset spec update to be observed".into()),
+        };
+        if !generation_observed(self.metadata.generation, status.observed_generation) {
+            return RolloutStatus::Progressing(
+                "Waiting for statefulset spec update to be observed".into());
+        }
+        let ready = status.ready_replicas.unwrap_or(0);
+        if let Some(desired) = self.spec.replicas {
+            if ready < desired {
+                return RolloutStatus::Progressing(format!(
+                    "Waiting for {} pods to be ready", desired - ready));
+            }
+        }
+        let partition = strategy.rolling_update.and_then(|ru| ru.partition);
+        if let (Some(partition), Some(desired)) = (partition, self.spec.replicas) {
+            let updated = status.updated_replicas.unwrap_or(0);
+            if updated < desired - partition {
+                return RolloutStatus::Progressing(format!(
+                    "Waiting for partitioned roll out to finish: {} out of {} new pods have been updated",
+                    updated, desired - partition));
+            }
+            return RolloutStatus::Complete(format!(
+                "partitioned roll out complete: {} new pods have been updated", updated));
+        }
+        if status.update_revision != status.current_revision {
+            return RolloutStatus::Progressing(format!(
+                "waiting for statefulset rolling update to complete {} pods at revision {}",
+                status.updated_replicas.unwrap_or(0), status.update_revision.unwrap_or_default()));
+        }
+        RolloutStatus::Complete(format!(
+            "statefulset rolling update complete {} pods at revision {}",
+            status.current_replicas.unwrap_or(0), status.current_revision.unwrap_or_default()))
+    }
+}
+
+impl Object<DaemonSetSpec, DaemonSetStatus> {
+    /// Compute the rollout status the same way `kubectl rollout status` does
+    pub fn rollout_status(&self) -> RolloutStatus {
+        let name = &self.metadata.name;
+        let strategy = self.spec.update_strategy.clone().unwrap_or_default();
+        if strategy.type_.as_deref().unwrap_or("RollingUpdate") != "RollingUpdate" {
+            return RolloutStatus::Failed(
+                "rollout status is only available for RollingUpdate strategy type".into());
+        }
+        let status = self.status.clone().unwrap_or_default();
+        if !generation_observed(self.metadata.generation, status.observed_generation) {
+            return RolloutStatus::Progressing(
+                "Waiting for daemon set spec update to be observed".into());
+        }
+        let desired = status.desired_number_scheduled;
+        let updated = status.updated_number_scheduled.unwrap_or(0);
+        if updated < desired {
+            return RolloutStatus::Progressing(format!(
+                "Waiting for daemon set {} rollout to finish: {} out of {} new pods have been updated",
+                name, updated, desired));
+        }
+        let available = status.number_available.unwrap_or(0);
+        if available < desired {
+            return RolloutStatus::Progressing(format!(
+                "Waiting for daemon set {} rollout to finish: {} of {} updated pods are available",
+                name, available, desired));
+        }
+        RolloutStatus::Complete(format!("daemon set {} successfully rolled out", name))
+    }
+}
+
+/// The revision of a Deployment or ReplicaSet, if annotated
+fn revision(meta: &ObjectMeta) -> Option<i64> {
+    meta.annotations.get(REVISION_ANNOTATION).and_then(|r| r.parse().ok())
+}
+
+/// Find the ReplicaSet for a revision, or the previous revision when `to_revision` is 0
+fn find_revision(rss: &[ReplicaSet], to_revision: i64) -> Option<&ReplicaSet> {
+    if to_revision > 0 {
+        return rss.iter().find(|rs| revision(&rs.metadata) == Some(to_revision));
+    }
+    let mut revisions: Vec<_> = rss.iter().filter(|rs| revision(&rs.metadata).is_some()).collect();
+    revisions.sort_by_key(|rs| revision(&rs.metadata));
+    revisions.into_iter().rev().nth(1)
+}
+
+impl Api<Deployment> {
+    /// Roll a Deployment back to an earlier revision like `kubectl rollout undo`
+    ///
+    /// A `to_revision` of 0 means the previous revision. The pod template of the
+    /// ReplicaSet owned by the Deployment at that revision is written back to the Deployment.
+    pub fn rollout_undo(&self, name: &str, to_revision: i64) -> Result<Deployment> {
+        let mut deploy = self.get(name)?;
+        let selector = deploy.spec.selector.match_labels.clone().unwrap_or_default()
+            .into_iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join(",");
+        let mut rsapi = RawApi::v1ReplicaSet();
+        rsapi.namespace = self.api.namespace.clone();
+        let lp = ListParams { label_selector: Some(selector), ..Default::default() };
+        let rss: Vec<ReplicaSet> = self.client.request::<ObjectList<ReplicaSet>>(rsapi.list(&lp)?)?
+            .items.into_iter()
+            .filter(|rs| rs.metadata.is_controlled_by(&deploy))
+            .collect();
+        let target = find_revision(&rss, to_revision).ok_or_else(|| {
+            ErrorKind::RequestValidation(format!("unable to find revision {} of deployment {}", to_revision, name))
+        })?;
+        let target_revision = revision(&target.metadata);
+        if target_revision == revision(&deploy.metadata) {
+            debug!("Deployment {} is already at revision {}", name, target_revision.unwrap_or_default());
+            return Ok(deploy);
+        }
+        let mut template = target.spec.template.clone().unwrap_or_default();
+        if let Some(labels) = template.metadata.as_mut().and_then(|m| m.labels.as_mut()) {
+            labels.remove("pod-template-hash");
+        }
+        deploy.spec.template = template;
+        let data = serde_json::to_vec(&deploy).context(ErrorKind::SerdeParse)?;
+        self.replace(name, &PostParams::default(), data)
+    }
+}
+
+/// Merge patch that bumps the restartedAt annotation on a pod template
+fn restart_patch() -> Result<Vec<u8>> {
+    let patch = json!({
+        "spec": {
+            "template": {
+                "metadata": {
+                    "annotations": {
+                        RESTARTED_AT_ANNOTATION: Utc::now().to_rfc3339()
+                    }
+                }
+            }
+        }
+    });
+    Ok(serde_json::to_vec(&patch).context(ErrorKind::SerdeParse)?)
+}
+
+macro_rules! rollout_impl {
+    ($spec:ty, $status:ty) => {
+        impl Api<Object<$spec, $status>> {
+            /// Fetch the object and compute its rollout status
+            pub fn rollout_status(&self, name: &str) -> Result<RolloutStatus> {
+                Ok(self.get(name)?.rollout_status())
+            }
+
+            /// Trigger a rolling restart like `kubectl rollout restart`
+            ///
+            /// Patches the `kubectl.kubernetes.io/restartedAt` annotation on the pod template.
+            pub fn rollout_restart(&self, name: &str) -> Result<Object<$spec, $status>> {
+                self.patch(name, &PatchParams::default(), restart_patch()?)
+            }
+        }
+    };
+}
+rollout_impl!(DeploymentSpec, DeploymentStatus);
+rollout_impl!(StatefulSetSpec, StatefulSetStatus);
+rollout_impl!(DaemonSetSpec, DaemonSetStatus);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn deployment(status: serde_json::Value) -> Object<DeploymentSpec, DeploymentStatus> {
+        serde_json::from_value(json!({
+            "metadata": { "name": "blog", "generation": 2 },
+            "spec": {
+                "replicas": 2,
+                "selector": {},
+                "template": {},
+            },
+            "status": status,
+        })).unwrap()
+    }
+
+    #[test]
+    fn deployment_rollout_progress() {
+        let d = deployment(json!({ "observedGeneration": 1 }));
+        assert_eq!(d.rollout_status(), RolloutStatus::Progressing(
+            "Waiting for deployment spec update to be observed".into()));
+
+        let d = deployment(json!({ "observedGeneration": 2, "replicas": 3, "updatedReplicas": 2, "availableReplicas": 2 }));
+        assert!(!d.rollout_status().is_complete());
+
+        let d = deployment(json!({ "observedGeneration": 2, "replicas": 2, "updatedReplicas": 2, "availableReplicas": 2 }));
+        assert!(d.rollout_status().is_complete());
+
+        let patch: serde_json::Value = serde_json::from_slice(&restart_patch().unwrap()).unwrap();
+        assert!(patch["spec"]["template"]["metadata"]["annotations"][RESTARTED_AT_ANNOTATION].is_string());
+
+        let d = deployment(json!({
+            "observedGeneration": 2,
+            "conditions": [{ "type": "Progressing", "status": "False", "reason": "ProgressDeadlineExceeded" }]
+        }));
+        assert_eq!(d.rollout_status(), RolloutStatus::Failed(
+            "deployment blog exceeded its progress deadline".into()));
+    }
+
+    #[test]
+    fn statefulset_and_daemonset_rollout_progress() {
+        let sts = |status: serde_json::Value| -> Object<StatefulSetSpec, StatefulSetStatus> {
+            serde_json::from_value(json!({
+                "metadata": { "name": "db", "generation": 1 },
+                "spec": {
+                    "replicas": 3,
+                    "selector": {},
+                    "serviceName": "db",
+                    "template": {},
+                    "updateStrategy": { "type": "RollingUpdate", "rollingUpdate": { "partition": 1 } },
+                },
+                "status": status,
+            })).unwrap()
+        };
+        let s = sts(json!({ "observedGeneration": 1, "replicas": 3, "readyReplicas": 2 }));
+        assert_eq!(s.rollout_status(), RolloutStatus::Progressing("Waiting for 1 pods to be ready".into()));
+        let s = sts(json!({ "observedGeneration": 1, "replicas": 3, "readyReplicas": 3, "updatedReplicas": 1 }));
+        assert_eq!(s.rollout_status(), RolloutStatus::Progressing(
+            "Waiting for partitioned roll out to finish: 1 out of 2 new pods have been updated".into()));
+        let s = sts(json!({ "observedGeneration": 1, "replicas": 3, "readyReplicas": 3, "updatedReplicas": 2 }));
+        assert_eq!(s.rollout_status(), RolloutStatus::Complete(
+            "partitioned roll out complete: 2 new pods have been updated".into()));
+
+        let ds = |status: serde_json::Value| -> Object<DaemonSetSpec, DaemonSetStatus> {
+            serde_json::from_value(json!({
+                "metadata": { "name": "agent", "generation": 1 },
+                "spec": { "selector": {}, "template": {} },
+                "status": status,
+            })).unwrap()
+        };
+        let counts = |updated: i32, available: i32| json!({
+            "observedGeneration": 1,
+            "currentNumberScheduled": 3,
+            "desiredNumberScheduled": 3,
+            "numberMisscheduled": 0,
+            "numberReady": available,
+            "updatedNumberScheduled": updated,
+            "numberAvailable": available,
+            "numberUnavailable": 3 - available,
+        });
+        assert_eq!(ds(counts(2, 2)).rollout_status(), RolloutStatus::Progressing(
+            "Waiting for daemon set agent rollout to finish: 2 out of 3 new pods have been updated".into()));
+        assert_eq!(ds(counts(3, 1)).rollout_status(), RolloutStatus::Progressing(
+            "Waiting for daemon set agent rollout to finish: 1 of 3 updated pods are available".into()));
+        assert!(ds(counts(3, 3)).rollout_status().is_complete());
+    }
+
+    #[test]
+    fn rollout_undo_revision_lookup() {
+        let rs = |rev: &str| -> ReplicaSet {
+            serde_json::from_value(json!({
+                "metadata": { "name": format!("blog-{}", rev), "annotations": { REVISION_ANNOTATION: rev } },
+                "spec": { "selector": {} },
+            })).unwrap()
+        };
+        let rss = vec![rs("1"), rs("3"), rs("2")];
+        assert_eq!(find_revision(&rss, 0).unwrap().metadata.name, "blog-2");
+        assert_eq!(find_revision(&rss, 1).unwrap().metadata.name, "blog-1");
+        assert!(find_revision(&rss, 4).is_none());
+        assert!(find_revision(&rss[..1], 0).is_none());
+    }
+}
@@ -0,0 +1,91 @@
+//! Stripping server populated fields so fetched objects can be created again
+use serde_json::Value;
+
+/// Metadata the apiserver sets, and rejects or ignores on create
+const SERVER_METADATA: &[&str] = &[
+    "uid",
+    "resourceVersion",
+    "creationTimestamp",
+    "deletionTimestamp",
+    "deletionGracePeriodSeconds",
+    "generation",
+    "selfLink",
+    "managedFields",
+];
+
+/// Labels the job controller adds to its generated selector
+const JOB_SELECTOR_LABELS: &[&str] = &["controller-uid", "batch.kubernetes.io/controller-uid"];
+
+fn remove(obj: &mut Value, parent: &[&str], keys: &[&str]) {
+    let mut cur = obj;
+    for p in parent {
+        cur = match cur.get_mut(*p) {
+            Some(v) => v,
+            None => return,
+        };
+    }
+    if let Some(map) = cur.as_object_mut() {
+        for k in keys {
+            map.remove(*k);
+        }
+    }
+}
+
+/// Turn a fetched object into one that can be submitted with `create`, like `kubectl neat`
+///
+/// Removes `status`, the server populated metadata (`uid`, `resourceVersion`,
+/// `creationTimestamp`, `managedFields`, ...), allocated cluster IPs of services that are
+/// not headless, and the generated selector of jobs. Owner references and node ports
+/// are kept; drop them as well when the objects go to another cluster.
+pub fn sanitize_for_create(obj: &mut Value) {
+    if let Some(map) = obj.as_object_mut() {
+        map.remove("status");
+    }
+    remove(obj, &["metadata"], SERVER_METADATA);
+
+    match obj["kind"].as_str().unwrap_or("") {
+        "Service" => {
+            let headless = obj["spec"]["clusterIP"] == "None";
+            if !headless {
+                remove(obj, &["spec"], &["clusterIP", "clusterIPs"]);
+            }
+        },
+        "Job" if obj["spec"]["manualSelector"] != true => {
+            remove(obj, &["spec"], &["selector"]);
+            remove(obj, &["spec", "template", "metadata", "labels"], JOB_SELECTOR_LABELS);
+        },
+        _ => {},
+    }
+}
+
+#[test]
+fn server_fields_are_stripped() {
+    use serde_json::json;
+    let mut svc = json!({
+        "apiVersion": "v1", "kind": "Service",
+        "metadata": { "name": "blog", "uid": "1", "resourceVersion": "2", "labels": { "app": "blog" } },
+        "spec": { "clusterIP": "10.0.0.1", "clusterIPs": ["10.0.0.1"], "ports": [{ "port": 80, "nodePort": 30080 }] },
+        "status": { "loadBalancer": {} },
+    });
+    sanitize_for_create(&mut svc);
+    assert_eq!(svc, json!({
+        "apiVersion": "v1", "kind": "Service",
+        "metadata": { "name": "blog", "labels": { "app": "blog" } },
+        "spec": { "ports": [{ "port": 80, "nodePort": 30080 }] },
+    }));
+
+    let mut headless = json!({ "kind": "Service", "metadata": {}, "spec": { "clusterIP": "None" } });
+    sanitize_for_create(&mut headless);
+    assert_eq!(headless["spec"]["clusterIP"], "None");
+
+    let mut job = json!({
+        "kind": "Job", "metadata": { "name": "migrate" },
+        "spec": {
+            "selector": { "matchLabels": { "controller-uid": "abc" } },
+            "template": { "metadata": { "labels": { "controller-uid": "abc", "job-name": "migrate" } } },
+        },
+    });
+    sanitize_for_create(&mut job);
+    assert!(job["spec"].get("selector").is_none());
+    assert_eq!(job["spec"]["template"]["metadata"]["labels"], json!({ "job-name": "migrate" }));
+}
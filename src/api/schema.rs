@@ -0,0 +1,384 @@
+//! Validating objects against OpenAPI schemas before sending them
+use std::collections::BTreeMap;
+use std::fmt;
+
+use failure::ResultExt;
+use serde_json::Value;
+
+use crate::api::GroupVersionKind;
+use crate::client::APIClient;
+use crate::{ErrorKind, Result};
+
+/// A field of an object that does not match its schema
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldError {
+    /// Where it is, like `spec.template.spec.containers[0].ports[1].containerPort`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+/// The name a `$ref` points to, from `#/definitions/..` or `#/components/schemas/..`
+fn ref_name(r: &str) -> &str {
+    r.rsplit('/').next().unwrap_or(r)
+}
+
+fn type_of(v: &Value) -> &'static str {
+    match v {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn join(path: &str, field: &str) -> String {
+    if path.is_empty() { field.to_string() } else { format!("{}.{}", path, field) }
+}
+
+/// Validates objects against the schemas of their kinds, as the apiserver would
+///
+/// Schemas come from the apiserver's OpenAPI document for built-in kinds and from
+/// CustomResourceDefinitions for custom ones. Checks types, required and unknown
+/// fields, enums, bounds and lengths, and reports every mismatch with its path,
+/// where the apiserver answers a terse `422` for the first few. Patterns, formats
+/// other than integer sizes, and admission are left to the apiserver.
+///
+/// ```no_run
+/// use kube::{api::SchemaValidator, client::APIClient, config};
+///
+/// let config = config::load_kube_config().expect("failed to load kubeconfig");
+/// let validator = SchemaValidator::fetch(&APIClient::new(config)).unwrap();
+/// let deployment = serde_json::json!({
+///     "apiVersion": "apps/v1", "kind": "Deployment",
+///     "metadata": { "name": "web" }, "spec": { "replicas": "3" },
+/// });
+/// for e in validator.validate(&deployment).unwrap() {
+///     println!("{}", e);
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct SchemaValidator {
+    /// Schemas `$ref`s point to, by name
+    definitions: BTreeMap<String, Value>,
+    /// The schema of each kind
+    kinds: BTreeMap<GroupVersionKind, Value>,
+}
+
+impl SchemaValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// From an OpenAPI v2 document, like the apiserver serves at `/openapi/v2`
+    pub fn from_openapi_v2(doc: &Value) -> Self {
+        let mut validator = Self::new();
+        if let Some(definitions) = doc["definitions"].as_object() {
+            validator.add_definitions(definitions);
+        }
+        validator
+    }
+
+    /// From OpenAPI v3 documents, like the apiserver serves for each group version
+    pub fn from_openapi_v3<'a>(docs: impl IntoIterator<Item = &'a Value>) -> Self {
+        let mut validator = Self::new();
+        for doc in docs {
+            if let Some(schemas) = doc["components"]["schemas"].as_object() {
+                validator.add_definitions(schemas);
+            }
+        }
+        validator
+    }
+
+    /// Add named schemas, and the kinds of those marked with `x-kubernetes-group-version-kind`
+    fn add_definitions(&mut self, definitions: &serde_json::Map<String, Value>) {
+        for (name, schema) in definitions {
+            for gvk in schema["x-kubernetes-group-version-kind"].as_array().into_iter().flatten() {
+                if let (Some(g), Some(v), Some(k)) = (gvk["group"].as_str(), gvk["version"].as_str(), gvk["kind"].as_str()) {
+                    self.kinds.insert(GroupVersionKind::new(g, v, k), schema.clone());
+                }
+            }
+            self.definitions.insert(name.clone(), schema.clone());
+        }
+    }
+
+    /// Fetch the schemas of all built-in kinds from the apiserver
+    pub fn fetch(client: &APIClient) -> Result<Self> {
+        let req = http::Request::get("/openapi/v2").body(vec![]).context(ErrorKind::RequestBuild)?;
+        Ok(Self::from_openapi_v2(&client.request::<Value>(req)?))
+    }
+
+    /// Use `schema` for objects of a kind
+    pub fn add_schema(&mut self, gvk: GroupVersionKind, schema: Value) {
+        self.kinds.insert(gvk, schema);
+    }
+
+    /// Add the schemas of each version of a CustomResourceDefinition
+    ///
+    /// Takes `apiextensions.k8s.io/v1` and `v1beta1` CRDs, with schemas per version
+    /// or one for all.
+    pub fn add_crd(&mut self, crd: &Value) -> Result<()> {
+        let spec = &crd["spec"];
+        let (group, kind) = match (spec["group"].as_str(), spec["names"]["kind"].as_str()) {
+            (Some(g), Some(k)) => (g, k),
+            _ => return Err(ErrorKind::RequestValidation("CRD without group and kind".into()).into()),
+        };
+        let shared = &spec["validation"]["openAPIV3Schema"];
+        let mut versions: Vec<(&str, &Value)> = spec["versions"].as_array().into_iter().flatten()
+            .filter_map(|v| Some((v["name"].as_str()?, &v["schema"]["openAPIV3Schema"])))
+            .collect();
+        if let Some(v) = spec["version"].as_str() {
+            versions.push((v, &Value::Null));
+        }
+        for (version, schema) in versions {
+            let schema = if schema.is_null() { shared } else { schema };
+            if !schema.is_null() {
+                self.kinds.insert(GroupVersionKind::new(group, version, kind), schema.clone());
+            }
+        }
+        Ok(())
+    }
+
+    /// The schema of a kind, if known
+    pub fn schema_for(&self, gvk: &GroupVersionKind) -> Option<&Value> {
+        self.kinds.get(gvk)
+    }
+
+    /// A schema with its `$ref`, if any, followed
+    pub(crate) fn resolve<'a>(&'a self, schema: &'a Value) -> &'a Value {
+        let mut schema = schema;
+        // v3 documents wrap references to allow descriptions next to them
+        if let Some([only]) = schema["allOf"].as_array().map(Vec::as_slice) {
+            if only["$ref"].is_string() {
+                schema = only;
+            }
+        }
+        match schema["$ref"].as_str().and_then(|r| self.definitions.get(ref_name(r))) {
+            Some(target) => target,
+            None => schema,
+        }
+    }
+
+    /// The fields of an object that do not match the schema of its kind
+    ///
+    /// Objects of kinds without a schema have no errors.
+    pub fn validate(&self, object: &Value) -> Result<Vec<FieldError>> {
+        let gvk = GroupVersionKind::from_value(object)?;
+        let mut errors = vec![];
+        match self.kinds.get(&gvk) {
+            Some(schema) => self.check_value(schema, object, "", false, &mut errors),
+            None => debug!("No schema for {}, not validating", gvk),
+        }
+        Ok(errors)
+    }
+
+    /// The fields of a merge patch to an object of a kind that do not match its schema
+    ///
+    /// Required fields may be missing, and `null` removes a field.
+    pub fn validate_patch(&self, gvk: &GroupVersionKind, patch: &Value) -> Vec<FieldError> {
+        let mut errors = vec![];
+        if let Some(schema) = self.kinds.get(gvk) {
+            self.check_value(schema, patch, "", true, &mut errors);
+        }
+        errors
+    }
+
+    /// Fail with all field errors of an object, for use before a create or replace
+    pub fn check(&self, object: &Value) -> Result<()> {
+        let errors = self.validate(object)?;
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let name = object["metadata"]["name"].as_str().unwrap_or("");
+        let listed = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ");
+        Err(ErrorKind::RequestValidation(format!("{} {} is invalid: {}", object["kind"].as_str().unwrap_or(""), name, listed)).into())
+    }
+
+    fn check_value(&self, schema: &Value, value: &Value, path: &str, patch: bool, errors: &mut Vec<FieldError>) {
+        let schema = self.resolve(schema);
+        let mut error = |message: String| errors.push(FieldError { path: path.to_string(), message });
+        if value.is_null() {
+            // dropped like an omitted field by the apiserver
+            return;
+        }
+        if schema["x-kubernetes-int-or-string"] == true {
+            if !(value.is_string() || value.is_i64() || value.is_u64()) {
+                error(format!("expected integer or string, got {}", type_of(value)));
+            }
+            return;
+        }
+        for s in schema["allOf"].as_array().into_iter().flatten() {
+            self.check_value(s, value, path, patch, errors);
+        }
+        let mut error = |message: String| errors.push(FieldError { path: path.to_string(), message });
+        let actual = type_of(value);
+        match schema["type"].as_str() {
+            Some("number") if actual == "integer" => {},
+            Some(expected) if expected != actual => {
+                return error(format!("expected {}, got {}", expected, actual));
+            },
+            _ => {},
+        }
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                let listed = allowed.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ");
+                error(format!("unsupported value {}, expected one of {}", value, listed));
+            }
+        }
+        if let Some(n) = value.as_f64() {
+            if schema["format"] == "int32" && (n < f64::from(i32::MIN) || n > f64::from(i32::MAX)) {
+                error(format!("{} does not fit in int32", value));
+            }
+            if let Some(min) = schema["minimum"].as_f64().filter(|m| n < *m) {
+                error(format!("must be greater than or equal to {}", min));
+            }
+            if let Some(max) = schema["maximum"].as_f64().filter(|m| n > *m) {
+                error(format!("must be less than or equal to {}", max));
+            }
+        }
+        if let Some(s) = value.as_str() {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema["minLength"].as_u64().filter(|m| len < *m) {
+                error(format!("must be at least {} characters long", min));
+            }
+            if let Some(max) = schema["maxLength"].as_u64().filter(|m| len > *m) {
+                error(format!("must be at most {} characters long", max));
+            }
+        }
+        if let Some(items) = value.as_array() {
+            let len = items.len() as u64;
+            if let Some(min) = schema["minItems"].as_u64().filter(|m| len < *m) {
+                error(format!("must have at least {} items", min));
+            }
+            if let Some(max) = schema["maxItems"].as_u64().filter(|m| len > *m) {
+                error(format!("must have at most {} items", max));
+            }
+            if schema["items"].is_object() {
+                for (i, item) in items.iter().enumerate() {
+                    self.check_value(&schema["items"], item, &format!("{}[{}]", path, i), patch, errors);
+                }
+            }
+        }
+        if let Some(fields) = value.as_object() {
+            self.check_fields(schema, fields, path, patch, errors);
+        }
+    }
+
+    fn check_fields(&self, schema: &Value, fields: &serde_json::Map<String, Value>, path: &str, patch: bool,
+                    errors: &mut Vec<FieldError>) {
+        let properties = schema["properties"].as_object();
+        if !patch {
+            for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                if fields.get(required).is_none_or(Value::is_null) {
+                    errors.push(FieldError { path: join(path, required), message: "required field is missing".into() });
+                }
+            }
+        }
+        let preserve = schema["x-kubernetes-preserve-unknown-fields"] == true;
+        for (name, value) in fields {
+            let field_path = join(path, name);
+            match properties.and_then(|p| p.get(name)) {
+                Some(s) => self.check_value(s, value, &field_path, patch, errors),
+                None => match &schema["additionalProperties"] {
+                    s @ Value::Object(_) => self.check_value(s, value, &field_path, patch, errors),
+                    Value::Bool(true) => {},
+                    // crd schemas need not declare the fields every object has
+                    _ if path.is_empty() && ["apiVersion", "kind", "metadata"].contains(&name.as_str()) => {},
+                    _ if properties.is_some() && !preserve => {
+                        errors.push(FieldError { path: field_path, message: "unknown field".into() });
+                    },
+                    _ => {},
+                },
+            }
+        }
+    }
+}
+
+#[test]
+fn objects_are_validated_against_schemas() {
+    use serde_json::json;
+    let doc = json!({ "definitions": {
+        "io.k8s.api.apps.v1.Deployment": {
+            "x-kubernetes-group-version-kind": [{ "group": "apps", "version": "v1", "kind": "Deployment" }],
+            "properties": {
+                "apiVersion": { "type": "string" }, "kind": { "type": "string" },
+                "metadata": { "$ref": "#/definitions/io.k8s.apimachinery.pkg.apis.meta.v1.ObjectMeta" },
+                "spec": { "$ref": "#/definitions/io.k8s.api.apps.v1.DeploymentSpec" },
+            },
+        },
+        "io.k8s.apimachinery.pkg.apis.meta.v1.ObjectMeta": {
+            "properties": { "name": { "type": "string" }, "labels": { "type": "object", "additionalProperties": { "type": "string" } } },
+        },
+        "io.k8s.api.apps.v1.DeploymentSpec": {
+            "required": ["selector", "template"],
+            "properties": {
+                "replicas": { "type": "integer", "format": "int32" },
+                "selector": { "type": "object" },
+                "template": { "properties": { "spec": { "properties": { "containers": { "type": "array", "items": {
+                    "required": ["name"],
+                    "properties": {
+                        "name": { "type": "string" },
+                        "ports": { "type": "array", "items": { "properties": {
+                            "containerPort": { "type": "integer", "format": "int32" },
+                            "protocol": { "type": "string", "enum": ["TCP", "UDP", "SCTP"] },
+                        } } },
+                    },
+                } } } } } },
+                "strategy": { "properties": { "rollingUpdate": { "properties": { "maxSurge": { "x-kubernetes-int-or-string": true } } } } },
+            },
+        },
+    }});
+    let mut validator = SchemaValidator::from_openapi_v2(&doc);
+    let deployment = json!({
+        "apiVersion": "apps/v1", "kind": "Deployment",
+        "metadata": { "name": "web", "labels": { "app": 1 } },
+        "spec": {
+            "replicas": "3",
+            "template": { "spec": { "containers": [{ "ports": [{ "containerPort": 80, "protocol": "HTTP" }], "imag": "nginx" }] } },
+            "strategy": { "rollingUpdate": { "maxSurge": "25%" } },
+        },
+    });
+    let errors: Vec<String> = validator.validate(&deployment).unwrap().iter().map(|e| e.to_string()).collect();
+    assert_eq!(errors, vec![
+        "metadata.labels.app: expected string, got integer",
+        "spec.selector: required field is missing",
+        "spec.replicas: expected integer, got string",
+        "spec.template.spec.containers[0].name: required field is missing",
+        "spec.template.spec.containers[0].imag: unknown field",
+        "spec.template.spec.containers[0].ports[0].protocol: unsupported value \"HTTP\", expected one of \"TCP\", \"UDP\", \"SCTP\"",
+    ]);
+    let err = validator.check(&deployment).unwrap_err();
+    assert!(err.to_string().contains("Deployment web is invalid: metadata.labels.app"), "{}", err);
+    let gvk = GroupVersionKind::new("apps", "v1", "Deployment");
+    assert!(validator.validate_patch(&gvk, &json!({ "spec": { "replicas": 2, "strategy": null } })).is_empty());
+    assert_eq!(validator.validate_patch(&gvk, &json!({ "spec": { "replicas": 1e10 } }))[0].message, "expected integer, got number");
+
+    validator.add_crd(&json!({
+        "apiVersion": "apiextensions.k8s.io/v1beta1", "kind": "CustomResourceDefinition",
+        "spec": {
+            "group": "example.com", "version": "v1", "names": { "kind": "Foo" },
+            "validation": { "openAPIV3Schema": { "type": "object", "properties": { "spec": { "type": "object", "properties": {
+                "size": { "type": "integer", "minimum": 1, "maximum": 10 },
+                "name": { "type": "string", "maxLength": 3 },
+                "extra": { "type": "object", "x-kubernetes-preserve-unknown-fields": true, "properties": {} },
+            } } } } },
+        },
+    })).unwrap();
+    let foo = json!({ "apiVersion": "example.com/v1", "kind": "Foo", "metadata": { "name": "a" },
+        "spec": { "size": 11, "name": "abcd", "extra": { "anything": true } } });
+    let errors: Vec<String> = validator.validate(&foo).unwrap().iter().map(|e| e.to_string()).collect();
+    assert_eq!(errors, vec!["spec.name: must be at most 3 characters long", "spec.size: must be less than or equal to 10"]);
+    assert!(validator.validate(&json!({ "apiVersion": "v1", "kind": "ConfigMap", "data": 1 })).unwrap().is_empty());
+}
@@ -5,9 +5,10 @@ use std::collections::BTreeMap;
 use core::marker::PhantomData;
 use crate::client::APIClient;
 use crate::api::{
-    RawApi, Api, KubeObject,
-    ObjectMeta
+    RawApi, Api, KubeObject, Resource, NamespaceResourceScope, ClusterResourceScope,
+    ObjectMeta, TypeMeta, ListParams, ObjectList,
 };
+use crate::Result;
 
 
 use k8s_openapi::apimachinery::pkg::apis::meta::v1::{MicroTime, Time};
@@ -61,6 +62,14 @@ impl KubeObject for v1Event {
     fn meta(&self) -> &ObjectMeta { &self.metadata }
 }
 
+impl Resource for v1Event {
+    const GROUP: &'static str = "";
+    const VERSION: &'static str = "v1";
+    const KIND: &'static str = "Event";
+    const PLURAL: &'static str = "events";
+    type Scope = NamespaceResourceScope;
+}
+
 impl Api<v1Event> {
     pub fn v1Event(client: APIClient) -> Self {
         Api {
@@ -71,12 +80,87 @@ impl Api<v1Event> {
     }
 }
 
+impl v1Event {
+    /// ListParams selecting the events about an object
+    ///
+    /// Matches on uid when the object has one, and on kind/name/namespace otherwise.
+    /// Also usable for `watch` calls or an `Informer` to follow new events.
+    pub fn list_params_for<K: KubeObject>(obj: &K) -> ListParams {
+        let meta = obj.meta();
+        let mut fields = vec![format!("involvedObject.name={}", meta.name)];
+        if let Some(ns) = &meta.namespace {
+            fields.push(format!("involvedObject.namespace={}", ns));
+        }
+        match (&meta.uid, obj.types().and_then(|t| t.kind.as_ref())) {
+            (Some(uid), _) => fields.push(format!("involvedObject.uid={}", uid)),
+            (None, Some(kind)) => fields.push(format!("involvedObject.kind={}", kind)),
+            (None, None) => {},
+        }
+        ListParams {
+            field_selector: Some(fields.join(",")),
+            ..Default::default()
+        }
+    }
+
+    /// The most recent time this event was seen
+    fn last_seen(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.lastTimestamp.as_ref().map(|t| t.0)
+            .or_else(|| self.eventTime.as_ref().map(|t| t.0))
+            .or_else(|| self.firstTimestamp.as_ref().map(|t| t.0))
+    }
+}
+
+/// Sort events oldest first and merge repeats of the same event
+///
+/// Events are repeats when type, reason, message and source component match.
+/// Their counts are summed and the latest timestamp is kept.
+fn summarize_events(events: Vec<v1Event>) -> Vec<v1Event> {
+    let mut res: Vec<v1Event> = vec![];
+    for e in events {
+        let component = |e: &v1Event| e.source.as_ref().and_then(|s| s.component.clone());
+        let dup = res.iter_mut().find(|x| {
+            x.type_ == e.type_ && x.reason == e.reason && x.message == e.message && component(x) == component(&e)
+        });
+        match dup {
+            Some(existing) => {
+                existing.count += std::cmp::max(e.count, 1);
+                if e.last_seen() > existing.last_seen() {
+                    existing.lastTimestamp = e.lastTimestamp;
+                    existing.eventTime = e.eventTime;
+                }
+            },
+            None => res.push(e),
+        }
+    }
+    res.sort_by_key(|e| e.last_seen());
+    res
+}
+
+impl Api<v1Event> {
+    /// Recent events about an object, like the events section of `kubectl describe`
+    ///
+    /// Events are looked up in the object's namespace unless this Api is already scoped.
+    /// The result is sorted oldest first with repeated events merged.
+    pub fn for_object<K: KubeObject>(&self, obj: &K) -> Result<Vec<v1Event>> {
+        let mut api = self.api.clone();
+        if api.namespace.is_none() {
+            api.namespace = obj.meta().namespace.clone();
+        }
+        let req = api.list(&v1Event::list_params_for(obj))?;
+        let events = self.client.request::<ObjectList<v1Event>>(req)?;
+        Ok(summarize_events(events.items))
+    }
+}
+
 use k8s_openapi::ByteString;
 /// Secret object
 ///
 /// https://kubernetes.io/docs/reference/federation/v1/definitions/#_v1_secret
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Default)]
 pub struct v1Secret {
+    #[serde(flatten)]
+    pub types: TypeMeta,
+
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
     pub data: BTreeMap<String, ByteString>,
 
@@ -93,6 +177,15 @@ pub struct v1Secret {
 // Special case implementation so we can make Informer<v1Secret> etc.
 impl KubeObject for v1Secret {
     fn meta(&self) -> &ObjectMeta { &self.metadata }
+    fn types(&self) -> Option<&TypeMeta> { Some(&self.types) }
+}
+
+impl Resource for v1Secret {
+    const GROUP: &'static str = "";
+    const VERSION: &'static str = "v1";
+    const KIND: &'static str = "Secret";
+    const PLURAL: &'static str = "secrets";
+    type Scope = NamespaceResourceScope;
 }
 
 impl Api<v1Secret> {
@@ -105,9 +198,91 @@ impl Api<v1Secret> {
     }
 }
 
+/// Constructors and accessors that do the base64 dance for you
+///
+/// Values in `data` are raw bytes; `ByteString` handles the base64 encoding on the wire.
+impl v1Secret {
+    /// An empty secret of a given type
+    pub fn new(name: &str, type_: &str) -> Self {
+        v1Secret {
+            types: TypeMeta {
+                apiVersion: Some("v1".into()),
+                kind: Some("Secret".into()),
+            },
+            metadata: ObjectMeta { name: name.into(), ..Default::default() },
+            type_: Some(type_.into()),
+            ..Default::default()
+        }
+    }
+
+    /// An empty `Opaque` secret
+    pub fn opaque(name: &str) -> Self {
+        Self::new(name, "Opaque")
+    }
+
+    /// A `kubernetes.io/tls` secret from PEM encoded certificate and key
+    pub fn tls(name: &str, cert_pem: &[u8], key_pem: &[u8]) -> Self {
+        Self::new(name, "kubernetes.io/tls")
+            .with("tls.crt", cert_pem)
+            .with("tls.key", key_pem)
+    }
+
+    /// A `kubernetes.io/dockerconfigjson` secret for pulling from a single registry
+    pub fn docker_registry(name: &str, server: &str, username: &str, password: &str, email: Option<&str>) -> Self {
+        let auth = base64::encode(&format!("{}:{}", username, password));
+        let mut entry = serde_json::json!({
+            "username": username,
+            "password": password,
+            "auth": auth,
+        });
+        if let Some(email) = email {
+            entry["email"] = email.into();
+        }
+        let config = serde_json::json!({ "auths": { server: entry } });
+        Self::new(name, "kubernetes.io/dockerconfigjson")
+            .with(".dockerconfigjson", config.to_string().as_bytes())
+    }
+
+    /// Builder style `insert`
+    pub fn with(mut self, key: &str, value: &[u8]) -> Self {
+        self.insert(key, value.to_vec());
+        self
+    }
+
+    /// Set a key to raw bytes
+    pub fn insert(&mut self, key: &str, value: Vec<u8>) {
+        self.stringData.remove(key);
+        self.data.insert(key.into(), ByteString(value));
+    }
+
+    /// Set a key to a string value via `stringData`
+    ///
+    /// The apiserver merges `stringData` into `data` on write.
+    pub fn insert_string(&mut self, key: &str, value: &str) {
+        self.data.remove(key);
+        self.stringData.insert(key.into(), value.into());
+    }
+
+    /// Read a key as raw bytes, looking at both `data` and unsubmitted `stringData`
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.stringData.get(key).map(|s| s.as_bytes().to_vec())
+            .or_else(|| self.data.get(key).map(|b| b.0.clone()))
+    }
+
+    /// Read a key as a utf8 string
+    ///
+    /// Returns `None` if the key is missing or the data is not valid utf8.
+    pub fn get_string(&self, key: &str) -> Option<String> {
+        self.get(key).and_then(|b| String::from_utf8(b).ok())
+    }
+}
+
 /// ConfigMap object
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, Default)]
 pub struct v1ConfigMap {
+    #[serde(flatten)]
+    pub types: TypeMeta,
+
     pub metadata: ObjectMeta,
 
     #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
@@ -119,6 +294,15 @@ pub struct v1ConfigMap {
 
 impl KubeObject for v1ConfigMap {
     fn meta(&self) -> &ObjectMeta { &self.metadata }
+    fn types(&self) -> Option<&TypeMeta> { Some(&self.types) }
+}
+
+impl Resource for v1ConfigMap {
+    const GROUP: &'static str = "";
+    const VERSION: &'static str = "v1";
+    const KIND: &'static str = "ConfigMap";
+    const PLURAL: &'static str = "configmaps";
+    type Scope = NamespaceResourceScope;
 }
 
 impl Api<v1ConfigMap> {
@@ -131,6 +315,97 @@ impl Api<v1ConfigMap> {
     }
 }
 
+impl v1ConfigMap {
+    /// An empty config map
+    pub fn new(name: &str) -> Self {
+        v1ConfigMap {
+            types: TypeMeta {
+                apiVersion: Some("v1".into()),
+                kind: Some("ConfigMap".into()),
+            },
+            metadata: ObjectMeta { name: name.into(), ..Default::default() },
+            ..Default::default()
+        }
+    }
+
+    /// Builder style `insert`
+    pub fn with(mut self, key: &str, value: &str) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    /// Set a utf8 value in `data`
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.binaryData.remove(key);
+        self.data.insert(key.into(), value.into());
+    }
+
+    /// Set a binary value in `binaryData`
+    pub fn insert_binary(&mut self, key: &str, value: Vec<u8>) {
+        self.data.remove(key);
+        self.binaryData.insert(key.into(), ByteString(value));
+    }
+
+    /// Read a utf8 value from `data`
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.data.get(key).map(String::as_str)
+    }
+
+    /// Read a value as bytes from either `binaryData` or `data`
+    pub fn get_binary(&self, key: &str) -> Option<Vec<u8>> {
+        self.binaryData.get(key).map(|b| b.0.clone())
+            .or_else(|| self.data.get(key).map(|s| s.as_bytes().to_vec()))
+    }
+}
+
+use k8s_openapi::api::core::v1::TopologySelectorTerm;
+
+/// StorageClass object
+#[derive(Deserialize, Serialize, Clone, Default)]
+pub struct v1StorageClass {
+    #[serde(flatten)]
+    pub types: TypeMeta,
+
+    pub metadata: ObjectMeta,
+
+    /// The volume plugin or CSI driver, e.g. `ebs.csi.aws.com`
+    pub provisioner: String,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub parameters: BTreeMap<String, String>,
+
+    /// `Delete` or `Retain`, defaults to `Delete`
+    pub reclaimPolicy: Option<String>,
+    /// `Immediate` or `WaitForFirstConsumer`, defaults to `Immediate`
+    pub volumeBindingMode: Option<String>,
+    pub allowVolumeExpansion: Option<bool>,
+    pub mountOptions: Option<Vec<String>>,
+    pub allowedTopologies: Option<Vec<TopologySelectorTerm>>,
+}
+
+impl KubeObject for v1StorageClass {
+    fn meta(&self) -> &ObjectMeta { &self.metadata }
+    fn types(&self) -> Option<&TypeMeta> { Some(&self.types) }
+}
+
+impl Resource for v1StorageClass {
+    const GROUP: &'static str = "storage.k8s.io";
+    const VERSION: &'static str = "v1";
+    const KIND: &'static str = "StorageClass";
+    const PLURAL: &'static str = "storageclasses";
+    type Scope = ClusterResourceScope;
+}
+
+impl Api<v1StorageClass> {
+    pub fn v1StorageClass(client: APIClient) -> Self {
+        Api {
+            api: RawApi::v1StorageClass(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
 use k8s_openapi::api::admissionregistration::v1beta1::ValidatingWebhook;
 
 /// ValidatingWebhookConfiguration object
@@ -145,6 +420,14 @@ impl KubeObject for v1beta1ValidatingWebhookConfiguration {
     fn meta(&self) -> &ObjectMeta { &self.metadata }
 }
 
+impl Resource for v1beta1ValidatingWebhookConfiguration {
+    const GROUP: &'static str = "admissionregistration.k8s.io";
+    const VERSION: &'static str = "v1beta1";
+    const KIND: &'static str = "ValidatingWebhookConfiguration";
+    const PLURAL: &'static str = "validatingwebhookconfigurations";
+    type Scope = ClusterResourceScope;
+}
+
 impl Api<v1beta1ValidatingWebhookConfiguration> {
     pub fn v1beta1ValidatingWebhookConfiguration(client: APIClient) -> Self {
         Api {
@@ -154,3 +437,54 @@ impl Api<v1beta1ValidatingWebhookConfiguration> {
         }
     }
 }
+
+#[test]
+fn secret_data_roundtrips_through_base64() {
+    let s = v1Secret::opaque("creds").with("password", b"hunter2");
+    let json = serde_json::to_value(&s).unwrap();
+    assert_eq!(json["data"]["password"], base64::encode("hunter2"));
+    assert_eq!(json["kind"], "Secret");
+    let parsed: v1Secret = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed.get_string("password").unwrap(), "hunter2");
+}
+
+#[test]
+fn docker_registry_secret() {
+    let s = v1Secret::docker_registry("regcred", "quay.io", "clux", "pw", None);
+    assert_eq!(s.type_.as_deref(), Some("kubernetes.io/dockerconfigjson"));
+    let cfg: serde_json::Value = serde_json::from_slice(&s.get(".dockerconfigjson").unwrap()).unwrap();
+    assert_eq!(cfg["auths"]["quay.io"]["auth"], base64::encode("clux:pw"));
+}
+
+#[test]
+fn events_are_sorted_and_merged() {
+    let ev = |reason: &str, count: i32, last: &str| -> v1Event {
+        serde_json::from_value(serde_json::json!({
+            "metadata": { "name": format!("blog.{}", last) },
+            "involvedObject": { "kind": "Pod", "name": "blog" },
+            "reason": reason,
+            "message": "msg",
+            "type": "Normal",
+            "count": count,
+            "lastTimestamp": last,
+        })).unwrap()
+    };
+    let events = summarize_events(vec![
+        ev("Pulled", 1, "2019-08-01T10:00:00Z"),
+        ev("Scheduled", 1, "2019-08-01T09:00:00Z"),
+        ev("Pulled", 2, "2019-08-01T11:00:00Z"),
+    ]);
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].reason, "Scheduled");
+    assert_eq!(events[1].count, 3);
+    assert_eq!(events[1].lastTimestamp.as_ref().unwrap().0.to_rfc3339(), "2019-08-01T11:00:00+00:00");
+
+    let pod = crate::api::Object::<crate::api::Void, crate::api::Void> {
+        types: TypeMeta { kind: Some("Pod".into()), apiVersion: Some("v1".into()) },
+        metadata: ObjectMeta { name: "blog".into(), namespace: Some("ns".into()), ..Default::default() },
+        spec: crate::api::Void {},
+        status: None,
+    };
+    let lp = v1Event::list_params_for(&pod);
+    assert_eq!(lp.field_selector.unwrap(), "involvedObject.name=blog,involvedObject.namespace=ns,involvedObject.kind=Pod");
+}
@@ -0,0 +1,314 @@
+//! PersistentVolumeClaim, PersistentVolume, StorageClass and VolumeSnapshot lifecycle helpers
+#![allow(non_snake_case)]
+use std::time::Duration;
+use serde_json::json;
+
+use crate::api::{Api, Object, ListParams, PatchParams, DeleteParams, Conditions, Quantity, v1StorageClass};
+use crate::api::wait::{poll_until, poll_until_explained};
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
+
+use k8s_openapi::api::core::v1::{
+    ObjectReference,
+    PersistentVolumeClaimSpec, PersistentVolumeClaimStatus,
+    PersistentVolumeSpec, PersistentVolumeStatus,
+};
+
+type PersistentVolumeClaim = Object<PersistentVolumeClaimSpec, PersistentVolumeClaimStatus>;
+type PersistentVolume = Object<PersistentVolumeSpec, PersistentVolumeStatus>;
+type VolumeSnapshot = Object<VolumeSnapshotSpec, VolumeSnapshotStatus>;
+
+/// The annotation marking the StorageClass claims get when they name none
+pub const DEFAULT_STORAGE_CLASS_ANNOTATION: &str = "storageclass.kubernetes.io/is-default-class";
+
+/// What a VolumeSnapshot is taken from; exactly one is set
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct VolumeSnapshotSource {
+    /// Snapshot this claim in the namespace of the snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persistentVolumeClaimName: Option<String>,
+    /// Import this pre-existing content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volumeSnapshotContentName: Option<String>,
+}
+
+/// Spec of a CSI VolumeSnapshot
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct VolumeSnapshotSpec {
+    pub source: VolumeSnapshotSource,
+    /// Defaults to the VolumeSnapshotClass marked as default for the driver
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volumeSnapshotClassName: Option<String>,
+}
+
+/// The last error taking or binding a snapshot, which may be retried
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct VolumeSnapshotError {
+    pub message: Option<String>,
+    pub time: Option<String>,
+}
+
+/// Status of a CSI VolumeSnapshot
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct VolumeSnapshotStatus {
+    pub boundVolumeSnapshotContentName: Option<String>,
+    pub creationTime: Option<String>,
+    /// Whether a volume can be restored from it
+    pub readyToUse: Option<bool>,
+    /// The minimum size of a volume restored from it, e.g. `10Gi`
+    pub restoreSize: Option<String>,
+    pub error: Option<VolumeSnapshotError>,
+}
+
+/// What a VolumeSnapshotContent represents; exactly one is set
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct VolumeSnapshotContentSource {
+    /// The CSI volume to snapshot, for dynamically provisioned snapshots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volumeHandle: Option<String>,
+    /// The CSI snapshot, for pre-existing snapshots
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshotHandle: Option<String>,
+}
+
+/// Spec of a CSI VolumeSnapshotContent, the snapshot on the storage backend
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct VolumeSnapshotContentSpec {
+    pub volumeSnapshotRef: ObjectReference,
+    /// `Delete` or `Retain` the backend snapshot when the content is deleted
+    pub deletionPolicy: String,
+    pub driver: String,
+    pub source: VolumeSnapshotContentSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volumeSnapshotClassName: Option<String>,
+}
+
+/// Status of a CSI VolumeSnapshotContent
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct VolumeSnapshotContentStatus {
+    pub snapshotHandle: Option<String>,
+    /// Nanoseconds since the epoch
+    pub creationTime: Option<i64>,
+    pub readyToUse: Option<bool>,
+    /// Bytes
+    pub restoreSize: Option<i64>,
+    pub error: Option<VolumeSnapshotError>,
+}
+
+/// Where a volume expansion is at
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResizeStatus {
+    /// The volume is being expanded by the storage provider
+    InProgress,
+    /// The volume is expanded, but the filesystem only grows once a pod (re)mounts it
+    FileSystemResizePending,
+    /// The capacity matches the requested size
+    Complete,
+}
+
+impl PersistentVolumeClaim {
+    /// The phase, e.g. `Pending` or `Bound`
+    pub fn phase(&self) -> Option<&str> {
+        self.status.as_ref().and_then(|s| s.phase.as_deref())
+    }
+
+    /// Whether the claim is bound to a volume
+    pub fn is_bound(&self) -> bool {
+        self.phase() == Some("Bound")
+    }
+
+    /// The requested storage size
+    pub fn requested_storage(&self) -> Option<&str> {
+        self.spec.resources.as_ref()
+            .and_then(|r| r.requests.as_ref())
+            .and_then(|r| r.get("storage"))
+            .map(|q| q.0.as_str())
+    }
+
+    /// The storage size of the bound volume
+    pub fn capacity(&self) -> Option<&str> {
+        self.status.as_ref()
+            .and_then(|s| s.capacity.as_ref())
+            .and_then(|c| c.get("storage"))
+            .map(|q| q.0.as_str())
+    }
+
+    /// Whether the volume is at least as large as requested, e.g. `10240Mi` for `10Gi`
+    fn capacity_satisfies_request(&self) -> bool {
+        match (self.capacity(), self.requested_storage()) {
+            (Some(cap), Some(req)) => match (cap.parse::<Quantity>(), req.parse::<Quantity>()) {
+                (Ok(cap), Ok(req)) => cap >= req,
+                _ => cap == req,
+            },
+            (cap, req) => cap == req,
+        }
+    }
+
+    /// The progress of an expansion towards the requested size
+    pub fn resize_status(&self) -> ResizeStatus {
+        let conds = self.status.as_ref().and_then(|s| s.conditions.clone()).unwrap_or_default();
+        if conds.is_condition_true("FileSystemResizePending") {
+            ResizeStatus::FileSystemResizePending
+        } else if conds.is_condition_true("Resizing") || !self.capacity_satisfies_request() {
+            ResizeStatus::InProgress
+        } else {
+            ResizeStatus::Complete
+        }
+    }
+}
+
+impl Api<PersistentVolumeClaim> {
+    /// Block until the claim is `Bound`, or fail after `timeout`
+    ///
+    /// Claims using a `WaitForFirstConsumer` storage class only bind once a pod uses them.
+    pub fn wait_for_bound(&self, name: &str, timeout: Duration) -> Result<PersistentVolumeClaim> {
+        poll_until(&format!("pvc {} to be bound", name), timeout, || {
+            let pvc = self.get(name)?;
+            Ok(if pvc.is_bound() { Some(pvc) } else { None })
+        })
+    }
+
+    /// Request expansion of the claim to a new size, e.g. `"20Gi"`
+    ///
+    /// The storage class must have `allowVolumeExpansion` set.
+    pub fn resize(&self, name: &str, size: &str) -> Result<PersistentVolumeClaim> {
+        let patch = json!({
+            "spec": { "resources": { "requests": { "storage": size } } }
+        });
+        let data = serde_json::to_vec(&patch).context(ErrorKind::SerdeParse)?;
+        self.patch(name, &PatchParams::default(), data)
+    }
+
+    /// Block until an expansion is complete or only waits on a filesystem resize
+    ///
+    /// Returns the final status; `FileSystemResizePending` means a pod must mount the volume.
+    pub fn wait_for_resize(&self, name: &str, timeout: Duration) -> Result<ResizeStatus> {
+        poll_until(&format!("pvc {} to be resized", name), timeout, || {
+            Ok(match self.get(name)?.resize_status() {
+                ResizeStatus::InProgress => None,
+                done => Some(done),
+            })
+        })
+    }
+}
+
+impl Api<PersistentVolume> {
+    /// Delete volumes in the `Released` phase, returning their names
+    ///
+    /// Released volumes have lost their claim but are kept around by a `Retain`
+    /// reclaim policy. Only do this once the data on them is no longer needed.
+    pub fn delete_released(&self, dp: &DeleteParams) -> Result<Vec<String>> {
+        let mut deleted = vec![];
+        for pv in self.list(&ListParams::default())?.items {
+            if pv.status.as_ref().and_then(|s| s.phase.as_deref()) != Some("Released") {
+                continue;
+            }
+            debug!("Deleting released pv {}", pv.metadata.name);
+            match self.delete(&pv.metadata.name, dp) {
+                Err(ref e) if e.status_code() == Some(404) => {},
+                Err(e) => return Err(e),
+                Ok(_) => deleted.push(pv.metadata.name),
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+impl Api<v1StorageClass> {
+    /// The class claims without a `storageClassName` get, if any
+    ///
+    /// Only one should be marked as default; if several are, the first listed is returned.
+    pub fn default_class(&self) -> Result<Option<v1StorageClass>> {
+        Ok(self.list(&ListParams::default())?.items.into_iter()
+            .find(|sc| sc.metadata.annotations.get(DEFAULT_STORAGE_CLASS_ANNOTATION).map(String::as_str) == Some("true")))
+    }
+}
+
+impl VolumeSnapshot {
+    /// A snapshot of a claim, taken with the default or given VolumeSnapshotClass
+    pub fn of_claim(name: &str, claim: &str, class: Option<&str>) -> Self {
+        Object {
+            types: crate::api::TypeMeta {
+                apiVersion: Some("snapshot.storage.k8s.io/v1".into()),
+                kind: Some("VolumeSnapshot".into()),
+            },
+            metadata: crate::api::ObjectMeta { name: name.into(), ..Default::default() },
+            spec: VolumeSnapshotSpec {
+                source: VolumeSnapshotSource { persistentVolumeClaimName: Some(claim.into()), ..Default::default() },
+                volumeSnapshotClassName: class.map(String::from),
+            },
+            status: None,
+        }
+    }
+
+    /// Whether volumes can be restored from the snapshot
+    pub fn is_ready(&self) -> bool {
+        self.status.as_ref().and_then(|s| s.readyToUse) == Some(true)
+    }
+
+    /// The last error taking the snapshot, if it has not succeeded since
+    pub fn error_message(&self) -> Option<&str> {
+        self.status.as_ref().and_then(|s| s.error.as_ref()).and_then(|e| e.message.as_deref())
+    }
+}
+
+impl Api<VolumeSnapshot> {
+    /// Block until the snapshot is ready to use, or fail after `timeout`
+    ///
+    /// Errors taking the snapshot are retried by the snapshot controller, so they
+    /// only end the wait at the timeout, whose error includes the last one.
+    pub fn wait_for_snapshot_ready(&self, name: &str, timeout: Duration) -> Result<VolumeSnapshot> {
+        poll_until_explained(&format!("volumesnapshot {} to be ready", name), timeout, |last_error| {
+            let snapshot = self.get(name)?;
+            if snapshot.is_ready() {
+                return Ok(Some(snapshot));
+            }
+            *last_error = snapshot.error_message().map(String::from);
+            Ok(None)
+        })
+    }
+}
+
+#[test]
+fn pvc_resize_status() {
+    let pvc = |status: serde_json::Value| -> PersistentVolumeClaim {
+        serde_json::from_value(json!({
+            "metadata": { "name": "data" },
+            "spec": { "resources": { "requests": { "storage": "20Gi" } } },
+            "status": status,
+        })).unwrap()
+    };
+    let p = pvc(json!({ "phase": "Bound", "capacity": { "storage": "10Gi" } }));
+    assert!(p.is_bound());
+    assert_eq!(p.resize_status(), ResizeStatus::InProgress);
+    let p = pvc(json!({
+        "phase": "Bound",
+        "capacity": { "storage": "10Gi" },
+        "conditions": [{ "type": "FileSystemResizePending", "status": "True" }],
+    }));
+    assert_eq!(p.resize_status(), ResizeStatus::FileSystemResizePending);
+    let p = pvc(json!({ "phase": "Bound", "capacity": { "storage": "20Gi" } }));
+    assert_eq!(p.resize_status(), ResizeStatus::Complete);
+}
+
+#[test]
+fn snapshots_report_readiness_and_errors() {
+    let snapshot = VolumeSnapshot::of_claim("data-1", "data", Some("csi-snapclass"));
+    let json = serde_json::to_value(&snapshot).unwrap();
+    assert_eq!(json["spec"], json!({
+        "source": { "persistentVolumeClaimName": "data" }, "volumeSnapshotClassName": "csi-snapclass",
+    }));
+    assert!(!snapshot.is_ready());
+    let failed: VolumeSnapshot = serde_json::from_value(json!({
+        "metadata": { "name": "data-1" },
+        "spec": { "source": { "persistentVolumeClaimName": "data" } },
+        "status": { "readyToUse": false, "error": { "message": "quota exceeded", "time": "2019-08-01T10:00:00Z" } },
+    })).unwrap();
+    assert_eq!(failed.error_message(), Some("quota exceeded"));
+    let ready: VolumeSnapshot = serde_json::from_value(json!({
+        "metadata": { "name": "data-1" },
+        "spec": { "source": { "persistentVolumeClaimName": "data" } },
+        "status": { "readyToUse": true, "restoreSize": "10Gi", "boundVolumeSnapshotContentName": "snapcontent-1" },
+    })).unwrap();
+    assert!(ready.is_ready());
+}
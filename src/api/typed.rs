@@ -2,24 +2,33 @@
 
 use either::{Either};
 use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::marker::PhantomData;
+use std::time::Duration;
 
 use crate::api::{
     RawApi,
+    GroupVersion,
+    GroupVersionResource,
     PostParams,
     DeleteParams,
     ListParams,
     PatchParams,
-    LogParams
+    LogParams,
+    PropagationPolicy,
 };
+use crate::api::output::Table;
+use crate::api::wait::poll_until;
+use crate::api::retry::{Backoff, retry_on_error};
 use crate::api::resource::{
-    ObjectList, Object, WatchEvent, KubeObject,
+    ObjectList, PartialList, Object, WatchEvent, KubeObject,
 };
 use crate::client::{
     APIClient,
     Status,
 };
-use crate::{Result};
+use crate::{Result, ErrorKind};
+use failure::ResultExt;
 
 /// A typed Api variant that does not expose request internals
 ///
@@ -52,12 +61,155 @@ pub trait Log: LogOperation {
     }
 }
 
+/// Marks a `Resource` as living in a namespace
+pub struct NamespaceResourceScope;
+
+/// Marks a `Resource` as cluster scoped, like nodes or namespaces themselves
+pub struct ClusterResourceScope;
+
+/// Where instances of a `Resource` live
+pub trait ResourceScope {
+    const NAMESPACED: bool;
+}
+
+impl ResourceScope for NamespaceResourceScope {
+    const NAMESPACED: bool = true;
+}
+
+impl ResourceScope for ClusterResourceScope {
+    const NAMESPACED: bool = false;
+}
+
+/// Types whose urls are known statically
+///
+/// Implemented for all the openapi backed types, and usable for your own custom resources.
+/// This powers the generic `Api::namespaced` and `Api::all` constructors, and
+/// `ResourceRequest`. As the scope is part of the type, binding a cluster scoped
+/// resource to a namespace does not compile:
+///
+/// ```compile_fail
+/// use kube::{api::{Api, Object, Void}, client::APIClient, config};
+/// use k8s_openapi::api::core::v1::{NodeSpec, NodeStatus};
+/// let client = APIClient::new(config::load_kube_config().unwrap());
+/// let nodes: Api<Object<NodeSpec, NodeStatus>> = Api::namespaced(client, "default");
+/// ```
+pub trait Resource {
+    /// API group, empty for the core group
+    const GROUP: &'static str;
+    const VERSION: &'static str;
+    const KIND: &'static str;
+    /// The plural name used in urls, e.g. `deployments`
+    const PLURAL: &'static str;
+    /// `NamespaceResourceScope` or `ClusterResourceScope`
+    type Scope: ResourceScope;
+
+    /// The `apiVersion` of its objects
+    fn api_version() -> String {
+        GroupVersion::new(Self::GROUP, Self::VERSION).api_version()
+    }
+
+    /// The request creator for this type, across all namespaces
+    fn raw_api() -> RawApi {
+        GroupVersionResource::new(Self::GROUP, Self::VERSION, Self::PLURAL).raw_api()
+    }
+}
+
+/// A `RawApi` built from a `Resource`, so its urls cannot be wrong
+///
+/// Derefs to `RawApi` for building the requests.
+///
+/// ```
+/// use kube::api::{ResourceRequest, ListParams, Resource, ClusterResourceScope};
+/// struct Foo;
+/// impl Resource for Foo {
+///     const GROUP: &'static str = "example.com";
+///     const VERSION: &'static str = "v1";
+///     const KIND: &'static str = "Foo";
+///     const PLURAL: &'static str = "foos";
+///     type Scope = ClusterResourceScope;
+/// }
+/// let req = ResourceRequest::<Foo>::all().list(&ListParams::default()).unwrap();
+/// assert_eq!(req.uri(), "/apis/example.com/v1/foos");
+/// ```
+pub struct ResourceRequest<K> {
+    api: RawApi,
+    phantom: PhantomData<K>,
+}
+
+impl<K: Resource> ResourceRequest<K> {
+    /// Requests across all namespaces, or for a cluster scoped resource
+    pub fn all() -> Self {
+        ResourceRequest { api: K::raw_api(), phantom: PhantomData }
+    }
+}
+
+impl<K: Resource<Scope = NamespaceResourceScope>> ResourceRequest<K> {
+    /// Requests within a namespace
+    pub fn namespaced(ns: &str) -> Self {
+        let mut api = K::raw_api();
+        api.namespace = Some(ns.to_string());
+        ResourceRequest { api, phantom: PhantomData }
+    }
+}
+
+impl<K> std::ops::Deref for ResourceRequest<K> {
+    type Target = RawApi;
+    fn deref(&self) -> &RawApi {
+        &self.api
+    }
+}
+
+/// Generic constructors for types implementing `Resource`
+impl<K> Api<K> where
+    K: Resource<Scope = NamespaceResourceScope>,
+{
+    /// An Api for a namespaced resource bound to a single namespace
+    ///
+    /// ```no_run
+    /// use kube::{api::{Api, Object}, client::APIClient, config};
+    /// use k8s_openapi::api::core::v1::{PodSpec, PodStatus};
+    /// let client = APIClient::new(config::load_kube_config().unwrap());
+    /// let pods: Api<Object<PodSpec, PodStatus>> = Api::namespaced(client, "default");
+    /// let blog = pods.get("blog");
+    /// ```
+    pub fn namespaced(client: APIClient, ns: &str) -> Self {
+        Api {
+            api: ResourceRequest::<K>::namespaced(ns).api,
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<K> Api<K> where
+    K: Resource,
+{
+    /// An Api across all namespaces, or for a cluster scoped resource
+    pub fn all(client: APIClient) -> Self {
+        Api {
+            api: K::raw_api(),
+            client,
+            phantom: PhantomData,
+        }
+    }
+}
+
 /// Expose same interface as Api for controlling scope/group/versions/ns
 impl<K> Api<K> {
+    /// The request builder behind this Api
+    #[cfg(feature = "openapi")]
+    pub(crate) fn raw(&self) -> &RawApi {
+        &self.api
+    }
     pub fn within(mut self, ns: &str) -> Self {
         self.api = self.api.within(ns);
         self
     }
+    /// Undo `within`, for requests across all namespaces
+    pub fn all_namespaces(mut self) -> Self {
+        self.api = self.api.all_namespaces();
+        self
+    }
     pub fn group(mut self, group: &str) -> Self {
         self.api = self.api.group(group);
         self
@@ -88,6 +240,11 @@ impl<K> Api<K> where
         let req = self.api.list(&lp)?;
         self.client.request::<ObjectList<K>>(req)
     }
+    /// List, leaving out the objects that fail to parse as `K` instead of failing
+    pub fn list_partial(&self, lp: &ListParams) -> Result<PartialList<K>> {
+        let req = self.api.list(&lp)?;
+        self.client.request::<PartialList<K>>(req)
+    }
     pub fn delete_collection(&self, lp: &ListParams) -> Result<Either<ObjectList<K>, Status>> {
         let req = self.api.delete_collection(&lp)?;
         self.client.request_status::<ObjectList<K>>(req)
@@ -104,6 +261,31 @@ impl<K> Api<K> where
         let req = self.api.watch(&lp, &version)?;
         self.client.request_events::<WatchEvent<K>>(req)
     }
+    /// List in all namespaces, regardless of `within`
+    pub fn list_all_namespaces(&self, lp: &ListParams) -> Result<ObjectList<K>> {
+        let req = self.api.list_all_namespaces(&lp)?;
+        self.client.request::<ObjectList<K>>(req)
+    }
+    /// Watch in all namespaces, regardless of `within`
+    pub fn watch_all_namespaces(&self, lp: &ListParams, version: &str) -> Result<Vec<WatchEvent<K>>> {
+        let req = self.api.watch_all_namespaces(&lp, &version)?;
+        self.client.request_events::<WatchEvent<K>>(req)
+    }
+    /// Watch a single object by name, e.g. to follow its status
+    pub fn watch_object(&self, name: &str, lp: &ListParams, version: &str) -> Result<Vec<WatchEvent<K>>> {
+        let req = self.api.watch_object(name, &lp, &version)?;
+        self.client.request_events::<WatchEvent<K>>(req)
+    }
+    /// List as the `Table` of columns kubectl prints
+    pub fn list_table(&self, lp: &ListParams) -> Result<Table> {
+        let req = self.api.list_table(&lp)?;
+        Table::from_response(self.client.request::<serde_json::Value>(req)?)
+    }
+    /// Get an object as the `Table` of columns kubectl prints
+    pub fn get_table(&self, name: &str) -> Result<Table> {
+        let req = self.api.get_table(name)?;
+        Table::from_response(self.client.request::<serde_json::Value>(req)?)
+    }
     pub fn get_status(&self, name: &str) -> Result<K> {
         let req = self.api.get_status(name)?;
         self.client.request::<K>(req)
@@ -116,6 +298,102 @@ impl<K> Api<K> where
         let req = self.api.replace_status(name, &pp, data)?;
         self.client.request::<K>(req)
     }
+
+    /// Get an object, or `None` if it does not exist
+    pub fn get_opt(&self, name: &str) -> Result<Option<K>> {
+        not_found_as_none(self.get(name))
+    }
+    /// Delete an object, or return `None` if it was already gone
+    pub fn delete_opt(&self, name: &str, dp: &DeleteParams) -> Result<Option<Either<K, Status>>> {
+        not_found_as_none(self.delete(name, dp))
+    }
+    /// Get the status of an object, or `None` if it does not exist
+    pub fn get_status_opt(&self, name: &str) -> Result<Option<K>> {
+        not_found_as_none(self.get_status(name))
+    }
+
+    /// Delete an object and block until it is gone from the apiserver, or fail after `timeout`
+    ///
+    /// Uses `PropagationPolicy::Foreground` unless another policy is set,
+    /// so that the object only disappears once its dependents have been garbage collected.
+    /// An object that is already gone counts as deleted.
+    pub fn delete_and_wait(&self, name: &str, dp: &DeleteParams, timeout: Duration) -> Result<()> {
+        let mut dp = dp.clone();
+        if dp.propagation_policy.is_none() {
+            dp.propagation_policy = Some(PropagationPolicy::Foreground);
+        }
+        if self.delete_opt(name, &dp)?.is_none() {
+            return Ok(());
+        }
+        poll_until(&format!("{} {} to be deleted", self.api.resource, name), timeout, || {
+            Ok(match self.get_opt(name)? {
+                Some(_) => None,
+                None => Some(()),
+            })
+        })
+    }
+}
+
+/// Map a `404 Not Found` to `None`
+fn not_found_as_none<T>(res: Result<T>) -> Result<Option<T>> {
+    match res {
+        Ok(x) => Ok(Some(x)),
+        Err(ref e) if e.is_not_found() => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read-modify-write helpers
+impl<K> Api<K> where
+    K: Clone + DeserializeOwned + Serialize + KubeObject,
+{
+    /// Get an object, or create it from `default` if it does not exist
+    ///
+    /// If someone else creates it first, their version is returned.
+    pub fn get_or_create<F>(&self, name: &str, pp: &PostParams, default: F) -> Result<K>
+    where
+        F: FnOnce() -> K,
+    {
+        match self.get(name) {
+            Err(ref e) if e.status_code() == Some(404) => {},
+            res => return res,
+        }
+        let data = serde_json::to_vec(&default()).context(ErrorKind::SerdeParse)?;
+        match self.create(pp, data) {
+            Err(ref e) if e.status_code() == Some(409) => self.get(name),
+            res => res,
+        }
+    }
+
+    /// Create or update an object through a mutation closure
+    ///
+    /// Fetches the object (or starts from `default` if it does not exist), runs `f` over it,
+    /// and writes it back with a replace (or create). The fetched resourceVersion is kept,
+    /// so concurrent modifications cause a `409 Conflict`, in which case the whole
+    /// read-modify-write is retried with a fresh read like `retry_on_conflict` does,
+    /// with the default `Backoff`.
+    pub fn upsert<D, F>(&self, name: &str, pp: &PostParams, default: D, mut f: F) -> Result<K>
+    where
+        D: Fn() -> K,
+        F: FnMut(&mut K),
+    {
+        // throttling is waited out like a conflict is retried
+        let retriable = |e: &crate::Error| e.status_code() == Some(409) || e.throttle().is_some();
+        retry_on_error(&Backoff::default(), retriable, || match self.get(name) {
+            Ok(mut obj) => {
+                f(&mut obj);
+                let data = serde_json::to_vec(&obj).context(ErrorKind::SerdeParse)?;
+                self.replace(name, pp, data)
+            },
+            Err(ref e) if e.status_code() == Some(404) => {
+                let mut obj = default();
+                f(&mut obj);
+                let data = serde_json::to_vec(&obj).context(ErrorKind::SerdeParse)?;
+                self.create(pp, data)
+            },
+            Err(e) => Err(e),
+        })
+    }
 }
 
 impl<K> LogOperation for Api<K> where
@@ -176,3 +454,84 @@ impl<K> Api<K> where
 }
 
 // all other native impls in openapi.rs
+
+#[test]
+fn get_opt_maps_not_found() {
+    use crate::client::fake::Fake;
+    let client = Fake::new().on("GET", "/apis/example.com/v1/namespaces/ns/foos/a", 200, r#"{"metadata":{"name":"a"},"spec":{}}"#).client();
+    let foos: Api<Object<serde_json::Value, serde_json::Value>> =
+        Api::customResource(client, "foos").group("example.com").within("ns");
+    assert_eq!(foos.get_opt("a").unwrap().map(|f| f.metadata.name), Some("a".to_string()));
+    assert!(foos.get_opt("b").unwrap().is_none());
+}
+
+#[test]
+fn delete_and_wait_polls_until_gone() {
+    use crate::client::fake::Fake;
+    // the object lingers for one GET after the delete, while its dependents go
+    let deleting = r#"{"metadata":{"name":"a","deletionTimestamp":"2019-01-01T00:00:00Z"},"spec":{}}"#;
+    let fake = Fake::new().once("DELETE", "*", 200, deleting).once("GET", "*", 200, deleting);
+    let client = fake.client();
+    let foos: Api<Object<serde_json::Value, serde_json::Value>> =
+        Api::customResource(client, "foos").group("example.com").within("ns");
+    foos.delete_and_wait("a", &DeleteParams::default(), Duration::from_secs(10)).unwrap();
+    assert_eq!(fake.lines(), vec![
+        "DELETE /apis/example.com/v1/namespaces/ns/foos/a?propagationPolicy=Foreground",
+        "GET /apis/example.com/v1/namespaces/ns/foos/a",
+        "GET /apis/example.com/v1/namespaces/ns/foos/a",
+    ]);
+}
+
+#[test]
+fn upsert_creates_and_retries_conflicts() {
+    use crate::client::fake::{status, Fake};
+    type Foo = Object<serde_json::Value, serde_json::Value>;
+    // answers with the statuses in order, then with conflicts
+    let upsert = |statuses: &[u16]| {
+        let foo = r#"{"metadata":{"name":"a","resourceVersion":"1"},"spec":{"n":1}}"#;
+        let fake = statuses.iter().fold(Fake::new(), |fake, &code| match code {
+            200 | 201 => fake.once("*", "*", code, foo),
+            _ => fake.once("*", "*", code, status(code, if code == 404 { "NotFound" } else { "Conflict" })),
+        }).on("*", "*", 409, status(409, "Conflict"));
+        let foos: Api<Foo> = Api::customResource(fake.client(), "foos").group("example.com").within("ns");
+        let default = || serde_json::from_str::<Foo>(r#"{"metadata":{"name":"a"},"spec":{}}"#).unwrap();
+        let res = foos.upsert("a", &PostParams::default(), default, |foo| foo.spec["n"] = 1.into());
+        let methods: Vec<_> = fake.requests().into_iter().map(|r| r.method).collect();
+        (res, methods)
+    };
+
+    let (res, methods) = upsert(&[404, 201]);
+    assert_eq!(res.unwrap().spec["n"], 1);
+    assert_eq!(methods, vec!["GET", "POST"]);
+
+    // a conflicting write is read and written again
+    let (res, methods) = upsert(&[200, 409, 200, 200]);
+    assert!(res.is_ok());
+    assert_eq!(methods, vec!["GET", "PUT", "GET", "PUT"]);
+
+    let (res, methods) = upsert(&[200, 409].repeat(5));
+    assert_eq!(res.err().unwrap().status_code(), Some(409));
+    assert_eq!(methods.len(), 10, "gives up after 5 attempts");
+}
+
+#[cfg(feature = "openapi")]
+#[test]
+fn tables_fall_back_to_plain_lists() {
+    use crate::api::output::format_table;
+    use crate::client::fake::Fake;
+    let fake = Fake::new()
+        .once("GET", "/api/v1/namespaces/ns/pods", 200, r#"{"kind":"Table","apiVersion":"meta.k8s.io/v1",
+            "columnDefinitions":[{"name":"Name","type":"string"},{"name":"Status","type":"string"}],
+            "rows":[{"cells":["blog","Running"]}]}"#)
+        .once("GET", "/api/v1/namespaces/ns/pods", 200, r#"{"kind":"PodList","apiVersion":"v1","metadata":{},"items":[
+            {"metadata":{"name":"blog","creationTimestamp":"2019-08-01T10:00:00Z"},"spec":{"containers":[]}}]}"#)
+        .once("GET", "/api/v1/namespaces/ns/pods", 200, r#"{"kind":"PodList","items":{}}"#);
+    let pods = Api::v1Pod(fake.client()).within("ns");
+    let table = pods.list_table(&ListParams::default()).unwrap();
+    assert_eq!(format_table(&table, false), "NAME   STATUS\nblog   Running\n");
+    let table = pods.list_table(&ListParams::default()).unwrap();
+    assert_eq!(format_table(&table, false), "NAME   CREATED AT\nblog   2019-08-01T10:00:00Z\n");
+    assert_eq!(table.rows[0].object.as_ref().unwrap()["metadata"]["name"], "blog");
+    assert!(pods.list_table(&ListParams::default()).is_err());
+    assert!(fake.requests().iter().all(|r| r.header("Accept").contains("as=Table")));
+}
@@ -0,0 +1,488 @@
+//! Quantities, ints-or-strings and durations, with the semantics kubernetes gives them
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::{Error, ErrorKind, Result};
+
+fn invalid(what: &str, value: &str) -> Error {
+    Error::from(ErrorKind::RequestValidation(format!("invalid {} {:?}", what, value)))
+}
+
+const NANO: i128 = 1_000_000_000;
+
+/// How a quantity was written, and so how it is written back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuantityFormat {
+    /// Powers of 1024: `Ki`, `Mi`, `Gi` ...
+    BinarySI,
+    /// Powers of 1000: `m`, `k`, `M`, `G` ...
+    DecimalSI,
+    /// Powers of ten: `1e3`
+    DecimalExponent,
+}
+
+const BINARY_SUFFIXES: [(&str, u32); 6] = [("Ki", 1), ("Mi", 2), ("Gi", 3), ("Ti", 4), ("Pi", 5), ("Ei", 6)];
+const DECIMAL_SUFFIXES: [(&str, i32); 10] =
+    [("n", -9), ("u", -6), ("m", -3), ("", 0), ("k", 3), ("M", 6), ("G", 9), ("T", 12), ("P", 15), ("E", 18)];
+
+/// A resource amount like `500m` cpu or `1Gi` of memory
+///
+/// Parsed exactly down to nano units, as the apiserver does; finer fractions are rounded
+/// up. Quantities compare and hash by value, so `1Gi == 1024Mi`, and are written in
+/// canonical form in the format they were parsed with: `1000m` is written as `1`.
+///
+/// ```
+/// use kube::api::Quantity;
+///
+/// let requests: Quantity = "1536Mi".parse().unwrap();
+/// let limit: Quantity = "2Gi".parse().unwrap();
+/// assert!(requests < limit);
+/// assert_eq!((limit - requests).to_string(), "512Mi");
+/// assert_eq!("0.5".parse::<Quantity>().unwrap().to_string(), "500m");
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Quantity {
+    nanos: i128,
+    format: QuantityFormat,
+}
+
+impl Quantity {
+    /// A whole number of units, e.g. cpus or bytes
+    pub fn from_units(units: i64, format: QuantityFormat) -> Self {
+        Quantity { nanos: i128::from(units) * NANO, format }
+    }
+
+    /// Thousandths of units, e.g. millicpus
+    pub fn from_millis(millis: i64) -> Self {
+        Quantity { nanos: i128::from(millis) * 1_000_000, format: QuantityFormat::DecimalSI }
+    }
+
+    pub fn format(&self) -> QuantityFormat {
+        self.format
+    }
+
+    /// The value in whole units, rounded up, like `Quantity.Value()` in Go
+    ///
+    /// Values beyond an `i64`, like `8Ei`, saturate at `i64::MAX` or `i64::MIN`.
+    pub fn value(&self) -> i64 {
+        saturate(div_ceil(self.nanos, NANO))
+    }
+
+    /// The value in thousandths of units, rounded up, like `Quantity.MilliValue()` in Go
+    ///
+    /// Saturates like `value`, which milli values already do above about `9.2P`.
+    pub fn milli_value(&self) -> i64 {
+        saturate(div_ceil(self.nanos, 1_000_000))
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        self.nanos as f64 / NANO as f64
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.nanos == 0
+    }
+}
+
+fn saturate(n: i128) -> i64 {
+    i64::try_from(n).unwrap_or(if n < 0 { i64::MIN } else { i64::MAX })
+}
+
+fn div_ceil(a: i128, b: i128) -> i128 {
+    let (q, r) = (a / b, a % b);
+    if r > 0 { q + 1 } else { q }
+}
+
+impl FromStr for Quantity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let number_end = s.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '+' || c == '-')).unwrap_or(s.len());
+        let (number, suffix) = s.split_at(number_end);
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(n) => (true, n),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (whole, frac) = number.split_once('.').unwrap_or((number, ""));
+        let digits = format!("{}{}", whole, frac);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) || digits.len() > 30 {
+            return Err(invalid("quantity", s));
+        }
+        let mantissa: i128 = digits.parse().map_err(|_| invalid("quantity", s))?;
+
+        // the value is mantissa * 10^exp10 * 1024^exp2, scaled to nanos
+        let (format, exp10, exp2) = if let Some((_, k)) = BINARY_SUFFIXES.iter().find(|(b, _)| *b == suffix) {
+            (QuantityFormat::BinarySI, 0, *k)
+        } else if let Some((_, e)) = DECIMAL_SUFFIXES.iter().find(|(d, _)| *d == suffix) {
+            (QuantityFormat::DecimalSI, *e, 0)
+        } else if let Some(e) = suffix.strip_prefix('e').or_else(|| suffix.strip_prefix('E')) {
+            let e: i32 = e.parse().map_err(|_| invalid("quantity", s))?;
+            (QuantityFormat::DecimalExponent, e, 0)
+        } else {
+            return Err(invalid("quantity", s));
+        };
+        let exp10 = exp10 + 9 - frac.len() as i32;
+        if !(-40..=40).contains(&exp10) {
+            return Err(invalid("quantity", s));
+        }
+        let overflow = || invalid("quantity", s);
+        let mut nanos = mantissa.checked_mul(1i128 << (10 * exp2)).ok_or_else(overflow)?;
+        nanos = if exp10 >= 0 {
+            nanos.checked_mul(10i128.checked_pow(exp10 as u32).ok_or_else(overflow)?).ok_or_else(overflow)?
+        } else {
+            match 10i128.checked_pow((-exp10) as u32) {
+                Some(div) => div_ceil(nanos, div),
+                None => if nanos > 0 { 1 } else { 0 },
+            }
+        };
+        Ok(Quantity { nanos: if negative { -nanos } else { nanos }, format })
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.nanos == 0 {
+            return f.write_str("0");
+        }
+        if self.format == QuantityFormat::BinarySI && self.nanos % NANO == 0 {
+            let units = self.nanos / NANO;
+            // binary suffixes only fit whole multiples of 1024, and values of at least 1Ki
+            if let Some((suffix, k)) = BINARY_SUFFIXES.iter().rev().find(|(_, k)| units % (1i128 << (10 * k)) == 0) {
+                return write!(f, "{}{}", units >> (10 * k), suffix);
+            }
+            if units.abs() < 1024 {
+                return write!(f, "{}", units);
+            }
+        }
+        let (suffix, e) = DECIMAL_SUFFIXES.iter().rev()
+            .find(|(_, e)| self.nanos % 10i128.pow((e + 9) as u32) == 0)
+            .unwrap_or(&DECIMAL_SUFFIXES[0]);
+        let scaled = self.nanos / 10i128.pow((e + 9) as u32);
+        match self.format {
+            QuantityFormat::DecimalExponent if *e != 0 => write!(f, "{}e{}", scaled, e),
+            _ => write!(f, "{}{}", scaled, suffix),
+        }
+    }
+}
+
+impl PartialEq for Quantity {
+    fn eq(&self, other: &Self) -> bool {
+        self.nanos == other.nanos
+    }
+}
+
+impl Eq for Quantity {}
+
+impl Hash for Quantity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.nanos.hash(state)
+    }
+}
+
+impl PartialOrd for Quantity {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Quantity {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.nanos.cmp(&other.nanos)
+    }
+}
+
+impl Quantity {
+    /// The sum, or `None` if it does not fit, in the format of `self`
+    pub fn checked_add(&self, rhs: &Quantity) -> Option<Quantity> {
+        Some(Quantity { nanos: self.nanos.checked_add(rhs.nanos)?, format: self.format })
+    }
+
+    /// The difference, or `None` if it does not fit, in the format of `self`
+    pub fn checked_sub(&self, rhs: &Quantity) -> Option<Quantity> {
+        Some(Quantity { nanos: self.nanos.checked_sub(rhs.nanos)?, format: self.format })
+    }
+}
+
+/// Results keep the format of the left hand side, and saturate where they do not
+/// fit; see `checked_add` and `checked_sub` to tell
+impl Add for Quantity {
+    type Output = Quantity;
+
+    fn add(self, rhs: Quantity) -> Quantity {
+        Quantity { nanos: self.nanos.saturating_add(rhs.nanos), format: self.format }
+    }
+}
+
+impl Sub for Quantity {
+    type Output = Quantity;
+
+    fn sub(self, rhs: Quantity) -> Quantity {
+        Quantity { nanos: self.nanos.saturating_sub(rhs.nanos), format: self.format }
+    }
+}
+
+impl AddAssign for Quantity {
+    fn add_assign(&mut self, rhs: Quantity) {
+        self.nanos = self.nanos.saturating_add(rhs.nanos);
+    }
+}
+
+impl SubAssign for Quantity {
+    fn sub_assign(&mut self, rhs: Quantity) {
+        self.nanos = self.nanos.saturating_sub(rhs.nanos);
+    }
+}
+
+impl Neg for Quantity {
+    type Output = Quantity;
+
+    fn neg(self) -> Quantity {
+        Quantity { nanos: self.nanos.saturating_neg(), format: self.format }
+    }
+}
+
+impl std::iter::Sum for Quantity {
+    fn sum<I: Iterator<Item = Quantity>>(iter: I) -> Quantity {
+        iter.fold(Quantity::from_units(0, QuantityFormat::DecimalSI), |a, b| a + b)
+    }
+}
+
+impl Serialize for Quantity {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        // plain numbers are accepted by the apiserver too
+        match serde_json::Value::deserialize(d)? {
+            serde_json::Value::String(s) => s.parse().map_err(de::Error::custom),
+            serde_json::Value::Number(n) => n.to_string().parse().map_err(de::Error::custom),
+            other => Err(de::Error::custom(format!("invalid quantity {}", other))),
+        }
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl TryFrom<&k8s_openapi::apimachinery::pkg::api::resource::Quantity> for Quantity {
+    type Error = Error;
+
+    fn try_from(q: &k8s_openapi::apimachinery::pkg::api::resource::Quantity) -> Result<Self> {
+        q.0.parse()
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl From<Quantity> for k8s_openapi::apimachinery::pkg::api::resource::Quantity {
+    fn from(q: Quantity) -> Self {
+        k8s_openapi::apimachinery::pkg::api::resource::Quantity(q.to_string())
+    }
+}
+
+/// A field holding either a number or a string, commonly a port name or a percentage
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum IntOrString {
+    Int(i32),
+    String(String),
+}
+
+impl IntOrString {
+    /// The value, resolving percentages like `25%` against `total`
+    ///
+    /// Fractions are rounded up or down as asked, as `maxSurge` and `maxUnavailable` of
+    /// a rolling update round differently. Strings that are not percentages fail.
+    pub fn scaled_value(&self, total: i32, round_up: bool) -> Result<i32> {
+        match self {
+            IntOrString::Int(i) => Ok(*i),
+            IntOrString::String(s) => {
+                let percent: i64 = s.strip_suffix('%')
+                    .and_then(|p| p.parse().ok())
+                    .ok_or_else(|| invalid("percentage", s))?;
+                let scaled = percent.checked_mul(i64::from(total)).ok_or_else(|| invalid("percentage", s))?;
+                let value = if round_up { div_ceil(scaled.into(), 100) as i64 } else { scaled.div_euclid(100) };
+                i32::try_from(value).map_err(|_| invalid("percentage", s))
+            },
+        }
+    }
+}
+
+impl From<i32> for IntOrString {
+    fn from(i: i32) -> Self {
+        IntOrString::Int(i)
+    }
+}
+
+impl From<&str> for IntOrString {
+    fn from(s: &str) -> Self {
+        IntOrString::String(s.into())
+    }
+}
+
+impl fmt::Display for IntOrString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntOrString::Int(i) => write!(f, "{}", i),
+            IntOrString::String(s) => f.write_str(s),
+        }
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl From<k8s_openapi::apimachinery::pkg::util::intstr::IntOrString> for IntOrString {
+    fn from(v: k8s_openapi::apimachinery::pkg::util::intstr::IntOrString) -> Self {
+        use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString as K8s;
+        match v {
+            K8s::Int(i) => IntOrString::Int(i),
+            K8s::String(s) => IntOrString::String(s),
+        }
+    }
+}
+
+#[cfg(feature = "openapi")]
+impl From<IntOrString> for k8s_openapi::apimachinery::pkg::util::intstr::IntOrString {
+    fn from(v: IntOrString) -> Self {
+        use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString as K8s;
+        match v {
+            IntOrString::Int(i) => K8s::Int(i),
+            IntOrString::String(s) => K8s::String(s),
+        }
+    }
+}
+
+const DURATION_UNITS: [(&str, u64); 7] = [
+    ("ns", 1), ("us", 1_000), ("µs", 1_000), ("μs", 1_000), ("ms", 1_000_000),
+    ("s", 1_000_000_000), ("m", 60_000_000_000),
+];
+
+/// Parse a Go style duration, like `300ms`, `1.5h` or `2h45m`
+///
+/// Used for fields like `metav1.Duration` and kubelet or controller flags. Negative
+/// durations are rejected, as `std::time::Duration` cannot hold them.
+pub fn parse_duration(s: &str) -> Result<Duration> {
+    if s == "0" {
+        return Ok(Duration::from_secs(0));
+    }
+    let mut rest = s.strip_prefix('+').unwrap_or(s);
+    if rest.is_empty() {
+        return Err(invalid("duration", s));
+    }
+    let mut nanos: u128 = 0;
+    while !rest.is_empty() {
+        let number_end = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(number_end);
+        let unit_end = tail.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+        let scale = match unit {
+            "h" => 3_600_000_000_000,
+            u => DURATION_UNITS.iter().find(|(name, _)| *name == u).map(|(_, n)| *n).ok_or_else(|| invalid("duration", s))?,
+        };
+        let (whole, frac) = number.split_once('.').unwrap_or((number, ""));
+        if (whole.is_empty() && frac.is_empty()) || !frac.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid("duration", s));
+        }
+        let whole: u128 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| invalid("duration", s))? };
+        let mut value = whole.checked_mul(u128::from(scale)).ok_or_else(|| invalid("duration", s))?;
+        // fractions beyond nanosecond precision are dropped, as Go does
+        let mut place = u128::from(scale);
+        for d in frac.chars() {
+            place /= 10;
+            value += u128::from(d.to_digit(10).unwrap_or(0)) * place;
+        }
+        nanos = nanos.checked_add(value).ok_or_else(|| invalid("duration", s))?;
+        rest = tail;
+    }
+    let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| invalid("duration", s))?;
+    Ok(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+}
+
+/// Format a duration as Go does, like `1h30m0s` or `1.5ms`
+pub fn format_duration(d: Duration) -> String {
+    let nanos = d.as_nanos();
+    let fraction = |value: u128, scale: u128, unit: &str| {
+        let (whole, frac) = (value / scale, value % scale);
+        if frac == 0 {
+            return format!("{}{}", whole, unit);
+        }
+        let width = scale.to_string().len() - 1;
+        let frac = format!("{:0width$}", frac, width = width);
+        format!("{}.{}{}", whole, frac.trim_end_matches('0'), unit)
+    };
+    if nanos == 0 {
+        "0s".into()
+    } else if nanos < 1_000 {
+        format!("{}ns", nanos)
+    } else if nanos < 1_000_000 {
+        fraction(nanos, 1_000, "µs")
+    } else if nanos < 1_000_000_000 {
+        fraction(nanos, 1_000_000, "ms")
+    } else {
+        let (h, m) = (nanos / 3_600_000_000_000, nanos / 60_000_000_000 % 60);
+        let secs = fraction(nanos % 60_000_000_000, 1_000_000_000, "s");
+        match (h, m) {
+            (0, 0) => secs,
+            (0, m) => format!("{}m{}", m, secs),
+            (h, m) => format!("{}h{}m{}", h, m, secs),
+        }
+    }
+}
+
+#[test]
+fn units_follow_kubernetes() {
+    let q = |s: &str| s.parse::<Quantity>().unwrap();
+    assert_eq!(q("500m").milli_value(), 500);
+    assert_eq!(q("1Gi").value(), 1 << 30);
+    assert_eq!(q("1Gi"), q("1024Mi"));
+    assert_eq!(q("1.5Gi").to_string(), "1536Mi");
+    assert_eq!(q("1000m").to_string(), "1");
+    assert_eq!(q("1500m").to_string(), "1500m");
+    assert_eq!(q("1e3").to_string(), "1e3");
+    assert_eq!(q("0.1").to_string(), "100m");
+    assert_eq!(q("100Mi").to_string(), "100Mi");
+    assert_eq!(q("1.5m").value(), 1);
+    assert_eq!(q("8Ei").value(), i64::MAX);
+    assert_eq!(q("-8Ei").value(), i64::MIN);
+    assert_eq!(q("7Ei").value(), 7 << 60);
+    assert_eq!(q("10P").milli_value(), i64::MAX);
+    assert_eq!((q("250m") + q("1")).to_string(), "1250m");
+    assert_eq!(vec![q("1Gi"), q("512Mi")].into_iter().sum::<Quantity>(), q("1.5Gi"));
+    let huge = q("100000000000000000000000000000");
+    assert!(huge.checked_add(&huge).is_none());
+    assert!((-huge).checked_sub(&huge).is_none());
+    assert_eq!(q("1").checked_sub(&q("250m")), Some(q("750m")));
+    assert!(huge + huge > huge);
+    assert!("1.2.3".parse::<Quantity>().is_err());
+    assert!("5Gb".parse::<Quantity>().is_err());
+    let parsed: Quantity = serde_json::from_str("2").unwrap();
+    assert_eq!(serde_json::to_string(&parsed).unwrap(), r#""2""#);
+
+    assert_eq!(IntOrString::from("25%").scaled_value(10, true).unwrap(), 3);
+    assert_eq!(IntOrString::from("25%").scaled_value(10, false).unwrap(), 2);
+    assert_eq!(IntOrString::from(4).scaled_value(10, false).unwrap(), 4);
+    assert!(IntOrString::from("http").scaled_value(10, false).is_err());
+    assert!(IntOrString::from("9223372036854775807%").scaled_value(10, false).is_err());
+    assert!(IntOrString::from("300%").scaled_value(i32::MAX, true).is_err());
+    assert_eq!(serde_json::from_str::<IntOrString>("8080").unwrap(), IntOrString::Int(8080));
+
+    assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+    assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+    assert_eq!(parse_duration("300ms").unwrap(), Duration::from_millis(300));
+    assert_eq!(parse_duration("2h45m0.5s").unwrap(), Duration::from_millis(9_900_500));
+    assert!(parse_duration("5").is_err());
+    assert!(parse_duration("-1s").is_err());
+    assert!(parse_duration("99999999999999999999999999999999h").is_err());
+    assert!(parse_duration("200000000000000000000000000000h200000000000000000000000000000h").is_err());
+    assert_eq!(format_duration(Duration::from_secs(5400)), "1h30m0s");
+    assert_eq!(format_duration(Duration::from_micros(1500)), "1.5ms");
+    assert_eq!(format_duration(Duration::from_millis(61_500)), "1m1.5s");
+}
@@ -0,0 +1,54 @@
+//! Polling helpers shared by the various `wait_for_*` methods
+use std::time::{Duration, Instant};
+
+use crate::{Result, Error, ErrorKind};
+
+/// How often a condition is re-checked while waiting
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Poll `f` until it returns `Some`, or fail with `ErrorKind::Timeout` after `timeout`
+///
+/// Errors returned by `f` are propagated immediately, except for throttling errors
+/// which wait out their `Retry-After` before polling again.
+pub(crate) fn poll_until<T, F>(what: &str, timeout: Duration, mut f: F) -> Result<T>
+where
+    F: FnMut() -> Result<Option<T>>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let mut interval = POLL_INTERVAL;
+        match f() {
+            Ok(Some(res)) => return Ok(res),
+            Ok(None) => {},
+            // back off as asked when throttled, rather than adding to the load
+            Err(ref e) if e.throttle().is_some() => {
+                interval = e.retry_after().unwrap_or(POLL_INTERVAL);
+            },
+            Err(e) => return Err(e),
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(Error::from(ErrorKind::Timeout(what.into())));
+        }
+        std::thread::sleep(std::cmp::min(interval, deadline - now));
+    }
+}
+
+/// `poll_until`, with the timeout naming what last held it up
+///
+/// `f` sets the reason it is still waiting, when it knows one, which makes the
+/// timeout read "<what>, but <reason>".
+#[cfg(feature = "openapi")]
+pub(crate) fn poll_until_explained<T, F>(what: &str, timeout: Duration, mut f: F) -> Result<T>
+where
+    F: FnMut(&mut Option<String>) -> Result<Option<T>>,
+{
+    let mut reason = None;
+    let res = poll_until(what, timeout, || f(&mut reason));
+    match (res, reason) {
+        (Err(ref e), Some(reason)) if matches!(e.kind(), ErrorKind::Timeout(_)) => {
+            Err(Error::from(ErrorKind::Timeout(format!("{}, but {}", what, reason))))
+        },
+        (res, _) => res,
+    }
+}
@@ -0,0 +1,444 @@
+//! Serving certificates for self-hosted admission webhooks
+//!
+//! The apiserver only calls webhooks over TLS, verified against the `caBundle` of the
+//! webhook configuration. `WebhookCertManager` creates a self-signed CA and a serving
+//! certificate for the webhook Service, keeps them in a Secret (so all replicas share
+//! them), injects the CA into the webhook configurations, and rotates before expiry.
+//!
+//! Rotation reissues the serving certificate from the same CA, so the `caBundle` stays
+//! valid for replicas still serving the old certificate. Only when the CA itself runs
+//! out is a new one created, and the old one stays in the `caBundle` until it expires.
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName, SubjectKeyIdentifier,
+};
+use openssl::x509::{X509, X509Name, X509NameBuilder};
+use serde_json::Value;
+
+use crate::api::{Api, RawApi, PostParams, Backoff, retry_on_conflict, v1Secret};
+use crate::client::APIClient;
+use crate::runtime::{ReconcileBudget, Runnable};
+use crate::{Result, Error, ErrorKind};
+use failure::ResultExt;
+
+/// Secret key holding the PEM encoded CA certificates
+pub const CA_CERT_KEY: &str = "ca.crt";
+
+/// Secret key holding the PEM encoded key of the signing CA
+pub const CA_KEY_KEY: &str = "ca.key";
+
+/// How many times longer than the serving certificates a CA is valid
+const CA_VALIDITY_FACTOR: u32 = 10;
+
+/// A CA and a serving certificate signed by it, PEM encoded
+#[derive(Clone, Debug)]
+pub struct WebhookCerts {
+    /// The signing CA, followed by earlier CAs that have not expired yet
+    pub ca_cert: Vec<u8>,
+    /// The key of the signing CA, to reissue the serving certificate with
+    pub ca_key: Option<Vec<u8>>,
+    pub cert: Vec<u8>,
+    pub key: Vec<u8>,
+}
+
+fn tls_err(msg: &str) -> ErrorKind {
+    ErrorKind::Tls(msg.to_string())
+}
+
+fn generate_key() -> Result<PKey<Private>> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).context(tls_err("Unable to create curve"))?;
+    let ec = EcKey::generate(&group).context(tls_err("Unable to generate key"))?;
+    Ok(PKey::from_ec_key(ec).context(tls_err("Unable to generate key"))?)
+}
+
+fn name(cn: &str) -> Result<X509Name> {
+    let mut name = X509NameBuilder::new().context(tls_err("Unable to build name"))?;
+    name.append_entry_by_nid(Nid::COMMONNAME, cn).context(tls_err("Invalid common name"))?;
+    Ok(name.build())
+}
+
+fn asn1_time(offset_secs: i64) -> Result<Asn1Time> {
+    Ok(Asn1Time::from_unix(Utc::now().timestamp() + offset_secs).context(tls_err("Invalid time"))?)
+}
+
+/// Build a certificate for `key`, self-signed unless an issuer is given
+fn too_long(validity: Duration) -> Error {
+    ErrorKind::RequestValidation(format!("certificate validity of {:?} is too long", validity)).into()
+}
+
+fn build_cert(cn: &str, key: &PKey<Private>, issuer: Option<(&X509, &PKey<Private>)>, dns_names: &[String], validity: Duration) -> Result<X509> {
+    let err = || tls_err("Unable to build certificate");
+    let mut b = X509::builder().context(err())?;
+    b.set_version(2).context(err())?;
+    let mut serial = BigNum::new().context(err())?;
+    serial.rand(127, MsbOption::MAYBE_ZERO, false).context(err())?;
+    let serial = serial.to_asn1_integer().context(err())?;
+    b.set_serial_number(&serial).context(err())?;
+    let subject = name(cn)?;
+    b.set_subject_name(&subject).context(err())?;
+    b.set_issuer_name(issuer.map(|(ca, _)| ca.subject_name()).unwrap_or(&subject)).context(err())?;
+    b.set_pubkey(key).context(err())?;
+    // backdated a little, for clock skew between us and the apiserver
+    let secs = i64::try_from(validity.as_secs()).map_err(|_| too_long(validity))?;
+    let (not_before, not_after) = (asn1_time(-300)?, asn1_time(secs)?);
+    b.set_not_before(&not_before).context(err())?;
+    b.set_not_after(&not_after).context(err())?;
+
+    if issuer.is_none() {
+        b.append_extension(BasicConstraints::new().critical().ca().build().context(err())?).context(err())?;
+        b.append_extension(KeyUsage::new().critical().key_cert_sign().crl_sign().build().context(err())?).context(err())?;
+    } else {
+        b.append_extension(BasicConstraints::new().critical().build().context(err())?).context(err())?;
+        b.append_extension(KeyUsage::new().critical().digital_signature().key_encipherment().build().context(err())?).context(err())?;
+        b.append_extension(ExtendedKeyUsage::new().server_auth().build().context(err())?).context(err())?;
+        let mut san = SubjectAlternativeName::new();
+        for dns in dns_names {
+            san.dns(dns);
+        }
+        let san = san.build(&b.x509v3_context(issuer.map(|(ca, _)| ca.as_ref()), None)).context(err())?;
+        b.append_extension(san).context(err())?;
+    }
+    let ski = SubjectKeyIdentifier::new().build(&b.x509v3_context(None, None)).context(err())?;
+    b.append_extension(ski).context(err())?;
+
+    let signing_key = issuer.map(|(_, k)| k).unwrap_or(key);
+    b.sign(signing_key, MessageDigest::sha256()).context(err())?;
+    Ok(b.build())
+}
+
+/// A serving certificate for a Service, signed by `ca`
+fn issue(service: &str, namespace: &str, ca: &X509, ca_key: &PKey<Private>, validity: Duration) -> Result<(Vec<u8>, Vec<u8>)> {
+    let dns_names = vec![
+        service.to_string(),
+        format!("{}.{}", service, namespace),
+        format!("{}.{}.svc", service, namespace),
+        format!("{}.{}.svc.cluster.local", service, namespace),
+    ];
+    let key = generate_key()?;
+    let cert = build_cert(&dns_names[2], &key, Some((ca, ca_key)), &dns_names, validity)?;
+    Ok((
+        cert.to_pem().context(tls_err("Unable to encode certificate"))?,
+        key.private_key_to_pem_pkcs8().context(tls_err("Unable to encode key"))?,
+    ))
+}
+
+impl WebhookCerts {
+    /// A fresh CA and a serving certificate for a Service valid for `validity`
+    ///
+    /// The serving certificate covers the `<service>.<namespace>.svc` names the apiserver uses.
+    /// The CA is valid ten times as long, so that the certificate can be reissued from it.
+    pub fn generate(service: &str, namespace: &str, validity: Duration) -> Result<Self> {
+        let ca_key = generate_key()?;
+        let ca_validity = validity.checked_mul(CA_VALIDITY_FACTOR).ok_or_else(|| too_long(validity))?;
+        let ca = build_cert(&format!("{}-ca", service), &ca_key, None, &[], ca_validity)?;
+        let (cert, key) = issue(service, namespace, &ca, &ca_key, validity)?;
+        Ok(WebhookCerts {
+            ca_cert: ca.to_pem().context(tls_err("Unable to encode certificate"))?,
+            ca_key: Some(ca_key.private_key_to_pem_pkcs8().context(tls_err("Unable to encode key"))?),
+            cert,
+            key,
+        })
+    }
+
+    /// A new serving certificate from the same CA, if it is valid for longer than `validity`
+    pub fn reissue(&self, service: &str, namespace: &str, validity: Duration) -> Result<Option<Self>> {
+        let ca_key = match &self.ca_key {
+            Some(pem) => PKey::private_key_from_pem(pem).context(tls_err("Invalid CA key"))?,
+            None => return Ok(None),
+        };
+        let ca = X509::from_pem(&self.ca_cert).context(tls_err("Invalid CA certificate"))?;
+        if ca.not_after() < asn1_time(validity.as_secs() as i64)? {
+            return Ok(None);
+        }
+        let (cert, key) = issue(service, namespace, &ca, &ca_key, validity)?;
+        Ok(Some(WebhookCerts { cert, key, ..self.clone() }))
+    }
+
+    /// Trust the CAs of `previous` too, as long as they have not expired
+    pub fn trusting(mut self, previous: &WebhookCerts) -> Result<Self> {
+        let now = asn1_time(0)?;
+        for ca in X509::stack_from_pem(&previous.ca_cert).context(tls_err("Invalid CA certificate"))? {
+            if ca.not_after() > now {
+                self.ca_cert.extend(ca.to_pem().context(tls_err("Unable to encode certificate"))?);
+            }
+        }
+        Ok(self)
+    }
+
+    /// Read certificates from a `kubernetes.io/tls` Secret with a `ca.crt` key
+    pub fn from_secret(secret: &v1Secret) -> Option<Self> {
+        Some(WebhookCerts {
+            ca_cert: secret.get(CA_CERT_KEY)?,
+            ca_key: secret.get(CA_KEY_KEY),
+            cert: secret.get("tls.crt")?,
+            key: secret.get("tls.key")?,
+        })
+    }
+
+    /// A `kubernetes.io/tls` Secret holding the certificates
+    pub fn to_secret(&self, name: &str) -> v1Secret {
+        let secret = v1Secret::tls(name, &self.cert, &self.key).with(CA_CERT_KEY, &self.ca_cert);
+        match &self.ca_key {
+            Some(key) => secret.with(CA_KEY_KEY, key),
+            None => secret,
+        }
+    }
+
+    /// Whether the serving certificate expires within `window`, or cannot be parsed
+    pub fn expires_within(&self, window: Duration) -> bool {
+        let threshold = match asn1_time(window.as_secs() as i64) {
+            Ok(t) => t,
+            Err(_) => return true,
+        };
+        match X509::from_pem(&self.cert) {
+            Ok(cert) => cert.not_after() < threshold,
+            Err(_) => true,
+        }
+    }
+}
+
+/// Keeps webhook serving certificates in a Secret and the CA in webhook configurations
+///
+/// ```no_run
+/// use kube::{api::WebhookCertManager, client::APIClient, config};
+///
+/// let client = APIClient::new(config::incluster_config().unwrap());
+/// let certs = WebhookCertManager::new(client, "default", "my-webhook", "my-webhook-certs")
+///     .validating_webhook("my-webhook.example.com")
+///     .ensure()
+///     .unwrap();
+/// // serve with certs.cert and certs.key
+/// ```
+///
+/// As a `Runnable`, a `Manager` re-checks it every `check_interval` and rotates as needed.
+/// Replicas serving the webhook must reload the Secret when it changes.
+pub struct WebhookCertManager {
+    client: APIClient,
+    namespace: String,
+    service: String,
+    secret: String,
+    validating: Vec<String>,
+    mutating: Vec<String>,
+    /// How long generated certificates are valid for, one year by default
+    pub validity: Duration,
+    /// How long before expiry certificates are replaced, 30 days by default
+    pub rotate_before: Duration,
+    /// How often to check when driven by a `Manager`, an hour by default
+    pub check_interval: Duration,
+    last_check: Option<Instant>,
+}
+
+impl WebhookCertManager {
+    /// Certificates for `service` in `namespace`, stored in the Secret `secret` next to it
+    pub fn new(client: APIClient, namespace: &str, service: &str, secret: &str) -> Self {
+        WebhookCertManager {
+            client,
+            namespace: namespace.into(),
+            service: service.into(),
+            secret: secret.into(),
+            validating: vec![],
+            mutating: vec![],
+            validity: Duration::from_secs(365 * 24 * 3600),
+            rotate_before: Duration::from_secs(30 * 24 * 3600),
+            check_interval: Duration::from_secs(3600),
+            last_check: None,
+        }
+    }
+
+    /// Inject the CA into a ValidatingWebhookConfiguration
+    pub fn validating_webhook(mut self, name: &str) -> Self {
+        self.validating.push(name.into());
+        self
+    }
+
+    /// Inject the CA into a MutatingWebhookConfiguration
+    pub fn mutating_webhook(mut self, name: &str) -> Self {
+        self.mutating.push(name.into());
+        self
+    }
+
+    /// Make sure valid certificates exist and the webhook configurations trust them
+    ///
+    /// Certificates are (re)generated when the Secret is missing, incomplete or expiring.
+    /// Webhook configurations are only written when their `caBundle` differs.
+    pub fn ensure(&self) -> Result<WebhookCerts> {
+        let certs = self.ensure_secret()?;
+        let configs = self.validating.iter().map(|n| (RawApi::v1beta1ValidatingWebhookConfiguration(), n))
+            .chain(self.mutating.iter().map(|n| (RawApi::v1beta1MutatingWebhookConfiguration(), n)));
+        for (api, name) in configs {
+            self.inject_ca_bundle(&api, name, &certs.ca_cert)?;
+        }
+        Ok(certs)
+    }
+
+    fn ensure_secret(&self) -> Result<WebhookCerts> {
+        let secrets = Api::v1Secret(self.client.clone()).within(&self.namespace);
+        let existing = secrets.get_opt(&self.secret)?;
+        let certs = match existing.as_ref().and_then(WebhookCerts::from_secret) {
+            Some(certs) if !certs.expires_within(self.rotate_before) => return Ok(certs),
+            Some(old) => match old.reissue(&self.service, &self.namespace, self.validity)? {
+                Some(certs) => {
+                    info!("Reissuing webhook certificate in {}/{}", self.namespace, self.secret);
+                    certs
+                },
+                None => {
+                    info!("Rotating webhook CA in {}/{}", self.namespace, self.secret);
+                    WebhookCerts::generate(&self.service, &self.namespace, self.validity)?.trusting(&old)?
+                },
+            },
+            None => WebhookCerts::generate(&self.service, &self.namespace, self.validity)?,
+        };
+        let mut secret = certs.to_secret(&self.secret);
+        let pp = PostParams::default();
+        let written = match existing {
+            Some(old) => {
+                // keep the resourceVersion, so a replica rotating at the same time conflicts
+                secret.metadata = old.metadata;
+                let data = serde_json::to_vec(&secret).context(ErrorKind::SerdeParse)?;
+                secrets.replace(&self.secret, &pp, data)
+            },
+            None => {
+                let data = serde_json::to_vec(&secret).context(ErrorKind::SerdeParse)?;
+                secrets.create(&pp, data)
+            },
+        };
+        match written {
+            // someone else wrote first; use theirs
+            Err(ref e) if e.status_code() == Some(409) => {
+                let secret = secrets.get(&self.secret)?;
+                WebhookCerts::from_secret(&secret).ok_or_else(|| ErrorKind::Tls(
+                    format!("Secret {}/{} is missing certificates", self.namespace, self.secret)).into())
+            },
+            Err(e) => Err(e),
+            Ok(_) => Ok(certs),
+        }
+    }
+
+    fn inject_ca_bundle(&self, api: &RawApi, name: &str, ca: &[u8]) -> Result<()> {
+        let bundle = base64::encode(ca);
+        retry_on_conflict(&Backoff::default(), || {
+            let mut config: Value = self.client.request(api.get(name)?)?;
+            let webhooks = match config["webhooks"].as_array_mut() {
+                Some(w) => w,
+                None => return Ok(()),
+            };
+            let mut changed = false;
+            for hook in webhooks.iter_mut() {
+                if hook["clientConfig"]["caBundle"] != bundle.as_str() {
+                    hook["clientConfig"]["caBundle"] = bundle.clone().into();
+                    changed = true;
+                }
+            }
+            if changed {
+                debug!("Injecting webhook CA into {} {}", api.resource, name);
+                let data = serde_json::to_vec(&config).context(ErrorKind::SerdeParse)?;
+                self.client.request::<Value>(api.replace(name, &PostParams::default(), data)?)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Runnable for WebhookCertManager {
+    fn name(&self) -> &str {
+        &self.secret
+    }
+
+    fn tick(&mut self, _budget: &ReconcileBudget) -> Result<usize> {
+        if self.last_check.map(|t| t.elapsed() < self.check_interval).unwrap_or(false) {
+            return Ok(0);
+        }
+        // a failed check is retried after the interval too, not on every tick
+        self.last_check = Some(Instant::now());
+        self.ensure()?;
+        Ok(1)
+    }
+}
+
+#[test]
+fn generated_certs_chain_to_the_ca() {
+    let certs = WebhookCerts::generate("hook", "ns", Duration::from_secs(3600)).unwrap();
+    let ca = X509::from_pem(&certs.ca_cert).unwrap();
+    let cert = X509::from_pem(&certs.cert).unwrap();
+    assert!(cert.verify(&ca.public_key().unwrap()).unwrap());
+    let sans: Vec<_> = cert.subject_alt_names().unwrap().iter()
+        .filter_map(|n| n.dnsname().map(String::from))
+        .collect();
+    assert!(sans.contains(&"hook.ns.svc".to_string()));
+    assert!(!certs.expires_within(Duration::from_secs(60)));
+    assert!(certs.expires_within(Duration::from_secs(7200)));
+
+    let parsed = WebhookCerts::from_secret(&certs.to_secret("hook-certs")).unwrap();
+    assert_eq!(parsed.key, certs.key);
+}
+
+#[test]
+fn rotation_keeps_trusting_the_old_ca() {
+    use crate::client::fake::{Fake, Objects};
+    use serde_json::json;
+    let objects = Objects::default();
+    let config_path = "/apis/admissionregistration.k8s.io/v1beta1/validatingwebhookconfigurations/hook";
+    objects.insert(config_path, json!({
+        "metadata": { "name": "hook" }, "webhooks": [{ "name": "hook.example.com", "clientConfig": {} }],
+    }));
+    let client = Fake::new().store("*", &objects).client();
+    let manager = |validity: u64, rotate_before: u64| {
+        let mut m = WebhookCertManager::new(client.clone(), "ns", "hook", "hook-certs").validating_webhook("hook");
+        m.validity = Duration::from_secs(validity);
+        m.rotate_before = Duration::from_secs(rotate_before);
+        m
+    };
+    let ca_bundle = || {
+        let bundle = objects.get(config_path).unwrap()["webhooks"][0]["clientConfig"]["caBundle"].clone();
+        let pem = base64::decode(bundle.as_str().unwrap()).unwrap();
+        X509::stack_from_pem(&pem).unwrap().iter().map(|ca| ca.to_der().unwrap()).collect::<Vec<_>>()
+    };
+    let signed_by = |certs: &WebhookCerts| {
+        let cert = X509::from_pem(&certs.cert).unwrap();
+        let ca = X509::from_pem(&certs.ca_cert).unwrap();
+        cert.verify(&ca.public_key().unwrap()).unwrap()
+    };
+
+    let first = manager(3600, 60).ensure().unwrap();
+    let first_ca = X509::from_pem(&first.ca_cert).unwrap().to_der().unwrap();
+    assert_eq!(ca_bundle(), vec![first_ca.clone()]);
+    assert_eq!(manager(3600, 60).ensure().unwrap().cert, first.cert, "valid certificates are kept");
+
+    // the serving certificate is due, and reissued from the same CA
+    let reissued = manager(3600, 7200).ensure().unwrap();
+    assert_ne!(reissued.cert, first.cert);
+    assert_eq!(reissued.ca_cert, first.ca_cert);
+    assert!(signed_by(&reissued));
+    assert_eq!(ca_bundle(), vec![first_ca.clone()], "the caBundle is left alone");
+
+    // the CA runs out before a new certificate would, so a new one is trusted next to it
+    let rotated = manager(360_000, 7200).ensure().unwrap();
+    assert!(signed_by(&rotated));
+    let bundle = ca_bundle();
+    assert_eq!(bundle.len(), 2);
+    assert_ne!(bundle[0], first_ca);
+    assert_eq!(bundle[1], first_ca, "replicas still serving the old certificate stay trusted");
+}
+
+#[test]
+fn failed_checks_wait_for_the_interval() {
+    use crate::client::fake::Fake;
+    assert!(WebhookCerts::generate("hook", "ns", Duration::from_secs(u64::MAX / 5)).is_err());
+
+    let fake = Fake::new().unreachable("*", "*");
+    let mut m = WebhookCertManager::new(fake.client(), "ns", "hook", "hook-certs");
+    let budget = ReconcileBudget::unlimited();
+    assert!(m.tick(&budget).is_err());
+    let tried = fake.requests().len();
+    assert_eq!(m.tick(&budget).unwrap(), 0);
+    assert_eq!(fake.requests().len(), tried);
+}
@@ -0,0 +1,188 @@
+//! A `Transport` caching objects, for tools reading the same objects over and over
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use failure::ResultExt;
+use serde_json::Value;
+
+use crate::client::{Body, Transport};
+use crate::{ErrorKind, Result};
+
+/// The url without its query, and the Accept header
+type Key = (String, String);
+
+struct Entry {
+    headers: http::HeaderMap,
+    body: Vec<u8>,
+    resource_version: String,
+    fetched: Instant,
+}
+
+/// A `Transport` wrapper caching the objects of plain GET requests
+///
+/// Objects younger than `max_age` are answered from the cache. Older ones are
+/// fetched again with the cached resourceVersion, which the apiserver serves from
+/// its watch cache instead of a quorum read from etcd. Responses are kept per
+/// `Accept` header, so a table is not answered with the object. Lists, watches and
+/// GETs with query parameters pass through, and writes through the cache drop the
+/// objects they touch. Clones share their cache.
+///
+/// ```
+/// use std::time::Duration;
+/// use kube::client::{APIClient, ResponseCache};
+///
+/// let transport = ResponseCache::new(reqwest::Client::new(), Duration::from_secs(5));
+/// let client = APIClient::with_transport("https://k8s.example.com", transport);
+/// ```
+pub struct ResponseCache<T> {
+    inner: Arc<T>,
+    max_age: Duration,
+    entries: Arc<Mutex<HashMap<Key, Entry>>>,
+}
+
+impl<T> Clone for ResponseCache<T> {
+    fn clone(&self) -> Self {
+        ResponseCache { inner: self.inner.clone(), max_age: self.max_age, entries: self.entries.clone() }
+    }
+}
+
+fn response(headers: &http::HeaderMap, body: Vec<u8>) -> Result<http::Response<Body>> {
+    let mut res = http::Response::builder();
+    res.status(http::StatusCode::OK);
+    for (k, v) in headers {
+        res.header(k, v);
+    }
+    Ok(res.body(Box::new(Cursor::new(body)) as Body).context(ErrorKind::RequestParse)?)
+}
+
+/// The resourceVersion of a single object, not of a list or a non resource document
+fn resource_version(body: &[u8]) -> Option<String> {
+    let value: Value = serde_json::from_slice(body).ok()?;
+    if value["kind"].as_str().is_none_or(|k| k.ends_with("List") || k == "Status") {
+        return None;
+    }
+    value["metadata"]["resourceVersion"].as_str().map(String::from)
+}
+
+impl<T: Transport> ResponseCache<T> {
+    pub fn new(inner: T, max_age: Duration) -> Self {
+        ResponseCache { inner: Arc::new(inner), max_age, entries: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Drop all cached objects
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Drop the objects at `url` (an object, its subresources, or its parent when a subresource)
+    fn invalidate(&self, url: &str) {
+        self.entries.lock().unwrap().retain(|(key, _), _| {
+            !(key == url || key.starts_with(&format!("{}/", url)) || url.starts_with(&format!("{}/", key)))
+        });
+    }
+
+    /// Fetch `url`, caching the response if it is an object
+    fn fetch(&self, mut request: http::Request<Vec<u8>>, key: Key, known: Option<String>) -> Result<http::Response<Body>> {
+        if let Some(rv) = &known {
+            let uri = format!("{}?resourceVersion={}", key.0, rv);
+            *request.uri_mut() = uri.parse::<http::Uri>().context(ErrorKind::RequestBuild)?;
+        }
+        let res = self.inner.execute(request)?;
+        if res.status() != http::StatusCode::OK {
+            if res.status() == http::StatusCode::NOT_FOUND {
+                self.entries.lock().unwrap().remove(&key);
+            }
+            return Ok(res);
+        }
+        let (parts, mut body) = res.into_parts();
+        let mut data = vec![];
+        body.read_to_end(&mut data).context(ErrorKind::RequestParse)?;
+        match resource_version(&data) {
+            Some(rv) => {
+                trace!("Caching {} at resourceVersion {}", key.0, rv);
+                let entry = Entry { headers: parts.headers.clone(), body: data.clone(), resource_version: rv, fetched: Instant::now() };
+                self.entries.lock().unwrap().insert(key, entry);
+            },
+            None => {
+                self.entries.lock().unwrap().remove(&key);
+            },
+        }
+        Ok(http::Response::from_parts(parts, Box::new(Cursor::new(data)) as Body))
+    }
+}
+
+impl<T: Transport> Transport for ResponseCache<T> {
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        let url = request.uri().to_string().split('?').next().unwrap_or("").to_string();
+        if request.method() != http::Method::GET {
+            self.invalidate(&url);
+            return self.inner.execute(request);
+        }
+        if request.uri().query().is_some_and(|q| !q.is_empty()) {
+            return self.inner.execute(request);
+        }
+        let accept = request.headers().get(http::header::ACCEPT)
+            .and_then(|a| a.to_str().ok())
+            .unwrap_or("")
+            .to_string();
+        let key = (url, accept);
+        let known = {
+            let entries = self.entries.lock().unwrap();
+            match entries.get(&key) {
+                Some(e) if e.fetched.elapsed() < self.max_age => return response(&e.headers, e.body.clone()),
+                Some(e) => Some(e.resource_version.clone()),
+                None => None,
+            }
+        };
+        self.fetch(request, key, known)
+    }
+}
+
+#[test]
+fn objects_are_cached_and_revalidated() {
+    use crate::client::fake::{respond, Fake};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    let served = Arc::new(AtomicUsize::new(0));
+    let counted = served.clone();
+    let fake = Fake::new()
+        .on("GET", "/api/v1/namespaces/ns/configmaps", 200, r#"{"kind":"ConfigMapList","metadata":{"resourceVersion":"1"},"items":[]}"#)
+        .handle("*", "*", move |req| {
+            let rv = counted.fetch_add(1, Ordering::SeqCst) + 1;
+            respond(200, if req.header("Accept").contains("as=Table") {
+                format!(r#"{{"kind":"Table","metadata":{{"resourceVersion":"{}"}},"rows":[]}}"#, rv)
+            } else {
+                format!(r#"{{"kind":"ConfigMap","metadata":{{"name":"blog","resourceVersion":"{}"}}}}"#, rv)
+            })
+        });
+    let get = |path: &str| http::Request::get(path).body(vec![]).unwrap();
+    let rv = |v: Value| v["metadata"]["resourceVersion"].as_str().unwrap().to_string();
+    let cache = ResponseCache::new(fake.clone(), Duration::from_secs(60));
+    let client = crate::client::APIClient::with_transport("https://k8s.example.com", cache.clone());
+    let path = "/api/v1/namespaces/ns/configmaps/blog";
+
+    assert_eq!(rv(client.request(get(path)).unwrap()), "1");
+    assert_eq!(rv(client.request(get(path)).unwrap()), "1");
+    assert_eq!(fake.requests().len(), 1);
+    client.request::<Value>(get("/api/v1/namespaces/ns/configmaps")).unwrap();
+    client.request::<Value>(get("/api/v1/namespaces/ns/configmaps")).unwrap();
+    assert_eq!(fake.requests().len(), 3, "lists are not cached");
+
+    client.request::<Value>(http::Request::put(path).body(vec![]).unwrap()).unwrap();
+    assert_eq!(rv(client.request(get(path)).unwrap()), "3", "writes invalidate");
+    let table = || http::Request::get(path).header("Accept", "application/json;as=Table;g=meta.k8s.io;v=v1beta1").body(vec![]).unwrap();
+    let res: Value = client.request(table()).unwrap();
+    assert_eq!(res["kind"], "Table", "objects do not answer other Accept headers");
+    client.request::<Value>(table()).unwrap();
+    assert_eq!(client.request::<Value>(get(path)).unwrap()["kind"], "ConfigMap");
+    assert_eq!(fake.requests().len(), 6);
+
+    let stale = ResponseCache::new(fake.clone(), Duration::from_secs(0));
+    let client = crate::client::APIClient::with_transport("https://k8s.example.com", stale);
+    client.request::<Value>(get(path)).unwrap();
+    client.request::<Value>(get(path)).unwrap();
+    let seen = fake.lines();
+    assert_eq!(seen[6], format!("GET {}", path));
+    assert_eq!(seen[7], format!("GET {}?resourceVersion=5", path));
+}
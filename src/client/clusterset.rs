@@ -0,0 +1,285 @@
+//! Clients for a fleet of clusters
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use failure::ResultExt;
+
+use crate::config::{self, Config, ConfigOptions};
+use crate::{Error, ErrorKind, Result};
+use super::APIClient;
+
+#[cfg(feature = "openapi")]
+use crate::api::{Api, ListParams};
+
+/// The Secret type Cluster API stores workload cluster kubeconfigs as
+pub const CLUSTER_API_SECRET_TYPE: &str = "cluster.x-k8s.io/secret";
+/// The label Cluster API puts the cluster name in
+pub const CLUSTER_API_NAME_LABEL: &str = "cluster.x-k8s.io/cluster-name";
+
+/// Where the kubeconfig of a cluster comes from
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClusterSource {
+    /// A context in the local kubeconfig
+    Context(String),
+    /// A kubeconfig stored under `key` in a Secret on the management cluster
+    Secret { namespace: String, name: String, key: String },
+    /// A client built elsewhere and handed to `add_client`, which is never rebuilt
+    Client,
+}
+
+/// What `ClusterSet::refresh` did
+#[derive(Debug, Default)]
+pub struct Refreshed {
+    /// Clusters whose client was rebuilt from a changed Secret
+    pub rotated: Vec<String>,
+    /// Clusters whose Secret could not be read or used; they keep their old client
+    pub failed: Vec<(String, Error)>,
+}
+
+struct Cached {
+    client: APIClient,
+    /// resourceVersion of the Secret the client was built from
+    version: Option<String>,
+}
+
+/// Builds and caches an `APIClient` per cluster
+///
+/// Clusters come from contexts in the local kubeconfig, or from kubeconfigs
+/// kept in Secrets on a management cluster as Cluster API does.
+/// Clients are built on first use; `refresh` rebuilds the ones whose Secret
+/// changed, e.g. because credentials were rotated.
+///
+/// ```no_run
+/// use kube::client::ClusterSet;
+///
+/// let clusters = ClusterSet::from_contexts().unwrap();
+/// for name in clusters.clusters() {
+///     let client = clusters.client(&name).unwrap();
+/// }
+/// ```
+pub struct ClusterSet {
+    #[cfg_attr(not(feature = "openapi"), allow(dead_code))]
+    management: Option<APIClient>,
+    sources: BTreeMap<String, ClusterSource>,
+    cache: Mutex<BTreeMap<String, Cached>>,
+}
+
+impl ClusterSet {
+    /// An empty set, with an optional client for reading kubeconfig Secrets
+    pub fn new(management: Option<APIClient>) -> Self {
+        ClusterSet { management, sources: BTreeMap::new(), cache: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// A set with one cluster per context in the local kubeconfig, named after the context
+    pub fn from_contexts() -> Result<Self> {
+        let path = config::utils::find_kubeconfig()
+            .context(ErrorKind::KubeConfig("Unable to load file".into()))?;
+        let kubeconfig = Config::load_config(path)?;
+        let mut set = ClusterSet::new(None);
+        for ctx in kubeconfig.contexts {
+            set.add(&ctx.name, ClusterSource::Context(ctx.name.clone()));
+        }
+        Ok(set)
+    }
+
+    /// A set with the workload clusters Cluster API manages in a namespace
+    ///
+    /// Finds the `<cluster>-kubeconfig` Secrets with the Cluster API secret type.
+    #[cfg(feature = "openapi")]
+    pub fn from_cluster_api(management: APIClient, namespace: &str) -> Result<Self> {
+        let secrets = Api::v1Secret(management.clone()).within(namespace);
+        let mut set = ClusterSet::new(Some(management));
+        for s in secrets.list(&ListParams::default())?.items {
+            if s.type_.as_deref() != Some(CLUSTER_API_SECRET_TYPE) || !s.metadata.name.ends_with("-kubeconfig") {
+                continue;
+            }
+            let cluster = s.metadata.labels.get(CLUSTER_API_NAME_LABEL).cloned()
+                .unwrap_or_else(|| s.metadata.name.trim_end_matches("-kubeconfig").to_string());
+            set.add(&cluster, ClusterSource::Secret {
+                namespace: namespace.into(),
+                name: s.metadata.name.clone(),
+                key: "value".into(),
+            });
+        }
+        Ok(set)
+    }
+
+    /// Add or replace a cluster
+    pub fn add(&mut self, cluster: &str, source: ClusterSource) {
+        self.cache.lock().unwrap().remove(cluster);
+        self.sources.insert(cluster.into(), source);
+    }
+
+    /// Add or replace a cluster with a client built elsewhere, e.g. for the cluster this runs in
+    pub fn add_client(&mut self, cluster: &str, client: APIClient) {
+        self.cache.lock().unwrap().insert(cluster.into(), Cached { client, version: None });
+        self.sources.insert(cluster.into(), ClusterSource::Client);
+    }
+
+    /// Remove a cluster
+    pub fn remove(&mut self, cluster: &str) -> Option<ClusterSource> {
+        self.cache.lock().unwrap().remove(cluster);
+        self.sources.remove(cluster)
+    }
+
+    /// Names of the clusters in the set
+    pub fn clusters(&self) -> Vec<String> {
+        self.sources.keys().cloned().collect()
+    }
+
+    /// Where a cluster's kubeconfig comes from
+    pub fn source(&self, cluster: &str) -> Option<&ClusterSource> {
+        self.sources.get(cluster)
+    }
+
+    /// The client for a cluster, built on first use
+    pub fn client(&self, cluster: &str) -> Result<APIClient> {
+        if let Some(c) = self.cache.lock().unwrap().get(cluster) {
+            return Ok(c.client.clone());
+        }
+        let cached = self.build(cluster)?;
+        let client = cached.client.clone();
+        self.cache.lock().unwrap().insert(cluster.into(), cached);
+        Ok(client)
+    }
+
+    /// Drop the cached client for a cluster, e.g. after it was denied access
+    pub fn invalidate(&self, cluster: &str) {
+        if self.sources.get(cluster) == Some(&ClusterSource::Client) {
+            return; // nothing to build it again from
+        }
+        self.cache.lock().unwrap().remove(cluster);
+    }
+
+    /// Rebuild the clients whose kubeconfig Secret changed
+    ///
+    /// A cluster that fails does not stop the others from being refreshed.
+    pub fn refresh(&self) -> Refreshed {
+        let mut refreshed = Refreshed::default();
+        for (cluster, source) in &self.sources {
+            if let ClusterSource::Secret { .. } = source {
+                let current = self.cache.lock().unwrap().get(cluster).map(|c| c.version.clone());
+                let cached = match current {
+                    None => continue, // not used yet, built fresh when it is
+                    Some(version) => match self.build(cluster) {
+                        Ok(cached) if cached.version == version => continue,
+                        Ok(cached) => cached,
+                        Err(e) => {
+                            warn!("Unable to refresh client for cluster {}: {}", cluster, e);
+                            refreshed.failed.push((cluster.clone(), e));
+                            continue;
+                        },
+                    },
+                };
+                debug!("kubeconfig for cluster {} changed, rebuilding client", cluster);
+                self.cache.lock().unwrap().insert(cluster.clone(), cached);
+                refreshed.rotated.push(cluster.clone());
+            }
+        }
+        refreshed
+    }
+
+    fn build(&self, cluster: &str) -> Result<Cached> {
+        let source = self.sources.get(cluster).ok_or_else(|| Error::from(ErrorKind::KubeConfigMissing {
+            kind: "cluster".into(),
+            name: cluster.into(),
+        }))?;
+        match source {
+            ClusterSource::Context(ctx) => {
+                let conf = config::load_kube_config_with(ConfigOptions {
+                    context: Some(ctx.clone()),
+                    ..Default::default()
+                })?;
+                Ok(Cached { client: APIClient::new(conf), version: None })
+            },
+            ClusterSource::Secret { namespace, name, key } => self.build_from_secret(namespace, name, key),
+            ClusterSource::Client => Err(ErrorKind::KubeConfigMissing {
+                kind: "cluster".into(),
+                name: cluster.into(),
+            }.into()),
+        }
+    }
+
+    #[cfg(feature = "openapi")]
+    fn build_from_secret(&self, namespace: &str, name: &str, key: &str) -> Result<Cached> {
+        let management = self.management.clone().ok_or_else(|| Error::from(ErrorKind::KubeConfig(
+            "A management client is needed to read kubeconfig Secrets".into())))?;
+        let secret = Api::v1Secret(management).within(namespace).get(name)?;
+        let data = secret.get(key).ok_or_else(|| Error::from(ErrorKind::KubeConfig(
+            format!("Secret {}/{} has no {} key", namespace, name, key))))?;
+        let kubeconfig = Config::from_yaml(&data)?;
+        let conf = config::load_kube_config_from(&kubeconfig, ConfigOptions::default())?;
+        Ok(Cached { client: APIClient::new(conf), version: secret.metadata.resourceVersion.clone() })
+    }
+
+    #[cfg(not(feature = "openapi"))]
+    fn build_from_secret(&self, _namespace: &str, _name: &str, _key: &str) -> Result<Cached> {
+        Err(ErrorKind::KubeConfig("Reading kubeconfig Secrets needs the openapi feature".into()).into())
+    }
+}
+
+#[test]
+fn clusters_by_name() {
+    let mut set = ClusterSet::new(None);
+    set.add("prod", ClusterSource::Secret {
+        namespace: "fleet".into(),
+        name: "prod-kubeconfig".into(),
+        key: "value".into(),
+    });
+    set.add("dev", ClusterSource::Context("kind-dev".into()));
+    assert_eq!(set.clusters(), vec!["dev", "prod"]);
+    assert_eq!(set.source("dev"), Some(&ClusterSource::Context("kind-dev".into())));
+    // secrets cannot be read without a management client
+    assert!(set.client("prod").is_err());
+    assert!(set.client("staging").is_err());
+    let refreshed = set.refresh();
+    assert!(refreshed.rotated.is_empty() && refreshed.failed.is_empty());
+}
+
+#[cfg(feature = "openapi")]
+#[test]
+fn refresh_continues_past_failing_clusters() {
+    use crate::client::fake::{status, Fake};
+    let kubeconfig = base64::encode(r#"
+apiVersion: v1
+kind: Config
+current-context: ctx
+clusters:
+- name: c
+  cluster:
+    server: https://workload.example.com
+contexts:
+- name: ctx
+  context:
+    cluster: c
+    user: u
+users:
+- name: u
+  user:
+    token: abc
+"#);
+    let secret = |name: &str, version: &str| serde_json::json!({
+        "metadata": { "name": name, "namespace": "fleet", "resourceVersion": version },
+        "data": { "value": kubeconfig },
+    }).to_string();
+    let path = "/api/v1/namespaces/fleet/secrets";
+    let fake = Fake::new()
+        .once("GET", &format!("{}/a-kubeconfig", path), 200, secret("a-kubeconfig", "1"))
+        .once("GET", &format!("{}/b-kubeconfig", path), 200, secret("b-kubeconfig", "1"))
+        .on("GET", &format!("{}/a-kubeconfig", path), 403, status(403, "Forbidden"))
+        .on("GET", &format!("{}/b-kubeconfig", path), 200, secret("b-kubeconfig", "2"));
+    let mut set = ClusterSet::new(Some(fake.client()));
+    for cluster in &["a", "b"] {
+        set.add(cluster, ClusterSource::Secret {
+            namespace: "fleet".into(),
+            name: format!("{}-kubeconfig", cluster),
+            key: "value".into(),
+        });
+        set.client(cluster).unwrap();
+    }
+    let refreshed = set.refresh();
+    assert_eq!(refreshed.rotated, vec!["b"]);
+    assert_eq!(refreshed.failed.len(), 1);
+    assert_eq!(refreshed.failed[0].0, "a");
+    // a keeps the client it had
+    assert!(set.client("a").is_ok());
+}
@@ -0,0 +1,230 @@
+//! A fake apiserver for tests, answering canned responses by method and path
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use serde_json::Value;
+
+use crate::client::{APIClient, Body, Transport};
+use crate::{ErrorKind, Result};
+
+/// A request the fake was sent
+#[derive(Clone, Debug)]
+pub(crate) struct Seen {
+    pub method: String,
+    /// The scheme and host it was sent to
+    pub base: String,
+    /// The path and query, without the apiserver's scheme and host
+    pub uri: String,
+    pub headers: http::HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl Seen {
+    pub fn path(&self) -> &str {
+        self.uri.split('?').next().unwrap_or("")
+    }
+
+    pub fn query(&self) -> &str {
+        self.uri.split_once('?').map(|(_, q)| q).unwrap_or("")
+    }
+
+    /// A header as a string, empty when missing
+    pub fn header(&self, name: &str) -> &str {
+        self.headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("")
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).expect("request body is json")
+    }
+}
+
+/// `GET /api/v1/pods?limit=1`, as requests are logged
+impl fmt::Display for Seen {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.method, self.uri)
+    }
+}
+
+type Handler = Arc<dyn Fn(&Seen) -> Result<http::Response<Body>> + Send + Sync>;
+
+struct Route {
+    method: String,
+    path: String,
+    query: Option<String>,
+    once: bool,
+    handler: Handler,
+}
+
+impl Route {
+    fn matches(&self, req: &Seen) -> bool {
+        let path = match self.path.strip_suffix('*') {
+            Some(prefix) => req.path().starts_with(prefix),
+            None => req.path() == self.path,
+        };
+        (self.method == "*" || self.method == req.method)
+            && path
+            && self.query.as_ref().is_none_or(|q| req.query().contains(q.as_str()))
+    }
+}
+
+/// A `Transport` answering from routes, and logging every request
+///
+/// Routes match a method, or `*` for any, and a path, ending in `*` to match any
+/// below it. A `?` in the path makes it match requests whose query contains what
+/// follows. Routes added with `once` answer a single request each, ahead of the
+/// others; otherwise the first route added that matches answers. Anything no route
+/// matches gets a `404 NotFound` Status. Clones share their routes and log.
+#[derive(Clone, Default)]
+pub(crate) struct Fake {
+    routes: Arc<Mutex<Vec<Route>>>,
+    log: Arc<Mutex<Vec<Seen>>>,
+}
+
+/// A response with a body
+pub(crate) fn respond<B: Into<Vec<u8>>>(status: u16, body: B) -> http::Response<Body> {
+    http::Response::builder().status(status).body(Box::new(Cursor::new(body.into())) as Body).unwrap()
+}
+
+/// The Status body the apiserver answers failures with
+pub(crate) fn status(code: u16, reason: &str) -> String {
+    serde_json::json!({
+        "kind": "Status", "apiVersion": "v1", "status": "Failure",
+        "code": code, "reason": reason, "message": reason,
+    }).to_string()
+}
+
+/// Objects kept by path, written and read back as through an apiserver
+///
+/// POSTs store the object below the collection by its name; PUTs replace what is at
+/// the path, and DELETEs remove it. Clones share their objects.
+#[derive(Clone, Default)]
+pub(crate) struct Objects(Arc<Mutex<BTreeMap<String, Value>>>);
+
+impl Objects {
+    pub fn get(&self, path: &str) -> Option<Value> {
+        self.0.lock().unwrap().get(path).cloned()
+    }
+
+    pub fn insert(&self, path: &str, obj: Value) {
+        self.0.lock().unwrap().insert(path.to_string(), obj);
+    }
+
+    /// Answer a request for an object
+    pub fn serve(&self, req: &Seen) -> http::Response<Body> {
+        let mut objects = self.0.lock().unwrap();
+        let (code, obj) = match req.method.as_str() {
+            "GET" => (200, objects.get(req.path()).cloned()),
+            "DELETE" => (200, objects.remove(req.path())),
+            "POST" => {
+                let obj = req.json();
+                let path = format!("{}/{}", req.path(), obj["metadata"]["name"].as_str().unwrap_or_default());
+                if objects.contains_key(&path) {
+                    return respond(409, status(409, "AlreadyExists"));
+                }
+                objects.insert(path, obj.clone());
+                (201, Some(obj))
+            },
+            _ => {
+                objects.insert(req.path().to_string(), req.json());
+                (200, objects.get(req.path()).cloned())
+            },
+        };
+        match obj {
+            Some(obj) => respond(code, obj.to_string()),
+            None => respond(404, status(404, "NotFound")),
+        }
+    }
+}
+
+impl Fake {
+    pub fn new() -> Self {
+        Fake::default()
+    }
+
+    fn route(self, method: &str, path: &str, once: bool, handler: Handler) -> Self {
+        let (path, query) = match path.split_once('?') {
+            Some((p, q)) => (p.to_string(), Some(q.to_string())),
+            None => (path.to_string(), None),
+        };
+        self.routes.lock().unwrap().push(Route { method: method.into(), path, query, once, handler });
+        self
+    }
+
+    /// Answer every matching request with `status` and `body`
+    pub fn on<B: Into<String>>(self, method: &str, path: &str, status: u16, body: B) -> Self {
+        let body = body.into();
+        self.route(method, path, false, Arc::new(move |_| Ok(respond(status, body.clone()))))
+    }
+
+    /// Answer the next matching request with `status` and `body`
+    pub fn once<B: Into<String>>(self, method: &str, path: &str, status: u16, body: B) -> Self {
+        let body = body.into();
+        self.route(method, path, true, Arc::new(move |_| Ok(respond(status, body.clone()))))
+    }
+
+    /// Answer every matching request with what `f` returns
+    pub fn handle<F>(self, method: &str, path: &str, f: F) -> Self
+    where
+        F: Fn(&Seen) -> http::Response<Body> + Send + Sync + 'static,
+    {
+        self.route(method, path, false, Arc::new(move |req| Ok(f(req))))
+    }
+
+    /// Keep the objects written to matching paths in `objects`
+    pub fn store(self, path: &str, objects: &Objects) -> Self {
+        let objects = objects.clone();
+        self.handle("*", path, move |req| objects.serve(req))
+    }
+
+    /// Fail every matching request as if the apiserver could not be reached
+    pub fn unreachable(self, method: &str, path: &str) -> Self {
+        self.route(method, path, false, Arc::new(|req| {
+            Err(ErrorKind::RequestSend(format!("{}{}", req.base, req.uri)).into())
+        }))
+    }
+
+    /// Every request so far, in order
+    pub fn requests(&self) -> Vec<Seen> {
+        self.log.lock().unwrap().clone()
+    }
+
+    /// Every request so far as `METHOD uri`
+    pub fn lines(&self) -> Vec<String> {
+        self.log.lock().unwrap().iter().map(|r| r.to_string()).collect()
+    }
+
+    /// A client sending its requests here
+    pub fn client(&self) -> APIClient {
+        APIClient::with_transport("https://k8s.example.com", self.clone())
+    }
+}
+
+impl Transport for Fake {
+    fn execute(&self, req: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        let (parts, body) = req.into_parts();
+        let uri = parts.uri.path_and_query().map(|p| p.to_string()).unwrap_or_else(|| parts.uri.to_string());
+        let base = match (parts.uri.scheme_part(), parts.uri.authority_part()) {
+            (Some(scheme), Some(host)) => format!("{}://{}", scheme, host),
+            _ => String::new(),
+        };
+        let seen = Seen { method: parts.method.to_string(), base, uri, headers: parts.headers, body };
+        self.log.lock().unwrap().push(seen.clone());
+        let handler = {
+            let mut routes = self.routes.lock().unwrap();
+            let pos = routes.iter().position(|r| r.once && r.matches(&seen))
+                .or_else(|| routes.iter().position(|r| !r.once && r.matches(&seen)));
+            match pos {
+                Some(i) if routes[i].once => Some(routes.remove(i).handler),
+                Some(i) => Some(routes[i].handler.clone()),
+                None => None,
+            }
+        };
+        // run unlocked, so handlers may block or send requests of their own
+        match handler {
+            Some(h) => h(&seen),
+            None => Ok(respond(404, status(404, "NotFound"))),
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! A `Transport` injecting faults, for testing how controllers cope with them
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::client::{Body, Transport};
+use crate::{Error, ErrorKind, Result};
+
+/// A fault to inject into a request
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// Delay the request, then send it
+    Latency(Duration),
+    /// Answer with this status and a matching `Status` body, without sending the request
+    ///
+    /// `429` answers come with a `Retry-After` of one second.
+    Status(u16),
+    /// Fail as if the connection could not be made or was reset
+    DropConnection,
+    /// Send the request, but cut the response body off after this many bytes
+    ///
+    /// Reading past the cut fails like a connection dropped mid response, which is
+    /// how a watch typically ends when a load balancer times it out.
+    Truncate(usize),
+}
+
+/// When and to which requests a `Fault` applies
+#[derive(Clone, Debug)]
+pub struct FaultRule {
+    fault: Fault,
+    method: Option<String>,
+    path: Option<String>,
+    watches: bool,
+    first: Option<usize>,
+    every: Option<usize>,
+    probability: Option<f64>,
+    matched: usize,
+}
+
+impl FaultRule {
+    /// Inject `fault` into every request, until narrowed down
+    pub fn new(fault: Fault) -> Self {
+        FaultRule { fault, method: None, path: None, watches: false, first: None, every: None, probability: None, matched: 0 }
+    }
+
+    /// Only requests with this method, e.g. `PUT`
+    pub fn method(mut self, method: &str) -> Self {
+        self.method = Some(method.to_uppercase());
+        self
+    }
+
+    /// Only requests whose path contains `path`, e.g. `/configmaps/`
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    /// Only watch requests
+    pub fn watches(mut self) -> Self {
+        self.watches = true;
+        self
+    }
+
+    /// Only the first `n` matching requests
+    pub fn first(mut self, n: usize) -> Self {
+        self.first = Some(n);
+        self
+    }
+
+    /// Only every `n`th matching request
+    pub fn every(mut self, n: usize) -> Self {
+        self.every = Some(n.max(1));
+        self
+    }
+
+    /// Only a share of matching requests, drawn from the injector's seeded generator
+    pub fn probability(mut self, p: f64) -> Self {
+        self.probability = Some(p);
+        self
+    }
+
+    fn matches(&self, req: &http::Request<Vec<u8>>) -> bool {
+        let query = req.uri().query().unwrap_or("");
+        self.method.as_ref().is_none_or(|m| req.method() == m.as_str())
+            && self.path.as_ref().is_none_or(|p| req.uri().path().contains(p.as_str()))
+            && (!self.watches || query.split('&').any(|q| q == "watch=true"))
+    }
+}
+
+/// A `Transport` wrapper that injects faults into matching requests
+///
+/// Rules are checked in order, and the first that applies wins. Whether a rule
+/// applies only depends on the requests seen and the seed, so tests are repeatable.
+/// Clones share their rules and record, so keep a clone to check `injected` later.
+///
+/// ```
+/// use kube::client::{APIClient, Fault, FaultInjector, FaultRule};
+///
+/// let transport = FaultInjector::new(reqwest::Client::new())
+///     .rule(FaultRule::new(Fault::Status(409)).method("PUT").first(2))
+///     .rule(FaultRule::new(Fault::Truncate(100)).watches().every(3));
+/// let client = APIClient::with_transport("https://k8s.example.com", transport);
+/// ```
+pub struct FaultInjector<T> {
+    inner: Arc<T>,
+    rules: Arc<Mutex<Vec<FaultRule>>>,
+    rng: Arc<Mutex<u64>>,
+    injected: Arc<Mutex<Vec<Fault>>>,
+}
+
+impl<T> Clone for FaultInjector<T> {
+    fn clone(&self) -> Self {
+        FaultInjector {
+            inner: self.inner.clone(),
+            rules: self.rules.clone(),
+            rng: self.rng.clone(),
+            injected: self.injected.clone(),
+        }
+    }
+}
+
+impl<T: Transport> FaultInjector<T> {
+    pub fn new(inner: T) -> Self {
+        FaultInjector {
+            inner: Arc::new(inner),
+            rules: Arc::new(Mutex::new(vec![])),
+            rng: Arc::new(Mutex::new(0x2545_f491_4f6c_dd1d)),
+            injected: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// Add a rule, checked after those added before it
+    pub fn rule(self, rule: FaultRule) -> Self {
+        self.rules.lock().unwrap().push(rule);
+        self
+    }
+
+    /// Seed the generator behind `FaultRule::probability`
+    pub fn seed(self, seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        *self.rng.lock().unwrap() = seed.max(1);
+        self
+    }
+
+    /// The faults injected so far, in order
+    pub fn injected(&self) -> Vec<Fault> {
+        self.injected.lock().unwrap().clone()
+    }
+
+    fn random(&self) -> f64 {
+        let mut x = self.rng.lock().unwrap();
+        *x ^= *x << 13;
+        *x ^= *x >> 7;
+        *x ^= *x << 17;
+        (*x >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn fault_for(&self, req: &http::Request<Vec<u8>>) -> Option<Fault> {
+        let mut rules = self.rules.lock().unwrap();
+        for rule in rules.iter_mut().filter(|r| r.matches(req)) {
+            rule.matched += 1;
+            let n = rule.matched;
+            if rule.first.is_some_and(|first| n > first) || rule.every.is_some_and(|every| n % every != 0) {
+                continue;
+            }
+            if let Some(p) = rule.probability {
+                if self.random() >= p {
+                    continue;
+                }
+            }
+            return Some(rule.fault.clone());
+        }
+        None
+    }
+}
+
+/// A body failing after `remaining` bytes
+struct Truncated {
+    inner: Body,
+    remaining: usize,
+}
+
+impl Read for Truncated {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 {
+            return Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset by fault injection"));
+        }
+        let max = buf.len().min(self.remaining);
+        let n = self.inner.read(&mut buf[..max])?;
+        self.remaining -= n;
+        Ok(n)
+    }
+}
+
+fn status_response(code: u16) -> Result<http::Response<Body>> {
+    let status = http::StatusCode::from_u16(code).map_err(|_| ErrorKind::RequestBuild)?;
+    let reason = status.canonical_reason().unwrap_or("Unknown").replace(' ', "");
+    let body = serde_json::json!({
+        "kind": "Status", "apiVersion": "v1", "metadata": {},
+        "status": "Failure", "message": "injected fault", "reason": reason, "code": code,
+    }).to_string();
+    let mut res = http::Response::builder();
+    res.status(status);
+    if code == 429 {
+        res.header("Retry-After", "1");
+    }
+    Ok(res.body(Box::new(io::Cursor::new(body)) as Body).map_err(|_| ErrorKind::RequestBuild)?)
+}
+
+impl<T: Transport> Transport for FaultInjector<T> {
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        let fault = match self.fault_for(&request) {
+            Some(f) => f,
+            None => return self.inner.execute(request),
+        };
+        debug!("Injecting {:?} into {} {}", fault, request.method(), request.uri());
+        self.injected.lock().unwrap().push(fault.clone());
+        match fault {
+            Fault::Latency(d) => {
+                std::thread::sleep(d);
+                self.inner.execute(request)
+            },
+            Fault::Status(code) => status_response(code),
+            Fault::DropConnection => Err(Error::from(ErrorKind::RequestSend(request.uri().to_string()))),
+            Fault::Truncate(n) => {
+                let (parts, body) = self.inner.execute(request)?.into_parts();
+                Ok(http::Response::from_parts(parts, Box::new(Truncated { inner: body, remaining: n }) as Body))
+            },
+        }
+    }
+}
+
+#[test]
+fn faults_are_injected_deterministically() {
+    use crate::client::{fake::Fake, APIClient};
+    use serde_json::Value;
+    let ok = || Fake::new().on("*", "*", 200, r#"{"items":[]}"#);
+    let transport = FaultInjector::new(ok())
+        .rule(FaultRule::new(Fault::Status(409)).method("put").first(1))
+        .rule(FaultRule::new(Fault::Status(429)).path("/secrets"))
+        .rule(FaultRule::new(Fault::Truncate(5)).watches().every(2));
+    let client = APIClient::with_transport("https://k8s.example.com", transport.clone());
+    let req = |method: &str, path: &str| http::Request::builder().method(method).uri(path).body(vec![]).unwrap();
+
+    let conflict = client.request::<Value>(req("PUT", "/api/v1/namespaces/a/configmaps/b")).unwrap_err();
+    assert_eq!(conflict.status_code(), Some(409));
+    assert!(client.request::<Value>(req("PUT", "/api/v1/namespaces/a/configmaps/b")).is_ok());
+    let throttled = client.request::<Value>(req("GET", "/api/v1/secrets")).unwrap_err();
+    assert_eq!(throttled.retry_after(), Some(Duration::from_secs(1)));
+    assert!(client.request::<Value>(req("GET", "/api/v1/pods?watch=true")).is_ok());
+    assert!(client.request::<Value>(req("GET", "/api/v1/pods?watch=true")).is_err());
+    assert_eq!(transport.injected(), vec![Fault::Status(409), Fault::Status(429), Fault::Truncate(5)]);
+
+    let sample = |seed| {
+        let t = FaultInjector::new(ok()).seed(seed).rule(FaultRule::new(Fault::DropConnection).probability(0.5));
+        (0..20).map(|_| t.execute(req("GET", "/api")).is_err()).collect::<Vec<_>>()
+    };
+    assert_eq!(sample(7), sample(7));
+    assert!(sample(7).contains(&true) && sample(7).contains(&false));
+}
@@ -0,0 +1,229 @@
+//! Splitting watch responses into events, however the chunks they arrive in are cut
+use std::io::{self, Read};
+
+use failure::ResultExt;
+use serde_json::Value;
+
+use crate::{ErrorKind, Result};
+
+/// Splits a stream of watch events into frames as chunks arrive
+///
+/// The apiserver writes one JSON object per line, but chunks, proxies and load
+/// balancers cut them anywhere: an event may span chunks, a chunk may carry many
+/// events, and proxies may drop the newlines between them. Frames are complete
+/// JSON objects, whatever separates them. A malformed line is skipped with a
+/// warning rather than failing the events after it.
+///
+/// ```
+/// use kube::client::FrameDecoder;
+///
+/// let mut frames = FrameDecoder::new();
+/// assert!(frames.push(br#"{"type":"ADDED","object":"#).is_empty());
+/// assert_eq!(frames.push(br#"{}}{"type":"DELETED","object":{}}"#).len(), 2);
+/// assert_eq!(frames.finish(), 0);
+/// ```
+#[derive(Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    /// How much of `buf` has been scanned for the end of a frame
+    scanned: usize,
+    /// Braces open at the end of the scan, outside of strings
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan what arrived since the last scan, returning whether a frame may have ended
+    ///
+    /// Frames end with the brace closing their first, or at the end of a line, which
+    /// is where malformed ones are skipped to. Everything else is left unparsed, so a
+    /// large frame arriving in many chunks is only parsed once.
+    fn scan(&mut self) -> bool {
+        let mut ended = false;
+        for &b in &self.buf[self.scanned..] {
+            match b {
+                b'\n' => ended = true,
+                _ if self.escaped => self.escaped = false,
+                b'\\' if self.in_string => self.escaped = true,
+                b'"' => self.in_string = !self.in_string,
+                _ if self.in_string => {},
+                b'{' => self.depth += 1,
+                b'}' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    ended |= self.depth == 0;
+                },
+                _ => {},
+            }
+        }
+        self.scanned = self.buf.len();
+        ended
+    }
+
+    /// Add a chunk, returning the frames it completes
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Value> {
+        self.buf.extend_from_slice(chunk);
+        if !self.scan() {
+            return vec![];
+        }
+        let mut frames = vec![];
+        let mut consumed = 0;
+        loop {
+            let rest = &self.buf[consumed..];
+            let mut stream = serde_json::Deserializer::from_slice(rest).into_iter::<Value>();
+            match stream.next() {
+                None => {
+                    consumed = self.buf.len();
+                    break;
+                },
+                Some(Ok(v)) => {
+                    consumed += stream.byte_offset();
+                    if v.is_object() {
+                        frames.push(v);
+                    } else {
+                        warn!("Skipping watch frame that is not an object: {}", v);
+                    }
+                },
+                Some(Err(ref e)) if e.is_eof() => break,
+                Some(Err(e)) => match rest.iter().position(|b| *b == b'\n') {
+                    Some(i) => {
+                        warn!("Skipping malformed watch frame {:?}: {}", String::from_utf8_lossy(&rest[..i]), e);
+                        consumed += i + 1;
+                    },
+                    // skip it once the rest of its line is here
+                    None => break,
+                },
+            }
+        }
+        self.buf.drain(..consumed);
+        if consumed > 0 {
+            // what is left starts a new frame
+            self.scanned = 0;
+            self.depth = 0;
+            self.in_string = false;
+            self.escaped = false;
+            self.scan();
+        }
+        frames
+    }
+
+    /// End the stream, returning how many bytes of a partial frame were left over
+    pub fn finish(self) -> usize {
+        let partial = self.buf.iter().filter(|b| !b.is_ascii_whitespace()).count();
+        if partial > 0 {
+            warn!("Discarding partial watch frame at end of stream: {:?}", String::from_utf8_lossy(&self.buf));
+        }
+        partial
+    }
+
+    /// Read all frames from `body` until it ends
+    ///
+    /// A body failing after some frames, like a watch whose connection was reset,
+    /// ends with the frames read so far; the caller watches again from the last one.
+    pub(crate) fn read_all<R: Read>(mut self, mut body: R, limit: Option<usize>) -> Result<Vec<Value>> {
+        let mut frames = vec![];
+        let mut chunk = [0u8; 8192];
+        let mut total = 0;
+        loop {
+            match body.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => {
+                    total += n;
+                    if let Some(limit) = limit.filter(|l| total > *l) {
+                        return Err(ErrorKind::ResponseTooLarge(limit).into());
+                    }
+                    frames.extend(self.push(&chunk[..n]));
+                },
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => {
+                    if frames.is_empty() {
+                        return Err(e).context(ErrorKind::RequestParse)?;
+                    }
+                    warn!("Watch ended early after {} events: {}", frames.len(), e);
+                    break;
+                },
+            }
+        }
+        self.finish();
+        Ok(frames)
+    }
+}
+
+/// Watch events, and a stream of them as an apiserver might send it
+#[cfg(test)]
+fn watch_stream() -> (Vec<String>, String) {
+    let events: Vec<String> = (0..40)
+        .map(|i| format!(r#"{{"type":"MODIFIED","object":{{"metadata":{{"name":"cm-{}","resourceVersion":"{}"}},
+            "spec":{{"note":"braces }}{{ and \"quotes\" \\n and ünïcödé ✓"}},"status":{{}}}}}}"#, i, i).replace('\n', ""))
+        .collect();
+    // mostly lines, but some run together or with blank lines in between
+    let mut stream = String::new();
+    for (i, e) in events.iter().enumerate() {
+        stream.push_str(e);
+        stream.push_str(match i % 7 { 0 => "", 3 => "\r\n\n", _ => "\n" });
+    }
+    (events, stream)
+}
+
+#[test]
+fn frames_survive_any_chunking() {
+    let (events, stream) = watch_stream();
+    let expected: Vec<Value> = events.iter().map(|e| serde_json::from_str(e).unwrap()).collect();
+
+    // cut the stream at pseudo random points, including inside multibyte characters
+    let mut x: u64 = 0x2545_f491_4f6c_dd1d;
+    for _ in 0..200 {
+        let (bytes, mut frames, mut decoder) = (stream.as_bytes(), vec![], FrameDecoder::new());
+        let mut at = 0;
+        while at < bytes.len() {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            let n = 1 + (x % 300) as usize;
+            let end = (at + n).min(bytes.len());
+            frames.extend(decoder.push(&bytes[at..end]));
+            at = end;
+        }
+        assert_eq!(decoder.finish(), 0);
+        assert_eq!(frames, expected);
+    }
+
+    let mut decoder = FrameDecoder::new();
+    let frames = decoder.push(format!("{}\n{{\"type\":garbage}}\n{}\n{}", events[0], events[1], &events[2][..50]).as_bytes());
+    assert_eq!(frames, expected[..2].to_vec(), "malformed lines are skipped");
+    assert_eq!(decoder.finish(), 50, "partial trailing frames are dropped");
+
+    // a large event arriving in small chunks is parsed once, not again with every chunk
+    let item = serde_json::json!({ "data": { "k": "v \\\"}\"" } }).to_string();
+    let items = vec![item; 20_000].join(",");
+    let large = format!(r#"{{"type":"ADDED","object":{{"items":[{}]}}}}"#, items);
+    let (mut decoder, mut frames) = (FrameDecoder::new(), vec![]);
+    let started = std::time::Instant::now();
+    for chunk in large.as_bytes().chunks(64) {
+        frames.extend(decoder.push(chunk));
+    }
+    assert!(started.elapsed() < std::time::Duration::from_secs(5), "took {:?}", started.elapsed());
+    assert_eq!(frames.len(), 1);
+    assert_eq!(frames[0]["object"]["items"].as_array().unwrap().len(), 20_000);
+    assert_eq!(frames[0]["object"]["items"][0]["data"]["k"], "v \\\"}\"");
+}
+
+#[cfg(feature = "faults")]
+#[test]
+fn truncated_watches_keep_earlier_events() {
+    use crate::client::{fake::Fake, APIClient, Fault, FaultInjector, FaultRule};
+    use crate::api::{Object, WatchEvent};
+    let (events, stream) = watch_stream();
+    // a watch cut off mid event keeps the events before the cut
+    let cut = stream.find(&events[5]).unwrap() + 20;
+    let transport = FaultInjector::new(Fake::new().on("GET", "/api/v1/configmaps", 200, stream.clone())).rule(FaultRule::new(Fault::Truncate(cut)));
+    let client = APIClient::with_transport("https://k8s.example.com", transport);
+    let req = http::Request::get("/api/v1/configmaps?watch=true").body(vec![]).unwrap();
+    let seen = client.request_events::<WatchEvent<Object<Value, Value>>>(req).unwrap();
+    assert_eq!(seen.len(), 5);
+    assert_eq!(seen[4].object().unwrap().metadata.resourceVersion.as_deref(), Some("4"));
+}
@@ -0,0 +1,268 @@
+//! A `Transport` building its client again when the apiserver stays unreachable
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use failure::ResultExt;
+
+use crate::client::{transport_for, APIClient, Body, Transport};
+use crate::config::Configuration;
+use crate::{Error, ErrorKind, Result};
+
+/// A change in the health of the connections of a `ReconnectingTransport`
+#[derive(Clone, Debug, PartialEq)]
+pub enum HealthEvent {
+    /// Requests or probes could not reach the apiserver this many times in a row
+    Unhealthy { failures: u32, error: String },
+    /// The client was built again from a freshly loaded configuration
+    Rebuilt { base_path: String },
+    /// Loading the configuration failed, so the current client is kept
+    RebuildFailed { error: String },
+    /// The apiserver was reached again after failures
+    Recovered,
+}
+
+type Loader = Box<dyn Fn() -> Result<Configuration> + Send + Sync>;
+type EventHandler = Arc<dyn Fn(&HealthEvent) + Send + Sync>;
+
+struct Health {
+    failures: u32,
+    rebuilds: u64,
+    /// Whether there were failures since the apiserver was last reached
+    unhealthy: bool,
+}
+
+struct Inner {
+    load: Loader,
+    /// The base path requests were made for, which is replaced by the current one
+    initial_base_path: String,
+    current: RwLock<(String, Arc<dyn Transport>)>,
+    health: Mutex<Health>,
+}
+
+/// A `Transport` that builds its client again when requests keep failing to connect
+///
+/// Long running clients can lose the apiserver for good without anything being wrong
+/// with it: DNS answers change after a VPN reconnects, or the cluster CA is rotated.
+/// After `failure_threshold` requests in a row fail to connect, the configuration is
+/// loaded again, e.g. from the kubeconfig, and requests go through a new client.
+/// Only transport failures count; any answer from the apiserver, even an error, is
+/// healthy. `spawn_prober` checks `/healthz` in the background, so that it also
+/// happens while no requests are made. Clones share their client and health.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use kube::{client::{HealthEvent, ReconnectingTransport}, config};
+///
+/// let transport = ReconnectingTransport::new(config::load_kube_config)
+///     .unwrap()
+///     .on_event(|event| if let HealthEvent::Rebuilt { base_path } = event {
+///         println!("reconnected to {}", base_path);
+///     });
+/// transport.spawn_prober(Duration::from_secs(30));
+/// let client = transport.client();
+/// ```
+#[derive(Clone)]
+pub struct ReconnectingTransport {
+    inner: Arc<Inner>,
+    threshold: u32,
+    handler: Option<EventHandler>,
+}
+
+impl ReconnectingTransport {
+    /// Build clients with configurations from `load`, now and whenever connections keep failing
+    pub fn new<F>(load: F) -> Result<Self>
+    where
+        F: Fn() -> Result<Configuration> + Send + Sync + 'static,
+    {
+        let configuration = load()?;
+        let base_path = configuration.base_path.clone();
+        Ok(ReconnectingTransport {
+            inner: Arc::new(Inner {
+                load: Box::new(load),
+                initial_base_path: base_path.clone(),
+                current: RwLock::new((base_path, transport_for(configuration))),
+                health: Mutex::new(Health { failures: 0, rebuilds: 0, unhealthy: false }),
+            }),
+            threshold: 3,
+            handler: None,
+        })
+    }
+
+    /// Rebuild after this many connection failures in a row, 3 by default
+    pub fn failure_threshold(mut self, failures: u32) -> Self {
+        self.threshold = failures.max(1);
+        self
+    }
+
+    /// Call `handler` whenever the health of the connections changes
+    pub fn on_event<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&HealthEvent) + Send + Sync + 'static,
+    {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// A client sending its requests through this transport
+    pub fn client(&self) -> APIClient {
+        APIClient::with_transport(&self.inner.initial_base_path, self.clone())
+    }
+
+    /// The apiserver requests currently go to
+    pub fn base_path(&self) -> String {
+        self.inner.current.read().unwrap().0.clone()
+    }
+
+    /// How many connection failures in a row there have been
+    pub fn consecutive_failures(&self) -> u32 {
+        self.inner.health.lock().unwrap().failures
+    }
+
+    /// How many times the client was built again
+    pub fn rebuilds(&self) -> u64 {
+        self.inner.health.lock().unwrap().rebuilds
+    }
+
+    fn emit(&self, event: HealthEvent) {
+        if let Some(handler) = &self.handler {
+            handler(&event);
+        }
+    }
+
+    /// Load the configuration again, and send requests through a new client
+    pub fn rebuild(&self) -> Result<()> {
+        let configuration = match (self.inner.load)() {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("Unable to reload configuration, keeping the current client: {}", e);
+                self.emit(HealthEvent::RebuildFailed { error: e.to_string() });
+                return Err(e);
+            },
+        };
+        let base_path = configuration.base_path.clone();
+        *self.inner.current.write().unwrap() = (base_path.clone(), transport_for(configuration));
+        {
+            let mut health = self.inner.health.lock().unwrap();
+            health.failures = 0;
+            health.rebuilds += 1;
+        }
+        info!("Rebuilt client for {}", base_path);
+        self.emit(HealthEvent::Rebuilt { base_path });
+        Ok(())
+    }
+
+    /// Count a request that reached the apiserver or failed to, rebuilding when due
+    fn record(&self, failure: Option<&Error>) {
+        let mut health = self.inner.health.lock().unwrap();
+        let e = match failure {
+            Some(e) => e,
+            None => {
+                health.failures = 0;
+                if std::mem::replace(&mut health.unhealthy, false) {
+                    drop(health);
+                    self.emit(HealthEvent::Recovered);
+                }
+                return;
+            },
+        };
+        health.failures += 1;
+        health.unhealthy = true;
+        let failures = health.failures;
+        drop(health);
+        debug!("Connection failure {} of {}: {}", failures, self.threshold, e);
+        self.emit(HealthEvent::Unhealthy { failures, error: e.to_string() });
+        // a failed reload is reported, and tried again after as many failures
+        if failures >= self.threshold && self.rebuild().is_err() {
+            self.inner.health.lock().unwrap().failures = 0;
+        }
+    }
+
+    /// Check that the apiserver can be reached, counting it like a request
+    pub fn probe(&self) -> Result<()> {
+        let uri = format!("{}/healthz", self.base_path());
+        let req = http::Request::get(uri).body(vec![]).context(ErrorKind::RequestBuild)?;
+        let transport = self.inner.current.read().unwrap().1.clone();
+        let res = transport.execute(req);
+        self.record(res.as_ref().err().filter(|e| is_connection_failure(e)));
+        res.map(|_| ())
+    }
+
+    /// Probe every `interval` on a background thread, until all clones are dropped
+    pub fn spawn_prober(&self, interval: Duration) -> JoinHandle<()> {
+        let weak: Weak<Inner> = Arc::downgrade(&self.inner);
+        let (threshold, handler) = (self.threshold, self.handler.clone());
+        std::thread::spawn(move || loop {
+            std::thread::sleep(interval);
+            match weak.upgrade() {
+                Some(inner) => {
+                    let _ = ReconnectingTransport { inner, threshold, handler: handler.clone() }.probe();
+                },
+                None => return,
+            }
+        })
+    }
+}
+
+fn is_connection_failure(e: &Error) -> bool {
+    matches!(e.kind(), ErrorKind::RequestSend(_))
+}
+
+impl Transport for ReconnectingTransport {
+    fn execute(&self, mut request: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        let (base_path, transport) = self.inner.current.read().unwrap().clone();
+        // a reloaded configuration may point at another server
+        let uri = request.uri().to_string();
+        if base_path != self.inner.initial_base_path && uri.starts_with(&self.inner.initial_base_path) {
+            let moved = format!("{}{}", base_path, &uri[self.inner.initial_base_path.len()..]);
+            *request.uri_mut() = moved.parse::<http::Uri>().context(ErrorKind::RequestBuild)?;
+        }
+        let res = transport.execute(request);
+        match &res {
+            Err(e) if is_connection_failure(e) => self.record(Some(e)),
+            Err(_) => {},
+            Ok(_) => self.record(None),
+        }
+        res
+    }
+}
+
+#[test]
+fn clients_are_rebuilt_after_connection_failures() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::client::fake::Fake;
+    let loads = Arc::new(AtomicUsize::new(0));
+    let l = loads.clone();
+    let events = Arc::new(Mutex::new(vec![]));
+    let e = events.clone();
+    let transport = ReconnectingTransport::new(move || {
+        // the first server is unreachable; the reloaded config points somewhere else
+        let n = l.fetch_add(1, Ordering::SeqCst);
+        Ok(Configuration::new(format!("https://k8s-{}.example.com", n), reqwest::Client::new()))
+    }).unwrap().failure_threshold(2).on_event(move |ev| e.lock().unwrap().push(ev.clone()));
+
+    // swap in canned servers for the clients the configurations would build
+    let (down, up) = (Fake::new().unreachable("*", "*"), Fake::new().on("*", "*", 200, "{}"));
+    transport.inner.current.write().unwrap().1 = Arc::new(down.clone());
+    let client = transport.client();
+    let get = || client.request::<serde_json::Value>(http::Request::get("/api").body(vec![]).unwrap());
+    assert!(get().is_err());
+    assert_eq!(transport.consecutive_failures(), 1);
+    assert!(get().is_err());
+    assert_eq!(transport.rebuilds(), 1);
+    assert_eq!(transport.base_path(), "https://k8s-1.example.com");
+    transport.inner.current.write().unwrap().1 = Arc::new(up.clone());
+    get().unwrap();
+    transport.probe().unwrap();
+    let seen: Vec<_> = down.requests().into_iter().chain(up.requests()).map(|r| format!("{}{}", r.base, r.uri)).collect();
+    assert_eq!(seen, vec![
+        "https://k8s-0.example.com/api", "https://k8s-0.example.com/api",
+        "https://k8s-1.example.com/api", "https://k8s-1.example.com/healthz",
+    ]);
+    assert_eq!(*events.lock().unwrap(), vec![
+        HealthEvent::Unhealthy { failures: 1, error: "Error executing request to https://k8s-0.example.com/api".into() },
+        HealthEvent::Unhealthy { failures: 2, error: "Error executing request to https://k8s-0.example.com/api".into() },
+        HealthEvent::Rebuilt { base_path: "https://k8s-1.example.com".into() },
+        HealthEvent::Recovered,
+    ]);
+}
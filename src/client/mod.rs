@@ -1,5 +1,21 @@
 //! A basic API client with standard kube error handling
 
+mod transport;
+#[cfg(feature = "faults")]
+mod faults;
+mod recorder;
+mod cache;
+mod clusterset;
+mod webhook;
+mod frames;
+mod health;
+#[cfg(test)]
+pub(crate) mod fake;
+#[cfg(unix)]
+mod unix;
+
+use std::io::Read;
+use std::sync::Arc;
 use serde_json::Value;
 use either::{Right, Left};
 use either::Either;
@@ -8,8 +24,20 @@ use http;
 use serde::de::DeserializeOwned;
 use serde_json;
 use failure::ResultExt;
-use crate::{ApiError, Error, ErrorKind, Result};
-use crate::config::Configuration;
+use crate::{ApiError, Error, ErrorKind, Result, Throttle};
+use crate::config::{AuthTransport, Configuration};
+
+pub use self::transport::{Body, Transport};
+#[cfg(feature = "faults")]
+pub use self::faults::{Fault, FaultInjector, FaultRule};
+pub use self::recorder::{RequestRecorder, RequestSummary, RequestStats};
+pub use self::cache::ResponseCache;
+pub use self::clusterset::{ClusterSet, ClusterSource, Refreshed, CLUSTER_API_SECRET_TYPE, CLUSTER_API_NAME_LABEL};
+pub use self::webhook::WebhookClient;
+pub use self::frames::FrameDecoder;
+pub use self::health::{HealthEvent, ReconnectingTransport};
+#[cfg(unix)]
+pub use self::unix::UnixSocketTransport;
 
 
 #[allow(non_snake_case)]
@@ -55,47 +83,162 @@ pub struct Status {
     pub code: u16,
 }
 
+type WarningHandler = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Why requests are made, for tracing them in audit logs
+#[derive(Clone, Debug)]
+struct Reason {
+    text: String,
+    correlation_id: String,
+}
+
 /// APIClient requires `config::Configuration` includes client to connect with kubernetes cluster.
 #[derive(Clone)]
 pub struct APIClient {
-    configuration: Configuration,
+    base_path: String,
+    transport: Arc<dyn Transport>,
+    max_response_size: Option<usize>,
+    warning_handler: Option<WarningHandler>,
+    reason: Option<Reason>,
+}
+
+/// The transport `APIClient::new` sends requests through for a configuration
+pub(crate) fn transport_for(configuration: Configuration) -> Arc<dyn Transport> {
+    match configuration.auth {
+        Some(provider) => Arc::new(AuthTransport::new(provider, Arc::new(configuration.client), configuration.rebuild)),
+        None => Arc::new(configuration.client),
+    }
 }
 
 impl APIClient {
     pub fn new(configuration: Configuration) -> Self {
-        APIClient { configuration }
+        let base_path = configuration.base_path.clone();
+        Self::with_transport(&base_path, transport_for(configuration))
     }
 
-    fn send(&self, request: http::Request<Vec<u8>>) -> Result<reqwest::Response>
+    /// Send requests to `base_path` through a custom transport
+    pub fn with_transport<T: Transport + 'static>(base_path: &str, transport: T) -> Self {
+        APIClient {
+            base_path: base_path.to_string(),
+            transport: Arc::new(transport),
+            max_response_size: None,
+            warning_handler: None,
+            reason: None,
+        }
+    }
+
+    /// Refuse to read response bodies larger than `bytes`
+    ///
+    /// Guards against running out of memory when a list or watch returns far more
+    /// data than expected. Such responses fail with `ErrorKind::ResponseTooLarge`.
+    pub fn with_max_response_size(mut self, bytes: usize) -> Self {
+        self.max_response_size = Some(bytes);
+        self
+    }
+
+    /// Pass `Warning` headers from the apiserver to `handler` instead of logging them
+    ///
+    /// The apiserver warns about e.g. deprecated apis, or unknown fields dropped
+    /// from writes with `FieldValidation::Warn`.
+    pub fn with_warning_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&str) + Send + Sync + 'static,
     {
-        let (parts, body) = request.into_parts();
-        let uri_str = format!("{}{}", self.configuration.base_path, parts.uri);
+        self.warning_handler = Some(Arc::new(handler));
+        self
+    }
+
+    /// A client whose requests carry `reason` and a fresh correlation id
+    ///
+    /// Both are sent in a comment of the `User-Agent`, which the apiserver records in
+    /// its audit log, and in an `X-Correlation-Id` header. Each request is also logged
+    /// locally with them, so an audit entry can be traced back to the controller action
+    /// that caused it: use one such client per reconcile or operation.
+    pub fn with_reason(&self, reason: &str) -> Self {
+        let mut id = [0u8; 8];
+        // a predictable id still correlates, so do not fail requests over it
+        if openssl::rand::rand_bytes(&mut id).is_err() {
+            id = (std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64).unwrap_or(0)).to_be_bytes();
+        }
+        let mut client = self.clone();
+        client.reason = Some(Reason {
+            text: reason.to_string(),
+            correlation_id: id.iter().map(|b| format!("{:02x}", b)).collect(),
+        });
+        client
+    }
+
+    /// The correlation id sent with requests, after `with_reason`
+    pub fn correlation_id(&self) -> Option<&str> {
+        self.reason.as_ref().map(|r| r.correlation_id.as_str())
+    }
+
+    /// Read the response body, enforcing `max_response_size`
+    fn read_body(&self, res: http::Response<Body>) -> Result<String> {
+        let limit = match self.max_response_size {
+            Some(limit) => limit,
+            None => {
+                let mut text = String::new();
+                res.into_body().read_to_string(&mut text).context(ErrorKind::RequestParse)?;
+                return Ok(text);
+            },
+        };
+        let len = res.headers().get(http::header::CONTENT_LENGTH)
+            .and_then(|l| l.to_str().ok())
+            .and_then(|l| l.parse::<u64>().ok());
+        if len.unwrap_or(0) > limit as u64 {
+            return Err(ErrorKind::ResponseTooLarge(limit).into());
+        }
+        // chunked responses have no length up front, so stop reading past the limit
+        read_limited(res.into_body(), limit)
+    }
+
+    /// Send a request, reporting warnings, and return the response as is
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>>
+    {
+        let (mut parts, body) = request.into_parts();
+        let uri_str = format!("{}{}", self.base_path, parts.uri);
         trace!("{} {}", parts.method, uri_str);
         //trace!("Request body: {:?}", String::from_utf8_lossy(&body));
-        let req = match parts.method {
-            http::Method::GET => self.configuration.client.get(&uri_str),
-            http::Method::POST => self.configuration.client.post(&uri_str),
-            http::Method::DELETE => self.configuration.client.delete(&uri_str),
-            http::Method::PUT => self.configuration.client.put(&uri_str),
-            http::Method::PATCH => self.configuration.client.patch(&uri_str),
-            other => Err(ErrorKind::InvalidMethod(other.to_string()))?
-        }.headers(parts.headers).body(body).build().context(ErrorKind::RequestBuild)?;
-        //trace!("Request Headers: {:?}", req.headers());
-        Ok(self.configuration.client.execute(req).context(ErrorKind::RequestSend)?)
+        parts.uri = uri_str.parse::<http::Uri>().context(ErrorKind::RequestBuild)?;
+        let method = parts.method.clone();
+        if let Some(reason) = &self.reason {
+            info!("{} {}: {} [{}]", method, uri_str, reason.text, reason.correlation_id);
+            let ua = user_agent(parts.headers.get(http::header::USER_AGENT).and_then(|h| h.to_str().ok()), reason);
+            parts.headers.insert(http::header::USER_AGENT, ua.parse::<http::HeaderValue>().context(ErrorKind::RequestBuild)?);
+            parts.headers.insert("X-Correlation-Id", reason.correlation_id.parse::<http::HeaderValue>().context(ErrorKind::RequestBuild)?);
+        }
+        let res = self.transport.execute(http::Request::from_parts(parts, body))?;
+        trace!("{} {}", res.status().as_str(), uri_str);
+        //trace!("Response Headers: {:?}", res.headers());
+        for w in res.headers().get_all("Warning").iter().filter_map(|h| h.to_str().ok()).filter_map(parse_warning) {
+            match &self.warning_handler {
+                Some(handler) => handler(&w),
+                None => warn!("{} {}: {}", method, uri_str, w),
+            }
+        }
+        Ok(res)
     }
 
+    /// Send a request and read the body of a successful response
+    fn send(&self, request: http::Request<Vec<u8>>) -> Result<String>
+    {
+        let res = self.execute(request)?;
+        let (s, headers) = (res.status(), res.headers().clone());
+        let text = self.read_body(res)?;
+        if s.is_client_error() || s.is_server_error() {
+            let throttle = throttle_info(s, &headers, &text);
+            Err(make_api_error(&text, &s, throttle))?;
+        }
+        Ok(text)
+    }
 
     pub fn request<T>(&self, request: http::Request<Vec<u8>>) -> Result<T>
     where
         T: DeserializeOwned,
     {
-        let mut res : reqwest::Response = self.send(request)?;
-        trace!("{} {}", res.status().as_str(), res.url());
-        //trace!("Response Headers: {:?}", res.headers());
-        let s = res.status();
-        let text = res.text().context(ErrorKind::RequestParse)?;
-        res.error_for_status().map_err(|e| make_api_error(&text, e, &s))?;
-
+        let text = self.send(request)?;
         serde_json::from_str(&text).map_err(|e| {
             warn!("{}, {:?}", text, e);
             Error::from(ErrorKind::SerdeParse)
@@ -104,29 +247,16 @@ impl APIClient {
 
     pub fn request_text(&self, request: http::Request<Vec<u8>>) -> Result<String>
     {
-        let mut res : reqwest::Response = self.send(request)?;
-        trace!("{} {}", res.status().as_str(), res.url());
-        //trace!("Response Headers: {:?}", res.headers());
-        let s = res.status();
-        let text = res.text().context(ErrorKind::RequestParse)?;
-        res.error_for_status().map_err(|e| make_api_error(&text, e, &s))?;
-
-        Ok(text)
+        self.send(request)
     }
 
     pub fn request_status<T>(&self, request: http::Request<Vec<u8>>) -> Result<Either<T, Status>>
     where
         T: DeserializeOwned,
     {
-        let mut res : reqwest::Response = self.send(request)?;
-        trace!("{} {}", res.status().as_str(), res.url());
-        //trace!("Response Headers: {:?}", res.headers());
-        let s = res.status();
-        let text = res.text().context(ErrorKind::RequestParse)?;
-        res.error_for_status().map_err(|e| make_api_error(&text, e, &s))?;
-
+        let text = self.send(request)?;
         // It needs to be JSON:
-        let v: Value = serde_json::from_str(&text).context(ErrorKind::SerdeParse)?;;
+        let v: Value = serde_json::from_str(&text).context(ErrorKind::SerdeParse)?;
         if v["kind"] == "Status" {
             trace!("Status from {}", text);
             Ok(Right(serde_json::from_str::<Status>(&text).map_err(|e| {
@@ -141,28 +271,82 @@ impl APIClient {
         }
     }
 
+    /// Send a request and return the status, headers and body of a successful response
+    ///
+    /// For conditional requests, whose `304 Not Modified` is returned as is.
+    pub fn request_with_headers(&self, request: http::Request<Vec<u8>>) -> Result<(StatusCode, http::HeaderMap, String)>
+    {
+        let res = self.execute(request)?;
+        let (s, headers) = (res.status(), res.headers().clone());
+        let text = self.read_body(res)?;
+        if s.is_client_error() || s.is_server_error() {
+            let throttle = throttle_info(s, &headers, &text);
+            return Err(make_api_error(&text, &s, throttle).into());
+        }
+        Ok((s, headers, text))
+    }
+
+    /// Send a request and return the body of a successful response to read as it arrives
+    ///
+    /// For responses that do not end by themselves, like followed logs. The response size
+    /// limit does not apply.
+    pub fn request_stream(&self, request: http::Request<Vec<u8>>) -> Result<Body>
+    {
+        let res = self.execute(request)?;
+        let (s, headers) = (res.status(), res.headers().clone());
+        if s.is_client_error() || s.is_server_error() {
+            let text = self.read_body(res)?;
+            let throttle = throttle_info(s, &headers, &text);
+            return Err(make_api_error(&text, &s, throttle).into());
+        }
+        Ok(res.into_body())
+    }
+
+    /// Send a watch request and decode the events in the response
+    ///
+    /// Events are decoded as they arrive, so a watch cut off mid response still
+    /// returns the events before the cut.
     pub fn request_events<T>(&self, request: http::Request<Vec<u8>>) -> Result<Vec<T>>
     where
         T: DeserializeOwned,
     {
-        let mut res : reqwest::Response = self.send(request)?;
-        trace!("{} {}", res.status().as_str(), res.url());
-        //trace!("Response Headers: {:?}", res.headers());
-        let s = res.status();
-        let text = res.text().context(ErrorKind::RequestParse)?;
-        res.error_for_status().map_err(|e| make_api_error(&text, e, &s))?;
-
-        // Should be able to coerce result into Vec<T> at this point
-        let mut xs : Vec<T> = vec![];
-        for l in text.lines() {
-            let r = serde_json::from_str(&l).map_err(|e| {
-                warn!("{} {:?}", l, e);
+        let body = self.request_stream(request)?;
+        let frames = FrameDecoder::new().read_all(body, self.max_response_size)?;
+        frames.into_iter()
+            .map(|f| serde_json::from_value(f.clone()).map_err(|e| {
+                warn!("{} {:?}", f, e);
                 Error::from(ErrorKind::SerdeParse)
-            })?;
-            xs.push(r);
+            }))
+            .collect()
+    }
+}
+
+fn read_limited<R: Read>(body: R, limit: usize) -> Result<String> {
+    let mut buf = Vec::new();
+    body.take(limit as u64 + 1).read_to_end(&mut buf).context(ErrorKind::RequestParse)?;
+    if buf.len() > limit {
+        return Err(ErrorKind::ResponseTooLarge(limit).into());
+    }
+    Ok(String::from_utf8(buf).context(ErrorKind::RequestParse)?)
+}
+
+/// The text of a `Warning: 299 - "text"` header, as sent by the apiserver
+fn parse_warning(header: &str) -> Option<String> {
+    let mut parts = header.splitn(3, ' ');
+    if parts.next()? != "299" {
+        return None;
+    }
+    let quoted = parts.nth(1)?.strip_prefix('"')?;
+    let mut text = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(chars.next()?),
+            '"' => return Some(text),
+            c => text.push(c),
         }
-        Ok(xs)
     }
+    None
 }
 
 /// Kubernetes returned error handling
@@ -172,22 +356,123 @@ impl APIClient {
 ///
 /// In either case, present an ApiError upstream.
 /// The latter is probably a bug if encountered.
-fn make_api_error(text: &str, error: reqwest::Error, s: &StatusCode) -> ErrorKind {
+fn make_api_error(text: &str, s: &StatusCode, throttle: Option<Throttle>) -> ErrorKind {
+    let throttle = throttle.map(Box::new);
     // Print better debug when things do fail
     //trace!("Parsing error: {}", text);
-    if let Ok(errdata) = serde_json::from_str::<ApiError>(text) {
+    if let Ok(mut errdata) = serde_json::from_str::<ApiError>(text) {
         debug!("Unsuccessful: {:?}", errdata);
+        errdata.throttle = throttle;
         ErrorKind::Api(errdata)
     } else {
         warn!("Unsuccessful data error parse: {}", text);
-        // Propagate errors properly via reqwest
+        // Reconstruct what we can from the status code
         let ae = ApiError {
             status: s.to_string(),
             code: s.as_u16(),
-            message: format!("{:?}", error),
-            reason: format!("{}", error),
+            message: text.to_string(),
+            reason: s.canonical_reason().unwrap_or("Unknown").to_string(),
+            throttle,
         };
         debug!("Unsuccessful: {:?} (reconstruct)", ae);
         ErrorKind::Api(ae)
     }
 }
+
+/// Throttling details from a `429 Too Many Requests` response
+///
+/// `Retry-After` takes precedence over the `retryAfterSeconds` in the Status details.
+fn throttle_info(s: StatusCode, headers: &http::HeaderMap, text: &str) -> Option<Throttle> {
+    if s != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| headers.get(name).and_then(|h| h.to_str().ok()).map(String::from);
+    let retry_after = header("Retry-After")
+        .and_then(|r| r.trim().parse::<u64>().ok())
+        .or_else(|| {
+            serde_json::from_str::<Status>(text).ok()
+                .and_then(|s| s.details)
+                .map(|d| u64::from(d.retryAfterSeconds))
+                .filter(|secs| *secs > 0)
+        })
+        .map(std::time::Duration::from_secs);
+    let throttle = Throttle {
+        retry_after,
+        flow_schema_uid: header("X-Kubernetes-PF-FlowSchema-UID"),
+        priority_level_uid: header("X-Kubernetes-PF-PriorityLevel-UID"),
+    };
+    debug!("Throttled: {:?}", throttle);
+    Some(throttle)
+}
+
+#[test]
+fn throttle_info_from_429() {
+    let mut headers = http::HeaderMap::new();
+    assert_eq!(throttle_info(StatusCode::OK, &headers, ""), None);
+    headers.insert("X-Kubernetes-PF-FlowSchema-UID", "fs-uid".parse().unwrap());
+    headers.insert("X-Kubernetes-PF-PriorityLevel-UID", "pl-uid".parse().unwrap());
+    let body = r#"{"status":"Failure","reason":"TooManyRequests","code":429,"details":{"retryAfterSeconds":3}}"#;
+    let t = throttle_info(StatusCode::TOO_MANY_REQUESTS, &headers, body).unwrap();
+    assert_eq!(t.retry_after, Some(std::time::Duration::from_secs(3)));
+    assert_eq!(t.flow_schema_uid.as_deref(), Some("fs-uid"));
+    assert_eq!(t.priority_level_uid.as_deref(), Some("pl-uid"));
+    headers.insert("Retry-After", "7".parse().unwrap());
+    let t = throttle_info(StatusCode::TOO_MANY_REQUESTS, &headers, body).unwrap();
+    assert_eq!(t.retry_after, Some(std::time::Duration::from_secs(7)));
+}
+
+#[test]
+fn warnings_are_unquoted() {
+    let w = parse_warning(r#"299 - "unknown field \"spec.replica\"""#);
+    assert_eq!(w.as_deref(), Some(r#"unknown field "spec.replica""#));
+    assert_eq!(parse_warning("199 - \"misc\""), None);
+    assert_eq!(parse_warning("299 - unquoted"), None);
+}
+
+/// The `User-Agent` carrying a reason, as a comment after any existing agent
+fn user_agent(existing: Option<&str>, reason: &Reason) -> String {
+    // comments cannot contain parentheses, and headers only visible ascii
+    let text: String = reason.text.chars().take(128)
+        .map(|c| if c.is_ascii_graphic() && c != '(' && c != ')' || c == ' ' { c } else { '_' })
+        .collect();
+    let product = existing.map(String::from).unwrap_or_else(|| format!("kube-rs/{}", env!("CARGO_PKG_VERSION")));
+    format!("{} ({}; correlation-id={})", product, text, reason.correlation_id)
+}
+
+#[test]
+fn reasons_are_sent_as_user_agent_comments() {
+    let fake = fake::Fake::new().on("GET", "/api/v1/pods", 200, "{}");
+    let client = fake.client();
+    let scaling = client.with_reason("scale blog (hpa)\n");
+    let id = scaling.correlation_id().unwrap().to_string();
+    assert_eq!(id.len(), 16);
+    let get = || http::Request::get("/api/v1/pods").body(vec![]).unwrap();
+    scaling.request::<Value>(get()).unwrap();
+    client.request::<Value>(get()).unwrap();
+    let seen = fake.requests();
+    assert_eq!(seen[0].header("User-Agent"), format!("kube-rs/{} (scale blog _hpa__; correlation-id={})", env!("CARGO_PKG_VERSION"), id));
+    assert_eq!(seen[0].header("X-Correlation-Id"), id);
+    assert_eq!((seen[1].header("User-Agent"), seen[1].header("X-Correlation-Id")), ("", ""));
+}
+
+#[test]
+fn read_limited_rejects_large_bodies() {
+    assert_eq!(read_limited(&b"{}"[..], 2).unwrap(), "{}");
+    let err = read_limited(&b"{\"a\":1}"[..], 2).unwrap_err();
+    match err.kind() {
+        ErrorKind::ResponseTooLarge(2) => {},
+        k => panic!("unexpected error {:?}", k),
+    }
+}
+
+#[test]
+fn requests_go_through_the_transport() {
+    let fake = fake::Fake::new().on("GET", "/api/v1/namespaces/default/pods/blog", 200, r#"{"name":"blog"}"#);
+    let client = fake.client();
+    let get = |path: &str| http::Request::get(path).body(vec![]).unwrap();
+    let v: Value = client.request(get("/api/v1/namespaces/default/pods/blog")).unwrap();
+    assert_eq!(v["name"], "blog");
+    let err = client.request::<Value>(get("/api/v1/namespaces/default/pods/gone")).unwrap_err();
+    assert_eq!(err.status_code(), Some(404));
+    assert!(fake.requests().iter().all(|r| r.base == "https://k8s.example.com"));
+}
@@ -0,0 +1,221 @@
+//! A `Transport` counting requests, for estimating the load a controller puts on the apiserver
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::api::GroupVersionResource;
+use crate::client::{Body, Transport};
+use crate::Result;
+
+/// How many minutes of per minute counts are kept for `peak_per_minute`
+const KEPT_MINUTES: u64 = 60;
+
+/// The kind of request and what it was for
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestKey {
+    verb: String,
+    /// Resource with any subresource, e.g. `pods/log`, or the path of non resource requests
+    resource: GroupVersionResource,
+}
+
+/// Classify a request like the apiserver's audit log does, e.g. `list` of `pods.v1`
+fn classify(req: &http::Request<Vec<u8>>) -> RequestKey {
+    let path = req.uri().path();
+    let query = req.uri().query().unwrap_or("");
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let (group, version, rest) = match segments.as_slice() {
+        ["api", version, rest @ ..] if !rest.is_empty() => ("", *version, rest),
+        ["apis", group, version, rest @ ..] if !rest.is_empty() => (*group, *version, rest),
+        _ => {
+            let verb = req.method().as_str().to_lowercase();
+            return RequestKey { verb, resource: GroupVersionResource::new("", "", path) };
+        },
+    };
+    let (resource, name, subresource) = match rest {
+        // subresources of the namespace itself, rather than a resource in it
+        ["namespaces", name, sub @ "status"] | ["namespaces", name, sub @ "finalize"] => ("namespaces", Some(name), Some(sub)),
+        ["namespaces", _, resource, more @ ..] => (*resource, more.first(), more.get(1)),
+        [resource, more @ ..] => (*resource, more.first(), more.get(1)),
+        [] => unreachable!(),
+    };
+    let resource = match subresource {
+        Some(sub) => format!("{}/{}", resource, sub),
+        None => resource.to_string(),
+    };
+    let watch = query.split('&').any(|q| q == "watch=true" || q == "watch=1");
+    let verb = match (req.method().as_str(), name.is_some()) {
+        ("GET", _) if watch => "watch",
+        ("GET", true) => "get",
+        ("GET", false) => "list",
+        ("POST", _) => "create",
+        ("PUT", _) => "update",
+        ("PATCH", _) => "patch",
+        ("DELETE", true) => "delete",
+        ("DELETE", false) => "deletecollection",
+        (method, _) => method,
+    };
+    RequestKey { verb: verb.to_lowercase(), resource: GroupVersionResource::new(group, version, &resource) }
+}
+
+#[derive(Default)]
+struct Counts {
+    total: u64,
+    /// minutes since recording started to requests in that minute
+    minutes: BTreeMap<u64, u64>,
+}
+
+struct Recording {
+    started: Instant,
+    counts: BTreeMap<RequestKey, Counts>,
+}
+
+/// The requests made for one verb and resource
+#[derive(Clone, Debug, PartialEq)]
+pub struct RequestStats {
+    /// As in RBAC rules, e.g. `list`, `watch` or `patch`
+    pub verb: String,
+    pub resource: GroupVersionResource,
+    pub count: u64,
+    /// Average over the whole recording
+    pub per_minute: f64,
+    /// Most requests in any one minute of the last hour
+    pub peak_per_minute: u64,
+}
+
+/// Requests recorded by a `RequestRecorder`, busiest first
+#[derive(Clone, Debug)]
+pub struct RequestSummary {
+    pub elapsed: Duration,
+    pub total: u64,
+    pub requests: Vec<RequestStats>,
+}
+
+/// Prints a table like `kubectl get`, with resources as `kubectl` accepts them
+impl fmt::Display for RequestSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |gvr: &GroupVersionResource| match (gvr.group.as_str(), gvr.version.as_str()) {
+            (_, "") => gvr.resource.clone(),
+            ("", v) => format!("{}.{}", gvr.resource, v),
+            (g, v) => format!("{}.{}.{}", gvr.resource, v, g),
+        };
+        let width = self.requests.iter().map(|r| name(&r.resource).len()).max().unwrap_or(0).max(8);
+        writeln!(f, "{:<16} {:<width$} {:>8} {:>10} {:>10}", "VERB", "RESOURCE", "COUNT", "PER MIN", "PEAK/MIN", width = width)?;
+        for r in &self.requests {
+            writeln!(f, "{:<16} {:<width$} {:>8} {:>10.1} {:>10}",
+                r.verb, name(&r.resource), r.count, r.per_minute, r.peak_per_minute, width = width)?;
+        }
+        write!(f, "{} requests in {}s", self.total, self.elapsed.as_secs())
+    }
+}
+
+/// A `Transport` wrapper counting requests per verb and resource over time
+///
+/// Run a controller against a test cluster through it to see which requests dominate
+/// before deploying to a large cluster. Watches are counted when they start, so long
+/// running ones only count once. Clones share their recording.
+///
+/// ```
+/// use kube::client::{APIClient, RequestRecorder};
+///
+/// let recorder = RequestRecorder::new(reqwest::Client::new());
+/// let client = APIClient::with_transport("https://k8s.example.com", recorder.clone());
+/// // ... run the controller with the client, then
+/// println!("{}", recorder.summary());
+/// ```
+pub struct RequestRecorder<T> {
+    inner: Arc<T>,
+    recording: Arc<Mutex<Recording>>,
+}
+
+impl<T> Clone for RequestRecorder<T> {
+    fn clone(&self) -> Self {
+        RequestRecorder { inner: self.inner.clone(), recording: self.recording.clone() }
+    }
+}
+
+impl<T: Transport> RequestRecorder<T> {
+    pub fn new(inner: T) -> Self {
+        RequestRecorder {
+            inner: Arc::new(inner),
+            recording: Arc::new(Mutex::new(Recording { started: Instant::now(), counts: BTreeMap::new() })),
+        }
+    }
+
+    /// The requests so far
+    pub fn summary(&self) -> RequestSummary {
+        let recording = self.recording.lock().unwrap();
+        let elapsed = recording.started.elapsed();
+        // a short recording would otherwise extrapolate to huge rates
+        let minutes = (elapsed.as_secs_f64() / 60.0).max(1.0);
+        let mut requests: Vec<RequestStats> = recording.counts.iter().map(|(key, counts)| RequestStats {
+            verb: key.verb.clone(),
+            resource: key.resource.clone(),
+            count: counts.total,
+            per_minute: counts.total as f64 / minutes,
+            peak_per_minute: counts.minutes.values().copied().max().unwrap_or(0),
+        }).collect();
+        requests.sort_by_key(|r| std::cmp::Reverse(r.count));
+        RequestSummary { elapsed, total: requests.iter().map(|r| r.count).sum(), requests }
+    }
+
+    /// Forget the requests so far, and start recording again
+    pub fn reset(&self) {
+        let mut recording = self.recording.lock().unwrap();
+        recording.started = Instant::now();
+        recording.counts.clear();
+    }
+
+    fn record(&self, key: RequestKey) {
+        let mut recording = self.recording.lock().unwrap();
+        let minute = recording.started.elapsed().as_secs() / 60;
+        let counts = recording.counts.entry(key).or_default();
+        counts.total += 1;
+        *counts.minutes.entry(minute).or_default() += 1;
+        counts.minutes.retain(|m, _| m + KEPT_MINUTES > minute);
+    }
+}
+
+impl<T: Transport> Transport for RequestRecorder<T> {
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        self.record(classify(&request));
+        self.inner.execute(request)
+    }
+}
+
+#[test]
+fn requests_are_counted_by_verb_and_resource() {
+    use crate::client::{fake::Fake, APIClient};
+    use serde_json::Value;
+    let recorder = RequestRecorder::new(Fake::new().on("*", "*", 200, "{}"));
+    let client = APIClient::with_transport("https://k8s.example.com", recorder.clone());
+    for (method, path) in &[
+        ("GET", "/api/v1/namespaces/a/pods"),
+        ("GET", "/api/v1/pods?watch=true&resourceVersion=1"),
+        ("GET", "/api/v1/namespaces/b/pods"),
+        ("GET", "/api/v1/namespaces/a/pods/web/log"),
+        ("PATCH", "/apis/apps/v1/namespaces/a/deployments/web/status"),
+        ("DELETE", "/api/v1/namespaces/gone"),
+        ("PUT", "/api/v1/namespaces/gone/finalize"),
+        ("GET", "/api/v1/namespaces/a/status"),
+        ("GET", "/version"),
+    ] {
+        let req = http::Request::builder().method(*method).uri(*path).body(vec![]).unwrap();
+        client.request::<Value>(req).unwrap();
+    }
+    let summary = recorder.summary();
+    let rows: Vec<_> = summary.requests.iter()
+        .map(|r| format!("{} {}", r.verb, r.resource.resource))
+        .collect();
+    assert_eq!(rows[0], "list pods");
+    assert_eq!(summary.requests[0].count, 2);
+    assert_eq!(summary.requests[0].peak_per_minute, 2);
+    for row in &["watch pods", "get pods/log", "patch deployments/status", "delete namespaces",
+        "update namespaces/finalize", "get namespaces/status", "get /version"] {
+        assert!(rows.contains(&row.to_string()), "{} missing from {:?}", row, rows);
+    }
+    assert_eq!(summary.total, 9);
+    assert!(summary.to_string().contains("patch            deployments/status.v1.apps"));
+    recorder.reset();
+    assert_eq!(recorder.summary().total, 0);
+}
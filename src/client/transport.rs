@@ -0,0 +1,45 @@
+//! The http transport underneath `APIClient`
+use std::io::Read;
+use std::sync::Arc;
+use failure::ResultExt;
+use crate::{ErrorKind, Result};
+
+/// A response body, read as it arrives
+pub type Body = Box<dyn Read + Send>;
+
+/// Something that can execute http requests for an `APIClient`
+///
+/// Implemented for `reqwest::Client`, which is what `APIClient::new` uses.
+/// Implement it to send requests through a custom connector, or to
+/// instrument or stub out the requests an `APIClient` makes.
+pub trait Transport: Send + Sync {
+    /// Execute a request, whose uri includes the scheme and host of the apiserver
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>>;
+}
+
+impl Transport for reqwest::Client {
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        let (parts, body) = request.into_parts();
+        let url = parts.uri.to_string();
+        let req = self.request(parts.method, &url)
+            .headers(parts.headers)
+            .body(body)
+            .build()
+            .context(ErrorKind::RequestBuild)?;
+        //trace!("Request Headers: {:?}", req.headers());
+        let res = reqwest::Client::execute(self, req).context(ErrorKind::RequestSend(url))?;
+        let mut builder = http::Response::builder();
+        builder.status(res.status());
+        for (k, v) in res.headers() {
+            builder.header(k, v);
+        }
+        Ok(builder.body(Box::new(res) as Body).context(ErrorKind::RequestParse)?)
+    }
+}
+
+/// Shared transports, e.g. one rebuilding its connections as credentials rotate
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        (**self).execute(request)
+    }
+}
@@ -0,0 +1,102 @@
+//! A transport talking to an apiserver listening on a unix domain socket
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use failure::ResultExt;
+
+use crate::{Error, ErrorKind, Result};
+use super::{Body, Transport};
+
+/// Sends requests over a unix domain socket, without TLS or authentication
+///
+/// Requests are made with HTTP/1.0 so responses, watches included, are delimited
+/// by the connection closing rather than chunked.
+///
+/// ```no_run
+/// use kube::client::{APIClient, UnixSocketTransport};
+///
+/// let client = APIClient::with_transport("http://localhost", UnixSocketTransport::new("/var/run/kube.sock"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct UnixSocketTransport {
+    path: PathBuf,
+}
+
+impl UnixSocketTransport {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        UnixSocketTransport { path: path.as_ref().to_path_buf() }
+    }
+}
+
+fn parse_error(msg: &str) -> Error {
+    let cause = failure::err_msg(format!("Invalid response from unix socket: {}", msg));
+    Error::from(cause.context(ErrorKind::RequestParse))
+}
+
+impl Transport for UnixSocketTransport {
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        let (parts, body) = request.into_parts();
+        let socket = self.path.display().to_string();
+        let mut stream = UnixStream::connect(&self.path).context(ErrorKind::RequestSend(socket.clone()))?;
+
+        let path = parts.uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let mut head = format!("{} {} HTTP/1.0\r\nHost: localhost\r\nContent-Length: {}\r\n",
+            parts.method, path, body.len()).into_bytes();
+        for (k, v) in &parts.headers {
+            head.extend_from_slice(k.as_str().as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(v.as_bytes());
+            head.extend_from_slice(b"\r\n");
+        }
+        head.extend_from_slice(b"\r\n");
+        stream.write_all(&head).context(ErrorKind::RequestSend(socket.clone()))?;
+        stream.write_all(&body).context(ErrorKind::RequestSend(socket.clone()))?;
+
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        reader.read_line(&mut line).context(ErrorKind::RequestParse)?;
+        let status = line.split_whitespace().nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .ok_or_else(|| parse_error(line.trim()))?;
+        let mut builder = http::Response::builder();
+        builder.status(status);
+        loop {
+            line.clear();
+            reader.read_line(&mut line).context(ErrorKind::RequestParse)?;
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let mut kv = header.splitn(2, ':');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) => { builder.header(k, v.trim()); },
+                _ => return Err(parse_error(header)),
+            }
+        }
+        Ok(builder.body(Box::new(reader) as Body).context(ErrorKind::RequestParse)?)
+    }
+}
+
+#[test]
+fn request_over_unix_socket() {
+    use std::io::Read;
+    use std::os::unix::net::UnixListener;
+    use super::APIClient;
+
+    let dir = tempfile::tempdir().unwrap();
+    let sock = dir.path().join("kube.sock");
+    let listener = UnixListener::bind(&sock).unwrap();
+    let server = std::thread::spawn(move || {
+        let (mut conn, _) = listener.accept().unwrap();
+        let mut buf = [0u8; 1024];
+        let n = conn.read(&mut buf).unwrap();
+        let req = String::from_utf8_lossy(&buf[..n]).to_string();
+        conn.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"kind\":\"Namespace\"}").unwrap();
+        req
+    });
+    let client = APIClient::with_transport("http://localhost", UnixSocketTransport::new(&sock));
+    let req = http::Request::get("/api/v1/namespaces/default").body(vec![]).unwrap();
+    let ns: serde_json::Value = client.request(req).unwrap();
+    assert_eq!(ns["kind"], "Namespace");
+    assert!(server.join().unwrap().starts_with("GET /api/v1/namespaces/default HTTP/1.0\r\n"));
+}
@@ -0,0 +1,111 @@
+//! Clients for webhooks configured through a kubeconfig, as the apiserver uses them
+use failure::ResultExt;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::config::{self, Config, ConfigOptions};
+use crate::{ErrorKind, Result};
+use super::{APIClient, Transport};
+
+/// An authenticated client for a service that is not an apiserver
+///
+/// Authorization and admission webhooks, audit backends and token authenticators are
+/// configured with a kubeconfig whose cluster `server` is the full url of the webhook,
+/// such as `https://authz.example.com/authorize`. Certificates, tokens and exec plugins
+/// in it work as for an apiserver.
+///
+/// ```no_run
+/// use kube::client::WebhookClient;
+/// use serde_json::{json, Value};
+///
+/// let webhook = WebhookClient::from_file("/etc/kubernetes/authz-webhook.yaml").unwrap();
+/// let review: Value = webhook.post(&json!({
+///     "apiVersion": "authorization.k8s.io/v1", "kind": "SubjectAccessReview",
+///     "spec": { "user": "jane", "resourceAttributes": { "verb": "get", "resource": "pods" } },
+/// })).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct WebhookClient {
+    client: APIClient,
+    path: String,
+}
+
+/// Split a url into its origin and path, the root if it has none
+fn split_url(url: &str) -> Result<(&str, &str)> {
+    let start = url.find("://")
+        .ok_or_else(|| ErrorKind::KubeConfig(format!("Webhook server {:?} is not a url", url)))? + 3;
+    Ok(match url[start..].find('/') {
+        Some(i) => url.split_at(start + i),
+        None => (url, "/"),
+    })
+}
+
+impl WebhookClient {
+    /// A client for the webhook in the current context of a kubeconfig file
+    pub fn from_file(path: &str) -> Result<Self> {
+        let data = std::fs::read(path).context(ErrorKind::KubeConfig(format!("Unable to read {}", path)))?;
+        Self::from_config(&Config::from_yaml(&data)?, Default::default())
+    }
+
+    /// A client for the webhook in a parsed kubeconfig
+    pub fn from_config(config: &Config, options: ConfigOptions) -> Result<Self> {
+        let configuration = config::load_kube_config_from(config, options)?;
+        let (origin, path) = split_url(&configuration.base_path).map(|(o, p)| (o.to_string(), p.to_string()))?;
+        let configuration = config::Configuration { base_path: origin, ..configuration };
+        Ok(WebhookClient { client: APIClient::new(configuration), path })
+    }
+
+    /// Send requests to the webhook at `url` through a custom transport
+    pub fn with_transport<T: Transport + 'static>(url: &str, transport: T) -> Result<Self> {
+        let (origin, path) = split_url(url)?;
+        Ok(WebhookClient { client: APIClient::with_transport(origin, transport), path: path.into() })
+    }
+
+    /// The client, for other requests to the same service
+    pub fn client(&self) -> &APIClient {
+        &self.client
+    }
+
+    /// Post `body` as json to the webhook url, and parse the json response
+    pub fn post<T: Serialize, R: DeserializeOwned>(&self, body: &T) -> Result<R> {
+        self.post_to(&self.path, body)
+    }
+
+    /// Post `body` as json to another path on the same service
+    pub fn post_to<T: Serialize, R: DeserializeOwned>(&self, path: &str, body: &T) -> Result<R> {
+        let data = serde_json::to_vec(body).context(ErrorKind::SerdeParse)?;
+        let req = http::Request::post(path)
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .header(http::header::ACCEPT, "application/json")
+            .body(data)
+            .context(ErrorKind::RequestBuild)?;
+        self.client.request(req)
+    }
+}
+
+#[test]
+fn webhooks_are_posted_to_their_url() {
+    use serde_json::{json, Value};
+    use crate::client::fake::{respond, Fake};
+    let fake = Fake::new()
+        .handle("POST", "/authorize", |req| respond(200, req.body.clone()))
+        .on("*", "*", 403, "forbidden");
+    assert_eq!(split_url("https://authz.example.com:8443/v1/authorize").unwrap(),
+        ("https://authz.example.com:8443", "/v1/authorize"));
+    assert_eq!(split_url("https://authz.example.com").unwrap(), ("https://authz.example.com", "/"));
+    assert!(split_url("authz.example.com").is_err());
+
+    let webhook = WebhookClient::with_transport("https://authz.example.com/authorize", fake.clone()).unwrap();
+    let review: Value = webhook.post(&json!({ "kind": "SubjectAccessReview" })).unwrap();
+    assert_eq!(review["kind"], "SubjectAccessReview");
+    let denied = webhook.post_to::<_, Value>("/admit", &json!({})).unwrap_err();
+    assert_eq!(denied.status_code(), Some(403));
+    assert_eq!(fake.lines(), vec!["POST /authorize", "POST /admit"]);
+
+    let config = Config::from_yaml(br#"
+clusters: [{ name: authz, cluster: { server: "https://authz.example.com/authorize" } }]
+users: [{ name: apiserver, user: { token: secret } }]
+contexts: [{ name: webhook, context: { cluster: authz, user: apiserver } }]
+current-context: webhook
+"#).unwrap();
+    assert_eq!(WebhookClient::from_config(&config, Default::default()).unwrap().path, "/authorize");
+}
@@ -57,6 +57,8 @@ pub struct Cluster {
     pub certificate_authority: Option<String>,
     #[serde(rename = "certificate-authority-data")]
     pub certificate_authority_data: Option<String>,
+    #[serde(rename = "disable-compression")]
+    pub disable_compression: Option<bool>,
 }
 
 /// NamedAuthInfo associates name with authentication.
@@ -113,6 +115,15 @@ pub struct ExecConfig {
     pub args: Option<Vec<String>>,
     pub command: String,
     pub env: Option<Vec<HashMap<String, String>>>,
+    /// `Never`, `IfAvailable` or `Always`, for whether the plugin may prompt for input
+    #[serde(rename = "interactiveMode")]
+    pub interactive_mode: Option<String>,
+    /// Shown when the command cannot be found
+    #[serde(rename = "installHint")]
+    pub install_hint: Option<String>,
+    /// Pass the cluster's server and CA to the plugin in `KUBERNETES_EXEC_INFO`
+    #[serde(rename = "provideClusterInfo")]
+    pub provide_cluster_info: Option<bool>,
 }
 
 /// NamedContext associates name with context.
@@ -132,11 +143,21 @@ pub struct Context {
 }
 
 impl Config {
+    /// Parse a kubeconfig, e.g. one stored in a Secret
+    pub fn from_yaml(data: &[u8]) -> Result<Config> {
+        Ok(serde_yaml::from_slice(data)
+            .context(ErrorKind::KubeConfig("Unable to parse kubeconfig as yaml".into()))?)
+    }
+
     pub(crate) fn load_config<P: AsRef<Path>>(path: P) -> Result<Config> {
-        let f = File::open(path)
-            .context(ErrorKind::KubeConfig("Unable to open config file".into()))?;
+        let file_error = |reason: &str| ErrorKind::KubeConfigFile {
+            path: path.as_ref().display().to_string(),
+            reason: reason.into(),
+        };
+        let f = File::open(&path)
+            .context(file_error("unable to open file"))?;
         let config = serde_yaml::from_reader(f)
-            .context(ErrorKind::KubeConfig("Unable to parse config file as yaml".into()))?;
+            .context(file_error("unable to parse file as yaml"))?;
         Ok(config)
     }
 }
@@ -146,7 +167,7 @@ impl Cluster {
         let res = utils::data_or_file_with_base64(
             &self.certificate_authority_data,
             &self.certificate_authority,
-        ).context(ErrorKind::KubeConfig("Unable to decode base64 certificates".into()))?;
+        ).context(ErrorKind::Tls("Unable to decode base64 certificates".into()))?;
         Ok(res)
     }
 }
@@ -176,11 +197,11 @@ impl AuthInfo {
 
     pub(crate) fn load_client_certificate(&self) -> Result<Vec<u8>> {
         Ok(utils::data_or_file_with_base64(&self.client_certificate_data, &self.client_certificate)
-            .context(ErrorKind::KubeConfig("Unable to decode base64 client cert".into()))?)
+            .context(ErrorKind::Tls("Unable to decode base64 client cert".into()))?)
     }
 
     pub(crate) fn load_client_key(&self) -> Result<Vec<u8>> {
         Ok(utils::data_or_file_with_base64(&self.client_key_data, &self.client_key)
-            .context(ErrorKind::KubeConfig("Unable to decode base64 client key".into()))?)
+            .context(ErrorKind::Tls("Unable to decode base64 client key".into()))?)
     }
 }
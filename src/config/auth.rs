@@ -0,0 +1,378 @@
+//! Pluggable sources of credentials, asked for before every request
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use failure::ResultExt;
+use reqwest::{header, Certificate, Client};
+use serde_json::Value;
+use url::form_urlencoded::Serializer;
+
+use crate::client::{Body, Transport};
+use crate::config::exec::{self, ExecCredentialStatus};
+use crate::config::{utils, AuthProviderConfig, Cluster, ExecConfig};
+use crate::oauth2::CredentialsClient;
+use crate::{Error, ErrorKind, Result};
+
+/// A PEM encoded client certificate and its private key
+#[derive(Clone, PartialEq)]
+pub struct ClientCertificate {
+    pub certificate: Vec<u8>,
+    pub key: Vec<u8>,
+}
+
+/// A source of credentials for an `APIClient`
+///
+/// Consulted before every request, so implementations should hold on to what they
+/// hand out until it expires. Set one with `Configuration::auth_provider` to get
+/// credentials from e.g. Vault or an internal token service. Like the client, this
+/// is synchronous: providers may block while they fetch credentials.
+pub trait AuthProvider: Send + Sync {
+    /// The bearer token to send, if any
+    fn get_token(&self) -> Result<Option<String>>;
+
+    /// The client certificate to present, if any
+    ///
+    /// The connections of the client are set up again whenever it changes.
+    fn get_identity(&self) -> Result<Option<ClientCertificate>> {
+        Ok(None)
+    }
+
+    /// Forget the current credentials, as the apiserver rejected them
+    ///
+    /// Called on `401 Unauthorized`, before the request is tried once more.
+    fn invalidate(&self) {}
+}
+
+/// The same token for every request
+pub struct StaticToken(String);
+
+impl StaticToken {
+    pub fn new<T: Into<String>>(token: T) -> Self {
+        StaticToken(token.into())
+    }
+}
+
+impl AuthProvider for StaticToken {
+    fn get_token(&self) -> Result<Option<String>> {
+        Ok(Some(self.0.clone()))
+    }
+}
+
+/// A token read from a file, and read again every minute
+///
+/// For tokens that are rotated on disk, such as projected service account tokens.
+pub struct TokenFile {
+    path: PathBuf,
+    current: Mutex<Option<(String, DateTime<Utc>)>>,
+}
+
+impl TokenFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        TokenFile { path: path.as_ref().to_path_buf(), current: Mutex::new(None) }
+    }
+}
+
+impl AuthProvider for TokenFile {
+    fn get_token(&self) -> Result<Option<String>> {
+        let mut current = self.current.lock().unwrap();
+        if let Some((token, read)) = &*current {
+            if Utc::now() < *read + Duration::minutes(1) {
+                return Ok(Some(token.clone()));
+            }
+        }
+        let token = std::fs::read_to_string(&self.path)
+            .context(ErrorKind::Auth(format!("Unable to read token file {}", self.path.display())))?
+            .trim().to_string();
+        *current = Some((token.clone(), Utc::now()));
+        Ok(Some(token))
+    }
+
+    fn invalidate(&self) {
+        *self.current.lock().unwrap() = None;
+    }
+}
+
+/// Credentials from an exec plugin, which is run again once they expire
+pub struct ExecProvider {
+    config: ExecConfig,
+    cluster: Cluster,
+    current: Mutex<Option<(ExecCredentialStatus, Option<DateTime<Utc>>)>>,
+}
+
+impl ExecProvider {
+    /// Run the plugin of a kubeconfig user, for credentials to `cluster`
+    pub fn new(config: ExecConfig, cluster: Cluster) -> Self {
+        ExecProvider { config, cluster, current: Mutex::new(None) }
+    }
+
+    fn status(&self) -> Result<ExecCredentialStatus> {
+        let mut current = self.current.lock().unwrap();
+        match &*current {
+            Some((status, Some(at))) if Utc::now() < *at => return Ok(status.clone()),
+            Some((status, None)) => return Ok(status.clone()),
+            _ => {},
+        }
+        debug!("Running exec plugin {} for credentials", self.config.command);
+        let status = exec::auth_exec(&self.config, &self.cluster)?
+            .status
+            .ok_or_else(|| ErrorKind::Auth("exec-plugin response did not contain a status".into()))?;
+        let expiry = exec::expiry(&status)?;
+        *current = Some((status.clone(), expiry));
+        Ok(status)
+    }
+}
+
+impl AuthProvider for ExecProvider {
+    fn get_token(&self) -> Result<Option<String>> {
+        Ok(self.status()?.token)
+    }
+
+    fn get_identity(&self) -> Result<Option<ClientCertificate>> {
+        let status = self.status()?;
+        Ok(match (status.client_certificate_data, status.client_key_data) {
+            (Some(cert), Some(key)) => Some(ClientCertificate { certificate: cert.into_bytes(), key: key.into_bytes() }),
+            _ => None,
+        })
+    }
+
+    fn invalidate(&self) {
+        *self.current.lock().unwrap() = None;
+    }
+}
+
+/// The `exp` claim of a JWT, without verifying it
+fn jwt_expiry(token: &str) -> Option<DateTime<Utc>> {
+    let payload = token.split('.').nth(1)?;
+    let claims: Value = serde_json::from_slice(&base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?).ok()?;
+    Some(Utc.timestamp(claims["exp"].as_i64()?, 0))
+}
+
+/// The `id-token` of an `oidc` auth provider, refreshed once it expires
+///
+/// A new one is asked for at the token endpoint of the `idp-issuer-url` with the
+/// `refresh-token`, `client-id` and `client-secret` of the kubeconfig, as kubectl does.
+/// Refreshed tokens are not written back to the kubeconfig.
+pub struct OidcProvider {
+    config: HashMap<String, String>,
+    /// The id and refresh tokens in use
+    current: Mutex<(Option<String>, Option<String>)>,
+}
+
+impl OidcProvider {
+    pub fn new(provider: &AuthProviderConfig) -> Self {
+        let config = provider.config.clone();
+        let current = (config.get("id-token").cloned(), config.get("refresh-token").cloned());
+        OidcProvider { config, current: Mutex::new(current) }
+    }
+
+    fn http_client(&self) -> Result<Client> {
+        let mut builder = Client::builder();
+        let ca = (self.config.get("idp-certificate-authority-data").cloned(), self.config.get("idp-certificate-authority").cloned());
+        if ca.0.is_some() || ca.1.is_some() {
+            let pem = utils::data_or_file_with_base64(&ca.0, &ca.1)
+                .context(ErrorKind::Tls("Unable to load idp-certificate-authority".into()))?;
+            let cert = Certificate::from_pem(&pem).context(ErrorKind::Tls("Invalid idp-certificate-authority".into()))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        Ok(builder.build().context(ErrorKind::Auth("Unable to build oidc client".into()))?)
+    }
+
+    /// A new id token, and the refresh token to use next time
+    fn refresh(&self, refresh_token: &str) -> Result<(String, String)> {
+        let issuer = self.config.get("idp-issuer-url")
+            .ok_or_else(|| ErrorKind::Auth("oidc auth provider has no idp-issuer-url to refresh with".into()))?;
+        let client = self.http_client()?;
+        let discovery: Value = client.get(&format!("{}/.well-known/openid-configuration", issuer.trim_end_matches('/')))
+            .send()
+            .and_then(|mut res| res.error_for_status_ref().map(|_| ()).and_then(|_| res.json()))
+            .context(ErrorKind::Auth(format!("Unable to discover the oidc provider {}", issuer)))?;
+        let endpoint = discovery["token_endpoint"].as_str()
+            .ok_or_else(|| ErrorKind::Auth(format!("oidc provider {} has no token_endpoint", issuer)))?;
+        let mut form = Serializer::new(String::new());
+        form.append_pair("grant_type", "refresh_token").append_pair("refresh_token", refresh_token);
+        for (field, key) in &[("client_id", "client-id"), ("client_secret", "client-secret")] {
+            if let Some(v) = self.config.get(*key) {
+                form.append_pair(field, v);
+            }
+        }
+        let res: Value = client.post(endpoint)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(form.finish())
+            .send()
+            .and_then(|mut res| res.error_for_status_ref().map(|_| ()).and_then(|_| res.json()))
+            .context(ErrorKind::Auth("Unable to refresh oidc id-token".into()))?;
+        let id_token = res["id_token"].as_str()
+            .ok_or_else(|| ErrorKind::Auth("oidc token response did not contain an id_token".into()))?;
+        // providers may rotate refresh tokens, or keep the one given
+        let refresh_token = res["refresh_token"].as_str().unwrap_or(refresh_token);
+        Ok((id_token.to_string(), refresh_token.to_string()))
+    }
+}
+
+impl AuthProvider for OidcProvider {
+    fn get_token(&self) -> Result<Option<String>> {
+        let mut current = self.current.lock().unwrap();
+        // refreshed a little early, so tokens do not expire on the way to the apiserver
+        let fresh = |t: &String| jwt_expiry(t).is_none_or(|at| Utc::now() + Duration::seconds(10) < at);
+        match &*current {
+            (Some(id), _) if fresh(id) => Ok(Some(id.clone())),
+            (id, None) => Ok(id.clone()),
+            (_, Some(refresh_token)) => {
+                let (id, refresh_token) = self.refresh(refresh_token)?;
+                *current = (Some(id.clone()), Some(refresh_token));
+                Ok(Some(id))
+            },
+        }
+    }
+
+    fn invalidate(&self) {
+        self.current.lock().unwrap().0 = None;
+    }
+}
+
+/// The access token of a `gcp` auth provider, from application default credentials once it expires
+///
+/// The `access-token` and `expiry` of the kubeconfig are used while valid, after which
+/// tokens are requested with the service account of `GOOGLE_APPLICATION_CREDENTIALS`.
+/// An `id-token` is used as is.
+pub struct GcpProvider {
+    id_token: Option<String>,
+    current: Mutex<Option<(String, Option<DateTime<Utc>>)>>,
+}
+
+impl GcpProvider {
+    pub fn new(provider: &AuthProviderConfig) -> Self {
+        let expiry = provider.config.get("expiry")
+            .and_then(|e| DateTime::parse_from_rfc3339(e).ok())
+            .map(|e| e.with_timezone(&Utc));
+        let current = provider.config.get("access-token").map(|t| (t.clone(), expiry));
+        GcpProvider { id_token: provider.config.get("id-token").cloned(), current: Mutex::new(current) }
+    }
+}
+
+impl AuthProvider for GcpProvider {
+    fn get_token(&self) -> Result<Option<String>> {
+        if let Some(id) = &self.id_token {
+            return Ok(Some(id.clone()));
+        }
+        let mut current = self.current.lock().unwrap();
+        match &*current {
+            Some((token, Some(at))) if Utc::now() < *at => return Ok(Some(token.clone())),
+            Some((token, None)) => return Ok(Some(token.clone())),
+            _ => {},
+        }
+        let token = CredentialsClient::new()?
+            .request_token(&vec!["https://www.googleapis.com/auth/cloud-platform".to_string()])?;
+        let expiry = Utc::now() + Duration::seconds(token.expiry.unwrap_or(3600));
+        *current = Some((token.access_token.clone(), Some(expiry)));
+        Ok(Some(token.access_token))
+    }
+
+    fn invalidate(&self) {
+        *self.current.lock().unwrap() = None;
+    }
+}
+
+/// Builds a client presenting a client certificate, or none
+pub(crate) type ClientFactory = dyn Fn(Option<&ClientCertificate>) -> Result<Client> + Send + Sync;
+
+/// A `Transport` authenticating each request with the credentials of an `AuthProvider`
+pub(crate) struct AuthTransport {
+    provider: Arc<dyn AuthProvider>,
+    /// Sets up connections again for a new client certificate, when the configuration knows how
+    rebuild: Option<Arc<ClientFactory>>,
+    current: RwLock<(Option<ClientCertificate>, Arc<dyn Transport>)>,
+}
+
+impl AuthTransport {
+    pub(crate) fn new(
+        provider: Arc<dyn AuthProvider>,
+        transport: Arc<dyn Transport>,
+        rebuild: Option<Arc<ClientFactory>>,
+    ) -> Self {
+        AuthTransport { provider, rebuild, current: RwLock::new((None, transport)) }
+    }
+
+    fn transport(&self) -> Result<Arc<dyn Transport>> {
+        let identity = self.provider.get_identity()?;
+        {
+            let current = self.current.read().unwrap();
+            if current.0 == identity {
+                return Ok(current.1.clone());
+            }
+        }
+        let rebuild = self.rebuild.as_ref().ok_or_else(|| Error::from(ErrorKind::Auth(
+            "client certificates from an auth provider need a configuration loaded from a kubeconfig".into())))?;
+        let transport: Arc<dyn Transport> = Arc::new(rebuild(identity.as_ref())?);
+        *self.current.write().unwrap() = (identity, transport.clone());
+        Ok(transport)
+    }
+
+    fn send(&self, parts: &http::request::Parts, body: &[u8]) -> Result<http::Response<Body>> {
+        let mut req = http::Request::new(body.to_vec());
+        *req.method_mut() = parts.method.clone();
+        *req.uri_mut() = parts.uri.clone();
+        *req.headers_mut() = parts.headers.clone();
+        if let Some(token) = self.provider.get_token()? {
+            let value = header::HeaderValue::from_str(&format!("Bearer {}", token))
+                .context(ErrorKind::Auth("Invalid bearer token".to_string()))?;
+            req.headers_mut().insert(header::AUTHORIZATION, value);
+        }
+        self.transport()?.execute(req)
+    }
+}
+
+impl Transport for AuthTransport {
+    fn execute(&self, request: http::Request<Vec<u8>>) -> Result<http::Response<Body>> {
+        let (parts, body) = request.into_parts();
+        let res = self.send(&parts, &body)?;
+        if res.status() != http::StatusCode::UNAUTHORIZED {
+            return Ok(res);
+        }
+        // revoked or rotated early, so try once more with new credentials
+        self.provider.invalidate();
+        self.send(&parts, &body)
+    }
+}
+
+#[test]
+fn providers_are_consulted_per_request() {
+    use crate::client::fake::{respond, Fake};
+    use crate::client::APIClient;
+    struct Rotating(Mutex<u32>);
+    impl AuthProvider for Rotating {
+        fn get_token(&self) -> Result<Option<String>> {
+            Ok(Some(format!("token-{}", self.0.lock().unwrap())))
+        }
+        fn invalidate(&self) {
+            *self.0.lock().unwrap() += 1;
+        }
+    }
+    let server = Fake::new().handle("GET", "/api", |req| {
+        respond(if req.header("Authorization") == "Bearer token-0" { 401 } else { 200 }, "{}")
+    });
+    let transport = AuthTransport::new(Arc::new(Rotating(Mutex::new(0))), Arc::new(server.clone()), None);
+    let client = APIClient::with_transport("https://k8s.example.com", transport);
+    let get = || client.request::<Value>(http::Request::get("/api").body(vec![]).unwrap());
+    get().unwrap();
+    get().unwrap();
+    let seen: Vec<_> = server.requests().iter().map(|r| r.header("Authorization").to_string()).collect();
+    assert_eq!(seen, vec!["Bearer token-0", "Bearer token-1", "Bearer token-1"],
+        "a rejected token is replaced and the request retried once");
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("token");
+    std::fs::write(&path, "first\n").unwrap();
+    let file = TokenFile::new(&path);
+    assert_eq!(file.get_token().unwrap().as_deref(), Some("first"));
+    std::fs::write(&path, "second").unwrap();
+    assert_eq!(file.get_token().unwrap().as_deref(), Some("first"), "read again only after a minute");
+    file.invalidate();
+    assert_eq!(file.get_token().unwrap().as_deref(), Some("second"));
+
+    let exp = Utc::now().timestamp() + 600;
+    let jwt = format!("x.{}.y", base64::encode_config(&format!("{{\"exp\":{}}}", exp), base64::URL_SAFE_NO_PAD));
+    assert_eq!(jwt_expiry(&jwt), Some(Utc.timestamp(exp, 0)));
+}
@@ -0,0 +1,345 @@
+//! Client certificates through a CertificateSigningRequest, like kubelet TLS bootstrapping
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Utc;
+use failure::ResultExt;
+use openssl::asn1::Asn1Time;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::x509::{X509, X509NameBuilder, X509Req};
+use serde_json::{json, Value};
+
+use crate::api::{PostParams, RawApi};
+use crate::api::wait::poll_until;
+use crate::client::APIClient;
+use crate::config::{load_kube_config_from, AuthInfo, Config, ConfigOptions, Configuration, Context};
+use crate::config::apis::{NamedAuthInfo, NamedCluster, NamedContext};
+use crate::config::kube_config::KubeConfigLoader;
+use crate::{Error, ErrorKind, Result};
+
+/// The signer kubelets request their client certificates from
+pub const KUBELET_CLIENT_SIGNER: &str = "kubernetes.io/kube-apiserver-client-kubelet";
+
+/// Certificates expiring sooner than this are requested again rather than reused
+const MIN_REMAINING: i64 = 60 * 60;
+
+fn tls_err(msg: &str) -> ErrorKind {
+    ErrorKind::Tls(msg.to_string())
+}
+
+/// Obtains a client certificate by submitting a CSR with bootstrap credentials
+///
+/// The bootstrap kubeconfig only needs permission to create and read CSRs, like the
+/// `system:node-bootstrapper` role. Someone, or an auto-approver, must approve the
+/// request. The result is a kubeconfig for the same cluster authenticating with the
+/// signed certificate, reused on later runs while valid if `persist` is set.
+///
+/// ```no_run
+/// use kube::config::{self, Config, TlsBootstrap};
+///
+/// let bootstrap = Config::from_yaml(&std::fs::read("bootstrap-kubeconfig").unwrap()).unwrap();
+/// let configuration = TlsBootstrap::new(bootstrap, "system:node:edge-1")
+///     .organization("system:nodes")
+///     .persist("/var/lib/edge/kubeconfig")
+///     .run()
+///     .unwrap();
+/// ```
+pub struct TlsBootstrap {
+    bootstrap: Config,
+    common_name: String,
+    organizations: Vec<String>,
+    signer_name: String,
+    expiration: Option<Duration>,
+    timeout: Duration,
+    persist: Option<PathBuf>,
+}
+
+impl TlsBootstrap {
+    /// Request a certificate for the user `common_name`, from the kubelet client signer
+    pub fn new(bootstrap: Config, common_name: &str) -> Self {
+        TlsBootstrap {
+            bootstrap,
+            common_name: common_name.into(),
+            organizations: vec![],
+            signer_name: KUBELET_CLIENT_SIGNER.into(),
+            expiration: None,
+            timeout: Duration::from_secs(15 * 60),
+            persist: None,
+        }
+    }
+
+    /// Add a group the user is in, e.g. `system:nodes`
+    pub fn organization(mut self, group: &str) -> Self {
+        self.organizations.push(group.into());
+        self
+    }
+
+    /// Request from another signer, such as `kubernetes.io/kube-apiserver-client`
+    pub fn signer_name(mut self, signer: &str) -> Self {
+        self.signer_name = signer.into();
+        self
+    }
+
+    /// Ask for a certificate valid this long; signers may issue shorter ones
+    pub fn expiration(mut self, validity: Duration) -> Self {
+        self.expiration = Some(validity);
+        self
+    }
+
+    /// How long to wait for approval. Defaults to 15 minutes
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Keep the resulting kubeconfig in a file readable only by us
+    pub fn persist<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.persist = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Obtain a certificate, or reuse the persisted one, and build a client config with it
+    pub fn run(&self) -> Result<Configuration> {
+        if let Some(config) = self.persisted() {
+            return load_kube_config_from(&config, ConfigOptions::default());
+        }
+        let bootstrap = load_kube_config_from(&self.bootstrap, ConfigOptions::default())?;
+        let config = self.request_certificate(&APIClient::new(bootstrap))?;
+        if let Some(path) = &self.persist {
+            write_private(path, serde_yaml::to_string(&config).context(ErrorKind::SerdeParse)?.as_bytes())?;
+        }
+        load_kube_config_from(&config, ConfigOptions::default())
+    }
+
+    /// Submit a CSR through `client`, and return a kubeconfig using the signed certificate
+    pub fn request_certificate(&self, client: &APIClient) -> Result<Config> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).context(tls_err("Unable to create curve"))?;
+        let ec = EcKey::generate(&group).context(tls_err("Unable to generate key"))?;
+        let key = PKey::from_ec_key(ec).context(tls_err("Unable to generate key"))?;
+        let mut name = X509NameBuilder::new().context(tls_err("Unable to build name"))?;
+        for o in &self.organizations {
+            name.append_entry_by_nid(Nid::ORGANIZATIONNAME, o).context(tls_err("Invalid organization"))?;
+        }
+        name.append_entry_by_nid(Nid::COMMONNAME, &self.common_name).context(tls_err("Invalid common name"))?;
+        let mut req = X509Req::builder().context(tls_err("Unable to build CSR"))?;
+        req.set_subject_name(&name.build()).context(tls_err("Unable to build CSR"))?;
+        req.set_pubkey(&key).context(tls_err("Unable to build CSR"))?;
+        req.sign(&key, MessageDigest::sha256()).context(tls_err("Unable to sign CSR"))?;
+        let pem = req.build().to_pem().context(tls_err("Unable to encode CSR"))?;
+
+        let mut spec = json!({
+            "request": base64::encode(&pem),
+            "signerName": self.signer_name,
+            "usages": ["digital signature", "key encipherment", "client auth"],
+        });
+        if let Some(validity) = self.expiration {
+            spec["expirationSeconds"] = validity.as_secs().into();
+        }
+        let create = |version: &str| -> Result<(RawApi, Value)> {
+            let api = RawApi {
+                group: "certificates.k8s.io".into(),
+                version: version.into(),
+                resource: "certificatesigningrequests".into(),
+                prefix: "apis".into(),
+                namespace: None,
+            };
+            let csr = json!({
+                "apiVersion": format!("certificates.k8s.io/{}", version),
+                "kind": "CertificateSigningRequest",
+                "metadata": { "generateName": "csr-" },
+                "spec": spec,
+            });
+            let data = serde_json::to_vec(&csr).context(ErrorKind::SerdeParse)?;
+            let created = client.request(api.create(&PostParams::default(), data)?)?;
+            Ok((api, created))
+        };
+        // certificates.k8s.io/v1 is only served from 1.19
+        let (api, created) = match create("v1") {
+            Err(ref e) if e.status_code() == Some(404) => {
+                debug!("certificates.k8s.io/v1 is not served, falling back to v1beta1");
+                create("v1beta1")?
+            },
+            res => res?,
+        };
+        let name = created["metadata"]["name"].as_str().unwrap_or_default().to_string();
+        info!("Waiting for approval of certificate signing request {}", name);
+
+        let cert = poll_until(&format!("certificate signing request {} to be signed", name), self.timeout, || {
+            let csr: Value = client.request(api.get(&name)?)?;
+            let conds = csr["status"]["conditions"].as_array().cloned().unwrap_or_default();
+            if let Some(c) = conds.iter().find(|c| c["type"] == "Denied" || c["type"] == "Failed") {
+                return Err(Error::from(ErrorKind::Auth(format!(
+                    "certificate signing request {} {}: {}", name, c["type"].as_str().unwrap_or_default().to_lowercase(),
+                    c["message"].as_str().unwrap_or_default(),
+                ))));
+            }
+            Ok(csr["status"]["certificate"].as_str().filter(|c| !c.is_empty()).map(String::from))
+        })?;
+        let key = key.private_key_to_pem_pkcs8().context(tls_err("Unable to encode key"))?;
+        self.certificate_config(cert, base64::encode(&key))
+    }
+
+    /// The bootstrap kubeconfig's cluster, with a user holding the certificate and key
+    fn certificate_config(&self, cert: String, key: String) -> Result<Config> {
+        let loader = KubeConfigLoader::from_config(&self.bootstrap, None, None, None)?;
+        let auth_info = AuthInfo {
+            username: None,
+            password: None,
+            token: None,
+            token_file: None,
+            client_certificate: None,
+            client_certificate_data: Some(cert),
+            client_key: None,
+            client_key_data: Some(key),
+            impersonate: None,
+            impersonate_groups: None,
+            auth_provider: None,
+            exec: None,
+        };
+        // the names kubelets use for their certificate kubeconfig
+        Ok(Config {
+            kind: Some("Config".into()),
+            api_version: Some("v1".into()),
+            preferences: None,
+            clusters: vec![NamedCluster { name: "default-cluster".into(), cluster: loader.cluster }],
+            auth_infos: vec![NamedAuthInfo { name: "default-auth".into(), auth_info }],
+            contexts: vec![NamedContext {
+                name: "default-context".into(),
+                context: Context {
+                    cluster: "default-cluster".into(),
+                    user: "default-auth".into(),
+                    namespace: loader.current_context.namespace,
+                    extensions: None,
+                },
+            }],
+            current_context: "default-context".into(),
+            extensions: None,
+        })
+    }
+
+    /// The persisted kubeconfig, if its certificate is still valid for a while
+    fn persisted(&self) -> Option<Config> {
+        let path = self.persist.as_ref()?;
+        if !path.exists() {
+            return None;
+        }
+        let config = Config::load_config(path).map_err(|e| warn!("Ignoring {}: {}", path.display(), e)).ok()?;
+        let cert = config.auth_infos.first()?.auth_info.load_client_certificate().ok()?;
+        let cert = X509::from_pem(&cert).ok()?;
+        let soon = Asn1Time::from_unix(Utc::now().timestamp() + MIN_REMAINING).ok()?;
+        if cert.not_after() < soon {
+            info!("Certificate in {} expires soon, requesting a new one", path.display());
+            return None;
+        }
+        Some(config)
+    }
+}
+
+fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+        .and_then(|mut f| {
+            // the mode only applies to files we create, so tighten one left from before
+            #[cfg(unix)]
+            f.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+            f.write_all(data)
+        })
+        .with_context(|_| ErrorKind::Io(path.display().to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+fn bootstrap_through(version: &str) -> (Config, Vec<String>) {
+    use crate::client::fake::{respond, Fake};
+    use openssl::x509::X509Builder;
+    use std::sync::{Arc, Mutex};
+    let path = format!("/apis/certificates.k8s.io/{}/certificatesigningrequests", version);
+    let api_version = format!("certificates.k8s.io/{}", version);
+    let signed = Arc::new(Mutex::new(String::new()));
+    let issued = signed.clone();
+    let fake = Fake::new()
+        .handle("POST", &path, move |req| {
+            let csr = req.json();
+            assert_eq!(csr["apiVersion"], api_version.as_str());
+            assert_eq!(csr["spec"]["signerName"], KUBELET_CLIENT_SIGNER);
+            let pem = base64::decode(csr["spec"]["request"].as_str().unwrap()).unwrap();
+            let csr = X509Req::from_pem(&pem).unwrap();
+            let cn = csr.subject_name().entries_by_nid(Nid::COMMONNAME).next().unwrap();
+            assert_eq!(cn.data().to_string().unwrap(), "system:node:edge-1");
+            // self-signed stands in for the cluster CA
+            let key = PKey::from_ec_key(EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap()).unwrap();
+            let mut cert = X509Builder::new().unwrap();
+            cert.set_subject_name(csr.subject_name()).unwrap();
+            cert.set_pubkey(&csr.public_key().unwrap()).unwrap();
+            cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+            cert.set_not_after(&Asn1Time::days_from_now(365).unwrap()).unwrap();
+            cert.sign(&key, MessageDigest::sha256()).unwrap();
+            *issued.lock().unwrap() = base64::encode(&cert.build().to_pem().unwrap());
+            respond(201, r#"{"metadata":{"name":"csr-abc"}}"#)
+        })
+        .handle("GET", &format!("{}/csr-abc", path), move |_| respond(200, format!(
+            r#"{{"metadata":{{"name":"csr-abc"}},"status":{{"certificate":"{}"}}}}"#, signed.lock().unwrap()
+        )));
+    let bootstrap = Config::from_yaml(br#"
+apiVersion: v1
+kind: Config
+current-context: bootstrap
+clusters:
+- name: edge
+  cluster:
+    server: https://k8s.example.com
+contexts:
+- name: bootstrap
+  context:
+    cluster: edge
+    user: bootstrap
+users:
+- name: bootstrap
+  user:
+    token: abcdef.0123456789abcdef
+"#).unwrap();
+    let config = TlsBootstrap::new(bootstrap, "system:node:edge-1")
+        .organization("system:nodes")
+        .request_certificate(&fake.client())
+        .unwrap();
+    assert_eq!(config.clusters[0].cluster.server, "https://k8s.example.com");
+    let user = &config.auth_infos[0].auth_info;
+    assert!(user.token.is_none());
+    let cert = X509::from_pem(&user.load_client_certificate().unwrap()).unwrap();
+    let key = PKey::private_key_from_pem(&user.load_client_key().unwrap()).unwrap();
+    assert!(key.public_eq(&cert.public_key().unwrap()));
+    (config, fake.lines())
+}
+
+#[test]
+fn certificates_are_requested_and_configured() {
+    let (_, requests) = bootstrap_through("v1");
+    assert!(requests.iter().all(|r| !r.contains("v1beta1")), "{:?}", requests);
+}
+
+#[test]
+fn certificates_fall_back_to_v1beta1() {
+    // a 1.15 apiserver, without certificates.k8s.io/v1
+    let (_, requests) = bootstrap_through("v1beta1");
+    assert_eq!(requests[0], "POST /apis/certificates.k8s.io/v1/certificatesigningrequests");
+}
+
+#[cfg(unix)]
+#[test]
+fn existing_files_are_made_private() {
+    use std::os::unix::fs::PermissionsExt;
+    let path = std::env::temp_dir().join(format!("kube-bootstrap-{}", std::process::id()));
+    std::fs::write(&path, b"old").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+    write_private(&path, b"new").unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(mode & 0o777, 0o600);
+}
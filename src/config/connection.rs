@@ -0,0 +1,75 @@
+//! Tuning of the connections made by the http client
+use std::time::Duration;
+use reqwest::ClientBuilder;
+
+/// Connection settings applied when building the client
+///
+/// The defaults match reqwest: HTTP/1.1 with an unbounded idle pool,
+/// no connect timeout and a 30 second request timeout.
+///
+/// TCP and HTTP/2 keep-alives cannot be configured on the blocking client.
+/// Long-lived watches behind NATs should instead keep `request_timeout` below
+/// the NAT idle timeout, and pass a matching `timeout` in `ListParams`, so that
+/// a silently dropped connection fails the request rather than stalling it.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionOptions {
+    /// Talk HTTP/2 straight away, without negotiating an upgrade
+    pub http2_prior_knowledge: bool,
+    /// Maximum idle connections to keep in the pool per host
+    pub max_idle_per_host: Option<usize>,
+    /// Disable Nagle's algorithm on the connections
+    pub tcp_nodelay: bool,
+    /// Timeout for establishing a connection
+    pub connect_timeout: Option<Duration>,
+    /// Timeout for a whole request, including reading the response
+    pub request_timeout: Option<Duration>,
+    /// Do not ask for gzip compressed responses
+    pub disable_compression: bool,
+}
+
+impl ConnectionOptions {
+    /// Apply the settings to a client builder
+    pub fn apply(&self, mut builder: ClientBuilder) -> ClientBuilder {
+        builder = builder.gzip(!self.disable_compression);
+        if self.http2_prior_knowledge {
+            builder = builder.h2_prior_knowledge();
+        }
+        if let Some(max) = self.max_idle_per_host {
+            builder = builder.max_idle_per_host(max);
+        }
+        if self.tcp_nodelay {
+            builder = builder.tcp_nodelay();
+        }
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.request_timeout {
+            builder = builder.timeout(timeout);
+        }
+        builder
+    }
+}
+
+#[test]
+fn compression_can_be_disabled() {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    // the request headers a client built with `options` sends
+    let headers_sent = |options: ConnectionOptions| {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut buf = [0; 4096];
+            let n = conn.read(&mut buf).unwrap();
+            conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}").unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_lowercase()
+        });
+        let config = crate::config::proxy_config_with(&base, &options).unwrap();
+        config.client.get(&format!("{}/version", base)).send().unwrap();
+        server.join().unwrap()
+    };
+    assert!(headers_sent(ConnectionOptions::default()).contains("accept-encoding: gzip"));
+    let options = ConnectionOptions { disable_compression: true, ..Default::default() };
+    assert!(!headers_sent(options).contains("accept-encoding: gzip"));
+}
@@ -1,8 +1,12 @@
-use std::process::Command;
+use std::io::IsTerminal;
+use std::process::{Command, Stdio};
 
+use chrono::{DateTime, Utc};
 use failure::ResultExt;
 use crate::{Error, Result, ErrorKind};
-use crate::config::{ExecConfig};
+use crate::config::{Cluster, ExecConfig};
+#[cfg(test)]
+use crate::config::Config;
 
 /// ExecCredentials is used by exec-based plugins to communicate credentials to
 /// HTTP transports.
@@ -12,13 +16,34 @@ pub struct ExecCredential {
     #[serde(rename = "apiVersion")]
     pub api_version: Option<String>,
     pub spec: Option<ExecCredentialSpec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub status: Option<ExecCredentialStatus>,
 }
 
 /// ExecCredenitalSpec holds request and runtime specific information provided
 /// by transport.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct ExecCredentialSpec {}
+pub struct ExecCredentialSpec {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interactive: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster: Option<ExecCluster>,
+}
+
+/// The cluster credentials are requested for, passed with `provideClusterInfo`
+///
+/// The kubeconfig cluster extensions client-go passes as `config` are not parsed
+/// by this crate, so are left out.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ExecCluster {
+    pub server: String,
+    #[serde(rename = "certificate-authority-data", skip_serializing_if = "Option::is_none")]
+    pub certificate_authority_data: Option<String>,
+    #[serde(rename = "insecure-skip-tls-verify", skip_serializing_if = "Option::is_none")]
+    pub insecure_skip_tls_verify: Option<bool>,
+    #[serde(rename = "disable-compression", skip_serializing_if = "Option::is_none")]
+    pub disable_compression: Option<bool>,
+}
 
 /// ExecCredentialStatus holds credentials for the transport to use.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -32,8 +57,50 @@ pub struct ExecCredentialStatus {
     pub client_key_data: Option<String>,
 }
 
-pub fn auth_exec(auth: &ExecConfig) -> Result<ExecCredential> {
+/// Whether the plugin gets to read from the terminal, as its `interactiveMode` asks
+fn interactive(auth: &ExecConfig, terminal: bool) -> Result<bool> {
+    match auth.interactive_mode.as_deref() {
+        Some("Never") => Ok(false),
+        // the default before client.authentication.k8s.io/v1 made the mode required
+        None | Some("IfAvailable") => Ok(terminal),
+        Some("Always") if terminal => Ok(true),
+        Some("Always") => Err(Error::from(ErrorKind::Auth(format!(
+            "exec plugin {} needs an interactive terminal, but stdin is not one", auth.command
+        )))),
+        Some(mode) => Err(Error::from(ErrorKind::Auth(format!("unknown exec plugin interactiveMode {}", mode)))),
+    }
+}
+
+/// The `KUBERNETES_EXEC_INFO` a plugin gets, telling it how it runs and, if asked, for which cluster
+fn exec_info(auth: &ExecConfig, cluster: &Cluster, interactive: bool) -> Result<String> {
+    let cluster = if auth.provide_cluster_info == Some(true) {
+        let ca = match (&cluster.certificate_authority_data, &cluster.certificate_authority) {
+            (None, None) => None,
+            // files are passed by content, as the plugin may resolve paths differently
+            _ => Some(base64::encode(&cluster.load_certificate_authority()?)),
+        };
+        Some(ExecCluster {
+            server: cluster.server.clone(),
+            certificate_authority_data: ca,
+            insecure_skip_tls_verify: cluster.insecure_skip_tls_verify,
+            disable_compression: cluster.disable_compression,
+        })
+    } else {
+        None
+    };
+    let info = ExecCredential {
+        kind: Some("ExecCredential".into()),
+        api_version: Some(auth.api_version.clone().unwrap_or_else(|| "client.authentication.k8s.io/v1beta1".into())),
+        spec: Some(ExecCredentialSpec { interactive: Some(interactive), cluster }),
+        status: None,
+    };
+    Ok(serde_json::to_string(&info).context(ErrorKind::SerdeParse)?)
+}
+
+pub fn auth_exec(auth: &ExecConfig, cluster: &Cluster) -> Result<ExecCredential> {
+    let interactive = interactive(auth, std::io::stdin().is_terminal())?;
     let mut cmd = Command::new(&auth.command);
+    cmd.env("KUBERNETES_EXEC_INFO", exec_info(auth, cluster, interactive)?);
     if let Some(args) = &auth.args {
         cmd.args(args);
     }
@@ -46,14 +113,130 @@ pub fn auth_exec(auth: &ExecConfig) -> Result<ExecCredential> {
             });
         cmd.envs(envs);
     }
-    let out = cmd.output()
-        .context(ErrorKind::KubeConfig("Unable to run auth exec".into()))?;
+    // prompts go to stderr, so the user needs to see it when asked for input; otherwise
+    // stdin is closed, so plugins fail instead of waiting for input that never comes
+    if interactive {
+        cmd.stdin(Stdio::inherit()).stderr(Stdio::inherit());
+    } else {
+        cmd.stdin(Stdio::null()).stderr(Stdio::piped());
+    }
+    let child = match cmd.stdout(Stdio::piped()).spawn() {
+        Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let hint = auth.install_hint.as_ref().map(|h| format!("\n\n{}", h)).unwrap_or_default();
+            return Err(Error::from(ErrorKind::Auth(format!("exec plugin {} not found{}", auth.command, hint))));
+        },
+        res => res.context(ErrorKind::Auth("Unable to run auth exec".into()))?,
+    };
+    let out = child.wait_with_output()
+        .context(ErrorKind::Auth("Unable to run auth exec".into()))?;
     if !out.status.success() {
         let err = format!("command `{:?}` failed: {:?}", cmd, out);
-        return Err(Error::from(ErrorKind::KubeConfig(err)));
+        return Err(Error::from(ErrorKind::Auth(err)));
     }
     let creds = serde_json::from_slice(&out.stdout)
-        .context(ErrorKind::KubeConfig("Unable to parse auth exec result as json".into()))?;
+        .context(ErrorKind::Auth("Unable to parse auth exec result as json".into()))?;
 
     Ok(creds)
 }
+
+/// When the credentials handed out by a plugin expire, if it says
+pub(crate) fn expiry(status: &ExecCredentialStatus) -> Result<Option<DateTime<Utc>>> {
+    match &status.expiration_timestamp {
+        Some(ts) => Ok(Some(DateTime::parse_from_rfc3339(ts)
+            .context(ErrorKind::Auth("Invalid exec-plugin expirationTimestamp".into()))?
+            .with_timezone(&Utc))),
+        None => Ok(None),
+    }
+}
+
+#[test]
+fn plugins_only_prompt_when_interactive() {
+    let plugin = |mode: Option<&str>, script: &str| ExecConfig {
+        api_version: Some("client.authentication.k8s.io/v1beta1".into()),
+        args: Some(vec!["-c".into(), script.into()]),
+        command: "sh".into(),
+        env: None,
+        interactive_mode: mode.map(String::from),
+        install_hint: Some("Install it with: brew install login".into()),
+        provide_cluster_info: None,
+    };
+    let cluster: Cluster = serde_yaml::from_str("server: https://k8s.example.com\ncertificate-authority-data: Q0E=").unwrap();
+    assert!(!interactive(&plugin(Some("Never"), ""), true).unwrap());
+    assert!(interactive(&plugin(None, ""), true).unwrap());
+    assert!(!interactive(&plugin(Some("IfAvailable"), ""), false).unwrap());
+    let err = interactive(&plugin(Some("Always"), ""), false).unwrap_err();
+    assert!(err.to_string().contains("needs an interactive terminal"));
+
+    // a prompt reads end of file rather than hanging
+    let prompting = plugin(Some("Never"), r#"echo 'MFA code:' >&2; read code; echo "{\"status\":{\"token\":\"t$code\"}}""#);
+    let creds = auth_exec(&prompting, &cluster).unwrap();
+    assert_eq!(creds.status.unwrap().token.unwrap(), "t");
+    let mut missing = plugin(Some("Never"), "");
+    missing.command = "kube-login-missing".into();
+    assert!(auth_exec(&missing, &cluster).unwrap_err().to_string().ends_with("brew install login"));
+
+    let mut scoped = plugin(Some("Never"), "");
+    assert_eq!(exec_info(&scoped, &cluster, false).unwrap(),
+        r#"{"kind":"ExecCredential","apiVersion":"client.authentication.k8s.io/v1beta1","spec":{"interactive":false}}"#);
+    scoped.provide_cluster_info = Some(true);
+    let info: serde_json::Value = serde_json::from_str(&exec_info(&scoped, &cluster, true).unwrap()).unwrap();
+    assert_eq!(info["spec"], serde_json::json!({
+        "interactive": true,
+        "cluster": { "server": "https://k8s.example.com", "certificate-authority-data": "Q0E=" },
+    }));
+}
+
+#[test]
+fn plugin_certificates_are_used_and_rotated() {
+    use openssl::{asn1::Asn1Time, ec::{EcGroup, EcKey}, hash::MessageDigest, nid::Nid, pkey::PKey, x509::{X509, X509NameBuilder}};
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "plugin-user").unwrap();
+    let name = name.build();
+    let mut cert = X509::builder().unwrap();
+    cert.set_subject_name(&name).unwrap();
+    cert.set_issuer_name(&name).unwrap();
+    cert.set_pubkey(&key).unwrap();
+    cert.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    cert.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    cert.sign(&key, MessageDigest::sha256()).unwrap();
+    let cert = cert.build();
+    let pem = |b: Vec<u8>| String::from_utf8(b).unwrap();
+
+    let dir = tempfile::tempdir().unwrap();
+    let write_credential = |expiry: &str| {
+        let credential = serde_json::json!({
+            "apiVersion": "client.authentication.k8s.io/v1beta1", "kind": "ExecCredential",
+            "status": {
+                "clientCertificateData": pem(cert.to_pem().unwrap()),
+                "clientKeyData": pem(key.private_key_to_pem_pkcs8().unwrap()),
+                "expirationTimestamp": expiry,
+            },
+        });
+        std::fs::write(dir.path().join("credential.json"), credential.to_string()).unwrap();
+    };
+    write_credential("2019-01-01T00:00:00Z");
+    let script = format!("echo run >> {0}/runs; cat {0}/credential.json", dir.path().display());
+    let config = Config::from_yaml(format!(r#"
+clusters: [{{ name: c, cluster: {{ server: "https://k8s.example.com" }} }}]
+users: [{{ name: u, user: {{ exec: {{ command: sh, args: ["-c", {:?}], interactiveMode: Never }} }} }}]
+contexts: [{{ name: c, context: {{ cluster: c, user: u }} }}]
+current-context: c
+"#, script).as_bytes()).unwrap();
+    let runs = || std::fs::read_to_string(dir.path().join("runs")).unwrap().lines().count();
+
+    let configuration = crate::config::load_kube_config_from(&config, Default::default()).unwrap();
+    let provider = configuration.auth.unwrap();
+    assert_eq!(runs(), 1);
+    let identity = provider.get_identity().unwrap().unwrap();
+    assert_eq!(identity.certificate, cert.to_pem().unwrap());
+    assert_eq!(runs(), 2);
+    write_credential("2999-01-01T00:00:00Z");
+    provider.get_identity().unwrap();
+    provider.get_token().unwrap();
+    assert_eq!(runs(), 3);
+    provider.invalidate();
+    provider.get_token().unwrap();
+    assert_eq!(runs(), 4, "rejected credentials are asked for again");
+}
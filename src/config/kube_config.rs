@@ -9,7 +9,7 @@ use crate::{Result, Error, ErrorKind};
 use crate::config::apis::{AuthInfo, Cluster, Config, Context};
 
 /// KubeConfigLoader loads current context, cluster, and authentication information.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct KubeConfigLoader {
     pub current_context: Context,
     pub cluster: Cluster,
@@ -24,20 +24,29 @@ impl KubeConfigLoader {
         user: Option<String>,
     ) -> Result<KubeConfigLoader> {
         let config = Config::load_config(path)?;
+        Self::from_config(&config, context, cluster, user)
+    }
+
+    pub fn from_config(
+        config: &Config,
+        context: Option<String>,
+        cluster: Option<String>,
+        user: Option<String>,
+    ) -> Result<KubeConfigLoader> {
         let context_name = context.as_ref().unwrap_or(&config.current_context);
         let current_context = config
             .contexts
             .iter()
             .find(|named_context| &named_context.name == context_name)
             .map(|named_context| &named_context.context)
-            .ok_or_else(|| ErrorKind::KubeConfig("Unable to load current context".into()))?;
+            .ok_or_else(|| ErrorKind::KubeConfigMissing { kind: "context".into(), name: context_name.clone() })?;
         let cluster_name = cluster.as_ref().unwrap_or(&current_context.cluster);
         let cluster = config
             .clusters
             .iter()
             .find(|named_cluster| &named_cluster.name == cluster_name)
             .map(|named_cluster| &named_cluster.cluster)
-            .ok_or_else(|| ErrorKind::KubeConfig("Unable to load cluster of context".into()))?;
+            .ok_or_else(|| ErrorKind::KubeConfigMissing { kind: "cluster".into(), name: cluster_name.clone() })?;
         let user_name = user.as_ref().unwrap_or(&current_context.user);
         let user = config
             .auth_infos
@@ -50,7 +59,7 @@ impl KubeConfigLoader {
                     Err(e) => Err(e),
                 }
             })
-            .ok_or_else(|| ErrorKind::KubeConfig("Unable to load user of context".into()))??;
+            .ok_or_else(|| ErrorKind::KubeConfigMissing { kind: "user".into(), name: user_name.clone() })??;
         Ok(KubeConfigLoader {
             current_context: current_context.clone(),
             cluster: cluster.clone(),
@@ -61,17 +70,21 @@ impl KubeConfigLoader {
     pub fn p12(&self, password: &str) -> Result<Pkcs12> {
         let client_cert = &self.user.load_client_certificate()?;
         let client_key = &self.user.load_client_key()?;
-
-        let x509 = X509::from_pem(&client_cert).context(ErrorKind::SslError)?;
-        let pkey = PKey::private_key_from_pem(&client_key).context(ErrorKind::SslError)?;
-
-        Ok(Pkcs12::builder()
-            .build(password, "kubeconfig", &pkey, &x509)
-            .context(ErrorKind::SslError)?)
+        pkcs12_from_pem(client_cert, client_key, password)
     }
 
     pub fn ca_bundle(&self) -> Option<Result<Vec<X509>>> {
         let bundle = self.cluster.load_certificate_authority().ok()?;
-        Some(X509::stack_from_pem(&bundle).map_err(|_| Error::from(ErrorKind::SslError)))
+        Some(X509::stack_from_pem(&bundle).map_err(|_| Error::from(ErrorKind::Tls("Invalid certificate authority".into()))))
     }
 }
+
+/// A client identity from PEM encoded certificate and key
+pub fn pkcs12_from_pem(cert: &[u8], key: &[u8], password: &str) -> Result<Pkcs12> {
+    let x509 = X509::from_pem(cert).context(ErrorKind::Tls("Invalid client certificate".into()))?;
+    let pkey = PKey::private_key_from_pem(key).context(ErrorKind::Tls("Invalid client key".into()))?;
+
+    Ok(Pkcs12::builder()
+        .build(password, "kubeconfig", &pkey, &x509)
+        .context(ErrorKind::Tls("Unable to build client identity".into()))?)
+}
@@ -6,10 +6,17 @@
 //! The full `Config` and child-objects are exposed here for convenience only.
 
 mod apis;
+mod auth;
+mod bootstrap;
+mod connection;
 mod exec;
 mod incluster_config;
 mod kube_config;
-mod utils;
+#[cfg(feature = "openshift")]
+mod openshift;
+pub(crate) mod utils;
+
+use std::sync::Arc;
 
 use base64;
 use failure::ResultExt;
@@ -17,12 +24,24 @@ use crate::{Error, ErrorKind, Result};
 use reqwest::{header, Certificate, Client, Identity};
 
 use self::kube_config::KubeConfigLoader;
+pub(crate) use self::auth::{AuthTransport, ClientFactory};
+pub use self::auth::{
+    AuthProvider, ClientCertificate, ExecProvider, GcpProvider, OidcProvider, StaticToken, TokenFile,
+};
+pub use self::bootstrap::{TlsBootstrap, KUBELET_CLIENT_SIGNER};
+pub use self::connection::ConnectionOptions;
+#[cfg(feature = "openshift")]
+pub use self::openshift::{openshift_login, request_openshift_token};
 
 /// Configuration stores kubernetes path and client for requests.
 #[derive(Clone)]
 pub struct Configuration {
     pub base_path: String,
     pub client: Client,
+    /// Credentials for each request, in place of those `client` was built with
+    pub(crate) auth: Option<Arc<dyn AuthProvider>>,
+    /// Builds `client` again with a client certificate from `auth`
+    pub(crate) rebuild: Option<Arc<ClientFactory>>,
 }
 
 impl Configuration {
@@ -30,8 +49,27 @@ impl Configuration {
         Configuration {
             base_path: base_path.to_owned(),
             client,
+            auth: None,
+            rebuild: None,
         }
     }
+
+    /// Authenticate requests with the credentials of `provider`, asked for before each one
+    ///
+    /// This replaces the credentials of the kubeconfig. Client certificates from the
+    /// provider can only be presented with a configuration loaded from a kubeconfig.
+    ///
+    /// ```no_run
+    /// use kube::config::{self, TokenFile};
+    ///
+    /// let config = config::load_kube_config()
+    ///     .expect("failed to load kubeconfig")
+    ///     .auth_provider(TokenFile::new("/var/run/secrets/tokens/api-token"));
+    /// ```
+    pub fn auth_provider<A: AuthProvider + 'static>(mut self, provider: A) -> Self {
+        self.auth = Some(Arc::new(provider));
+        self
+    }
 }
 
 /// Returns a config includes authentication and cluster infomation from kubeconfig file.
@@ -53,6 +91,8 @@ pub struct ConfigOptions {
     pub context: Option<String>,
     pub cluster: Option<String>,
     pub user: Option<String>,
+    /// Connection tuning for the client, including disabling compression regardless of the kubeconfig
+    pub connection: ConnectionOptions,
 }
 
 /// Returns a config includes authentication and cluster information from kubeconfig file.
@@ -70,34 +110,70 @@ pub fn load_kube_config_with(options: ConfigOptions) -> Result<Configuration> {
 
     let loader =
         KubeConfigLoader::load(kubeconfig, options.context, options.cluster, options.user)?;
-    let token = match &loader.user.token {
-        Some(token) => Some(token.clone()),
-        None => {
-            if let Some(exec) = &loader.user.exec {
-                let creds = exec::auth_exec(exec)?;
-                let status = creds
-                    .status
-                    .ok_or_else(|| ErrorKind::KubeConfig("exec-plugin response did not contain a status".into()))?;
-                status.token
-            } else {
-                None
-            }
-        }
-    };
+    configuration_from_loader(loader, &options.connection)
+}
+
+/// Returns a config from an already parsed kubeconfig, such as one read from a Secret.
+///
+/// Relative file paths in the kubeconfig are resolved against the working directory.
+pub fn load_kube_config_from(config: &Config, options: ConfigOptions) -> Result<Configuration> {
+    let loader =
+        KubeConfigLoader::from_config(config, options.context, options.cluster, options.user)?;
+    configuration_from_loader(loader, &options.connection)
+}
+
+fn configuration_from_loader(
+    loader: KubeConfigLoader,
+    connection: &ConnectionOptions,
+) -> Result<Configuration> {
+    let client = client_from_loader(&loader, connection)?;
+    let mut configuration = Configuration::new(loader.cluster.server.clone(), client);
+    let (tls, conn) = (loader.clone(), connection.clone());
+    configuration.rebuild = Some(Arc::new(move |identity: Option<&ClientCertificate>| {
+        // only the cluster's TLS settings, with the certificate as the sole credentials
+        let mut loader = tls.clone();
+        let user = &mut loader.user;
+        user.token = None;
+        user.token_file = None;
+        user.username = None;
+        user.password = None;
+        user.exec = None;
+        user.auth_provider = None;
+        user.client_certificate = None;
+        user.client_key = None;
+        user.client_certificate_data = identity.map(|i| base64::encode(&i.certificate));
+        user.client_key_data = identity.map(|i| base64::encode(&i.key));
+        client_from_loader(&loader, &conn)
+    }));
+    if let (None, Some(exec)) = (&loader.user.token, &loader.user.exec) {
+        // plugin credentials may expire, so they are asked for with each request
+        let provider = ExecProvider::new(exec.clone(), loader.cluster.clone());
+        // a missing or failing plugin is reported now rather than on the first request
+        provider.get_token()?;
+        configuration.auth = Some(Arc::new(provider));
+    }
+    Ok(configuration)
+}
+
+/// A client authenticated as the kubeconfig says, leaving exec plugins to `ExecProvider`
+fn client_from_loader(loader: &KubeConfigLoader, connection: &ConnectionOptions) -> Result<Client> {
 
-    let mut client_builder = Client::builder();
+    // responses are gzip compressed unless the cluster opts out with `disable-compression`
+    let mut connection = connection.clone();
+    connection.disable_compression |= loader.cluster.disable_compression == Some(true);
+    let mut client_builder = connection.apply(Client::builder());
 
     if let Some(bundle) = loader.ca_bundle() {
         for ca in bundle? {
-            let cert = Certificate::from_der(&ca.to_der().context(ErrorKind::SslError)?)
-                .context(ErrorKind::SslError)?;
+            let cert = Certificate::from_der(&ca.to_der().context(ErrorKind::Tls("Invalid certificate authority".into()))?)
+                .context(ErrorKind::Tls("Invalid certificate authority".into()))?;
             client_builder = client_builder.add_root_certificate(cert);
         }
     }
     match loader.p12(" ") {
         Ok(p12) => {
-            let req_p12 = Identity::from_pkcs12_der(&p12.to_der().context(ErrorKind::SslError)?, " ")
-                .context(ErrorKind::SslError)?;
+            let req_p12 = Identity::from_pkcs12_der(&p12.to_der().context(ErrorKind::Tls("Invalid client identity".into()))?, " ")
+                .context(ErrorKind::Tls("Invalid client identity".into()))?;
             client_builder = client_builder.identity(req_p12);
         }
         Err(_) => {
@@ -111,14 +187,14 @@ pub fn load_kube_config_with(options: ConfigOptions) -> Result<Configuration> {
     let mut headers = header::HeaderMap::new();
 
     match (
-        utils::data_or_file(&token, &loader.user.token_file),
-        (loader.user.username, loader.user.password),
+        utils::data_or_file(&loader.user.token, &loader.user.token_file),
+        (&loader.user.username, &loader.user.password),
     ) {
         (Ok(token), _) => {
             headers.insert(
                 header::AUTHORIZATION,
                 header::HeaderValue::from_str(&format!("Bearer {}", token))
-                    .context(ErrorKind::KubeConfig("Invalid bearer token".to_string()))?,
+                    .context(ErrorKind::Auth("Invalid bearer token".to_string()))?,
             );
         }
         (_, (Some(u), Some(p))) => {
@@ -126,19 +202,15 @@ pub fn load_kube_config_with(options: ConfigOptions) -> Result<Configuration> {
             headers.insert(
                 header::AUTHORIZATION,
                 header::HeaderValue::from_str(&format!("Basic {}", encoded))
-                    .context(ErrorKind::KubeConfig("Invalid bearer token".to_string()))?,
+                    .context(ErrorKind::Auth("Invalid bearer token".to_string()))?,
             );
         }
         _ => {}
     }
 
     let client_builder = client_builder.default_headers(headers);
-
-    Ok(Configuration::new(
-        loader.cluster.server,
-        client_builder.build()
-            .context(ErrorKind::KubeConfig("Unable to build client".to_string()))?,
-    ))
+    Ok(client_builder.build()
+        .context(ErrorKind::KubeConfig("Unable to build client".to_string()))?)
 }
 
 /// Returns a config which is used by clients within pods on kubernetes.
@@ -152,6 +224,23 @@ pub fn load_kube_config_with(options: ConfigOptions) -> Result<Configuration> {
 ///     .expect("failed to load incluster config");
 /// ```
 pub fn incluster_config() -> Result<Configuration> {
+    incluster_config_with(&ConnectionOptions::default())
+}
+
+/// Returns an in cluster config with tuned connection settings.
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+/// use kube::config::{self, ConnectionOptions};
+///
+/// let kubeconfig = config::incluster_config_with(&ConnectionOptions {
+///     max_idle_per_host: Some(4),
+///     request_timeout: Some(Duration::from_secs(330)),
+///     ..Default::default()
+/// }).expect("failed to load incluster config");
+/// ```
+pub fn incluster_config_with(connection: &ConnectionOptions) -> Result<Configuration> {
     let server = incluster_config::kube_server().ok_or_else(||
         Error::from(ErrorKind::KubeConfig(format!(
             "Unable to load incluster config, {} and {} must be defined",
@@ -159,21 +248,22 @@ pub fn incluster_config() -> Result<Configuration> {
             incluster_config::SERVICE_PORTENV
     ))))?;
 
-    let ca = incluster_config::load_cert().context(ErrorKind::SslError)?;
-    let req_ca = Certificate::from_der(&ca.to_der().context(ErrorKind::SslError)?)
-        .context(ErrorKind::SslError)?;
+    let ca = incluster_config::load_cert()
+        .context(ErrorKind::Tls("Unable to load in cluster certificate authority".into()))?;
+    let req_ca = Certificate::from_der(&ca.to_der().context(ErrorKind::Tls("Invalid certificate authority".into()))?)
+        .context(ErrorKind::Tls("Invalid certificate authority".into()))?;
 
     let token = incluster_config::load_token()
-        .context(ErrorKind::KubeConfig("Unable to load in cluster token".to_string()))?;
+        .context(ErrorKind::Auth("Unable to load in cluster token".to_string()))?;
 
     let mut headers = header::HeaderMap::new();
     headers.insert(
         header::AUTHORIZATION,
         header::HeaderValue::from_str(&format!("Bearer {}", token))
-            .context(ErrorKind::KubeConfig("Invalid bearer token".to_string()))?,
+            .context(ErrorKind::Auth("Invalid bearer token".to_string()))?,
     );
 
-    let client_builder = Client::builder()
+    let client_builder = connection.apply(Client::builder())
         .add_root_certificate(req_ca)
         .default_headers(headers);
 
@@ -185,6 +275,29 @@ pub fn incluster_config() -> Result<Configuration> {
 }
 
 
+/// Returns a config for an apiserver that needs no TLS setup or credentials,
+/// such as the one `kubectl proxy` serves on `http://127.0.0.1:8001`.
+///
+/// # Example
+/// ```no_run
+/// use kube::config;
+///
+/// let kubeconfig = config::proxy_config("http://127.0.0.1:8001")
+///     .expect("failed to build proxy config");
+/// ```
+pub fn proxy_config(base_path: &str) -> Result<Configuration> {
+    proxy_config_with(base_path, &ConnectionOptions::default())
+}
+
+/// Returns a proxy config with tuned connection settings.
+pub fn proxy_config_with(base_path: &str, connection: &ConnectionOptions) -> Result<Configuration> {
+    Ok(Configuration::new(
+        base_path.trim_end_matches('/').to_string(),
+        connection.apply(Client::builder()).build()
+            .context(ErrorKind::KubeConfig("Unable to build client".to_string()))?
+    ))
+}
+
 // Expose raw config structs
 pub use apis::{
     Config,
@@ -0,0 +1,113 @@
+//! The OAuth token login flow used by `oc login`
+use failure::ResultExt;
+use reqwest::{header, Certificate, Client, RedirectPolicy, StatusCode};
+use url::Url;
+
+use crate::{Error, ErrorKind, Result};
+use super::Configuration;
+
+/// The OAuth client OpenShift provides for non-interactive (challenge based) logins
+const CHALLENGING_CLIENT: &str = "openshift-challenging-client";
+
+#[derive(Deserialize)]
+struct OAuthMetadata {
+    authorization_endpoint: String,
+}
+
+fn client(ca_pem: Option<&[u8]>) -> Result<Client> {
+    let mut builder = Client::builder().redirect(RedirectPolicy::none());
+    if let Some(pem) = ca_pem {
+        let cert = Certificate::from_pem(pem).context(ErrorKind::Tls("Invalid CA bundle".into()))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    Ok(builder.build().context(ErrorKind::KubeConfig("Unable to build client".to_string()))?)
+}
+
+/// Extract the access token from the redirect the OAuth server answers with
+fn token_from_location(location: &str) -> Result<String> {
+    let url = Url::parse(location)
+        .context(ErrorKind::Auth("Invalid OAuth redirect location".to_string()))?;
+    let fragment = url.fragment().unwrap_or("");
+    let params = url::form_urlencoded::parse(fragment.as_bytes())
+        .chain(url.query_pairs());
+    let mut token = None;
+    for (k, v) in params {
+        match k.as_ref() {
+            "access_token" => token = Some(v.into_owned()),
+            "error" => return Err(Error::from(ErrorKind::Auth(format!("OAuth login failed: {}", v)))),
+            _ => {},
+        }
+    }
+    token.ok_or_else(|| Error::from(ErrorKind::Auth("OAuth redirect did not contain a token".into())))
+}
+
+/// Exchange a username and password for an OpenShift access token
+///
+/// Discovers the OAuth server through the apiserver's `.well-known` endpoint and asks the
+/// `openshift-challenging-client` for a token, which is what `oc login -u -p` does.
+/// `ca_pem` is the CA bundle for the apiserver and OAuth server, if not publicly trusted.
+pub fn request_openshift_token(server: &str, username: &str, password: &str, ca_pem: Option<&[u8]>) -> Result<String> {
+    let client = client(ca_pem)?;
+    let discovery = format!("{}/.well-known/oauth-authorization-server", server.trim_end_matches('/'));
+    let meta: OAuthMetadata = client.get(&discovery).send()
+        .context(ErrorKind::Auth("Unable to discover the OAuth server".to_string()))?
+        .json()
+        .context(ErrorKind::Auth("Invalid OAuth server metadata".to_string()))?;
+
+    let mut authorize = Url::parse(&meta.authorization_endpoint)
+        .context(ErrorKind::Auth("Invalid OAuth authorization endpoint".to_string()))?;
+    authorize.query_pairs_mut()
+        .append_pair("response_type", "token")
+        .append_pair("client_id", CHALLENGING_CLIENT);
+    let res = client.get(authorize)
+        .basic_auth(username, Some(password))
+        .header("X-CSRF-Token", "1")
+        .send()
+        .context(ErrorKind::Auth("OAuth token request failed".to_string()))?;
+    match res.status() {
+        StatusCode::FOUND => {
+            let location = res.headers().get(header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .ok_or_else(|| Error::from(ErrorKind::Auth("OAuth redirect without a location".into())))?;
+            token_from_location(location)
+        },
+        StatusCode::UNAUTHORIZED => Err(Error::from(ErrorKind::Auth("Invalid username or password".into()))),
+        s => Err(Error::from(ErrorKind::Auth(format!("Unexpected OAuth response {}", s)))),
+    }
+}
+
+/// Log in to an OpenShift cluster with a username and password
+///
+/// # Example
+/// ```no_run
+/// use kube::config;
+///
+/// let config = config::openshift_login("https://api.example.com:6443", "developer", "developer", None)
+///     .expect("failed to log in");
+/// ```
+pub fn openshift_login(server: &str, username: &str, password: &str, ca_pem: Option<&[u8]>) -> Result<Configuration> {
+    let token = request_openshift_token(server, username, password, ca_pem)?;
+    let mut headers = header::HeaderMap::new();
+    headers.insert(
+        header::AUTHORIZATION,
+        header::HeaderValue::from_str(&format!("Bearer {}", token))
+            .context(ErrorKind::Auth("Invalid bearer token".to_string()))?,
+    );
+    let mut builder = Client::builder().default_headers(headers);
+    if let Some(pem) = ca_pem {
+        builder = builder.add_root_certificate(Certificate::from_pem(pem).context(ErrorKind::Tls("Invalid CA bundle".into()))?);
+    }
+    Ok(Configuration::new(
+        server.to_string(),
+        builder.build()
+            .context(ErrorKind::KubeConfig("Unable to build client".to_string()))?,
+    ))
+}
+
+#[test]
+fn token_is_read_from_fragment() {
+    let loc = "https://oauth.example.com/oauth/token/implicit#access_token=sha256~abc&expires_in=86400&token_type=Bearer";
+    assert_eq!(token_from_location(loc).unwrap(), "sha256~abc");
+    let loc = "https://oauth.example.com/oauth/token/implicit?error=access_denied";
+    assert!(token_from_location(loc).is_err());
+}
@@ -19,6 +19,23 @@ pub struct ApiError {
     #[serde(default)]
     pub reason: String,
     pub code: u16,
+    /// Throttling details, set when the apiserver answered `429 Too Many Requests`
+    #[serde(skip)]
+    pub throttle: Option<Box<Throttle>>,
+}
+
+/// Why and for how long the apiserver throttled a request
+///
+/// With API Priority and Fairness enabled, the apiserver reports which
+/// flow schema and priority level the request was classified into.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Throttle {
+    /// How long to wait before retrying, from `Retry-After` or the Status details
+    pub retry_after: Option<std::time::Duration>,
+    /// From the `X-Kubernetes-PF-FlowSchema-UID` header
+    pub flow_schema_uid: Option<String>,
+    /// From the `X-Kubernetes-PF-PriorityLevel-UID` header
+    pub priority_level_uid: Option<String>,
 }
 
 #[derive(Debug, Fail)]
@@ -33,21 +50,47 @@ pub enum ErrorKind {
 
     #[fail(display = "Error building request")]
     RequestBuild,
-    #[fail(display = "Error executing request")]
-    RequestSend,
+    /// The request could not be sent, or the response not received
+    #[fail(display = "Error executing request to {}", _0)]
+    RequestSend(String),
     #[fail(display = "Error parsing response")]
     RequestParse,
+    #[fail(display = "Response exceeded the limit of {} bytes", _0)]
+    ResponseTooLarge(usize),
     #[fail(display = "Invalid API method {}", _0)]
     InvalidMethod(String),
     #[fail(display = "Request validation failed with {}", _0)]
     RequestValidation(String),
+    #[fail(display = "Conversion failed: {}", _0)]
+    Conversion(String),
+    #[fail(display = "Timed out waiting for {}", _0)]
+    Timeout(String),
+    #[fail(display = "Job failed: {}", _0)]
+    JobFailed(String),
+    #[fail(display = "Pod failed: {}", _0)]
+    PodFailed(String),
+    #[fail(display = "Leadership lost by {}", _0)]
+    LeadershipLost(String),
+    #[fail(display = "Invalid JSONPath: {}", _0)]
+    JsonPath(String),
+    #[fail(display = "I/O error on {}", _0)]
+    Io(String),
 
-    /// Configuration error
+    /// Configuration error not covered by the more specific variants below
     #[fail(display = "Error loading kube config: {}", _0)]
     KubeConfig(String),
-
-    #[fail(display = "Error deserializing response")]
-    SslError,
+    /// The kubeconfig file could not be read or parsed
+    #[fail(display = "Unable to load kubeconfig {}: {}", path, reason)]
+    KubeConfigFile { path: String, reason: String },
+    /// The context, cluster or user to use does not exist in the kubeconfig
+    #[fail(display = "No {} named {:?} in kubeconfig", kind, name)]
+    KubeConfigMissing { kind: String, name: String },
+    /// Credentials could not be obtained or used
+    #[fail(display = "Authentication failed: {}", _0)]
+    Auth(String),
+    /// Certificates or keys could not be loaded
+    #[fail(display = "TLS error: {}", _0)]
+    Tls(String),
 }
 
 use std::fmt::{self, Display};
@@ -81,6 +124,38 @@ impl Error {
             _ => None,
         }
     }
+    /// The http status code returned by the apiserver, if this is an `ApiError`
+    pub fn status_code(&self) -> Option<u16> {
+        match self.kind() {
+            ErrorKind::Api(e) => Some(e.code),
+            _ => None,
+        }
+    }
+    /// Whether the apiserver answered `404 Not Found`
+    pub fn is_not_found(&self) -> bool {
+        self.status_code() == Some(404)
+    }
+    /// Throttling details if the apiserver answered `429 Too Many Requests`
+    pub fn throttle(&self) -> Option<&Throttle> {
+        match self.kind() {
+            ErrorKind::Api(e) => e.throttle.as_deref(),
+            _ => None,
+        }
+    }
+    /// Whether the same request might succeed if tried again later
+    ///
+    /// True for transport failures, throttling and apiserver unavailability.
+    pub fn is_retryable(&self) -> bool {
+        match self.kind() {
+            ErrorKind::RequestSend(_) => true,
+            ErrorKind::Api(e) => matches!(e.code, 429 | 500 | 502 | 503 | 504),
+            _ => false,
+        }
+    }
+    /// How long the apiserver asked us to back off for, if throttled
+    pub fn retry_after(&self) -> Option<std::time::Duration> {
+        self.throttle().and_then(|t| t.retry_after)
+    }
 }
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
@@ -110,4 +185,22 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub mod client;
 pub mod config;
 pub mod api;
+#[cfg(feature = "openapi")]
+pub mod runtime;
 mod oauth2;
+
+#[test]
+fn retryable_errors() {
+    let api = |code| Error::from(ErrorKind::Api(ApiError {
+        status: "Failure".into(),
+        message: String::new(),
+        reason: String::new(),
+        code,
+        throttle: None,
+    }));
+    assert!(api(503).is_retryable());
+    assert!(api(429).is_retryable());
+    assert!(!api(404).is_retryable());
+    assert!(Error::from(ErrorKind::RequestSend("https://k8s.example.com".into())).is_retryable());
+    assert!(!Error::from(ErrorKind::Auth("Invalid bearer token".into())).is_retryable());
+}
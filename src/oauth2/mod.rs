@@ -69,11 +69,11 @@ impl Credentials {
     pub fn load() -> Result<Credentials> {
         let path = env::var_os(GOOGLE_APPLICATION_CREDENTIALS)
             .map(PathBuf::from)
-            .ok_or_else(|| ErrorKind::KubeConfig("Missing GOOGLE_APPLICATION_CREDENTIALS env".into()))?;
+            .ok_or_else(|| ErrorKind::Auth("Missing GOOGLE_APPLICATION_CREDENTIALS env".into()))?;
         let f = File::open(path)
-            .context(ErrorKind::KubeConfig("Unable to load credentials file".into()))?;
+            .context(ErrorKind::Auth("Unable to load credentials file".into()))?;
         let config = serde_json::from_reader(f)
-            .context(ErrorKind::KubeConfig("Unable to parse credentials file".into()))?;
+            .context(ErrorKind::Auth("Unable to parse credentials file".into()))?;
         Ok(config)
     }
 }
@@ -120,7 +120,7 @@ impl CredentialsClient {
     }
     pub fn request_token(&self, scopes: &Vec<String>) -> Result<Token> {
         let private_key = PKey::private_key_from_pem(&self.credentials.private_key.as_bytes())
-            .context(ErrorKind::SslError)?;
+            .context(ErrorKind::Tls("Invalid service account private key".into()))?;
         let encoded = &self.jws_encode(
             &Claim::new(&self.credentials, scopes),
             &Header{
@@ -139,9 +139,9 @@ impl CredentialsClient {
             .body(body)
             .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
             .send()
-            .context(ErrorKind::KubeConfig("Unable to request token".into()))?
+            .context(ErrorKind::Auth("Unable to request token".into()))?
             .json()
-            .context(ErrorKind::KubeConfig("Unable to parse request token".into()))?;
+            .context(ErrorKind::Auth("Unable to parse request token".into()))?;
         Ok(token_response.to_token())
     }
 
@@ -150,13 +150,13 @@ impl CredentialsClient {
         let encoded_claims = self.base64_encode(serde_json::to_string(&claim).unwrap().as_bytes());
         let signature_base = format!("{}.{}", encoded_header, encoded_claims);
         let mut signer = Signer::new(MessageDigest::sha256(), &key)
-            .context(ErrorKind::SslError)?;
+            .context(ErrorKind::Tls("Unable to sign token request".into()))?;
         signer.set_rsa_padding(Padding::PKCS1)
-            .context(ErrorKind::SslError)?;
+            .context(ErrorKind::Tls("Unable to sign token request".into()))?;
         signer.update(signature_base.as_bytes())
-            .context(ErrorKind::SslError)?;
+            .context(ErrorKind::Tls("Unable to sign token request".into()))?;
         let signature = signer.sign_to_vec()
-            .context(ErrorKind::SslError)?;
+            .context(ErrorKind::Tls("Unable to sign token request".into()))?;
         Ok(format!("{}.{}", signature_base, self.base64_encode(&signature)))
     }
 
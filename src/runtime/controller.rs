@@ -0,0 +1,402 @@
+//! Level triggered reconcile loops over a reflector cache
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+
+use crate::api::{KubeObject, Reflector};
+use crate::{Error, Result};
+
+/// What to do with an object after reconciling it
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    /// Reconcile again after this long, even if nothing changed
+    Requeue(Duration),
+    /// Only reconcile again once the object changes
+    AwaitChange,
+}
+
+/// An object in the cache, by namespace and name
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct ObjectRef {
+    pub namespace: Option<String>,
+    pub name: String,
+}
+
+impl ObjectRef {
+    pub fn from_obj<K: KubeObject>(obj: &K) -> Self {
+        ObjectRef {
+            namespace: obj.meta().namespace.clone(),
+            name: obj.meta().name.clone(),
+        }
+    }
+}
+
+/// A limit on reconciles running at once, shared between controllers
+///
+/// Permits are handed out first come first served, so a busy controller
+/// cannot starve the others sharing the budget.
+#[derive(Clone)]
+pub struct ReconcileBudget {
+    state: Arc<(Mutex<BudgetState>, Condvar)>,
+}
+
+struct BudgetState {
+    available: Option<usize>,
+    next_ticket: u64,
+    serving: u64,
+}
+
+/// A reconcile slot, returned to the budget on drop
+pub struct Permit {
+    budget: ReconcileBudget,
+}
+
+impl ReconcileBudget {
+    /// Allow at most `max` reconciles at once, and at least one
+    pub fn new(max: usize) -> Self {
+        Self::with(Some(std::cmp::max(max, 1)))
+    }
+
+    /// No limit
+    pub fn unlimited() -> Self {
+        Self::with(None)
+    }
+
+    fn with(available: Option<usize>) -> Self {
+        ReconcileBudget {
+            state: Arc::new((Mutex::new(BudgetState { available, next_ticket: 0, serving: 0 }), Condvar::new())),
+        }
+    }
+
+    /// Wait for a free slot
+    pub fn acquire(&self) -> Permit {
+        let (lock, cvar) = &*self.state;
+        let mut state = lock.lock().unwrap();
+        let ticket = state.next_ticket;
+        state.next_ticket += 1;
+        while state.serving != ticket || state.available == Some(0) {
+            state = cvar.wait(state).unwrap();
+        }
+        state.serving += 1;
+        if let Some(n) = state.available.as_mut() {
+            *n -= 1;
+        }
+        cvar.notify_all();
+        Permit { budget: self.clone() }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.budget.state;
+        if let Some(n) = lock.lock().unwrap().available.as_mut() {
+            *n += 1;
+        }
+        cvar.notify_all();
+    }
+}
+
+/// Something the `Manager` can drive
+pub trait Runnable: Send {
+    /// Name used in logs
+    fn name(&self) -> &str;
+    /// Reconcile whatever is due within `budget`, returning how many objects were reconciled
+    fn tick(&mut self, budget: &ReconcileBudget) -> Result<usize>;
+}
+
+/// A failed reconcile, as seen by the error policy
+#[derive(Clone, Debug)]
+pub struct ReconcileRequest {
+    pub obj_ref: ObjectRef,
+    /// How many reconciles in a row failed, this one included
+    pub attempt: u32,
+}
+
+/// The default error policy: back off exponentially from 5s, up to 5 minutes
+pub fn default_error_policy(_err: &Error, req: &ReconcileRequest) -> Action {
+    let secs = 5u64.saturating_mul(1 << std::cmp::min(req.attempt.saturating_sub(1), 6));
+    Action::Requeue(Duration::from_secs(std::cmp::min(secs, 300)))
+}
+
+type Reconciler<K> = Arc<dyn Fn(&K) -> Result<Action> + Send + Sync>;
+type ErrorPolicy = Box<dyn Fn(&Error, &ReconcileRequest) -> Action + Send + Sync>;
+type DeadLetter<K> = Box<dyn Fn(&K, &Error) + Send + Sync>;
+
+/// Calls a reconcile function for every object in a reflector that changed or is due a requeue
+///
+/// The reflector must be polled elsewhere, e.g. by the `Manager` that runs this controller.
+/// When a reconcile fails, the `error_policy` decides when to retry; after `max_retries`
+/// failures in a row the object is handed to `on_dead_letter` and parked until it changes.
+/// Up to `concurrency` objects are
+/// reconciled at once (one by default), but never the same object twice at the same time.
+pub struct Controller<K> where
+    K: Clone + DeserializeOwned + KubeObject
+{
+    name: String,
+    reflector: Reflector<K>,
+    reconcile: Reconciler<K>,
+    /// resourceVersions of the objects as last seen
+    seen: BTreeMap<ObjectRef, Option<String>>,
+    /// when objects are due a reconcile
+    queue: BTreeMap<ObjectRef, Instant>,
+    /// consecutive failures per object
+    failures: BTreeMap<ObjectRef, u32>,
+    error_policy: ErrorPolicy,
+    max_retries: Option<u32>,
+    dead_letter: Option<DeadLetter<K>>,
+    concurrency: usize,
+}
+
+impl<K> Controller<K> where
+    K: Clone + DeserializeOwned + KubeObject,
+{
+    pub fn new<F>(name: &str, reflector: Reflector<K>, reconcile: F) -> Self
+    where
+        F: Fn(&K) -> Result<Action> + Send + Sync + 'static,
+    {
+        Controller {
+            name: name.into(),
+            reflector,
+            reconcile: Arc::new(reconcile),
+            seen: BTreeMap::new(),
+            queue: BTreeMap::new(),
+            failures: BTreeMap::new(),
+            error_policy: Box::new(default_error_policy),
+            max_retries: None,
+            dead_letter: None,
+            concurrency: 1,
+        }
+    }
+
+    /// Reconcile up to `n` objects at once
+    pub fn concurrency(mut self, n: usize) -> Self {
+        self.concurrency = std::cmp::max(n, 1);
+        self
+    }
+
+    /// Always wait `after` before retrying a failed reconcile
+    pub fn error_requeue(self, after: Duration) -> Self {
+        self.error_policy(move |_, _| Action::Requeue(after))
+    }
+
+    /// Decide what to do after a failed reconcile, e.g. by the kind of error
+    pub fn error_policy<F>(mut self, policy: F) -> Self
+    where
+        F: Fn(&Error, &ReconcileRequest) -> Action + Send + Sync + 'static,
+    {
+        self.error_policy = Box::new(policy);
+        self
+    }
+
+    /// Stop retrying an object after this many failures in a row
+    ///
+    /// It is retried again once it changes.
+    pub fn max_retries(mut self, n: u32) -> Self {
+        self.max_retries = Some(n);
+        self
+    }
+
+    /// Called with objects exceeding `max_retries`, e.g. to emit an Event about them
+    pub fn on_dead_letter<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&K, &Error) + Send + Sync + 'static,
+    {
+        self.dead_letter = Some(Box::new(f));
+        self
+    }
+
+    /// Queue changed objects and reconcile those that are due
+    pub fn tick(&mut self) -> Result<usize> where K: Send + Sync {
+        self.tick_within(&ReconcileBudget::unlimited())
+    }
+
+    /// Like `tick`, taking a slot from `budget` for every reconcile
+    ///
+    /// Nothing is reconciled until the reflector has synced.
+    pub fn tick_within(&mut self, budget: &ReconcileBudget) -> Result<usize> where K: Send + Sync {
+        if !self.reflector.has_synced() {
+            debug!("Controller {} waiting for its cache to sync", self.name);
+            return Ok(0);
+        }
+        let objects = self.reflector.read()?;
+        let now = Instant::now();
+        let mut present = BTreeSet::new();
+        for obj in &objects {
+            let key = ObjectRef::from_obj(obj);
+            let version = obj.meta().resourceVersion.clone();
+            if self.seen.get(&key) != Some(&version) {
+                self.queue.insert(key.clone(), now);
+                self.seen.insert(key.clone(), version);
+                self.failures.remove(&key);
+            }
+            present.insert(key);
+        }
+        self.seen.retain(|k, _| present.contains(k));
+        self.queue.retain(|k, _| present.contains(k));
+        self.failures.retain(|k, _| present.contains(k));
+
+        let due: Vec<ObjectRef> = self.queue.iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(k, _)| k.clone())
+            .collect();
+        let by_key: BTreeMap<ObjectRef, &K> = objects.iter().map(|o| (ObjectRef::from_obj(o), o)).collect();
+        let work = Mutex::new(due.iter().rev().collect::<Vec<_>>());
+        let results = Mutex::new(vec![]);
+        let workers = std::cmp::min(self.concurrency, due.len());
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let key = match work.lock().unwrap().pop() {
+                        Some(key) => key,
+                        None => break,
+                    };
+                    let _permit = budget.acquire();
+                    let res = (self.reconcile)(by_key[key]);
+                    results.lock().unwrap().push((key.clone(), res));
+                });
+            }
+        });
+        for (key, res) in results.into_inner().unwrap() {
+            self.queue.remove(&key);
+            let action = match res {
+                Ok(action) => {
+                    self.failures.remove(&key);
+                    action
+                },
+                Err(e) => {
+                    let attempt = self.failures.get(&key).map_or(1, |n| n + 1);
+                    self.failures.insert(key.clone(), attempt);
+                    warn!("{}: reconcile of {:?} failed (attempt {}): {}", self.name, key, attempt, e);
+                    if self.max_retries.is_some_and(|max| attempt >= max) {
+                        warn!("{}: giving up on {:?} until it changes", self.name, key);
+                        if let Some(dead_letter) = &self.dead_letter {
+                            dead_letter(by_key[&key], &e);
+                        }
+                        Action::AwaitChange
+                    } else {
+                        (self.error_policy)(&e, &ReconcileRequest { obj_ref: key.clone(), attempt })
+                    }
+                },
+            };
+            if let Action::Requeue(after) = action {
+                self.queue.insert(key, Instant::now() + after);
+            }
+        }
+        Ok(due.len())
+    }
+}
+
+impl<K> Runnable for Controller<K> where
+    K: Clone + DeserializeOwned + KubeObject + Send + Sync,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+    fn tick(&mut self, budget: &ReconcileBudget) -> Result<usize> {
+        self.tick_within(budget)
+    }
+}
+
+#[test]
+fn budget_limits_concurrency() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    let budget = ReconcileBudget::new(2);
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+    let threads: Vec<_> = (0..6).map(|_| {
+        let (budget, running, peak) = (budget.clone(), running.clone(), peak.clone());
+        std::thread::spawn(move || {
+            let _permit = budget.acquire();
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(10));
+            running.fetch_sub(1, Ordering::SeqCst);
+        })
+    }).collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(peak.load(Ordering::SeqCst), 2);
+    // a budget of nothing would never let a reconcile through
+    drop(ReconcileBudget::new(0).acquire());
+}
+
+#[test]
+fn default_policy_backs_off() {
+    let err = Error::from(crate::ErrorKind::RequestSend("https://k8s.example.com".into()));
+    let req = |attempt| ReconcileRequest { obj_ref: ObjectRef { namespace: None, name: "a".into() }, attempt };
+    assert_eq!(default_error_policy(&err, &req(1)), Action::Requeue(Duration::from_secs(5)));
+    assert_eq!(default_error_policy(&err, &req(3)), Action::Requeue(Duration::from_secs(20)));
+    assert_eq!(default_error_policy(&err, &req(30)), Action::Requeue(Duration::from_secs(300)));
+}
+
+/// A synced reflector over the named objects at their resourceVersions, listed again on `reset`
+#[cfg(test)]
+fn stub_reflector(objects: Arc<Mutex<Vec<(&'static str, &'static str)>>>) -> Reflector<crate::api::Object<crate::api::Void, crate::api::Void>> {
+    use crate::api::Api;
+    use crate::client::fake::{respond, Fake};
+    let client = Fake::new().handle("GET", "*", move |_| {
+        let items: Vec<serde_json::Value> = objects.lock().unwrap().iter().map(|(name, version)| serde_json::json!({
+            "metadata": { "name": name, "namespace": "ns", "resourceVersion": version }, "spec": {},
+        })).collect();
+        respond(200, serde_json::json!({ "metadata": { "resourceVersion": "1" }, "items": items }).to_string())
+    }).client();
+    Reflector::new(Api::customResource(client, "foos").group("example.com").within("ns")).init().unwrap()
+}
+
+#[test]
+fn changed_and_requeued_objects_are_reconciled() {
+    let objects = Arc::new(Mutex::new(vec![("a", "1"), ("b", "1")]));
+    let reflector = stub_reflector(objects.clone());
+    let calls = Arc::new(Mutex::new(vec![]));
+    let seen = calls.clone();
+    let mut controller = Controller::new("foos", reflector.clone(), move |foo: &crate::api::Object<_, _>| {
+        seen.lock().unwrap().push(foo.metadata.name.clone());
+        Ok(match foo.metadata.name.as_str() {
+            "b" => Action::Requeue(Duration::from_millis(100)),
+            _ => Action::AwaitChange,
+        })
+    });
+    assert_eq!(controller.tick().unwrap(), 2);
+    assert_eq!(controller.tick().unwrap(), 0, "objects at the same resourceVersion are not reconciled again");
+    std::thread::sleep(Duration::from_millis(150));
+    assert_eq!(controller.tick().unwrap(), 1, "b is requeued");
+
+    objects.lock().unwrap()[0].1 = "2";
+    reflector.reset().unwrap();
+    assert_eq!(controller.tick().unwrap(), 1, "a changed");
+    assert_eq!(*calls.lock().unwrap(), vec!["a", "b", "b", "a"]);
+}
+
+#[test]
+fn failing_objects_are_dead_lettered_until_they_change() {
+    let objects = Arc::new(Mutex::new(vec![("a", "1")]));
+    let reflector = stub_reflector(objects.clone());
+    let attempts = Arc::new(Mutex::new(vec![]));
+    let dead = Arc::new(Mutex::new(vec![]));
+    let (policy_seen, dead_seen) = (attempts.clone(), dead.clone());
+    let mut controller = Controller::new("foos", reflector.clone(), |_: &crate::api::Object<_, _>| {
+        Err(crate::ErrorKind::RequestSend("https://k8s.example.com".into()).into())
+    })
+        .error_policy(move |_, req| {
+            policy_seen.lock().unwrap().push(req.attempt);
+            Action::Requeue(Duration::from_millis(0))
+        })
+        .max_retries(3)
+        .on_dead_letter(move |foo, e| dead_seen.lock().unwrap().push(format!("{}: {}", foo.metadata.name, e)));
+
+    for _ in 0..5 {
+        controller.tick().unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(*attempts.lock().unwrap(), vec![1, 2], "the error policy decides until max_retries");
+    assert_eq!(*dead.lock().unwrap(), vec!["a: Error executing request to https://k8s.example.com"]);
+    assert_eq!(controller.tick().unwrap(), 0, "parked");
+
+    objects.lock().unwrap()[0].1 = "2";
+    reflector.reset().unwrap();
+    assert_eq!(controller.tick().unwrap(), 1, "retried once it changes");
+    assert_eq!(*attempts.lock().unwrap(), vec![1, 2, 1], "with its failures forgotten");
+}
@@ -0,0 +1,164 @@
+//! Leader election on a coordination.k8s.io Lease, like client-go's `leaderelection`
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::Utc;
+use failure::ResultExt;
+use k8s_openapi::api::coordination::v1::LeaseSpec;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+
+use crate::api::{Api, Object, ObjectMeta, PostParams, TypeMeta, Void};
+use crate::client::APIClient;
+use crate::{ErrorKind, Result};
+
+type Lease = Object<LeaseSpec, Void>;
+
+/// Competes for a Lease so only one replica of a controller is active
+///
+/// The defaults match client-go: a 15s lease, renewed within 10s, retried every 2s.
+#[derive(Clone)]
+pub struct LeaderElection {
+    api: Api<Lease>,
+    name: String,
+    identity: String,
+    pub lease_duration: Duration,
+    pub renew_deadline: Duration,
+    pub retry_period: Duration,
+}
+
+/// Whether `identity` may take or renew a lease with this spec at `now`
+fn can_acquire(spec: &LeaseSpec, identity: &str, now: chrono::DateTime<Utc>) -> bool {
+    let holder = spec.holder_identity.as_deref().unwrap_or("");
+    if holder.is_empty() || holder == identity {
+        return true;
+    }
+    // someone else holds it; it is up for grabs once they stop renewing
+    let duration = chrono::Duration::seconds(i64::from(spec.lease_duration_seconds.unwrap_or(0)));
+    match &spec.renew_time {
+        Some(MicroTime(renewed)) => *renewed + duration < now,
+        None => true,
+    }
+}
+
+impl LeaderElection {
+    /// Elect a leader through the Lease `name` in `namespace`
+    ///
+    /// `identity` must be unique per replica; the pod name is a good choice.
+    pub fn new(client: APIClient, namespace: &str, name: &str, identity: &str) -> Self {
+        LeaderElection {
+            api: Api::v1Lease(client).within(namespace),
+            name: name.into(),
+            identity: identity.into(),
+            lease_duration: Duration::from_secs(15),
+            renew_deadline: Duration::from_secs(10),
+            retry_period: Duration::from_secs(2),
+        }
+    }
+
+    /// The identity this replica competes with
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    fn spec_for(&self, mut spec: LeaseSpec) -> LeaseSpec {
+        let now = MicroTime(Utc::now());
+        if spec.holder_identity.as_deref() != Some(self.identity.as_str()) {
+            spec.lease_transitions = Some(spec.lease_transitions.map_or(0, |t| t + 1));
+            spec.acquire_time = Some(now.clone());
+            spec.holder_identity = Some(self.identity.clone());
+        }
+        spec.lease_duration_seconds = Some(self.lease_duration.as_secs() as i32);
+        spec.renew_time = Some(now);
+        spec
+    }
+
+    /// Take the lease if it is free or expired, or renew it if we hold it
+    ///
+    /// Returns whether we are the leader. Losing a race to another replica is not an error.
+    pub fn try_acquire_or_renew(&self) -> Result<bool> {
+        let (res, lease) = match self.api.get_opt(&self.name)? {
+            None => {
+                let lease = Lease {
+                    types: TypeMeta {
+                        apiVersion: Some("coordination.k8s.io/v1".into()),
+                        kind: Some("Lease".into()),
+                    },
+                    metadata: ObjectMeta { name: self.name.clone(), ..Default::default() },
+                    spec: self.spec_for(LeaseSpec::default()),
+                    status: None,
+                };
+                let data = serde_json::to_vec(&lease).context(ErrorKind::SerdeParse)?;
+                (self.api.create(&PostParams::default(), data), lease)
+            },
+            Some(mut lease) => {
+                if !can_acquire(&lease.spec, &self.identity, Utc::now()) {
+                    return Ok(false);
+                }
+                lease.spec = self.spec_for(lease.spec);
+                let data = serde_json::to_vec(&lease).context(ErrorKind::SerdeParse)?;
+                (self.api.replace(&self.name, &PostParams::default(), data), lease)
+            },
+        };
+        match res {
+            Ok(_) => {
+                trace!("{} holds lease {}", self.identity, lease.metadata.name);
+                Ok(true)
+            },
+            // someone else wrote the lease first
+            Err(ref e) if e.status_code() == Some(409) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Block until we are the leader, or `shutdown` is set
+    ///
+    /// Returns whether leadership was acquired.
+    pub fn acquire(&self, shutdown: &AtomicBool) -> Result<bool> {
+        info!("{} waiting to acquire lease {}", self.identity, self.name);
+        while !shutdown.load(Ordering::SeqCst) {
+            match self.try_acquire_or_renew() {
+                Ok(true) => {
+                    info!("{} acquired lease {}", self.identity, self.name);
+                    return Ok(true);
+                },
+                Ok(false) => {},
+                Err(e) => warn!("Failed to acquire lease {}: {}", self.name, e),
+            }
+            std::thread::sleep(self.retry_period);
+        }
+        Ok(false)
+    }
+
+    /// Give up the lease so another replica can take over straight away
+    pub fn release(&self) -> Result<()> {
+        let mut lease = match self.api.get_opt(&self.name)? {
+            Some(lease) => lease,
+            None => return Ok(()),
+        };
+        if lease.spec.holder_identity.as_deref() != Some(self.identity.as_str()) {
+            return Ok(());
+        }
+        lease.spec.holder_identity = None;
+        lease.spec.lease_duration_seconds = Some(1);
+        let data = serde_json::to_vec(&lease).context(ErrorKind::SerdeParse)?;
+        match self.api.replace(&self.name, &PostParams::default(), data) {
+            Err(ref e) if e.status_code() == Some(409) => Ok(()),
+            res => res.map(|_| ()),
+        }
+    }
+}
+
+#[test]
+fn lease_acquisition() {
+    let now = Utc::now();
+    let spec = |holder: Option<&str>, renewed_secs_ago: i64| LeaseSpec {
+        holder_identity: holder.map(String::from),
+        lease_duration_seconds: Some(15),
+        renew_time: Some(MicroTime(now - chrono::Duration::seconds(renewed_secs_ago))),
+        ..Default::default()
+    };
+    assert!(can_acquire(&spec(None, 0), "a", now));
+    assert!(can_acquire(&spec(Some("a"), 0), "a", now));
+    assert!(!can_acquire(&spec(Some("b"), 5), "a", now));
+    assert!(can_acquire(&spec(Some("b"), 20), "a", now));
+}
@@ -0,0 +1,402 @@
+//! Running several controllers behind one leader election
+use std::any::Any;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+
+use crate::api::{Api, Backoff, KubeObject, Reflector};
+use crate::runtime::{LeaderElection, ReconcileBudget, Runnable};
+use crate::{ErrorKind, Result};
+use failure::ResultExt;
+
+/// How often controllers look for changed or requeued objects
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a reflector whose polls keep failing waits between them
+const POLL_BACKOFF: Backoff = Backoff {
+    steps: usize::MAX,
+    duration: Duration::from_secs(1),
+    factor: 2.0,
+    cap: Some(Duration::from_secs(30)),
+};
+
+struct SharedReflector {
+    reflector: Box<dyn Any + Send>,
+    init: Box<dyn Fn() -> Result<()> + Send>,
+    poll: Arc<dyn Fn() -> Result<()> + Send + Sync>,
+}
+
+/// Runs controllers and the reflectors they share, optionally behind leader election
+///
+/// `run` blocks until `shutdown_handle` is set, or leadership is lost, which fails
+/// with `ErrorKind::LeadershipLost` so the process can exit and restart as a follower.
+/// Stopping waits for in flight reflector polls, so takes up to their timeout.
+///
+/// ```no_run
+/// use kube::{api::Api, client::APIClient, config};
+/// use kube::runtime::{Action, Controller, LeaderElection, Manager};
+///
+/// let client = APIClient::new(config::incluster_config().unwrap());
+/// let mut manager = Manager::new()
+///     .leader_election(LeaderElection::new(client.clone(), "default", "my-operator", "pod-1"))
+///     .health_endpoint("0.0.0.0:8081");
+/// let pods = manager.reflector(Api::v1Pod(client));
+/// manager.add(Controller::new("pods", pods, |pod| {
+///     println!("reconciling {}", pod.metadata.name);
+///     Ok(Action::AwaitChange)
+/// }));
+/// manager.run().unwrap();
+/// ```
+pub struct Manager {
+    leader: Option<LeaderElection>,
+    health_addr: Option<String>,
+    reflectors: HashMap<String, SharedReflector>,
+    controllers: Vec<Box<dyn Runnable>>,
+    budget: ReconcileBudget,
+    shutdown: Arc<AtomicBool>,
+    ready: Arc<AtomicBool>,
+}
+
+impl Default for Manager {
+    fn default() -> Self {
+        Manager {
+            leader: None,
+            health_addr: None,
+            reflectors: HashMap::new(),
+            controllers: vec![],
+            budget: ReconcileBudget::unlimited(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            ready: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl Manager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only run the controllers while holding this lease
+    pub fn leader_election(mut self, le: LeaderElection) -> Self {
+        self.leader = Some(le);
+        self
+    }
+
+    /// Serve `/healthz` and `/readyz` on `addr`
+    ///
+    /// `/readyz` succeeds once the manager leads and its caches are filled.
+    pub fn health_endpoint(mut self, addr: &str) -> Self {
+        self.health_addr = Some(addr.into());
+        self
+    }
+
+    /// Limit the reconciles running at once across all controllers
+    ///
+    /// Each controller still only runs up to its own `concurrency`. A `max` of 0 is taken as 1.
+    pub fn max_concurrent_reconciles(mut self, max: usize) -> Self {
+        self.budget = ReconcileBudget::new(max);
+        self
+    }
+
+    /// Set this to stop `run`, e.g. from a signal handler
+    pub fn shutdown_handle(&self) -> Arc<AtomicBool> {
+        self.shutdown.clone()
+    }
+
+    /// A reflector for `api`, shared with every other caller asking for the same one
+    ///
+    /// The manager fills and polls it once running.
+    pub fn reflector<K>(&mut self, api: Api<K>) -> Reflector<K>
+    where
+        K: Clone + DeserializeOwned + KubeObject + Send + Sync + 'static,
+    {
+        let key = format!("{}/{:?}", std::any::type_name::<K>(), api.raw());
+        if let Some(shared) = self.reflectors.get(&key) {
+            if let Some(r) = shared.reflector.downcast_ref::<Reflector<K>>() {
+                return r.clone();
+            }
+        }
+        let reflector = Reflector::new(api);
+        let (r1, r2) = (reflector.clone(), reflector.clone());
+        self.reflectors.insert(key, SharedReflector {
+            reflector: Box::new(reflector.clone()),
+            init: Box::new(move || r1.clone().init().map(|_| ())),
+            poll: Arc::new(move || r2.poll()),
+        });
+        reflector
+    }
+
+    /// Add a controller to run
+    pub fn add<R: Runnable + 'static>(&mut self, controller: R) {
+        self.controllers.push(Box::new(controller));
+    }
+
+    fn serve_health(&self, addr: &str) -> Result<()> {
+        let listener = TcpListener::bind(addr).with_context(|_| ErrorKind::Io(addr.to_string()))?;
+        let ready = self.ready.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut line = String::new();
+                let mut reader = BufReader::new(&stream);
+                if reader.read_line(&mut line).is_err() {
+                    continue;
+                }
+                let path = line.split_whitespace().nth(1).unwrap_or("/");
+                let ok = match path {
+                    "/healthz" => true,
+                    "/readyz" => ready.load(Ordering::SeqCst),
+                    _ => false,
+                };
+                let status = if ok { "200 OK" } else if path == "/readyz" { "503 Service Unavailable" } else { "404 Not Found" };
+                let _ = (&stream).write_all(format!(
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).as_bytes());
+            }
+        });
+        Ok(())
+    }
+
+    /// Run until shut down or leadership is lost
+    pub fn run(self) -> Result<()> {
+        if let Some(addr) = &self.health_addr {
+            self.serve_health(addr)?;
+        }
+        if let Some(le) = &self.leader {
+            if !le.acquire(&self.shutdown)? {
+                return Ok(());
+            }
+        }
+        for shared in self.reflectors.values() {
+            if let Err(e) = (shared.init)() {
+                // let another replica take over rather than wait for the lease to expire
+                if let Some(le) = &self.leader {
+                    if let Err(release) = le.release() {
+                        warn!("Failed to release lease: {}", release);
+                    }
+                }
+                return Err(e);
+            }
+        }
+        self.ready.store(true, Ordering::SeqCst);
+
+        let mut threads: Vec<JoinHandle<()>> = vec![];
+        for shared in self.reflectors.values() {
+            let (poll, shutdown) = (shared.poll.clone(), self.shutdown.clone());
+            threads.push(std::thread::spawn(move || {
+                let mut failures = 0;
+                while !shutdown.load(Ordering::SeqCst) {
+                    match poll() {
+                        Ok(()) => failures = 0,
+                        Err(e) => {
+                            failures += 1;
+                            let wait = POLL_BACKOFF.wait(failures);
+                            warn!("Reflector poll failed: {}, retrying in {:?}", e, wait);
+                            let failed = Instant::now();
+                            // in steps, so as not to hold up shutdown
+                            while failed.elapsed() < wait && !shutdown.load(Ordering::SeqCst) {
+                                std::thread::sleep(std::cmp::min(wait, TICK_INTERVAL) / 10);
+                            }
+                        },
+                    }
+                }
+            }));
+        }
+        for mut controller in self.controllers {
+            let (shutdown, budget) = (self.shutdown.clone(), self.budget.clone());
+            threads.push(std::thread::spawn(move || {
+                info!("Starting controller {}", controller.name());
+                while !shutdown.load(Ordering::SeqCst) {
+                    if let Err(e) = controller.tick(&budget) {
+                        warn!("Controller {} failed: {}", controller.name(), e);
+                    }
+                    std::thread::sleep(TICK_INTERVAL);
+                }
+            }));
+        }
+
+        let mut result = Ok(());
+        if let Some(le) = &self.leader {
+            let mut renewed = Instant::now();
+            while !self.shutdown.load(Ordering::SeqCst) {
+                std::thread::sleep(le.retry_period);
+                match le.try_acquire_or_renew() {
+                    Ok(true) => renewed = Instant::now(),
+                    Ok(false) => {
+                        result = Err(ErrorKind::LeadershipLost(le.identity().into()).into());
+                        break;
+                    },
+                    Err(e) => {
+                        warn!("Failed to renew lease: {}", e);
+                        if renewed.elapsed() > le.renew_deadline {
+                            result = Err(ErrorKind::LeadershipLost(le.identity().into()).into());
+                            break;
+                        }
+                    },
+                }
+            }
+        } else {
+            while !self.shutdown.load(Ordering::SeqCst) {
+                std::thread::sleep(TICK_INTERVAL);
+            }
+        }
+
+        info!("Shutting down");
+        self.ready.store(false, Ordering::SeqCst);
+        self.shutdown.store(true, Ordering::SeqCst);
+        for t in threads {
+            let _ = t.join();
+        }
+        if let (Some(le), Ok(())) = (&self.leader, &result) {
+            le.release()?;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::fake::{respond, status, Fake, Objects};
+    use std::net::TcpStream;
+
+    const LEASE: &str = "/apis/coordination.k8s.io/v1/namespaces/ns/leases/operator";
+
+    /// Keeps a lease and serves pods, until told to fail either
+    #[derive(Default)]
+    struct Apiserver {
+        lease: Objects,
+        leases_fail: AtomicBool,
+        pods_listed: AtomicBool,
+        pods_fail: AtomicBool,
+    }
+
+    fn manager(apiserver: &Arc<Apiserver>, health: Option<&str>) -> Manager {
+        let (leases, pods) = (apiserver.clone(), apiserver.clone());
+        let client = Fake::new()
+            .handle("*", "/apis/coordination.k8s.io/*", move |req| match leases.leases_fail.load(Ordering::SeqCst) {
+                true => respond(500, status(500, "InternalError")),
+                false => leases.lease.serve(req),
+            })
+            .handle("GET", "/api/v1/pods?watch=true", |_| {
+                std::thread::sleep(Duration::from_millis(20));
+                respond(200, "")
+            })
+            .handle("GET", "/api/v1/pods", move |_| {
+                // the list filling the cache only answers once allowed to
+                while !pods.pods_listed.load(Ordering::SeqCst) {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                match pods.pods_fail.load(Ordering::SeqCst) {
+                    true => respond(500, status(500, "InternalError")),
+                    false => respond(200, r#"{"metadata":{"resourceVersion":"1"},"items":[]}"#),
+                }
+            })
+            .client();
+        let mut le = LeaderElection::new(client.clone(), "ns", "operator", "pod-1");
+        le.retry_period = Duration::from_millis(20);
+        le.renew_deadline = Duration::from_millis(200);
+        let mut manager = Manager::new().leader_election(le);
+        if let Some(addr) = health {
+            manager = manager.health_endpoint(addr);
+        }
+        manager.reflector(Api::v1Pod(client));
+        manager
+    }
+
+    fn holder(apiserver: &Apiserver) -> Option<String> {
+        let lease = apiserver.lease.get(LEASE)?;
+        lease["spec"]["holderIdentity"].as_str().map(String::from)
+    }
+
+    /// The status line of a GET on the health endpoint, once it is up
+    fn get(port: u16, path: &str) -> String {
+        for _ in 0..100 {
+            if let Ok(mut stream) = TcpStream::connect(("127.0.0.1", port)) {
+                stream.write_all(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes()).unwrap();
+                let mut line = String::new();
+                BufReader::new(stream).read_line(&mut line).unwrap();
+                return line.trim().to_string();
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("health endpoint did not come up");
+    }
+
+    #[test]
+    fn ready_once_filled_and_stops_when_leadership_is_lost() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let apiserver = Arc::new(Apiserver::default());
+        let manager = manager(&apiserver, Some(&format!("127.0.0.1:{}", port)));
+        let running = std::thread::spawn(move || manager.run());
+
+        assert_eq!(get(port, "/healthz"), "HTTP/1.1 200 OK");
+        assert_eq!(get(port, "/readyz"), "HTTP/1.1 503 Service Unavailable");
+        apiserver.pods_listed.store(true, Ordering::SeqCst);
+        let filled = Instant::now();
+        while get(port, "/readyz") != "HTTP/1.1 200 OK" {
+            assert!(filled.elapsed() < Duration::from_secs(5), "never became ready");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(holder(&apiserver).as_deref(), Some("pod-1"));
+
+        // renewals keep failing for longer than the renew deadline
+        apiserver.leases_fail.store(true, Ordering::SeqCst);
+        let failing = Instant::now();
+        let err = running.join().unwrap().expect_err("losing leadership is an error");
+        assert!(failing.elapsed() >= Duration::from_millis(200));
+        match err.kind() {
+            ErrorKind::LeadershipLost(identity) => assert_eq!(identity, "pod-1"),
+            kind => panic!("unexpected error {}", kind),
+        }
+    }
+
+    #[test]
+    fn lease_is_released_when_caches_fail_to_fill() {
+        let apiserver = Arc::new(Apiserver::default());
+        apiserver.pods_listed.store(true, Ordering::SeqCst);
+        apiserver.pods_fail.store(true, Ordering::SeqCst);
+        assert!(manager(&apiserver, None).run().is_err());
+        assert!(apiserver.lease.get(LEASE).is_some(), "the lease was taken");
+        assert_eq!(holder(&apiserver), None, "and given up");
+    }
+
+    #[test]
+    fn failing_reflector_polls_back_off() {
+        // watches are throttled without a wait, and relists fail after the first
+        let fake = Fake::new()
+            .handle("GET", "/api/v1/pods?watch=true", |_| http::Response::builder()
+                .status(429)
+                .header("Retry-After", "0")
+                .body(Box::new(std::io::Cursor::new(status(429, "TooManyRequests"))) as crate::client::Body)
+                .unwrap())
+            .once("GET", "/api/v1/pods", 200, r#"{"metadata":{"resourceVersion":"1"},"items":[]}"#)
+            .on("GET", "/api/v1/pods", 500, status(500, "InternalError"));
+        let mut manager = Manager::new();
+        manager.reflector(Api::v1Pod(fake.client()));
+        let shutdown = manager.shutdown_handle();
+        let running = std::thread::spawn(move || manager.run());
+        std::thread::sleep(Duration::from_millis(500));
+        shutdown.store(true, Ordering::SeqCst);
+        let stopping = Instant::now();
+        running.join().unwrap().unwrap();
+        assert!(stopping.elapsed() < Duration::from_secs(2));
+        let lists = fake.requests().iter().filter(|r| !r.query().contains("watch=true")).count();
+        assert!(lists <= 3, "{} lists", lists);
+    }
+
+    #[test]
+    fn health_endpoint_bind_failures_are_io_errors() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap().to_string();
+        let manager = Manager::new().health_endpoint(&addr);
+        match manager.run().unwrap_err().kind() {
+            ErrorKind::Io(a) => assert_eq!(*a, addr),
+            kind => panic!("unexpected error {}", kind),
+        }
+    }
+}
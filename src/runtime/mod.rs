@@ -0,0 +1,19 @@
+//! Building blocks for operators: leader election, controllers and a manager running them,
+//! and node heartbeats for providers acting as a kubelet
+//!
+//! Enabled with the `openapi` feature.
+
+mod leader;
+pub use self::leader::LeaderElection;
+
+mod controller;
+pub use self::controller::{
+    Action, Controller, ObjectRef, Permit, ReconcileBudget, ReconcileRequest, Runnable,
+    default_error_policy,
+};
+
+mod manager;
+pub use self::manager::Manager;
+
+mod node;
+pub use self::node::{NodeHeartbeat, NODE_LEASE_NAMESPACE};
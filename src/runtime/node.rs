@@ -0,0 +1,177 @@
+//! Node registration and heartbeats, for providers acting as a kubelet like virtual-kubelet
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use failure::ResultExt;
+use k8s_openapi::api::coordination::v1::LeaseSpec;
+use k8s_openapi::api::core::v1::{NodeCondition, NodeSpec, NodeStatus};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::{MicroTime, Time};
+use serde_json::json;
+
+use crate::api::{Api, Object, ObjectMeta, OwnerReference, PatchParams, PostParams, TypeMeta, Void};
+use crate::client::APIClient;
+use crate::{ErrorKind, Result};
+
+type Node = Object<NodeSpec, NodeStatus>;
+type Lease = Object<LeaseSpec, Void>;
+
+/// The namespace kubelets keep their node leases in
+pub const NODE_LEASE_NAMESPACE: &str = "kube-node-lease";
+
+/// Keeps a Node alive, through its Lease and its status
+///
+/// The node lifecycle controller marks nodes `NotReady` and evicts their pods when
+/// neither is updated in time. The defaults match the kubelet: a 40s lease renewed
+/// every 10s, with the status reported every minute.
+#[derive(Clone)]
+pub struct NodeHeartbeat {
+    nodes: Api<Node>,
+    leases: Api<Lease>,
+    name: String,
+    pub lease_duration: Duration,
+    pub renew_interval: Duration,
+    pub status_interval: Duration,
+}
+
+/// Conditions to report at `now`, given those reported before
+///
+/// Heartbeat times are set to `now`; transition times only where the status changed.
+fn heartbeat_conditions(previous: &[NodeCondition], current: Vec<NodeCondition>, now: DateTime<Utc>) -> Vec<NodeCondition> {
+    current.into_iter().map(|mut c| {
+        let before = previous.iter().find(|p| p.type_ == c.type_);
+        c.last_heartbeat_time = Some(Time(now));
+        c.last_transition_time = match before {
+            Some(p) if p.status == c.status => p.last_transition_time.clone(),
+            _ => Some(Time(now)),
+        };
+        c
+    }).collect()
+}
+
+impl NodeHeartbeat {
+    /// Heartbeats for the Node `name`
+    pub fn new(client: APIClient, name: &str) -> Self {
+        NodeHeartbeat {
+            nodes: Api::v1Node(client.clone()),
+            leases: Api::v1Lease(client).within(NODE_LEASE_NAMESPACE),
+            name: name.into(),
+            lease_duration: Duration::from_secs(40),
+            renew_interval: Duration::from_secs(10),
+            status_interval: Duration::from_secs(60),
+        }
+    }
+
+    /// Create the Node, or return it if it is already registered
+    ///
+    /// The name of `node` is set to ours. An existing node is left as is, so providers
+    /// restarting keep the labels and taints added by administrators.
+    pub fn register(&self, mut node: Node) -> Result<Node> {
+        node.metadata.name = self.name.clone();
+        let data = serde_json::to_vec(&node).context(ErrorKind::SerdeParse)?;
+        match self.nodes.create(&PostParams::default(), data) {
+            Err(ref e) if e.status_code() == Some(409) => {
+                debug!("Node {} is already registered", self.name);
+                self.nodes.get(&self.name)
+            },
+            res => res,
+        }
+    }
+
+    /// Renew the node's Lease, creating it if missing
+    ///
+    /// The Lease is owned by the Node, so it is garbage collected along with it.
+    pub fn renew_lease(&self) -> Result<Lease> {
+        let now = MicroTime(Utc::now());
+        let duration = Some(self.lease_duration.as_secs() as i32);
+        match self.leases.get_opt(&self.name)? {
+            Some(mut lease) => {
+                lease.spec.holder_identity = Some(self.name.clone());
+                lease.spec.lease_duration_seconds = duration;
+                lease.spec.renew_time = Some(now);
+                let data = serde_json::to_vec(&lease).context(ErrorKind::SerdeParse)?;
+                self.leases.replace(&self.name, &PostParams::default(), data)
+            },
+            None => {
+                let node = self.nodes.get(&self.name)?;
+                let mut metadata = ObjectMeta { name: self.name.clone(), ..Default::default() };
+                metadata.add_owner_reference(OwnerReference::for_owner(&node)?);
+                let lease = Lease {
+                    types: TypeMeta {
+                        apiVersion: Some("coordination.k8s.io/v1".into()),
+                        kind: Some("Lease".into()),
+                    },
+                    metadata,
+                    spec: LeaseSpec {
+                        holder_identity: Some(self.name.clone()),
+                        lease_duration_seconds: duration,
+                        renew_time: Some(now),
+                        ..Default::default()
+                    },
+                    status: None,
+                };
+                let data = serde_json::to_vec(&lease).context(ErrorKind::SerdeParse)?;
+                self.leases.create(&PostParams::default(), data)
+            },
+        }
+    }
+
+    /// Report the node's status, with heartbeat and transition times filled in
+    ///
+    /// The status replaces the reported conditions, addresses and capacity wholesale,
+    /// so should hold everything the provider reports.
+    pub fn update_status(&self, mut status: NodeStatus) -> Result<Node> {
+        let node = self.nodes.get(&self.name)?;
+        let previous = node.status.and_then(|s| s.conditions).unwrap_or_default();
+        let conditions = status.conditions.take().unwrap_or_default();
+        status.conditions = Some(heartbeat_conditions(&previous, conditions, Utc::now()));
+        let patch = json!({ "status": status });
+        let data = serde_json::to_vec(&patch).context(ErrorKind::SerdeParse)?;
+        self.nodes.patch_status(&self.name, &PatchParams::default(), data)
+    }
+
+    /// Renew the lease and report `status()` at their intervals, until `shutdown` is set
+    ///
+    /// Failures are logged and retried at the next interval, as the kubelet does.
+    pub fn run<F>(&self, status: F, shutdown: &AtomicBool) -> Result<()>
+    where
+        F: Fn() -> NodeStatus,
+    {
+        let (mut renewed, mut reported): (Option<Instant>, Option<Instant>) = (None, None);
+        while !shutdown.load(Ordering::SeqCst) {
+            if renewed.is_none_or(|t| t.elapsed() >= self.renew_interval) {
+                match self.renew_lease() {
+                    Ok(_) => trace!("Renewed lease of node {}", self.name),
+                    Err(e) => warn!("Failed to renew lease of node {}: {}", self.name, e),
+                }
+                renewed = Some(Instant::now());
+            }
+            if reported.is_none_or(|t| t.elapsed() >= self.status_interval) {
+                if let Err(e) = self.update_status(status()) {
+                    warn!("Failed to update status of node {}: {}", self.name, e);
+                }
+                reported = Some(Instant::now());
+            }
+            std::thread::sleep(Duration::from_secs(1).min(self.renew_interval));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn heartbeats_keep_transition_times() {
+    let now = Utc::now();
+    let before = now - chrono::Duration::minutes(5);
+    let cond = |type_: &str, status: &str| NodeCondition {
+        type_: type_.into(),
+        status: status.into(),
+        last_transition_time: Some(Time(before)),
+        ..Default::default()
+    };
+    let previous = vec![cond("Ready", "True"), cond("MemoryPressure", "False")];
+    let current = vec![cond("Ready", "True"), cond("MemoryPressure", "True"), cond("PIDPressure", "False")];
+    let reported = heartbeat_conditions(&previous, current, now);
+    assert!(reported.iter().all(|c| c.last_heartbeat_time == Some(Time(now))));
+    let transitions: Vec<_> = reported.iter().map(|c| c.last_transition_time.clone().unwrap().0).collect();
+    assert_eq!(transitions, vec![before, now, now]);
+}